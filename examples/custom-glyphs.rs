@@ -1,7 +1,7 @@
 use glyphon::{
-    Attrs, Buffer, Cache, Color, ContentType, CustomGlyph, Family, FontSystem, Metrics,
-    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, Resolution, Shaping, SwashCache, TextArea,
-    TextAtlas, TextBounds, TextRenderer, Viewport,
+    Attrs, Buffer, Cache, Color, ContentType, CustomGlyph, CustomGlyphAlign, CustomGlyphZOrder,
+    Family, FontSystem, Metrics, QuarterTurns, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+    Resolution, Shaping, SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
 };
 use std::sync::Arc;
 use wgpu::{
@@ -109,6 +109,7 @@ impl WindowState {
                 let (svg, content_type) = match input.id {
                     0 => (&svg_0, ContentType::Mask),
                     1 => (&svg_1, ContentType::Color),
+                    2 => (&svg_1, ContentType::TintedColor),
                     _ => return None,
                 };
 
@@ -117,11 +118,8 @@ impl WindowState {
                 let scale_x = input.width as f32 / svg_size.width();
                 let scale_y = input.height as f32 / svg_size.height();
 
-                let Some(mut pixmap) =
-                    resvg::tiny_skia::Pixmap::new(input.width as u32, input.height as u32)
-                else {
-                    return None;
-                };
+                let mut pixmap =
+                    resvg::tiny_skia::Pixmap::new(input.width as u32, input.height as u32)?;
 
                 let mut transform = resvg::usvg::Transform::from_scale(scale_x, scale_y);
 
@@ -211,12 +209,12 @@ impl winit::application::ApplicationHandler for Application {
             WindowEvent::Resized(size) => {
                 surface_config.width = size.width;
                 surface_config.height = size.height;
-                surface.configure(&device, &surface_config);
+                surface.configure(device, surface_config);
                 window.request_redraw();
             }
             WindowEvent::RedrawRequested => {
                 viewport.update(
-                    &queue,
+                    queue,
                     Resolution {
                         width: surface_config.width,
                         height: surface_config.height,
@@ -230,61 +228,96 @@ impl winit::application::ApplicationHandler for Application {
                         font_system,
                         atlas,
                         viewport,
-                        [TextArea {
-                            buffer: &text_buffer,
-                            left: 10.0,
-                            top: 10.0,
-                            scale: 1.0,
-                            bounds: TextBounds {
+                        [TextArea::new(
+                            text_buffer,
+                            10.0,
+                            10.0,
+                            1.0,
+                            TextBounds {
                                 left: 0,
                                 top: 0,
                                 right: 650,
                                 bottom: 180,
                             },
-                            default_color: Color::rgb(255, 255, 255),
-                            custom_glyphs: &[
-                                CustomGlyph {
-                                    id: 0,
-                                    left: 300.0,
-                                    top: 5.0,
-                                    width: 64.0,
-                                    height: 64.0,
-                                    color: Some(Color::rgb(200, 200, 255)),
-                                    snap_to_physical_pixel: true,
-                                    metadata: 0,
-                                },
-                                CustomGlyph {
-                                    id: 1,
-                                    left: 400.0,
-                                    top: 5.0,
-                                    width: 64.0,
-                                    height: 64.0,
-                                    color: None,
-                                    snap_to_physical_pixel: true,
-                                    metadata: 0,
-                                },
-                                CustomGlyph {
-                                    id: 0,
-                                    left: 300.0,
-                                    top: 130.0,
-                                    width: 64.0,
-                                    height: 64.0,
-                                    color: Some(Color::rgb(200, 255, 200)),
-                                    snap_to_physical_pixel: true,
-                                    metadata: 0,
-                                },
-                                CustomGlyph {
-                                    id: 1,
-                                    left: 400.0,
-                                    top: 130.0,
-                                    width: 64.0,
-                                    height: 64.0,
-                                    color: None,
-                                    snap_to_physical_pixel: true,
-                                    metadata: 0,
-                                },
-                            ],
-                        }],
+                            Color::rgb(255, 255, 255),
+                        )
+                        .with_custom_glyphs(&[
+                            CustomGlyph {
+                                id: 0,
+                                left: 300.0,
+                                top: 5.0,
+                                width: 64.0,
+                                height: 64.0,
+                                color: Some(Color::rgb(200, 200, 255)),
+                                snap_to_physical_pixel: true,
+                                z_order: CustomGlyphZOrder::BelowText,
+                                align: CustomGlyphAlign::Top,
+                                rotation: QuarterTurns::Zero,
+                                flip_x: false,
+                                flip_y: false,
+                                metadata: 0,
+                            },
+                            CustomGlyph {
+                                id: 1,
+                                left: 400.0,
+                                top: 5.0,
+                                width: 64.0,
+                                height: 64.0,
+                                color: None,
+                                snap_to_physical_pixel: true,
+                                z_order: CustomGlyphZOrder::BelowText,
+                                align: CustomGlyphAlign::Top,
+                                rotation: QuarterTurns::Zero,
+                                flip_x: false,
+                                flip_y: false,
+                                metadata: 0,
+                            },
+                            CustomGlyph {
+                                id: 2,
+                                left: 500.0,
+                                top: 5.0,
+                                width: 64.0,
+                                height: 64.0,
+                                color: Some(Color::rgb(255, 200, 200)),
+                                snap_to_physical_pixel: true,
+                                z_order: CustomGlyphZOrder::BelowText,
+                                align: CustomGlyphAlign::Top,
+                                rotation: QuarterTurns::Zero,
+                                flip_x: false,
+                                flip_y: false,
+                                metadata: 0,
+                            },
+                            CustomGlyph {
+                                id: 0,
+                                left: 300.0,
+                                top: 130.0,
+                                width: 64.0,
+                                height: 64.0,
+                                color: Some(Color::rgb(200, 255, 200)),
+                                snap_to_physical_pixel: true,
+                                z_order: CustomGlyphZOrder::BelowText,
+                                align: CustomGlyphAlign::Top,
+                                rotation: QuarterTurns::Zero,
+                                flip_x: false,
+                                flip_y: false,
+                                metadata: 0,
+                            },
+                            CustomGlyph {
+                                id: 1,
+                                left: 400.0,
+                                top: 130.0,
+                                width: 64.0,
+                                height: 64.0,
+                                color: None,
+                                snap_to_physical_pixel: true,
+                                z_order: CustomGlyphZOrder::BelowText,
+                                align: CustomGlyphAlign::Top,
+                                rotation: QuarterTurns::One,
+                                flip_x: false,
+                                flip_y: false,
+                                metadata: 0,
+                            },
+                        ])],
                         swash_cache,
                         rasterize_svg,
                     )
@@ -315,7 +348,7 @@ impl winit::application::ApplicationHandler for Application {
                         occlusion_query_set: None,
                     });
 
-                    text_renderer.render(&atlas, &viewport, &mut pass).unwrap();
+                    text_renderer.render(atlas, viewport, &mut pass).unwrap();
                 }
 
                 queue.submit(Some(encoder.finish()));
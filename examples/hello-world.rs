@@ -155,12 +155,12 @@ impl winit::application::ApplicationHandler for Application {
             WindowEvent::Resized(size) => {
                 surface_config.width = size.width;
                 surface_config.height = size.height;
-                surface.configure(&device, &surface_config);
+                surface.configure(device, surface_config);
                 window.request_redraw();
             }
             WindowEvent::RedrawRequested => {
                 viewport.update(
-                    &queue,
+                    queue,
                     Resolution {
                         width: surface_config.width,
                         height: surface_config.height,
@@ -174,20 +174,19 @@ impl winit::application::ApplicationHandler for Application {
                         font_system,
                         atlas,
                         viewport,
-                        [TextArea {
-                            buffer: text_buffer,
-                            left: 10.0,
-                            top: 10.0,
-                            scale: 1.0,
-                            bounds: TextBounds {
+                        [TextArea::new(
+                            text_buffer,
+                            10.0,
+                            10.0,
+                            1.0,
+                            TextBounds {
                                 left: 0,
                                 top: 0,
                                 right: 600,
                                 bottom: 160,
                             },
-                            default_color: Color::rgb(255, 255, 255),
-                            custom_glyphs: &[],
-                        }],
+                            Color::rgb(255, 255, 255),
+                        )],
                         swash_cache,
                     )
                     .unwrap();
@@ -212,7 +211,7 @@ impl winit::application::ApplicationHandler for Application {
                         occlusion_query_set: None,
                     });
 
-                    text_renderer.render(&atlas, &viewport, &mut pass).unwrap();
+                    text_renderer.render(atlas, viewport, &mut pass).unwrap();
                 }
 
                 queue.submit(Some(encoder.finish()));
@@ -0,0 +1,557 @@
+//! Renders tens of thousands of glyphs at many sizes to stress the glyph atlas.
+//!
+//! This is useful both as a rough performance yardstick and as an environment for reproducing
+//! atlas issues. Live statistics are shown in the top left corner.
+//!
+//! Run with `cargo run --release --example stress-test --features stress-test`.
+//!
+//! Controls:
+//! - `Up` / `Down`: increase or decrease the number of text areas
+//! - `T`: toggle calling `TextAtlas::trim` after every frame
+//! - `S`: toggle jittering the scale of every text area each frame (forces new rasterizations)
+//! - `G`: cycle the growth policy of the atlas (double, linear, never)
+//! - `F`: cycle how scaled glyphs are filtered (exact rasterization, linear, nearest)
+//! - `R`: recreate the atlas from scratch
+
+use glyphon::{
+    Attrs, Buffer, Cache, Color, Family, FontSystem, GrowthPolicy, Metrics, Resolution, Shaping,
+    SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use wgpu::{
+    CommandEncoderDescriptor, CompositeAlphaMode, DeviceDescriptor, Instance, InstanceDescriptor,
+    LoadOp, MultisampleState, Operations, PresentMode, RenderPassColorAttachment,
+    RenderPassDescriptor, RequestAdapterOptions, SurfaceConfiguration, TextureFormat,
+    TextureUsages, TextureViewDescriptor,
+};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const LATIN: &str = include_str!("../samples/latin.txt");
+const ARABIC: &str = include_str!("../samples/arabic.txt");
+const FONT_SIZES: [f32; 8] = [8.0, 10.0, 12.0, 14.0, 18.0, 24.0, 32.0, 48.0];
+const INITIAL_AREA_COUNT: usize = 400;
+const AREA_WIDTH: f32 = 320.0;
+const AREA_HEIGHT: f32 = 60.0;
+/// The growth step of [`GrowthPolicy::Linear`] in pixels.
+const LINEAR_GROWTH_STEP: u32 = 256;
+/// The font size glyphs are rasterized at with [`Filtering::Linear`].
+const MAX_RASTER_FONT_SIZE: u16 = 16;
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// How glyphs of scaled text areas are drawn.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Filtering {
+    /// Rasterize glyphs at every scaled size.
+    Exact,
+    /// Rasterize large glyphs at [`MAX_RASTER_FONT_SIZE`] and scale them up with linear filtering.
+    Linear,
+    /// Rasterize glyphs at their original size and repeat their pixels (integer scaling).
+    Nearest,
+}
+
+impl Filtering {
+    fn next(self) -> Self {
+        match self {
+            Self::Exact => Self::Linear,
+            Self::Linear => Self::Nearest,
+            Self::Nearest => Self::Exact,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Linear => "linear",
+            Self::Nearest => "nearest",
+        }
+    }
+}
+
+struct Settings {
+    area_count: usize,
+    trim: bool,
+    jitter_scale: bool,
+    growth_policy: GrowthPolicy,
+    filtering: Filtering,
+}
+
+impl Settings {
+    fn next_growth_policy(&mut self) {
+        self.growth_policy = match self.growth_policy {
+            GrowthPolicy::Double => GrowthPolicy::Linear(LINEAR_GROWTH_STEP),
+            GrowthPolicy::Linear(_) => GrowthPolicy::Never,
+            GrowthPolicy::Never => GrowthPolicy::Double,
+        };
+    }
+
+    fn growth_policy_name(&self) -> &'static str {
+        match self.growth_policy {
+            GrowthPolicy::Double => "double",
+            GrowthPolicy::Linear(_) => "linear",
+            GrowthPolicy::Never => "never",
+        }
+    }
+
+    /// Applies the settings that are stored in the atlas and the renderer.
+    fn apply(&self, atlas: &mut TextAtlas, text_renderer: &mut TextRenderer) {
+        atlas.set_growth_policy(self.growth_policy);
+        text_renderer.set_max_raster_font_size(
+            (self.filtering == Filtering::Linear).then_some(MAX_RASTER_FONT_SIZE),
+        );
+    }
+}
+
+struct Timings {
+    frames: u32,
+    prepare: Duration,
+    last_report: Instant,
+    average_prepare: Duration,
+    fps: f32,
+}
+
+struct WindowState {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    surface: wgpu::Surface<'static>,
+    surface_config: SurfaceConfiguration,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    cache: Cache,
+    viewport: glyphon::Viewport,
+    atlas: glyphon::TextAtlas,
+    text_renderer: glyphon::TextRenderer,
+    stats_renderer: glyphon::TextRenderer,
+    buffers: Vec<glyphon::Buffer>,
+    stats_buffer: glyphon::Buffer,
+
+    settings: Settings,
+    timings: Timings,
+    frame_index: u64,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the wgpu surface is dropped, otherwise the
+    // program may crash when closed. This is probably a bug in wgpu.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    async fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+
+        // Set up surface
+        let instance = Instance::new(&InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions::default())
+            .await
+            .unwrap();
+        let (device, queue) = adapter
+            .request_device(&DeviceDescriptor::default(), None)
+            .await
+            .unwrap();
+
+        let surface = instance
+            .create_surface(window.clone())
+            .expect("Create surface");
+        let swapchain_format = TextureFormat::Bgra8UnormSrgb;
+        let surface_config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format: swapchain_format,
+            width: physical_size.width,
+            height: physical_size.height,
+            // Don't wait for vsync so that frame times reflect the actual work being done
+            present_mode: PresentMode::AutoNoVsync,
+            alpha_mode: CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        surface.configure(&device, &surface_config);
+
+        // Set up text renderers
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device, &cache);
+        let mut atlas = TextAtlas::new(&device, &queue, &cache, swapchain_format);
+        let mut text_renderer =
+            TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
+        let stats_renderer =
+            TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
+
+        let settings = Settings {
+            area_count: INITIAL_AREA_COUNT,
+            trim: true,
+            jitter_scale: false,
+            growth_policy: GrowthPolicy::default(),
+            filtering: Filtering::Exact,
+        };
+        settings.apply(&mut atlas, &mut text_renderer);
+
+        let buffers = create_buffers(&mut font_system, settings.area_count);
+        let mut stats_buffer = Buffer::new(&mut font_system, Metrics::new(16.0, 20.0));
+        stats_buffer.set_size(&mut font_system, Some(600.0), None);
+
+        Self {
+            device,
+            queue,
+            surface,
+            surface_config,
+            font_system,
+            swash_cache,
+            cache,
+            viewport,
+            atlas,
+            text_renderer,
+            stats_renderer,
+            buffers,
+            stats_buffer,
+            settings,
+            timings: Timings {
+                frames: 0,
+                prepare: Duration::ZERO,
+                last_report: Instant::now(),
+                average_prepare: Duration::ZERO,
+                fps: 0.0,
+            },
+            frame_index: 0,
+            window,
+        }
+    }
+
+    fn recreate_atlas(&mut self) {
+        self.atlas = TextAtlas::new(
+            &self.device,
+            &self.queue,
+            &self.cache,
+            self.surface_config.format,
+        );
+        self.text_renderer = TextRenderer::new(
+            &mut self.atlas,
+            &self.device,
+            MultisampleState::default(),
+            None,
+        );
+        self.stats_renderer = TextRenderer::new(
+            &mut self.atlas,
+            &self.device,
+            MultisampleState::default(),
+            None,
+        );
+        self.settings
+            .apply(&mut self.atlas, &mut self.text_renderer);
+    }
+
+    fn update_stats_text(&mut self) {
+        let stats = self.atlas.stats();
+        let mut text = format!(
+            "areas: {} | glyphs: {} | fps: {:.0} | prepare: {:.2} ms\n",
+            self.settings.area_count,
+            self.text_renderer.glyph_count(),
+            self.timings.fps,
+            self.timings.average_prepare.as_secs_f64() * 1000.0,
+        );
+
+        for (name, texture) in [("mask", stats.mask), ("color", stats.color)] {
+            text.push_str(&format!(
+                "{name} atlas: {size}x{size}, {cached} cached, {in_use} in use, {occupied:.0}% occupied, grew {growths} times\n",
+                size = texture.size,
+                cached = texture.cached_glyphs,
+                in_use = texture.glyphs_in_use,
+                occupied = texture.allocated_area as f32 / (texture.size * texture.size) as f32
+                    * 100.0,
+                growths = texture.growth_count,
+            ));
+        }
+
+        text.push_str(&format!(
+            "trim [T]: {} | scale jitter [S]: {} | growth [G]: {} | filtering [F]: {} | recreate atlas [R] | areas [Up/Down]",
+            on_off(self.settings.trim),
+            on_off(self.settings.jitter_scale),
+            self.settings.growth_policy_name(),
+            self.settings.filtering.name(),
+        ));
+
+        self.stats_buffer.set_text(
+            &mut self.font_system,
+            &text,
+            Attrs::new().family(Family::Monospace),
+            Shaping::Basic,
+        );
+        self.stats_buffer
+            .shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn grid_layout(&self) -> GridLayout {
+        GridLayout {
+            columns: ((self.surface_config.width as f32 / AREA_WIDTH) as usize).max(1),
+            scale: if self.settings.jitter_scale {
+                1.0 + (self.frame_index % 16) as f32 * 0.05
+            } else {
+                1.0
+            },
+            integer_scaling: self.settings.filtering == Filtering::Nearest,
+        }
+    }
+}
+
+/// How the text areas are laid out in a grid for a frame.
+struct GridLayout {
+    columns: usize,
+    scale: f32,
+    integer_scaling: bool,
+}
+
+/// Lays out a text area for each of the `buffers` in a grid filling the width of the window.
+fn text_areas<'a>(
+    buffers: &'a [Buffer],
+    layout: &'a GridLayout,
+) -> impl Iterator<Item = TextArea<'a>> + 'a {
+    buffers.iter().enumerate().map(move |(i, buffer)| {
+        let left = (i % layout.columns) as f32 * AREA_WIDTH;
+        let top = 100.0 + (i / layout.columns) as f32 * AREA_HEIGHT;
+
+        TextArea::new(
+            buffer,
+            left,
+            top,
+            layout.scale,
+            TextBounds {
+                left: left as i32,
+                top: top as i32,
+                right: (left + AREA_WIDTH) as i32,
+                bottom: (top + AREA_HEIGHT) as i32,
+            },
+            Color::rgb(
+                128 + (i * 37 % 128) as u8,
+                128 + (i * 59 % 128) as u8,
+                128 + (i * 83 % 128) as u8,
+            ),
+        )
+        .with_integer_scaling(layout.integer_scaling)
+    })
+}
+
+fn create_buffers(font_system: &mut FontSystem, count: usize) -> Vec<Buffer> {
+    let latin_lines: Vec<&str> = LATIN.lines().filter(|line| !line.is_empty()).collect();
+    let arabic_lines: Vec<&str> = ARABIC.lines().filter(|line| !line.is_empty()).collect();
+
+    (0..count)
+        .map(|i| {
+            let font_size = FONT_SIZES[i % FONT_SIZES.len()];
+            let text = if i % 5 == 4 {
+                arabic_lines[i % arabic_lines.len()]
+            } else {
+                latin_lines[i % latin_lines.len()]
+            };
+
+            let mut buffer = Buffer::new(font_system, Metrics::new(font_size, font_size * 1.2));
+            buffer.set_size(font_system, Some(AREA_WIDTH), Some(AREA_HEIGHT));
+            buffer.set_text(
+                font_system,
+                text,
+                Attrs::new().family(Family::SansSerif),
+                Shaping::Advanced,
+            );
+            buffer.shape_until_scroll(font_system, false);
+            buffer
+        })
+        .collect()
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (1280, 800);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("glyphon stress test");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(pollster::block_on(WindowState::new(window)));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(size) => {
+                state.surface_config.width = size.width;
+                state.surface_config.height = size.height;
+                state
+                    .surface
+                    .configure(&state.device, &state.surface_config);
+                state.window.request_redraw();
+            }
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key.as_ref() {
+                    Key::Named(NamedKey::ArrowUp) => {
+                        state.settings.area_count *= 2;
+                        state.buffers =
+                            create_buffers(&mut state.font_system, state.settings.area_count);
+                    }
+                    Key::Named(NamedKey::ArrowDown) => {
+                        state.settings.area_count = (state.settings.area_count / 2).max(1);
+                        state.buffers =
+                            create_buffers(&mut state.font_system, state.settings.area_count);
+                    }
+                    Key::Character("t") => state.settings.trim = !state.settings.trim,
+                    Key::Character("s") => {
+                        state.settings.jitter_scale = !state.settings.jitter_scale
+                    }
+                    Key::Character("g") => {
+                        state.settings.next_growth_policy();
+                        state
+                            .settings
+                            .apply(&mut state.atlas, &mut state.text_renderer);
+                    }
+                    Key::Character("f") => {
+                        state.settings.filtering = state.settings.filtering.next();
+                        state
+                            .settings
+                            .apply(&mut state.atlas, &mut state.text_renderer);
+                    }
+                    Key::Character("r") => state.recreate_atlas(),
+                    _ => {}
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                state.frame_index += 1;
+
+                let resolution = Resolution {
+                    width: state.surface_config.width,
+                    height: state.surface_config.height,
+                };
+                state.viewport.update(&state.queue, resolution);
+
+                let layout = state.grid_layout();
+                let prepare_start = Instant::now();
+                let result = state.text_renderer.prepare(
+                    &state.device,
+                    &state.queue,
+                    &mut state.font_system,
+                    &mut state.atlas,
+                    &state.viewport,
+                    text_areas(&state.buffers, &layout),
+                    &mut state.swash_cache,
+                );
+                state.timings.prepare += prepare_start.elapsed();
+                state.timings.frames += 1;
+
+                if let Err(error) = result {
+                    eprintln!("{error}");
+                }
+
+                let elapsed = state.timings.last_report.elapsed();
+                if elapsed >= Duration::from_millis(500) {
+                    state.timings.fps = state.timings.frames as f32 / elapsed.as_secs_f32();
+                    state.timings.average_prepare =
+                        state.timings.prepare / state.timings.frames.max(1);
+                    state.timings.frames = 0;
+                    state.timings.prepare = Duration::ZERO;
+                    state.timings.last_report = Instant::now();
+                }
+
+                state.update_stats_text();
+                state
+                    .stats_renderer
+                    .prepare(
+                        &state.device,
+                        &state.queue,
+                        &mut state.font_system,
+                        &mut state.atlas,
+                        &state.viewport,
+                        [TextArea::new(
+                            &state.stats_buffer,
+                            10.0,
+                            10.0,
+                            1.0,
+                            TextBounds::default(),
+                            Color::rgb(255, 255, 0),
+                        )],
+                        &mut state.swash_cache,
+                    )
+                    .unwrap();
+
+                let frame = state.surface.get_current_texture().unwrap();
+                let view = frame.texture.create_view(&TextureViewDescriptor::default());
+                let mut encoder = state
+                    .device
+                    .create_command_encoder(&CommandEncoderDescriptor { label: None });
+                {
+                    let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                        label: None,
+                        color_attachments: &[Some(RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: Operations {
+                                load: LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+
+                    state
+                        .text_renderer
+                        .render(&state.atlas, &state.viewport, &mut pass)
+                        .unwrap();
+                    state
+                        .stats_renderer
+                        .render(&state.atlas, &state.viewport, &mut pass)
+                        .unwrap();
+                }
+
+                state.queue.submit(Some(encoder.finish()));
+                frame.present();
+
+                if state.settings.trim {
+                    state.atlas.trim();
+                }
+
+                state.window.request_redraw();
+            }
+            WindowEvent::CloseRequested => event_loop.exit(),
+            _ => {}
+        }
+    }
+}
@@ -72,10 +72,10 @@ impl WindowState {
             .await
             .unwrap();
 
-        let (color_mode, swapchain_format) = if USE_WEB_COLORS {
-            (ColorMode::Web, TextureFormat::Bgra8Unorm)
+        let swapchain_format = if USE_WEB_COLORS {
+            TextureFormat::Bgra8Unorm
         } else {
-            (ColorMode::Accurate, TextureFormat::Bgra8UnormSrgb)
+            TextureFormat::Bgra8UnormSrgb
         };
 
         let surface = instance
@@ -101,7 +101,7 @@ impl WindowState {
         let cache = Cache::new(&device);
         let viewport = Viewport::new(&device, &cache);
         let mut atlas =
-            TextAtlas::with_color_mode(&device, &queue, &cache, swapchain_format, color_mode);
+            TextAtlas::with_color_mode(&device, &queue, &cache, swapchain_format, ColorMode::Auto);
         let text_renderer =
             TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
 
@@ -136,7 +136,7 @@ impl WindowState {
             surface,
             surface_config,
             physical_size: physical_size.cast(),
-            scale_factor: scale_factor as f32,
+            scale_factor,
             font_system,
             swash_cache,
             viewport,
@@ -199,7 +199,7 @@ impl winit::application::ApplicationHandler for Application {
             WindowEvent::Resized(size) => {
                 surface_config.width = size.width;
                 surface_config.height = size.height;
-                surface.configure(&device, &surface_config);
+                surface.configure(device, surface_config);
                 window.request_redraw();
 
                 *scale_factor = window.scale_factor() as f32;
@@ -214,7 +214,7 @@ impl winit::application::ApplicationHandler for Application {
             }
             WindowEvent::RedrawRequested => {
                 viewport.update(
-                    &queue,
+                    queue,
                     Resolution {
                         width: surface_config.width,
                         height: surface_config.height,
@@ -232,20 +232,19 @@ impl winit::application::ApplicationHandler for Application {
                 let text_areas: Vec<TextArea> = buffers
                     .iter()
                     .map(|b| {
-                        let a = TextArea {
-                            buffer: b,
+                        let a = TextArea::new(
+                            b,
                             left,
                             top,
-                            scale: scale_factor,
-                            bounds: TextBounds {
+                            scale_factor,
+                            TextBounds {
                                 left: bounds_left,
                                 top: top.floor() as i32,
                                 right: bounds_right,
                                 bottom: top.floor() as i32 + physical_size.height,
                             },
-                            default_color: FONT_COLOR,
-                            custom_glyphs: &[],
-                        };
+                            FONT_COLOR,
+                        );
 
                         let total_lines = b
                             .layout_runs()
@@ -289,7 +288,7 @@ impl winit::application::ApplicationHandler for Application {
                         occlusion_query_set: None,
                     });
 
-                    text_renderer.render(&atlas, &viewport, &mut pass).unwrap();
+                    text_renderer.render(atlas, viewport, &mut pass).unwrap();
                 }
 
                 queue.submit(Some(encoder.finish()));
@@ -0,0 +1,81 @@
+//! Utilities for writing benchmarks against glyphon.
+//!
+//! These are the same helpers that glyphon uses for its own benchmarks, exposed so that
+//! downstream crates can benchmark glyphon on their own documents.
+
+use crate::{Attrs, Buffer, FontSystem, Metrics, Shaping};
+use pollster::block_on;
+use wgpu::{BackendOptions, Dx12BackendOptions};
+
+/// The first chapter of Moby Dick, in English.
+pub const LATIN_SAMPLE: &str = include_str!("../samples/latin.txt");
+
+/// Al-Kindi's First Philosophy, in Arabic.
+pub const ARABIC_SAMPLE: &str = include_str!("../samples/arabic.txt");
+
+/// A headless device and queue for benchmarking.
+pub struct BenchState {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl BenchState {
+    /// Creates a device and queue on the default adapter.
+    ///
+    /// The adapter can be selected with the `WGPU_ADAPTER_NAME` and `WGPU_BACKEND` environment
+    /// variables.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            flags: wgpu::InstanceFlags::empty(),
+            backend_options: BackendOptions {
+                gl: wgpu::GlBackendOptions {
+                    gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+                },
+                dx12: Dx12BackendOptions {
+                    shader_compiler: wgpu::Dx12Compiler::Fxc,
+                },
+            },
+        });
+
+        let adapter = block_on(wgpu::util::initialize_adapter_from_env_or_default(
+            &instance, None,
+        ))
+        .unwrap();
+
+        let (device, queue) = block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("Benchmark Device"),
+                required_features: adapter.features(),
+                required_limits: adapter.limits(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .unwrap();
+
+        Self { device, queue }
+    }
+}
+
+/// Creates one shaped [`Buffer`] for each of `texts`.
+pub fn shaped_buffers<'a>(
+    font_system: &mut FontSystem,
+    texts: impl IntoIterator<Item = &'a str>,
+    metrics: Metrics,
+    width: Option<f32>,
+    attrs: Attrs,
+    shaping: Shaping,
+) -> Vec<Buffer> {
+    texts
+        .into_iter()
+        .map(|text| {
+            let mut buffer = Buffer::new(font_system, metrics);
+            buffer.set_size(font_system, width, None);
+            buffer.set_text(font_system, text, attrs, shaping);
+            buffer.shape_until_scroll(font_system, false);
+            buffer
+        })
+        .collect()
+}
@@ -1,19 +1,27 @@
-use crate::{GlyphToRender, Params};
+use crate::{BlendMode, GlyphToRender, Params};
 use std::{
     borrow::Cow,
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
     mem,
     num::NonZeroU64,
     ops::Deref,
-    sync::{Arc, Mutex},
+    pin::pin,
+    slice,
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
 };
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry,
-    BindingResource, BindingType, BlendState, Buffer, BufferBindingType, ColorTargetState,
-    ColorWrites, DepthStencilState, Device, FilterMode, FragmentState, MultisampleState,
-    PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor, PrimitiveState,
-    PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
-    SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages,
-    TextureFormat, TextureSampleType, TextureView, TextureViewDimension, VertexFormat, VertexState,
+    BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor,
+    BufferUsages, ColorTargetState, ColorWrites, DepthStencilState, Device, ErrorFilter, Features,
+    FilterMode, FragmentState, MultisampleState, PipelineCompilationOptions, PipelineLayout,
+    PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, PushConstantRange, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureFormat, TextureSampleType,
+    TextureView, TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexState, VertexStepMode,
 };
 
 /// A cache to share common resources (e.g., pipelines, layouts, shaders) between multiple text
@@ -23,27 +31,297 @@ pub struct Cache(Arc<Inner>);
 
 #[derive(Debug)]
 struct Inner {
+    label_prefix: String,
+    /// The number of generations labeled with [`Cache::generation_label`] so far, by resource
+    generations: Mutex<HashMap<&'static str, u64>>,
     sampler: Sampler,
-    shader: ShaderModule,
-    vertex_buffers: [wgpu::VertexBufferLayout<'static>; 1],
+    quad_indices: Buffer,
     atlas_layout: BindGroupLayout,
     uniforms_layout: BindGroupLayout,
-    pipeline_layout: PipelineLayout,
-    cache: Mutex<
-        Vec<(
-            TextureFormat,
-            MultisampleState,
-            Option<DepthStencilState>,
-            RenderPipeline,
-        )>,
-    >,
+    /// The shaders and layouts of glyph pipelines, by the features they were created for
+    programs: Mutex<Vec<(PipelineFeatures, ShaderModule, PipelineLayout)>>,
+    cache: Mutex<PipelineCache>,
+    fallback_shader: ShaderModule,
+    fallback_layout: BindGroupLayout,
+    fallback_pipeline_layout: PipelineLayout,
+    fallback_cache: Mutex<PipelineCache<RenderPipeline>>,
+    vertex_pulling_layout: OnceLock<BindGroupLayout>,
+    masking: OnceLock<Masking>,
 }
 
+/// Resources used to render glyphs through a mask texture
+#[derive(Debug)]
+struct Masking {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+/// The optional features of the pipeline of a renderer, which can be combined freely.
+///
+/// Each combination of features is a separate pipeline, with a shader assembled from the parts
+/// needed by the features.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PipelineFeatures {
+    /// Reads glyphs from the vertex buffer bound as a storage buffer, see
+    /// `TextRenderer::set_vertex_pulling`
+    pub vertex_pulling: bool,
+    /// Reads an optional color for each glyph from another vertex buffer, see
+    /// `TextRenderer::set_color_overrides`
+    pub color_overrides: bool,
+    /// Multiplies the alpha of glyphs by a mask texture, see `TextRenderer::set_mask`
+    pub mask: bool,
+    /// Reads the params of the viewport from push constants instead of its uniform buffer, see
+    /// `TextRenderer::set_push_constants`
+    pub push_constants: bool,
+    /// Reads the pick ID of each glyph from another vertex buffer and writes it to a second
+    /// color attachment, see `TextRenderer::set_pick_ids`
+    pub pick_ids: bool,
+    /// Draws quads as indexed triangle lists instead of triangle strips, see
+    /// `TextRenderer::set_indexed_quads`
+    pub indexed_quads: bool,
+}
+
+impl PipelineFeatures {
+    /// Returns the bind group of the vertex buffer read as a storage buffer, if any.
+    pub(crate) fn vertex_pulling_group(self) -> Option<u32> {
+        self.vertex_pulling.then_some(2)
+    }
+
+    /// Returns the bind group of the mask texture, if any, which follows the bind group of the
+    /// vertex buffer.
+    pub(crate) fn mask_group(self) -> Option<u32> {
+        self.mask.then_some(2 + self.vertex_pulling as u32)
+    }
+
+    /// Returns the vertex buffer slots of the glyphs, their color overrides and their pick IDs,
+    /// for the buffers that are used.
+    pub(crate) fn vertex_buffer_slots(self) -> [Option<u32>; 3] {
+        let mut next_slot = 0;
+        [!self.vertex_pulling, self.color_overrides, self.pick_ids].map(|used| {
+            used.then(|| {
+                next_slot += 1;
+                next_slot - 1
+            })
+        })
+    }
+
+    /// Returns the features that change the shader or the layout of the pipeline.
+    fn program(self) -> Self {
+        Self {
+            indexed_quads: false,
+            ..self
+        }
+    }
+
+    fn name(self) -> String {
+        let names = [
+            (self.vertex_pulling, "vertex pulling"),
+            (self.color_overrides, "color overrides"),
+            (self.mask, "mask"),
+            (self.push_constants, "push constants"),
+            (self.pick_ids, "pick ids"),
+            (self.indexed_quads, "indexed quads"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect::<Vec<_>>();
+
+        match names.is_empty() {
+            true => "pipeline".to_owned(),
+            false => format!("pipeline ({})", names.join(", ")),
+        }
+    }
+
+    /// Returns the source of the shader of pipelines with these features.
+    ///
+    /// The entry points `vs_main` and `fs_main` of `shader.wgsl` are used as they are when no
+    /// feature changes them. Otherwise, the entry points `vs_main_features` and
+    /// `fs_main_features` are generated, which read the inputs of the features and pass them on
+    /// to the functions shared by all pipelines.
+    fn shader_source(self) -> String {
+        let mut source = include_str!("shader.wgsl").to_owned();
+        if self.push_constants {
+            source = source.replace(
+                "@group(1) @binding(0)\nvar<uniform> params: Params;",
+                "var<push_constant> params: Params;",
+            );
+        }
+        if self.vertex_pulling {
+            source.push_str(include_str!("vertex_pulling.wgsl"));
+        }
+        if let Some(group) = self.mask_group() {
+            source.push_str(&include_str!("mask.wgsl").replace("MASK_GROUP", &group.to_string()));
+        }
+
+        if self.vertex_entry_point() != "vs_main" {
+            let mut inputs = Vec::new();
+            if self.vertex_pulling {
+                inputs.push("@builtin(vertex_index) vertex_idx: u32");
+                inputs.push("@builtin(instance_index) instance_idx: u32");
+            } else {
+                inputs.push("in_vert: VertexInput");
+            }
+            if self.color_overrides {
+                inputs.push("@location(8) color_override: vec2<u32>");
+            }
+            if self.pick_ids {
+                inputs.push("@location(9) pick_id: u32");
+            }
+
+            let glyph = match self.vertex_pulling {
+                true => "pulled_glyph(vertex_idx, instance_idx)",
+                false => "in_vert",
+            };
+            let glyph = match self.color_overrides {
+                true => format!("apply_color_override({glyph}, color_override)"),
+                false => glyph.to_owned(),
+            };
+            let pick_id = match self.pick_ids {
+                true => "\n    vert_output.pick_id = pick_id;",
+                false => "",
+            };
+
+            source.push_str(&format!(
+                "\n@vertex\nfn vs_main_features({}) -> VertexOutput {{\n    \
+                 var vert_output = glyph_vertex({glyph});{pick_id}\n    \
+                 return vert_output;\n}}\n",
+                inputs.join(", "),
+            ));
+        }
+
+        if self.fragment_entry_point() != "fs_main" {
+            let (pick_output, pick_id) = match self.pick_ids {
+                // Written over the whole quad of the glyph, to the second color attachment
+                true => ("\n    @location(1) pick_id: u32,", ", in_frag.pick_id"),
+                false => ("", ""),
+            };
+            let mask = match self.mask {
+                true => "\n    color.a *= mask_alpha(in_frag.position.xy);",
+                false => "",
+            };
+
+            source.push_str(&format!(
+                "\nstruct FragmentOutput {{\n    @location(0) color: vec4<f32>,{pick_output}\n}}\n\
+                 \n@fragment\nfn fs_main_features(in_frag: VertexOutput) -> FragmentOutput {{\n    \
+                 var color = fragment_color(in_frag);{mask}\n    \
+                 return FragmentOutput(color{pick_id});\n}}\n",
+            ));
+        }
+
+        source
+    }
+
+    fn vertex_entry_point(self) -> &'static str {
+        match self.vertex_pulling || self.color_overrides || self.pick_ids {
+            true => "vs_main_features",
+            false => "vs_main",
+        }
+    }
+
+    fn fragment_entry_point(self) -> &'static str {
+        match self.mask || self.pick_ids {
+            true => "fs_main_features",
+            false => "fs_main",
+        }
+    }
+}
+
+/// The stages that read the params of the viewport from push constants
+pub(crate) const PUSH_CONSTANT_STAGES: ShaderStages =
+    ShaderStages::VERTEX.union(ShaderStages::FRAGMENT);
+
+/// The format of the second color attachment that pipelines writing pick IDs render to, see
+/// `TextRenderer::set_pick_ids`
+pub(crate) const PICK_ID_FORMAT: TextureFormat = TextureFormat::R32Uint;
+
+/// The corners of the two triangles of a glyph quad, indexed by the vertex shader as
+/// `x | y << 1`, see `TextRenderer::set_indexed_quads`
+const QUAD_INDICES: [u16; 6] = [0, 1, 2, 2, 1, 3];
+
+/// The attributes of the glyphs in the vertex buffer of a renderer
+const GLYPH_ATTRIBUTES: [VertexAttribute; 8] = [
+    VertexAttribute {
+        format: VertexFormat::Sint32x2,
+        offset: 0,
+        shader_location: 0,
+    },
+    VertexAttribute {
+        format: VertexFormat::Uint32,
+        offset: mem::size_of::<u32>() as u64 * 2,
+        shader_location: 1,
+    },
+    VertexAttribute {
+        format: VertexFormat::Uint32,
+        offset: mem::size_of::<u32>() as u64 * 3,
+        shader_location: 2,
+    },
+    VertexAttribute {
+        format: VertexFormat::Uint32,
+        offset: mem::size_of::<u32>() as u64 * 4,
+        shader_location: 6,
+    },
+    VertexAttribute {
+        format: VertexFormat::Uint32,
+        offset: mem::size_of::<u32>() as u64 * 5,
+        shader_location: 3,
+    },
+    VertexAttribute {
+        format: VertexFormat::Uint32,
+        offset: mem::size_of::<u32>() as u64 * 6,
+        shader_location: 4,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32,
+        offset: mem::size_of::<u32>() as u64 * 7,
+        shader_location: 5,
+    },
+    VertexAttribute {
+        format: VertexFormat::Uint32,
+        offset: mem::size_of::<u32>() as u64 * 8,
+        shader_location: 7,
+    },
+];
+
+/// An optional color for each glyph, see `TextRenderer::set_color_overrides`
+const COLOR_OVERRIDE_ATTRIBUTES: [VertexAttribute; 1] = [VertexAttribute {
+    format: VertexFormat::Uint32x2,
+    offset: 0,
+    shader_location: 8,
+}];
+
+/// The pick ID of each glyph, see `TextRenderer::set_pick_ids`
+const PICK_ID_ATTRIBUTES: [VertexAttribute; 1] = [VertexAttribute {
+    format: VertexFormat::Uint32,
+    offset: 0,
+    shader_location: 9,
+}];
+
+/// Pipelines that failed to be created are cached as `None`, so they aren't created again.
+type PipelineCache<P = Option<RenderPipeline>> = Vec<(
+    TextureFormat,
+    MultisampleState,
+    Option<DepthStencilState>,
+    BlendMode,
+    PipelineFeatures,
+    P,
+)>;
+
 impl Cache {
     /// Creates a new `Cache` with the given `device`.
     pub fn new(device: &Device) -> Self {
+        Self::with_label_prefix(device, "glyphon")
+    }
+
+    /// Creates a new `Cache` with the given `device`, starting the debug labels of all GPU
+    /// resources created by glyphon with `label_prefix` instead of `glyphon`.
+    ///
+    /// This includes the resources of all atlases, renderers and viewports created with the
+    /// cache, which makes it easier to tell apart multiple sets of them in GPU captures.
+    pub fn with_label_prefix(device: &Device, label_prefix: &str) -> Self {
+        let label = |name: &str| format!("{label_prefix} {name}");
+
         let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("glyphon sampler"),
+            label: Some(&label("sampler")),
             min_filter: FilterMode::Nearest,
             mag_filter: FilterMode::Nearest,
             mipmap_filter: FilterMode::Nearest,
@@ -52,47 +330,21 @@ impl Cache {
             ..Default::default()
         });
 
-        let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("glyphon shader"),
-            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+        let quad_indices = device.create_buffer(&BufferDescriptor {
+            label: Some(&label("quad indices")),
+            size: mem::size_of_val(&QUAD_INDICES) as u64,
+            usage: BufferUsages::INDEX,
+            mapped_at_creation: true,
         });
-
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<GlyphToRender>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: VertexFormat::Sint32x2,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: VertexFormat::Uint32,
-                    offset: mem::size_of::<u32>() as u64 * 2,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    format: VertexFormat::Uint32,
-                    offset: mem::size_of::<u32>() as u64 * 3,
-                    shader_location: 2,
-                },
-                wgpu::VertexAttribute {
-                    format: VertexFormat::Uint32,
-                    offset: mem::size_of::<u32>() as u64 * 4,
-                    shader_location: 3,
-                },
-                wgpu::VertexAttribute {
-                    format: VertexFormat::Uint32,
-                    offset: mem::size_of::<u32>() as u64 * 5,
-                    shader_location: 4,
-                },
-                wgpu::VertexAttribute {
-                    format: VertexFormat::Float32,
-                    offset: mem::size_of::<u32>() as u64 * 6,
-                    shader_location: 5,
-                },
-            ],
-        };
+        for (bytes, index) in quad_indices
+            .slice(..)
+            .get_mapped_range_mut()
+            .chunks_exact_mut(mem::size_of::<u16>())
+            .zip(QUAD_INDICES)
+        {
+            bytes.copy_from_slice(&index.to_ne_bytes());
+        }
+        quad_indices.unmap();
 
         let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
@@ -122,14 +374,24 @@ impl Cache {
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
             ],
-            label: Some("glyphon atlas bind group layout"),
+            label: Some(&label("atlas bind group layout")),
         });
 
         let uniforms_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[BindGroupLayoutEntry {
                 binding: 0,
-                visibility: ShaderStages::VERTEX,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -137,23 +399,67 @@ impl Cache {
                 },
                 count: None,
             }],
-            label: Some("glyphon uniforms bind group layout"),
+            label: Some(&label("uniforms bind group layout")),
+        });
+
+        let fallback_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&label("fallback shader")),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("fallback.wgsl"))),
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        let fallback_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(mem::size_of::<[f32; 4]>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+            label: Some(&label("fallback bind group layout")),
+        });
+
+        let fallback_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&atlas_layout, &uniforms_layout],
+            bind_group_layouts: &[&fallback_layout],
             push_constant_ranges: &[],
         });
 
         Self(Arc::new(Inner {
+            label_prefix: label_prefix.to_owned(),
+            generations: Mutex::new(HashMap::new()),
             sampler,
-            shader,
-            vertex_buffers: [vertex_buffer_layout],
+            quad_indices,
             uniforms_layout,
             atlas_layout,
-            pipeline_layout,
+            programs: Mutex::new(Vec::new()),
             cache: Mutex::new(Vec::new()),
+            fallback_shader,
+            fallback_layout,
+            fallback_pipeline_layout,
+            fallback_cache: Mutex::new(Vec::new()),
+            vertex_pulling_layout: OnceLock::new(),
+            masking: OnceLock::new(),
         }))
     }
 
@@ -162,6 +468,7 @@ impl Cache {
         device: &Device,
         color_atlas: &TextureView,
         mask_atlas: &TextureView,
+        compressed_color_atlas: &TextureView,
     ) -> BindGroup {
         device.create_bind_group(&BindGroupDescriptor {
             layout: &self.0.atlas_layout,
@@ -178,8 +485,12 @@ impl Cache {
                     binding: 2,
                     resource: BindingResource::Sampler(&self.0.sampler),
                 },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(compressed_color_atlas),
+                },
             ],
-            label: Some("glyphon atlas bind group"),
+            label: Some(&self.label("atlas bind group")),
         })
     }
 
@@ -190,47 +501,298 @@ impl Cache {
                 binding: 0,
                 resource: buffer.as_entire_binding(),
             }],
-            label: Some("glyphon uniforms bind group"),
+            label: Some(&self.label("uniforms bind group")),
         })
     }
 
+    pub(crate) fn create_fallback_bind_group(
+        &self,
+        device: &Device,
+        canvas: &TextureView,
+        params: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.0.fallback_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(canvas),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.0.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+            label: Some(&self.label("fallback bind group")),
+        })
+    }
+
+    /// Returns the debug label of the resource `name`.
+    pub(crate) fn label(&self, name: &str) -> String {
+        format!("{} {name}", self.0.label_prefix)
+    }
+
+    /// Returns the debug label of a new generation of the resource `name` with `size`, e.g.
+    /// `glyphon mask atlas #3 (1024)` for the third mask atlas texture created with this cache.
+    ///
+    /// Resources are replaced when they grow, so numbering them tells their generations apart in
+    /// GPU captures.
+    pub(crate) fn generation_label(&self, name: &'static str, size: impl Display) -> String {
+        let mut generations = lock(&self.0.generations);
+        let generation = generations.entry(name).or_default();
+        *generation += 1;
+        format!("{} {name} #{generation} ({size})", self.0.label_prefix)
+    }
+
+    /// Returns the index buffer of the two triangles making up a glyph quad.
+    pub(crate) fn quad_indices(&self) -> &Buffer {
+        &self.0.quad_indices
+    }
+
+    /// Returns whether `device` supports pipelines with `features`, i.e. storage buffers in
+    /// vertex shaders for vertex pulling, push constants large enough for the params of the
+    /// viewport, and enough bind groups for all of them.
+    pub(crate) fn supports(device: &Device, features: PipelineFeatures) -> bool {
+        let limits = device.limits();
+        let bind_groups = 2 + features.vertex_pulling as u32 + features.mask as u32;
+
+        (!features.vertex_pulling || limits.max_storage_buffers_per_shader_stage > 0)
+            && (!features.push_constants
+                || device.features().contains(Features::PUSH_CONSTANTS)
+                    && limits.max_push_constant_size >= mem::size_of::<Params>() as u32)
+            && limits.max_bind_groups >= bind_groups
+    }
+
+    /// Returns the pipeline used to render glyphs from the atlas with `features`, or `None` if
+    /// the device doesn't support the features or failed to create it.
     pub(crate) fn get_or_create_pipeline(
         &self,
         device: &Device,
         format: TextureFormat,
         multisample: MultisampleState,
         depth_stencil: Option<DepthStencilState>,
+        blend_mode: BlendMode,
+        features: PipelineFeatures,
+    ) -> Option<RenderPipeline> {
+        if !Self::supports(device, features) {
+            return None;
+        }
+
+        get_or_insert(
+            &self.0.cache,
+            format,
+            multisample,
+            depth_stencil,
+            blend_mode,
+            features,
+            |depth_stencil| {
+                create_checked(device, || {
+                    let (shader, layout) = self.program(device, features);
+
+                    create_glyph_pipeline(
+                        device,
+                        &self.label(&features.name()),
+                        &layout,
+                        &shader,
+                        features,
+                        format,
+                        multisample,
+                        depth_stencil,
+                        blend_mode,
+                    )
+                })
+            },
+        )
+    }
+
+    /// Returns the shader and the pipeline layout of pipelines with `features`, creating them
+    /// the first time.
+    fn program(
+        &self,
+        device: &Device,
+        features: PipelineFeatures,
+    ) -> (ShaderModule, PipelineLayout) {
+        let features = features.program();
+        let mut programs = lock(&self.0.programs);
+        if let Some((_, shader, layout)) = programs.iter().find(|(f, _, _)| *f == features) {
+            return (shader.clone(), layout.clone());
+        }
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&self.label(&format!("{} shader", features.name()))),
+            source: ShaderSource::Wgsl(Cow::Owned(features.shader_source())),
+        });
+
+        // The uniforms stay bound with push constants, so the groups after them don't move
+        let mut bind_group_layouts = vec![&self.0.atlas_layout, &self.0.uniforms_layout];
+        if features.vertex_pulling {
+            bind_group_layouts.push(self.vertex_pulling_layout(device));
+        }
+        if features.mask {
+            bind_group_layouts.push(&self.masking(device).layout);
+        }
+
+        let push_constant_range = PushConstantRange {
+            stages: PUSH_CONSTANT_STAGES,
+            range: 0..mem::size_of::<Params>() as u32,
+        };
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: match features.push_constants {
+                true => slice::from_ref(&push_constant_range),
+                false => &[],
+            },
+        });
+
+        programs.push((features, shader.clone(), layout.clone()));
+        (shader, layout)
+    }
+
+    /// Creates the bind group of the storage buffer read by pipelines with vertex pulling.
+    ///
+    /// Only the first `size` bytes of `buffer` are bound.
+    pub(crate) fn create_vertex_pulling_bind_group(
+        &self,
+        device: &Device,
+        buffer: &Buffer,
+        size: u64,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: self.vertex_pulling_layout(device),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(size),
+                }),
+            }],
+            label: Some(&self.label("vertex pulling bind group")),
+        })
+    }
+
+    /// Returns the layout of the storage buffer read by pipelines with vertex pulling, creating
+    /// it the first time.
+    fn vertex_pulling_layout(&self, device: &Device) -> &BindGroupLayout {
+        self.0.vertex_pulling_layout.get_or_init(|| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(mem::size_of::<GlyphToRender>() as u64),
+                    },
+                    count: None,
+                }],
+                label: Some(&self.label("vertex pulling bind group layout")),
+            })
+        })
+    }
+
+    /// Creates the bind group of the mask texture sampled by pipelines with a mask.
+    pub(crate) fn create_mask_bind_group(&self, device: &Device, mask: &TextureView) -> BindGroup {
+        let masking = self.masking(device);
+
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: &masking.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(mask),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&masking.sampler),
+                },
+            ],
+            label: Some(&self.label("mask bind group")),
+        })
+    }
+
+    /// Returns the resources used to render glyphs through a mask texture, creating them the
+    /// first time.
+    fn masking(&self, device: &Device) -> &Masking {
+        self.0.masking.get_or_init(|| {
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some(&self.label("mask bind group layout")),
+            });
+
+            // Masks are usually smaller than the viewport, e.g. gradients, so they are filtered
+            let sampler = device.create_sampler(&SamplerDescriptor {
+                label: Some(&self.label("mask sampler")),
+                min_filter: FilterMode::Linear,
+                mag_filter: FilterMode::Linear,
+                ..Default::default()
+            });
+
+            Masking { layout, sampler }
+        })
+    }
+
+    /// Returns the pipeline used to render text that was rasterized on the CPU.
+    pub(crate) fn get_or_create_fallback_pipeline(
+        &self,
+        device: &Device,
+        format: TextureFormat,
+        multisample: MultisampleState,
+        depth_stencil: Option<DepthStencilState>,
+        blend_mode: BlendMode,
     ) -> RenderPipeline {
         let Inner {
-            cache,
-            pipeline_layout,
-            shader,
-            vertex_buffers,
+            fallback_cache,
+            fallback_pipeline_layout,
+            fallback_shader,
             ..
         } = self.0.deref();
 
-        let mut cache = cache.lock().expect("Write pipeline cache");
-
-        cache
-            .iter()
-            .find(|(fmt, ms, ds, _)| fmt == &format && ms == &multisample && ds == &depth_stencil)
-            .map(|(_, _, _, p)| p.clone())
-            .unwrap_or_else(|| {
-                let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-                    label: Some("glyphon pipeline"),
-                    layout: Some(pipeline_layout),
+        get_or_insert(
+            fallback_cache,
+            format,
+            multisample,
+            depth_stencil,
+            blend_mode,
+            PipelineFeatures::default(),
+            |depth_stencil| {
+                device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some(&self.label("fallback pipeline")),
+                    layout: Some(fallback_pipeline_layout),
                     vertex: VertexState {
-                        module: shader,
+                        module: fallback_shader,
                         entry_point: Some("vs_main"),
-                        buffers: vertex_buffers,
+                        buffers: &[],
                         compilation_options: PipelineCompilationOptions::default(),
                     },
                     fragment: Some(FragmentState {
-                        module: shader,
+                        module: fallback_shader,
                         entry_point: Some("fs_main"),
                         targets: &[Some(ColorTargetState {
                             format,
-                            blend: Some(BlendState::ALPHA_BLENDING),
+                            blend: blend_mode.blend_state(),
                             write_mask: ColorWrites::default(),
                         })],
                         compilation_options: PipelineCompilationOptions::default(),
@@ -239,16 +801,173 @@ impl Cache {
                         topology: PrimitiveTopology::TriangleStrip,
                         ..Default::default()
                     },
-                    depth_stencil: depth_stencil.clone(),
+                    depth_stencil,
                     multisample,
                     multiview: None,
                     cache: None,
-                });
+                })
+            },
+        )
+    }
+}
 
-                cache.push((format, multisample, depth_stencil, pipeline.clone()));
+/// Locks `mutex`, ignoring poisoning.
+///
+/// The data guarded by the cache is only ever appended to or incremented, so it stays valid if a
+/// thread panics while holding the lock (e.g. a wgpu validation error while creating a pipeline).
+/// Recovering keeps text rendering working on other threads instead of panicking forever after.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-                pipeline
-            })
-            .clone()
+/// Returns the pipeline in `cache` for the given state, creating it with `create` if there is
+/// none yet.
+fn get_or_insert<P: Clone>(
+    cache: &Mutex<PipelineCache<P>>,
+    format: TextureFormat,
+    multisample: MultisampleState,
+    depth_stencil: Option<DepthStencilState>,
+    blend_mode: BlendMode,
+    features: PipelineFeatures,
+    create: impl FnOnce(Option<DepthStencilState>) -> P,
+) -> P {
+    let mut cache = lock(cache);
+
+    cache
+        .iter()
+        .find(|(fmt, ms, ds, bm, f, _)| {
+            fmt == &format
+                && ms == &multisample
+                && ds == &depth_stencil
+                && bm == &blend_mode
+                && f == &features
+        })
+        .map(|(_, _, _, _, _, p)| p.clone())
+        .unwrap_or_else(|| {
+            let pipeline = create(depth_stencil.clone());
+
+            cache.push((
+                format,
+                multisample,
+                depth_stencil,
+                blend_mode,
+                features,
+                pipeline.clone(),
+            ));
+
+            pipeline
+        })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_glyph_pipeline(
+    device: &Device,
+    label: &str,
+    layout: &PipelineLayout,
+    shader: &ShaderModule,
+    features: PipelineFeatures,
+    format: TextureFormat,
+    multisample: MultisampleState,
+    depth_stencil: Option<DepthStencilState>,
+    blend_mode: BlendMode,
+) -> RenderPipeline {
+    let glyph_buffer = VertexBufferLayout {
+        array_stride: mem::size_of::<GlyphToRender>() as wgpu::BufferAddress,
+        step_mode: VertexStepMode::Instance,
+        attributes: &GLYPH_ATTRIBUTES,
+    };
+    let color_override_buffer = VertexBufferLayout {
+        array_stride: mem::size_of::<[u32; 2]>() as wgpu::BufferAddress,
+        step_mode: VertexStepMode::Instance,
+        attributes: &COLOR_OVERRIDE_ATTRIBUTES,
+    };
+    let pick_id_buffer = VertexBufferLayout {
+        array_stride: mem::size_of::<u32>() as wgpu::BufferAddress,
+        step_mode: VertexStepMode::Instance,
+        attributes: &PICK_ID_ATTRIBUTES,
+    };
+    // In the order of `PipelineFeatures::vertex_buffer_slots`
+    let buffers = [glyph_buffer, color_override_buffer, pick_id_buffer]
+        .into_iter()
+        .zip(features.vertex_buffer_slots())
+        .filter_map(|(buffer, slot)| slot.map(|_| buffer))
+        .collect::<Vec<_>>();
+
+    let color_target = Some(ColorTargetState {
+        format,
+        blend: blend_mode.blend_state(),
+        write_mask: ColorWrites::default(),
+    });
+    // Integer formats can't be blended, so the ID of the topmost glyph replaces the others
+    let pick_target = Some(ColorTargetState {
+        format: PICK_ID_FORMAT,
+        blend: None,
+        write_mask: ColorWrites::ALL,
+    });
+    let targets = [color_target, pick_target];
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: shader,
+            entry_point: Some(features.vertex_entry_point()),
+            buffers: &buffers,
+            compilation_options: PipelineCompilationOptions::default(),
+        },
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: Some(features.fragment_entry_point()),
+            targets: if features.pick_ids {
+                &targets
+            } else {
+                &targets[..1]
+            },
+            compilation_options: PipelineCompilationOptions::default(),
+        }),
+        primitive: PrimitiveState {
+            topology: match features.indexed_quads {
+                true => PrimitiveTopology::TriangleList,
+                false => PrimitiveTopology::TriangleStrip,
+            },
+            ..Default::default()
+        },
+        depth_stencil,
+        multisample,
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Calls `create`, returning `None` if the device reported an error while doing so.
+///
+/// Errors are only caught if the device reports them immediately, which native backends do. On
+/// the web, errors are reported asynchronously and the result is always assumed to be valid.
+fn create_checked<T>(device: &Device, create: impl FnOnce() -> T) -> Option<T> {
+    device.push_error_scope(ErrorFilter::Internal);
+    device.push_error_scope(ErrorFilter::Validation);
+
+    let value = create();
+
+    let validation_error = poll_once(device.pop_error_scope());
+    let internal_error = poll_once(device.pop_error_scope());
+
+    match (validation_error, internal_error) {
+        (Some(Some(_)), _) | (_, Some(Some(_))) => None,
+        _ => Some(value),
+    }
+}
+
+/// Polls `future` once, returning its output if it is ready.
+fn poll_once<F: Future>(future: F) -> Option<F::Output> {
+    // `Waker::noop` needs Rust 1.85
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(|_| NOOP, |_| {}, |_| {}, |_| {});
+    const NOOP: RawWaker = RawWaker::new(std::ptr::null(), &VTABLE);
+
+    // SAFETY: The functions of the vtable don't use the data pointer
+    let waker = unsafe { Waker::from_raw(NOOP) };
+    match pin!(future).poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
     }
 }
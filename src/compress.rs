@@ -0,0 +1,468 @@
+use crate::{
+    text_atlas::{read_texture_rows, AtlasTextureStats},
+    text_render::GlyphonCacheKey,
+    Cache, CompressError, CustomGlyphId, GlyphDetails, TextAtlas,
+};
+use rustc_hash::FxHasher;
+use std::{collections::HashMap, hash::BuildHasherDefault, iter};
+use wgpu::{
+    BufferAsyncError, Device, Extent3d, Features, Origin3d, Queue, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// The width and height of a BC7 block in texels.
+const BLOCK_SIZE: u32 = 4;
+
+/// The number of bytes of a BC7 block.
+const BLOCK_BYTES: usize = 16;
+
+/// The interpolation weights of the 4-bit indices of BC7, out of 64.
+const WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// The glyphs moved out of the color atlas by [`TextAtlas::install_compressed_color_atlas`],
+/// stored in a BC7 compressed texture at the positions they had in the color atlas.
+pub(crate) struct CompressedPage {
+    pub(crate) texture: Texture,
+    pub(crate) texture_view: TextureView,
+    pub(crate) size: u32,
+    pub(crate) glyphs: HashMap<GlyphonCacheKey, GlyphDetails, BuildHasherDefault<FxHasher>>,
+    pub(crate) cache_hits: u64,
+    pub(crate) eviction_count: u64,
+}
+
+impl CompressedPage {
+    pub(crate) fn gpu_memory_usage(&self) -> u64 {
+        // BC7 uses one byte per texel
+        self.size as u64 * self.size as u64
+    }
+
+    /// Copies the texture to the CPU and decodes it, blocking until the copy is done. Returns the
+    /// rows of the texture, with 4 bytes per texel.
+    pub(crate) fn read_texture(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        cache: &Cache,
+    ) -> Result<Vec<u8>, BufferAsyncError> {
+        let blocks_per_row = (self.size / BLOCK_SIZE) as usize;
+        let blocks = read_texture_rows(
+            device,
+            queue,
+            cache,
+            &self.texture,
+            self.size,
+            (blocks_per_row * BLOCK_BYTES) as u32,
+            blocks_per_row as u32,
+        )?;
+
+        Ok(decode_bc7(&blocks, self.size))
+    }
+
+    /// Removes the custom glyphs for which `evict` returns `true`, returning how many glyphs were
+    /// removed. Their space in the texture isn't reused.
+    pub(crate) fn evict_custom_glyphs(&mut self, evict: impl Fn(CustomGlyphId) -> bool) -> usize {
+        let count = self.glyphs.len();
+        self.glyphs
+            .retain(|key, _| !matches!(key, GlyphonCacheKey::Custom(key) if evict(key.glyph_id)));
+        let evicted = count - self.glyphs.len();
+        self.eviction_count += evicted as u64;
+
+        evicted
+    }
+
+    /// Returns the number of pixels covered by the images of the glyphs.
+    fn glyph_area(&self) -> u32 {
+        self.glyphs
+            .values()
+            .map(|details| details.width as u32 * details.height as u32)
+            .sum()
+    }
+
+    pub(crate) fn stats(&self) -> AtlasTextureStats {
+        AtlasTextureStats {
+            size: self.size,
+            cached_glyphs: self.glyphs.len(),
+            allocated_area: self.glyph_area(),
+            glyph_area: self.glyph_area(),
+            cache_hits: self.cache_hits,
+            ..AtlasTextureStats::default()
+        }
+    }
+}
+
+/// The color atlas of a [`TextAtlas`] read back by [`TextAtlas::snapshot_color_atlas`].
+///
+/// Compressing the snapshot with [`ColorAtlasSnapshot::compress`] only uses the CPU, so it can be
+/// done on another thread while the atlas keeps being used for rendering.
+pub struct ColorAtlasSnapshot {
+    size: u32,
+    data: Vec<u8>,
+    revision: (u32, u64, u64),
+}
+
+impl ColorAtlasSnapshot {
+    /// Compresses the snapshot to BC7.
+    ///
+    /// With the `rayon` feature, the rows of blocks are compressed in parallel on the rayon thread
+    /// pool.
+    pub fn compress(&self) -> CompressedColorAtlas {
+        trace_span!("compress_color_atlas", size = self.size);
+
+        CompressedColorAtlas {
+            size: self.size.next_multiple_of(BLOCK_SIZE),
+            blocks: encode_bc7(&self.data, self.size),
+            revision: self.revision,
+        }
+    }
+}
+
+/// A color atlas compressed by [`ColorAtlasSnapshot::compress`], to be installed with
+/// [`TextAtlas::install_compressed_color_atlas`].
+pub struct CompressedColorAtlas {
+    size: u32,
+    blocks: Vec<u8>,
+    revision: (u32, u64, u64),
+}
+
+impl TextAtlas {
+    /// Moves the glyphs of the color atlas (emoji and colored custom glyphs) to a texture
+    /// compressed with BC7, which uses a quarter of the memory and bandwidth.
+    ///
+    /// This is meant to be called once after a warmup phase, when most color glyphs that will be
+    /// shown are cached. The glyphs keep being drawn from the compressed texture and are never
+    /// evicted, while glyphs that are cached afterwards go to a new, small uncompressed color
+    /// atlas. Compression is lossy, and blocks until the atlas was read back and compressed; see
+    /// [`TextAtlas::snapshot_color_atlas`] to compress it on another thread instead.
+    ///
+    /// Renderers have to be prepared again before rendering, since the glyphs moved to another
+    /// texture. Fails with [`CompressError::Unsupported`] if `device` doesn't support
+    /// [`Features::TEXTURE_COMPRESSION_BC`].
+    pub fn compress_color_atlas(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<(), CompressError> {
+        let snapshot = self.snapshot_color_atlas(device, queue)?;
+        self.install_compressed_color_atlas(device, queue, snapshot.compress())
+    }
+
+    /// Reads the color atlas back to the CPU, blocking until the copy is done, to compress it with
+    /// [`ColorAtlasSnapshot::compress`] and install it with
+    /// [`TextAtlas::install_compressed_color_atlas`].
+    ///
+    /// See [`TextAtlas::compress_color_atlas`].
+    pub fn snapshot_color_atlas(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<ColorAtlasSnapshot, CompressError> {
+        self.check_compression(device)?;
+
+        self.flush_uploads(device, queue);
+        let data = self
+            .color_atlas
+            .read_texture(device, queue, &self.cache)
+            .map_err(|_| CompressError::ReadbackFailed)?;
+
+        Ok(ColorAtlasSnapshot {
+            size: self.color_atlas.size,
+            data,
+            revision: self.color_atlas.revision(),
+        })
+    }
+
+    /// Replaces the color atlas with a compressed color atlas created from a snapshot of it.
+    ///
+    /// Fails with [`CompressError::AtlasChanged`] if color glyphs were cached or evicted since the
+    /// snapshot was taken, in which case a new snapshot has to be taken. See
+    /// [`TextAtlas::compress_color_atlas`].
+    pub fn install_compressed_color_atlas(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        compressed: CompressedColorAtlas,
+    ) -> Result<(), CompressError> {
+        self.check_compression(device)?;
+        if compressed.revision != self.color_atlas.revision() {
+            return Err(CompressError::AtlasChanged);
+        }
+
+        let page_bytes = compressed.size as u64 * compressed.size as u64;
+        let old_bytes = self.color_atlas.gpu_memory_usage();
+        let new_bytes = self
+            .color_atlas
+            .kind
+            .texture_bytes(self.color_atlas.initial_size());
+        self.memory.release(old_bytes);
+        if !self.memory.try_reserve(page_bytes + new_bytes) {
+            self.memory.add(old_bytes);
+            return Err(CompressError::MemoryBudgetExceeded);
+        }
+
+        let format = if self.color_atlas.kind.texture_format().is_srgb() {
+            TextureFormat::Bc7RgbaUnormSrgb
+        } else {
+            TextureFormat::Bc7RgbaUnorm
+        };
+        let extent = Extent3d {
+            width: compressed.size,
+            height: compressed.size,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some(&self.cache.label("compressed color atlas")),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &compressed.blocks,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(compressed.size / BLOCK_SIZE * BLOCK_BYTES as u32),
+                rows_per_image: None,
+            },
+            extent,
+        );
+
+        let mut glyphs = self.color_atlas.take_glyphs(device, &self.cache);
+        self.compressed_color = Some(CompressedPage {
+            texture_view: texture.create_view(&TextureViewDescriptor::default()),
+            texture,
+            size: compressed.size,
+            glyphs: iter::from_fn(|| glyphs.pop_lru()).collect(),
+            cache_hits: 0,
+            eviction_count: 0,
+        });
+        self.rebind(device);
+
+        Ok(())
+    }
+
+    /// Returns `true` if the glyphs of the color atlas were moved to a compressed texture with
+    /// [`TextAtlas::compress_color_atlas`].
+    pub fn has_compressed_color_atlas(&self) -> bool {
+        self.compressed_color.is_some()
+    }
+
+    /// Returns the details of a glyph stored in the compressed color atlas.
+    pub(crate) fn compressed_glyph(&self, key: &GlyphonCacheKey) -> Option<&GlyphDetails> {
+        self.compressed_color.as_ref()?.glyphs.get(key)
+    }
+
+    fn check_compression(&self, device: &Device) -> Result<(), CompressError> {
+        if !device.features().contains(Features::TEXTURE_COMPRESSION_BC) {
+            return Err(CompressError::Unsupported);
+        }
+        if self.compressed_color.is_some() {
+            return Err(CompressError::AlreadyCompressed);
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes the rows of an RGBA image of `size` x `size` texels as BC7 blocks, repeating the last
+/// row and column to fill partial blocks.
+fn encode_bc7(data: &[u8], size: u32) -> Vec<u8> {
+    let size = size as usize;
+    let blocks_per_row = size.div_ceil(BLOCK_SIZE as usize);
+
+    let encode_row = |(block_y, row): (usize, &mut [u8])| {
+        for (block_x, block) in row.chunks_exact_mut(BLOCK_BYTES).enumerate() {
+            let mut texels = [[0; 4]; 16];
+            for (i, texel) in texels.iter_mut().enumerate() {
+                let x = (block_x * 4 + i % 4).min(size - 1);
+                let y = (block_y * 4 + i / 4).min(size - 1);
+                let start = (y * size + x) * 4;
+                texel.copy_from_slice(&data[start..start + 4]);
+            }
+            block.copy_from_slice(&encode_block(&texels));
+        }
+    };
+
+    let mut blocks = vec![0; blocks_per_row * blocks_per_row * BLOCK_BYTES];
+    let row_bytes = blocks_per_row * BLOCK_BYTES;
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::{
+            iter::IndexedParallelIterator, iter::ParallelIterator, slice::ParallelSliceMut,
+        };
+        blocks
+            .par_chunks_mut(row_bytes)
+            .enumerate()
+            .for_each(encode_row);
+    }
+    #[cfg(not(feature = "rayon"))]
+    blocks
+        .chunks_mut(row_bytes)
+        .enumerate()
+        .for_each(encode_row);
+
+    blocks
+}
+
+/// Decodes BC7 blocks written by [`encode_bc7`] to the rows of an RGBA image of `size` x `size`
+/// texels.
+fn decode_bc7(blocks: &[u8], size: u32) -> Vec<u8> {
+    let size = size as usize;
+    let blocks_per_row = size / BLOCK_SIZE as usize;
+    let mut data = vec![0; size * size * 4];
+
+    for (index, block) in blocks.chunks_exact(BLOCK_BYTES).enumerate() {
+        let (block_x, block_y) = (index % blocks_per_row, index / blocks_per_row);
+        let texels = decode_block(block.try_into().unwrap());
+        for (i, texel) in texels.iter().enumerate() {
+            let (x, y) = (block_x * 4 + i % 4, block_y * 4 + i / 4);
+            let start = (y * size + x) * 4;
+            data[start..start + 4].copy_from_slice(texel);
+        }
+    }
+
+    data
+}
+
+/// Encodes a block of 4x4 texels with mode 6 of BC7, which interpolates color and alpha between
+/// two endpoints with 16 steps.
+fn encode_block(texels: &[[u8; 4]; 16]) -> [u8; BLOCK_BYTES] {
+    let mut min = [u8::MAX; 4];
+    let mut max = [0; 4];
+    let mut sum = [0; 4];
+    for texel in texels {
+        for c in 0..4 {
+            min[c] = min[c].min(texel[c]);
+            max[c] = max[c].max(texel[c]);
+            sum[c] += texel[c] as i32;
+        }
+    }
+
+    // Use the diagonal of the bounding box along which the channels vary together with the
+    // channel that varies the most
+    let main = (0..4).max_by_key(|&c| max[c] - min[c]).unwrap();
+    let mut endpoints = [min, max];
+    for c in 0..4 {
+        let covariance: i32 = texels
+            .iter()
+            .map(|texel| {
+                (texel[main] as i32 * 16 - sum[main]) * (texel[c] as i32 * 16 - sum[c]) / 256
+            })
+            .sum();
+        if covariance < 0 {
+            endpoints[0][c] = max[c];
+            endpoints[1][c] = min[c];
+        }
+    }
+
+    let mut quantized = endpoints.map(quantize_endpoint);
+    let palette = palette(quantized.map(|(endpoint, p)| expand_endpoint(endpoint, p)));
+    let mut indices = texels.map(|texel| {
+        (0..16)
+            .min_by_key(|&i| {
+                (0..4)
+                    .map(|c| (palette[i][c] as i32 - texel[c] as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .unwrap() as u8
+    });
+
+    // The highest bit of the index of the first texel is implied to be zero
+    if indices[0] >= 8 {
+        quantized.swap(0, 1);
+        indices = indices.map(|index| 15 - index);
+    }
+
+    let mut writer = BitWriter::default();
+    writer.write(1 << 6, 7);
+    for c in 0..4 {
+        writer.write(quantized[0].0[c] as u32, 7);
+        writer.write(quantized[1].0[c] as u32, 7);
+    }
+    writer.write(quantized[0].1 as u32, 1);
+    writer.write(quantized[1].1 as u32, 1);
+    for (i, index) in indices.into_iter().enumerate() {
+        writer.write(index as u32, if i == 0 { 3 } else { 4 });
+    }
+
+    writer.bits.to_le_bytes()
+}
+
+/// Decodes a block written by [`encode_block`].
+fn decode_block(block: [u8; BLOCK_BYTES]) -> [[u8; 4]; 16] {
+    let bits = u128::from_le_bytes(block);
+    let read = |offset: u32, count: u32| ((bits >> offset) & ((1 << count) - 1)) as u8;
+
+    // The channels of both endpoints alternate, followed by the lowest bit of each endpoint
+    let endpoint = |index: u32| [0, 1, 2, 3].map(|c| read(7 + c * 14 + index * 7, 7));
+    let palette = palette([
+        expand_endpoint(endpoint(0), read(63, 1)),
+        expand_endpoint(endpoint(1), read(64, 1)),
+    ]);
+
+    let mut texels = [[0; 4]; 16];
+    let mut offset = 65;
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let count = if i == 0 { 3 } else { 4 };
+        *texel = palette[read(offset, count) as usize];
+        offset += count;
+    }
+
+    texels
+}
+
+/// Returns the 7-bit channels and the shared lowest bit that represent `endpoint` most closely.
+fn quantize_endpoint(endpoint: [u8; 4]) -> ([u8; 4], u8) {
+    (0..2)
+        .map(|p| {
+            let channels = endpoint.map(|c| ((c as u32 + 1 - p as u32) / 2).min(127) as u8);
+            (channels, p)
+        })
+        .min_by_key(|&(channels, p)| {
+            let expanded = expand_endpoint(channels, p);
+            (0..4)
+                .map(|c| (expanded[c] as i32 - endpoint[c] as i32).pow(2))
+                .sum::<i32>()
+        })
+        .unwrap()
+}
+
+fn expand_endpoint(channels: [u8; 4], p: u8) -> [u8; 4] {
+    channels.map(|c| (c << 1) | p)
+}
+
+/// Returns the colors that the indices of a block interpolate between `endpoints`.
+fn palette(endpoints: [[u8; 4]; 2]) -> [[u8; 4]; 16] {
+    WEIGHTS.map(|weight| {
+        [0, 1, 2, 3].map(|c| {
+            (((64 - weight) * endpoints[0][c] as u32 + weight * endpoints[1][c] as u32 + 32) >> 6)
+                as u8
+        })
+    })
+}
+
+/// Writes the fields of a block, starting at the lowest bit.
+#[derive(Default)]
+struct BitWriter {
+    bits: u128,
+    offset: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u128) << self.offset;
+        self.offset += count;
+    }
+}
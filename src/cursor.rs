@@ -0,0 +1,113 @@
+use crate::{
+    text_render::physical_text_glyph, Affinity, Cursor, LayoutGlyph, LayoutRun, TextArea,
+    TextRenderer,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+impl TextArea<'_> {
+    /// Returns the cursor nearest to the position (`x`, `y`), as the text area is rendered by
+    /// `renderer` (see [`TextRenderer::prepare`]). The position is in the same pixels as
+    /// [`TextArea::left`] and [`TextArea::top`] (see [`TextArea::scale_factor`]).
+    ///
+    /// Unlike [`crate::Buffer::hit`], this takes the scale, integer scaling and the
+    /// [pixel snapping](TextRenderer::pixel_snapping) and
+    /// [raster font size limit](TextRenderer::max_raster_font_size) of `renderer` into account,
+    /// so that the cursor matches what was actually drawn.
+    /// Clusters made of multiple graphemes (e.g. ligatures) are split evenly between them.
+    /// Positions between glyphs or beyond the text snap to the nearest glyph edge.
+    ///
+    /// Returns `None` if the position is clipped by [`TextArea::bounds`] or the buffer has no
+    /// visible lines.
+    pub fn cursor_at(&self, renderer: &TextRenderer, x: f32, y: f32) -> Option<Cursor> {
+        let text_area = self.physical();
+        let (x, y) = (x * self.scale_factor, y * self.scale_factor);
+
+        let bounds = text_area.bounds;
+        if x < bounds.left.max(0) as f32
+            || x >= bounds.right as f32
+            || y < bounds.top.max(0) as f32
+            || y >= bounds.bottom as f32
+        {
+            return None;
+        }
+
+        let integer_scale = text_area.integer_scale();
+        let (top, scale) = match integer_scale {
+            Some(integer_scale) => (text_area.top.round(), integer_scale as f32),
+            None => (text_area.top, text_area.scale),
+        };
+
+        let run = text_area.layout_runs().min_by(|a, b| {
+            let distance = |run: &LayoutRun| {
+                let run_top = top + run.line_top * scale;
+                let run_bottom = run_top + run.line_height * scale;
+                (run_top - y).max(y - run_bottom).max(0.0)
+            };
+            distance(a).total_cmp(&distance(b))
+        })?;
+
+        // Left edge of the glyph as it was drawn, positioned the same way as by `prepare`
+        let glyph_left = |glyph: &LayoutGlyph| {
+            let (physical_glyph, glyph_scale) = physical_text_glyph(
+                glyph,
+                &text_area,
+                run.line_y,
+                integer_scale,
+                renderer.pixel_snapping(),
+            );
+            let (physical_glyph, _) = if renderer.uses_fallback() {
+                (physical_glyph, glyph_scale)
+            } else {
+                renderer.limit_raster_font_size(physical_glyph, glyph_scale)
+            };
+            // Subpixel offsets of integer scaled glyphs are part of their unscaled images
+            let x_bin_scale = integer_scale.map_or(1.0, f32::from);
+            physical_glyph.x as f32 + physical_glyph.cache_key.x_bin.as_float() * x_bin_scale
+                - glyph.font_size * glyph.x_offset * scale
+        };
+
+        let Some((glyph, glyph_x)) = run
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph, glyph_left(glyph)))
+            .min_by(|(a, a_x), (b, b_x)| {
+                let distance = |glyph: &LayoutGlyph, glyph_x: f32| {
+                    (glyph_x - x).max(x - glyph_x - glyph.w * scale).max(0.0)
+                };
+                distance(a, *a_x).total_cmp(&distance(b, *b_x))
+            })
+        else {
+            return Some(Cursor::new(run.line_i, 0));
+        };
+
+        let cluster = &run.text[glyph.start..glyph.end];
+        let graphemes = cluster.grapheme_indices(true).count().max(1);
+        let grapheme_w = glyph.w * scale / graphemes as f32;
+        let position = ((x - glyph_x) / grapheme_w).clamp(0.0, graphemes as f32);
+
+        // Graphemes of right-to-left glyphs are drawn from right to left, so their right half is
+        // the one before the grapheme
+        let (grapheme, after) = {
+            let grapheme = (position as usize).min(graphemes - 1);
+            let right_half = position - grapheme as f32 >= 0.5;
+            if glyph.level.is_rtl() {
+                (graphemes - 1 - grapheme, !right_half)
+            } else {
+                (grapheme, right_half)
+            }
+        };
+
+        let (start, end) = cluster
+            .grapheme_indices(true)
+            .nth(grapheme)
+            .map_or((0, cluster.len()), |(start, egc)| {
+                (start, start + egc.len())
+            });
+
+        Some(if after {
+            Cursor::new_with_affinity(run.line_i, glyph.start + end, Affinity::Before)
+        } else {
+            Cursor::new_with_affinity(run.line_i, glyph.start + start, Affinity::After)
+        })
+    }
+}
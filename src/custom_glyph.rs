@@ -1,5 +1,5 @@
-use crate::Color;
-use cosmic_text::SubpixelBin;
+use crate::{CacheKey, Color, FontSystem, LayoutRun, SwashCache, TextArea};
+use cosmic_text::{CacheKeyFlags, SubpixelBin};
 
 pub type CustomGlyphId = u16;
 
@@ -10,14 +10,15 @@ pub struct CustomGlyph {
     pub id: CustomGlyphId,
     /// The position of the left edge of the glyph
     pub left: f32,
-    /// The position of the top edge of the glyph
+    /// The position of the top edge of the glyph, or of the line the glyph is aligned to (see
+    /// [`CustomGlyph::align`])
     pub top: f32,
     /// The width of the glyph
     pub width: f32,
     /// The height of the glyph
     pub height: f32,
     /// The color of this glyph (only relevant if the glyph is rendered with the
-    /// type [`ContentType::Mask`])
+    /// type [`ContentType::Mask`] or [`ContentType::TintedColor`])
     ///
     /// Set to `None` to use [`crate::TextArea::default_color`].
     pub color: Option<Color>,
@@ -25,18 +26,291 @@ pub struct CustomGlyph {
     /// pixel and the resulting `SubpixelBin`'s in `RasterizationRequest` will always
     /// be `Zero` (useful for images and other large glyphs).
     pub snap_to_physical_pixel: bool,
+    /// Whether this glyph is drawn below or above the text of its text area
+    ///
+    /// When rendering with a depth buffer, the depth returned for the glyph's metadata decides
+    /// which one is visible instead.
+    pub z_order: CustomGlyphZOrder,
+    /// How the glyph is positioned vertically
+    pub align: CustomGlyphAlign,
+    /// How far the image of the glyph is rotated clockwise
+    ///
+    /// `width` and `height` are the size of the glyph on screen, so the image of a glyph rotated
+    /// by an odd number of quarter turns is rasterized with its width and height swapped. Rotated
+    /// and flipped glyphs share the image of the upright glyph in the atlas and are always
+    /// snapped to physical pixels.
+    pub rotation: QuarterTurns,
+    /// Whether the image of the glyph is mirrored horizontally, before it is rotated
+    pub flip_x: bool,
+    /// Whether the image of the glyph is mirrored vertically, before it is rotated
+    pub flip_y: bool,
     /// Additional metadata about the glyph
     pub metadata: usize,
 }
 
+impl CustomGlyph {
+    /// Returns the position of the top edge of the glyph in `text_area`, after aligning it to its
+    /// line.
+    pub(crate) fn aligned_top(
+        &self,
+        text_area: &TextArea,
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+    ) -> f32 {
+        if self.align == CustomGlyphAlign::Top {
+            return self.top;
+        }
+
+        let Some(run) = text_area.layout_runs().min_by(|a, b| {
+            let distance = |run: &LayoutRun| {
+                (run.line_top - self.top)
+                    .max(self.top - run.line_top - run.line_height)
+                    .max(0.0)
+            };
+            distance(a).total_cmp(&distance(b))
+        }) else {
+            return self.top;
+        };
+
+        // Use the metrics of the font of the text next to the glyph, as the line may mix fonts
+        let center = self.left + self.width / 2.0;
+        let font_metrics = run
+            .glyphs
+            .iter()
+            .min_by(|a, b| {
+                let distance =
+                    |glyph: &cosmic_text::LayoutGlyph| (glyph.x + glyph.w / 2.0 - center).abs();
+                distance(a).total_cmp(&distance(b))
+            })
+            .and_then(|glyph| {
+                let font = font_system.get_font(glyph.font_id)?;
+                let metrics = font.as_swash().metrics(&[]).scale(glyph.font_size);
+                if metrics.cap_height > 0.0 || self.align != CustomGlyphAlign::CapHeight {
+                    return Some((metrics.ascent, metrics.cap_height));
+                }
+
+                // Older fonts don't specify their cap height, so measure a capital letter instead
+                let capital = font.as_swash().charmap().map('H');
+                drop(font);
+                let (cache_key, _, _) = CacheKey::new(
+                    glyph.font_id,
+                    capital,
+                    glyph.font_size,
+                    (0.0, 0.0),
+                    CacheKeyFlags::empty(),
+                );
+                let image = cache.get_image(font_system, cache_key).as_ref()?;
+                Some((metrics.ascent, image.placement.top as f32))
+            });
+
+        match (self.align, font_metrics) {
+            (CustomGlyphAlign::Baseline, _) => run.line_y - self.height,
+            (CustomGlyphAlign::Ascender, Some((ascent, _))) => run.line_y - ascent,
+            (CustomGlyphAlign::CapHeight, Some((_, cap_height))) => run.line_y - cap_height,
+            _ => self.top,
+        }
+    }
+
+    /// Returns how the image of the glyph is mapped to its quad on screen.
+    pub(crate) fn orientation(&self) -> GlyphOrientation {
+        use GlyphOrientation as O;
+
+        let rotation = match self.rotation {
+            QuarterTurns::Zero => 0,
+            QuarterTurns::One => O::TRANSPOSE | O::FLIP_V,
+            QuarterTurns::Two => O::FLIP_U | O::FLIP_V,
+            QuarterTurns::Three => O::TRANSPOSE | O::FLIP_U,
+        };
+        // Flipping the image before rotating it flips the final image coordinates
+        let flip = (self.flip_x as u16 * O::FLIP_U) | (self.flip_y as u16 * O::FLIP_V);
+
+        GlyphOrientation(rotation ^ flip)
+    }
+}
+
+/// A clockwise rotation of a [`CustomGlyph`] by a multiple of 90 degrees
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuarterTurns {
+    /// The glyph is upright
+    #[default]
+    Zero,
+    /// The glyph is rotated by 90 degrees
+    One,
+    /// The glyph is upside down
+    Two,
+    /// The glyph is rotated by 270 degrees
+    Three,
+}
+
+/// How the image of a glyph is mapped to its quad on screen, by swapping and mirroring the
+/// coordinates of the image.
+///
+/// The coordinates of each corner of the quad are first swapped if the image is transposed, and
+/// then mirrored along the flipped axes of the image.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GlyphOrientation(u16);
+
+impl GlyphOrientation {
+    /// The rows of the image run vertically on screen
+    const TRANSPOSE: u16 = 0x1;
+    /// The image is mirrored along its horizontal axis
+    const FLIP_U: u16 = 0x2;
+    /// The image is mirrored along its vertical axis
+    const FLIP_V: u16 = 0x4;
+    /// All bits of an orientation
+    pub(crate) const MASK: u16 = 0x7;
+
+    pub(crate) fn from_bits(bits: u16) -> Self {
+        Self(bits & Self::MASK)
+    }
+
+    pub(crate) fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub(crate) fn is_upright(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether the width of the image is the height of the quad.
+    pub(crate) fn transposes(self) -> bool {
+        self.0 & Self::TRANSPOSE != 0
+    }
+
+    /// Whether the image is mirrored along the given axis of the image.
+    pub(crate) fn flips(self, axis: usize) -> bool {
+        self.0 & (Self::FLIP_U << axis) != 0
+    }
+
+    /// Returns the axis of the quad on screen that shows the given axis of the image.
+    pub(crate) fn screen_axis(self, image_axis: usize) -> usize {
+        image_axis ^ self.transposes() as usize
+    }
+
+    /// Returns the image data with the given size as it is shown on screen, with its width and
+    /// height swapped if it is transposed.
+    pub(crate) fn apply(
+        self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        bytes_per_pixel: usize,
+    ) -> (Vec<u8>, u16, u16) {
+        let (screen_width, screen_height) = match self.transposes() {
+            true => (height, width),
+            false => (width, height),
+        };
+
+        let mut oriented = Vec::with_capacity(data.len());
+        for y in 0..screen_height as usize {
+            for x in 0..screen_width as usize {
+                let (mut u, mut v) = match self.transposes() {
+                    true => (y, x),
+                    false => (x, y),
+                };
+                if self.flips(0) {
+                    u = width as usize - 1 - u;
+                }
+                if self.flips(1) {
+                    v = height as usize - 1 - v;
+                }
+                let start = (v * width as usize + u) * bytes_per_pixel;
+                oriented.extend_from_slice(&data[start..start + bytes_per_pixel]);
+            }
+        }
+
+        (oriented, screen_width, screen_height)
+    }
+}
+
+/// How a [`CustomGlyph`] is positioned vertically
+///
+/// Except for [`CustomGlyphAlign::Top`], [`CustomGlyph::top`] selects the line the glyph is
+/// aligned to, which is the layout line closest to it. This keeps inline icons aligned with the
+/// text around them, also on lines that mix font sizes.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CustomGlyphAlign {
+    /// The top edge of the glyph is at [`CustomGlyph::top`]
+    #[default]
+    Top,
+    /// The bottom edge of the glyph is on the baseline of the line
+    Baseline,
+    /// The top edge of the glyph is at the ascender of the font next to it
+    Ascender,
+    /// The top edge of the glyph is at the cap height of the font next to it, so that the glyph
+    /// lines up with capital letters if it is as high as they are
+    CapHeight,
+}
+
+/// The order in which a custom glyph is drawn relative to the text of its text area
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CustomGlyphZOrder {
+    /// The glyph is drawn before the text, so text overlapping it is drawn over it
+    #[default]
+    BelowText,
+    /// The glyph is drawn after the text, e.g. for badges that should overlap it
+    AboveText,
+}
+
+/// An image drawn in place of custom glyphs that couldn't be rasterized, see
+/// [`crate::TextRenderer::set_custom_glyph_placeholder`]
+///
+/// Each placeholder is cached once in the atlas and scaled to the size of the missing glyphs.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CustomGlyphPlaceholder {
+    /// The outline of a box
+    #[default]
+    Box,
+    /// A checkerboard pattern
+    Checkerboard,
+}
+
+impl CustomGlyphPlaceholder {
+    /// The width and height of the image of placeholders in the atlas
+    const SIZE: u16 = 16;
+
+    /// Rasterizes the placeholder as a mask, returning its data, width and height.
+    pub(crate) fn rasterize(self) -> (Vec<u8>, u16, u16) {
+        let size = Self::SIZE;
+        let data = (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x, y)))
+            .map(|(x, y)| match self {
+                Self::Box => {
+                    let edge = x.min(y).min(size - 1 - x).min(size - 1 - y);
+                    if edge == 0 {
+                        u8::MAX
+                    } else {
+                        0
+                    }
+                }
+                Self::Checkerboard => {
+                    if (x / 4 + y / 4) % 2 == 0 {
+                        u8::MAX
+                    } else {
+                        u8::MAX / 3
+                    }
+                }
+            })
+            .collect();
+
+        (data, size, size)
+    }
+}
+
 /// A request to rasterize a custom glyph
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct RasterizeCustomGlyphRequest {
     /// The unique identifier of the glyph
     pub id: CustomGlyphId,
     /// The width of the glyph in physical pixels
+    ///
+    /// For glyphs registered with [`crate::TextAtlas::register_scalable_custom_glyph`], this is
+    /// always the registered width.
     pub width: u16,
     /// The height of the glyph in physical pixels
+    ///
+    /// For glyphs registered with [`crate::TextAtlas::register_scalable_custom_glyph`], this is
+    /// always the registered height.
     pub height: u16,
     /// Binning of fractional X offset
     ///
@@ -98,19 +372,25 @@ pub struct CustomGlyphCacheKey {
 }
 
 /// The type of image data contained in a rasterized glyph
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum ContentType {
     /// Each pixel contains 32 bits of rgba data
     Color,
     /// Each pixel contains a single 8 bit channel
     Mask,
+    /// Each pixel contains 32 bits of rgba data, of which the luminance modulates the color of
+    /// the glyph
+    ///
+    /// This is useful for symbolic icons with gradients, which lose their shading when only the
+    /// alpha channel is kept with [`ContentType::Mask`].
+    TintedColor,
 }
 
 impl ContentType {
     /// The number of bytes per pixel for this content type
     pub fn bytes_per_pixel(&self) -> usize {
         match self {
-            Self::Color => 4,
+            Self::Color | Self::TintedColor => 4,
             Self::Mask => 1,
         }
     }
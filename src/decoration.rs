@@ -0,0 +1,156 @@
+use crate::{Color, CustomGlyphId};
+
+/// A line to draw in a text area, such as an underline, a strikethrough or a tab leader
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Decoration {
+    /// The position of the left edge of the line
+    pub left: f32,
+    /// The position of the top edge of the line
+    pub top: f32,
+    /// The length of the line
+    pub width: f32,
+    /// The thickness of the line
+    pub thickness: f32,
+    /// The color of the line
+    ///
+    /// Set to `None` to use [`crate::TextArea::default_color`].
+    pub color: Option<Color>,
+    /// How the line is drawn
+    pub style: DecorationStyle,
+    /// Additional metadata about the line, passed to `metadata_to_depth`
+    pub metadata: usize,
+}
+
+/// How a [`Decoration`] is drawn
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum DecorationStyle {
+    /// A continuous line
+    #[default]
+    Solid,
+    /// Dashes of length `dash`, separated by `gap`
+    Dashed { dash: f32, gap: f32 },
+    /// Round dots as wide as the line is thick, separated by `gap`
+    Dotted { gap: f32 },
+    /// Copies of the custom glyph `id`, each `width` wide and as high as the line is thick,
+    /// separated by `gap`
+    ///
+    /// This can be used for wavy underlines or tab leaders made of arbitrary shapes. The glyph is
+    /// rasterized once, with [`crate::TextRenderer::prepare_with_custom`].
+    Pattern {
+        id: CustomGlyphId,
+        width: f32,
+        gap: f32,
+    },
+}
+
+/// The image repeated along a [`Decoration`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum DecorationPattern {
+    /// A single opaque pixel, stretched to the length of a dash
+    Solid,
+    /// A round dot
+    Dot { diameter: u16 },
+    /// A custom glyph, rasterized at the size of a tile
+    Custom(CustomGlyphId),
+}
+
+impl DecorationPattern {
+    /// Rasterizes built-in patterns as a mask, returning its data, width and height.
+    pub(crate) fn rasterize(&self) -> Option<(Vec<u8>, u16, u16)> {
+        match *self {
+            Self::Solid => Some((vec![u8::MAX], 1, 1)),
+            Self::Dot { diameter } => {
+                let radius = diameter as f32 / 2.0;
+                let data = (0..diameter)
+                    .flat_map(|y| (0..diameter).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let distance = (x as f32 + 0.5 - radius).hypot(y as f32 + 0.5 - radius);
+                        ((radius - distance + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8
+                    })
+                    .collect();
+                Some((data, diameter, diameter))
+            }
+            Self::Custom(_) => None,
+        }
+    }
+}
+
+/// A [`Decoration`] in physical pixels
+pub(crate) struct DecorationQuad {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: u16,
+    pub(crate) pattern: DecorationPattern,
+    /// The width of each copy of the pattern
+    pub(crate) tile: u16,
+    /// The distance between the left edges of consecutive copies of the pattern, or `None` if the
+    /// pattern is stretched along the whole line
+    pub(crate) period: Option<u16>,
+}
+
+impl Decoration {
+    /// Returns the decoration in physical pixels for a text area at `left`, `top` with `scale`,
+    /// or `None` if it is empty.
+    pub(crate) fn quad(&self, left: f32, top: f32, scale: f32) -> Option<DecorationQuad> {
+        let to_pixels = |length: f32| (length * scale).round().clamp(0.0, u16::MAX as f32) as u16;
+
+        let x = (left + self.left * scale).round() as i32;
+        let y = (top + self.top * scale).round() as i32;
+        let width = (self.width * scale).round() as i32;
+        let height = to_pixels(self.thickness).max(1);
+        if width <= 0 || self.thickness <= 0.0 {
+            return None;
+        }
+
+        let (pattern, tile, gap) = match self.style {
+            DecorationStyle::Solid => (
+                DecorationPattern::Solid,
+                width.min(u16::MAX as i32) as u16,
+                None,
+            ),
+            DecorationStyle::Dashed { dash, gap } => (
+                DecorationPattern::Solid,
+                to_pixels(dash).max(1),
+                Some(to_pixels(gap)),
+            ),
+            DecorationStyle::Dotted { gap } => (
+                DecorationPattern::Dot { diameter: height },
+                height,
+                Some(to_pixels(gap)),
+            ),
+            DecorationStyle::Pattern { id, width, gap } => (
+                DecorationPattern::Custom(id),
+                to_pixels(width),
+                Some(to_pixels(gap)),
+            ),
+        };
+        if tile == 0 {
+            return None;
+        }
+
+        Some(DecorationQuad {
+            x,
+            y,
+            width,
+            height,
+            pattern,
+            tile,
+            period: gap.map(|gap| tile.saturating_add(gap)),
+        })
+    }
+}
+
+impl DecorationQuad {
+    /// Returns the left edge of every copy of the pattern along the line that overlaps the
+    /// horizontal range from `left` to `right`. The first and last copies may extend beyond the
+    /// range and should be clipped.
+    pub(crate) fn tiles(&self, left: i32, right: i32) -> impl Iterator<Item = i32> {
+        let period = self.period.unwrap_or(self.tile).max(1) as i64;
+        // Skip the copies left of the range, which may be many for lines far off screen
+        let skipped = (left as i64 - self.x as i64).max(0) / period;
+        let start = self.x as i64 + skipped * period;
+        let end = (self.x as i64 + self.width as i64).min(right as i64);
+        (start..end).step_by(period as usize).map(|x| x as i32)
+    }
+}
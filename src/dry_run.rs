@@ -0,0 +1,325 @@
+use crate::{
+    decoration::DecorationPattern,
+    text_render::{
+        custom_glyph_position, decoration_quads, place_glyph, visible_runs, GlyphEmission,
+        GlyphScale, PlacedGlyph,
+    },
+    Color, ContentType, CustomGlyphZOrder, FontSystem, GlyphAnimation, GlyphSource, PixelSnapping,
+    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, Resolution, SwashCache, SwashContent,
+    TextArea, TextBounds, TextRenderer,
+};
+use cosmic_text::SubpixelBin;
+
+/// A glyph that would be rendered by [`TextRenderer::prepare`], returned by
+/// [`TextRenderer::prepare_dry_run`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunGlyph {
+    /// The index of the text area the glyph belongs to, in the order the text areas were given to
+    /// `prepare_dry_run`
+    pub text_area: usize,
+    /// Where the glyph came from in the text area
+    pub source: GlyphSource,
+    /// The screen area covered by the glyph in physical pixels, after clipping
+    pub rect: TextBounds,
+    /// The color the glyph would be drawn with
+    ///
+    /// This is the tint of [`crate::TextArea::color_glyph_style`] for glyphs of the type
+    /// [`ContentType::Color`].
+    pub color: Color,
+    /// The type of the image of the glyph
+    pub content_type: ContentType,
+    /// The width of each copy of the image and the distance between the left edges of consecutive
+    /// copies, for images that are repeated along `rect` (e.g. dashed decorations)
+    pub repeat: Option<[u16; 2]>,
+}
+
+impl TextRenderer {
+    /// Lays out the text areas like [`TextRenderer::prepare_with_custom`] and returns the glyphs
+    /// that would be rendered, without creating or uploading any GPU resources.
+    ///
+    /// This doesn't need a [`wgpu::Device`], a renderer or an atlas, so it can be used to test the
+    /// placement and clipping of text in headless unit tests. Glyphs are rasterized on the CPU
+    /// with `cache` to find the size of their images.
+    ///
+    /// Settings of renderers (e.g. [`TextRenderer::set_char_filter`] or
+    /// [`TextRenderer::set_color_glyph_policy`]) aren't applied, and glyphs are snapped with the
+    /// default [`crate::PixelSnapping`]. Custom glyphs are positioned as if they weren't
+    /// registered with [`crate::TextAtlas::register_scalable_custom_glyph`], so the position of
+    /// scalable glyphs that aren't snapped to physical pixels may differ by a pixel.
+    pub fn prepare_dry_run<'a>(
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+        resolution: Resolution,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        mut rasterize_custom_glyph: impl FnMut(
+            RasterizeCustomGlyphRequest,
+        ) -> Option<RasterizedCustomGlyph>,
+    ) -> Vec<DryRunGlyph> {
+        trace_span!("prepare_dry_run");
+
+        let mut glyphs = Vec::new();
+        let mut emission = GlyphEmission {
+            snapping: PixelSnapping::default(),
+            char_filter: None,
+            emoji_decomposer: None,
+        };
+        let mut text_glyphs = Vec::new();
+
+        for (text_area_index, text_area) in text_areas.into_iter().enumerate() {
+            let exclusions = text_area.physical_exclusions();
+            let mut text_area = text_area.physical();
+            let integer_scale = text_area.integer_scale();
+            if let Some(integer_scale) = integer_scale {
+                text_area.left = text_area.left.round();
+                text_area.top = text_area.top.round();
+                text_area.scale = integer_scale as f32;
+            }
+
+            let bounds = TextBounds {
+                left: text_area.bounds.left.max(0),
+                top: text_area.bounds.top.max(0),
+                right: text_area.bounds.right.min(resolution.width as i32),
+                bottom: text_area.bounds.bottom.min(resolution.height as i32),
+            };
+
+            let glyph = |placed: PlacedGlyph, color: Color, content_type, source| DryRunGlyph {
+                text_area: text_area_index,
+                source,
+                rect: TextBounds {
+                    left: placed.pos[0],
+                    top: placed.pos[1],
+                    right: placed.pos[0] + placed.dim[0] as i32,
+                    bottom: placed.pos[1] + placed.dim[1] as i32,
+                },
+                color: match content_type {
+                    ContentType::Color => text_area.color_glyph_style.tint,
+                    ContentType::Mask | ContentType::TintedColor => color,
+                },
+                content_type,
+                repeat: None,
+            };
+
+            let mut glyphs_above_text = Vec::new();
+
+            for (glyph_index, custom_glyph) in text_area.custom_glyphs.iter().enumerate() {
+                let width = (custom_glyph.width * text_area.scale).round() as u16;
+                let height = (custom_glyph.height * text_area.scale).round() as u16;
+                if width == 0 || height == 0 {
+                    continue;
+                }
+                let orientation = custom_glyph.orientation();
+
+                let (x, y, x_bin, y_bin) = custom_glyph_position(
+                    text_area.left + (custom_glyph.left * text_area.scale),
+                    text_area.top
+                        + (custom_glyph.aligned_top(&text_area, font_system, cache)
+                            * text_area.scale),
+                    custom_glyph.snap_to_physical_pixel || !orientation.is_upright(),
+                );
+
+                // Rotated glyphs are rasterized upright
+                let (upright_width, upright_height) = match orientation.transposes() {
+                    true => (height, width),
+                    false => (width, height),
+                };
+                let input = RasterizeCustomGlyphRequest {
+                    id: custom_glyph.id,
+                    width: upright_width,
+                    height: upright_height,
+                    x_bin,
+                    y_bin,
+                    scale: text_area.scale,
+                };
+                let Some(output) = (rasterize_custom_glyph)(input) else {
+                    continue;
+                };
+                output.validate(&input, None);
+
+                let Some(placed) = place_glyph(
+                    x,
+                    y,
+                    0.0,
+                    text_area.scale,
+                    GlyphScale::None,
+                    [0, 0],
+                    [width, height],
+                    [0, 0],
+                    bounds,
+                ) else {
+                    continue;
+                };
+
+                let dry_run_glyph = glyph(
+                    placed,
+                    custom_glyph.color.unwrap_or(text_area.default_color),
+                    output.content_type,
+                    GlyphSource::Custom { index: glyph_index },
+                );
+                match custom_glyph.z_order {
+                    CustomGlyphZOrder::BelowText => {
+                        push_excluding(&mut glyphs, dry_run_glyph, &exclusions)
+                    }
+                    CustomGlyphZOrder::AboveText => glyphs_above_text.push(dry_run_glyph),
+                }
+            }
+
+            for (decoration_index, decoration) in text_area.decorations.iter().enumerate() {
+                let Some(quad) = decoration.quad(text_area.left, text_area.top, text_area.scale)
+                else {
+                    continue;
+                };
+
+                let (content_type, width, height, glyph_scale) = match quad.pattern {
+                    DecorationPattern::Custom(id) => {
+                        let input = RasterizeCustomGlyphRequest {
+                            id,
+                            width: quad.tile,
+                            height: quad.height,
+                            x_bin: SubpixelBin::Zero,
+                            y_bin: SubpixelBin::Zero,
+                            scale: text_area.scale,
+                        };
+                        let Some(output) = (rasterize_custom_glyph)(input) else {
+                            continue;
+                        };
+                        output.validate(&input, None);
+
+                        (
+                            output.content_type,
+                            quad.tile,
+                            quad.height,
+                            GlyphScale::None,
+                        )
+                    }
+                    pattern => {
+                        let Some((_, width, height)) = pattern.rasterize() else {
+                            continue;
+                        };
+                        let glyph_scale = match pattern {
+                            DecorationPattern::Solid => GlyphScale::Size(quad.tile, quad.height),
+                            _ => GlyphScale::None,
+                        };
+
+                        (ContentType::Mask, width, height, glyph_scale)
+                    }
+                };
+
+                let color = decoration.color.unwrap_or(text_area.default_color);
+                let line_max_x = bounds.right.min(quad.x.saturating_add(quad.width));
+                let period = quad.period.map_or(i32::MAX, i32::from);
+
+                for (x, min_x, max_x) in
+                    decoration_quads(&quad, GlyphAnimation::None, bounds.left, bounds.right)
+                {
+                    let Some(placed) = place_glyph(
+                        x,
+                        quad.y,
+                        0.0,
+                        text_area.scale,
+                        glyph_scale,
+                        [0, 0],
+                        [width, height],
+                        [0, 0],
+                        TextBounds {
+                            left: min_x,
+                            right: max_x,
+                            ..bounds
+                        },
+                    ) else {
+                        continue;
+                    };
+
+                    let mut dry_run_glyph = glyph(
+                        placed,
+                        color,
+                        content_type,
+                        GlyphSource::Decoration {
+                            index: decoration_index,
+                        },
+                    );
+                    if min_x == i32::MIN {
+                        let width = (line_max_x - x).min(u16::MAX as i32);
+                        dry_run_glyph.rect.left = x;
+                        dry_run_glyph.rect.right = x + width;
+                        dry_run_glyph.repeat = Some([quad.tile, period as u16]);
+                    }
+                    push_excluding(&mut glyphs, dry_run_glyph, &exclusions);
+                }
+            }
+
+            for run in visible_runs(&text_area) {
+                emission.text_glyphs(
+                    &run,
+                    &text_area,
+                    integer_scale,
+                    font_system,
+                    &mut text_glyphs,
+                );
+
+                for text_glyph in text_glyphs.drain(..) {
+                    let physical_glyph = text_glyph.physical;
+                    let Some(image) = cache.get_image(font_system, physical_glyph.cache_key) else {
+                        continue;
+                    };
+                    if image.placement.width == 0 || image.placement.height == 0 {
+                        continue;
+                    }
+
+                    let Some(placed) = place_glyph(
+                        physical_glyph.x,
+                        physical_glyph.y,
+                        0.0,
+                        text_area.scale,
+                        text_glyph.glyph_scale,
+                        [image.placement.left as i16, image.placement.top as i16],
+                        [image.placement.width as u16, image.placement.height as u16],
+                        [0, 0],
+                        bounds,
+                    ) else {
+                        continue;
+                    };
+
+                    let content_type = match image.content {
+                        SwashContent::Color => ContentType::Color,
+                        SwashContent::Mask | SwashContent::SubpixelMask => ContentType::Mask,
+                    };
+                    let dry_run_glyph = glyph(
+                        placed,
+                        text_glyph.color,
+                        content_type,
+                        GlyphSource::Text {
+                            line: run.line_i,
+                            cluster: text_glyph.cluster,
+                        },
+                    );
+                    push_excluding(&mut glyphs, dry_run_glyph, &exclusions);
+                }
+            }
+
+            for dry_run_glyph in glyphs_above_text {
+                push_excluding(&mut glyphs, dry_run_glyph, &exclusions);
+            }
+        }
+
+        glyphs
+    }
+}
+
+/// Pushes the parts of `glyph` that aren't covered by any of `exclusions`, like
+/// `TextRenderer::prepare` does.
+fn push_excluding(glyphs: &mut Vec<DryRunGlyph>, glyph: DryRunGlyph, exclusions: &[TextBounds]) {
+    let parts = glyph.rect.subtract(exclusions);
+    if glyph.repeat.is_some() {
+        if !parts.is_empty() {
+            glyphs.push(glyph);
+        }
+        return;
+    }
+
+    for rect in parts {
+        glyphs.push(DryRunGlyph {
+            rect,
+            ..glyph.clone()
+        });
+    }
+}
@@ -0,0 +1,126 @@
+//! Helpers to prepare chat-like text before it is shaped into a [`crate::Buffer`], enabled by the
+//! `emoji` feature.
+//!
+//! Replacing shortcodes before shaping means that the same emoji glyphs end up cached in the
+//! atlas, regardless of how the emoji were typed.
+
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits `text` into extended grapheme clusters, returning the byte offset of each cluster.
+///
+/// Emoji sequences (e.g. flags, skin tones and ZWJ sequences) are kept in a single cluster.
+pub fn clusters(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.grapheme_indices(true)
+}
+
+/// Returns `true` if `cluster` is likely displayed as an emoji, i.e. if it starts with a
+/// pictographic character or is requested to be displayed as an emoji with a variation
+/// selector.
+///
+/// This is a heuristic rather than a lookup of the Unicode emoji data: clusters starting in the
+/// pictograph blocks (U+1F000 to U+1FAFF, including flags) and clusters containing U+FE0F are
+/// emoji, and symbols from U+2600 to U+27BF are emoji only on their own. Other characters with
+/// an emoji presentation (e.g. `©`) are only emoji when followed by U+FE0F.
+pub fn is_emoji(cluster: &str) -> bool {
+    crate::emoji_fallback::is_emoji(cluster)
+}
+
+/// Returns the emoji for a shortcode without colons (e.g. `smile` for `:smile:`), or `None` if
+/// the shortcode is unknown.
+///
+/// Shortcodes are looked up in the [GitHub](https://github.com/github/gemoji) set provided by the
+/// `emojis` crate.
+pub fn shortcode(name: &str) -> Option<&'static str> {
+    emojis::get_by_shortcode(name).map(emojis::Emoji::as_str)
+}
+
+/// Replaces `:shortcode:`s in `text` with emoji, leaving unknown shortcodes as they are.
+///
+/// Returns `text` without copying it if it doesn't contain any known shortcode.
+pub fn replace_shortcodes(text: &str) -> Cow<'_, str> {
+    let mut replaced = String::new();
+    // The end of the text that was copied to `replaced`
+    let mut copied = 0;
+    let mut search = 0;
+
+    while let Some(start) = text[search..].find(':').map(|start| search + start) {
+        let name_start = start + 1;
+        let name_len = text[name_start..]
+            .find(|c: char| !is_shortcode_char(c))
+            .unwrap_or(text.len() - name_start);
+        let name_end = name_start + name_len;
+
+        let emoji = if text[name_end..].starts_with(':') {
+            shortcode(&text[name_start..name_end])
+        } else {
+            None
+        };
+        match emoji {
+            Some(emoji) => {
+                replaced.push_str(&text[copied..start]);
+                replaced.push_str(emoji);
+                copied = name_end + 1;
+                search = copied;
+            }
+            // The colon closing an unknown shortcode may open the next one
+            None => search = name_end,
+        }
+    }
+
+    if copied == 0 {
+        Cow::Borrowed(text)
+    } else {
+        replaced.push_str(&text[copied..]);
+        Cow::Owned(replaced)
+    }
+}
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcodes() {
+        assert_eq!(shortcode("smile"), Some("\u{1F604}"));
+        assert_eq!(shortcode("+1"), Some("\u{1F44D}"));
+        assert_eq!(shortcode("thumbsup"), Some("\u{1F44D}"));
+        assert_eq!(shortcode("heart"), Some("\u{2764}\u{FE0F}"));
+        assert_eq!(shortcode(""), None);
+        assert_eq!(shortcode("not_an_emoji"), None);
+        assert_eq!(shortcode(":smile:"), None);
+    }
+
+    #[test]
+    fn replace_known_shortcodes() {
+        assert_eq!(
+            replace_shortcodes("hi :wave: :+1::tada:!"),
+            "hi \u{1F44B} \u{1F44D}\u{1F389}!"
+        );
+    }
+
+    #[test]
+    fn keep_unknown_shortcodes() {
+        assert!(matches!(
+            replace_shortcodes("no emoji: here :nope: or :"),
+            Cow::Borrowed(_)
+        ));
+        // The colon closing an unknown shortcode opens the next one
+        assert_eq!(replace_shortcodes(":nope:smile:"), ":nope\u{1F604}");
+        assert_eq!(replace_shortcodes("::smile::"), ":\u{1F604}:");
+        assert_eq!(replace_shortcodes(":smile"), ":smile");
+    }
+
+    #[test]
+    fn emoji_clusters() {
+        let text = "a\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{1F1FA}\u{1F1F8}b";
+        let clusters: Vec<_> = clusters(text)
+            .map(|(_, cluster)| is_emoji(cluster))
+            .collect();
+        assert_eq!(clusters, [false, true, true, false]);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{Color, FontSystem};
+use cosmic_text::{fontdb, ttf_parser, LayoutGlyph};
+use rustc_hash::FxHasher;
+use std::{
+    collections::HashMap,
+    hash::{BuildHasherDefault, Hash, Hasher},
+};
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Returns `true` if `cluster` is likely displayed as an emoji, see [`crate::emoji::is_emoji`].
+pub(crate) fn is_emoji(cluster: &str) -> bool {
+    let mut chars = cluster.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    matches!(first, '\u{1F000}'..='\u{1FAFF}')
+        || cluster.contains('\u{FE0F}')
+        || (matches!(first, '\u{2600}'..='\u{27BF}') && chars.next().is_none())
+}
+
+/// Returns the color of the glyphs of `cluster` if it is an emoji colored from `colors`, see
+/// [`crate::TextArea::monochrome_emoji_colors`].
+///
+/// The color is picked by the text of the emoji, so that the same emoji always get the same color,
+/// and its alpha is multiplied by the alpha of `text_color`.
+pub(crate) fn monochrome_emoji_color(
+    cluster: &str,
+    colors: &[Color],
+    text_color: Color,
+) -> Option<Color> {
+    if colors.is_empty() || !is_emoji(cluster) {
+        return None;
+    }
+
+    let mut hasher = FxHasher::default();
+    cluster.hash(&mut hasher);
+    let color = colors[(hasher.finish() % colors.len() as u64) as usize];
+
+    let alpha = color.a() as u32 * text_color.a() as u32 / 255;
+    Some(Color::rgba(color.r(), color.g(), color.b(), alpha as u8))
+}
+
+/// Draws the emoji of ZWJ sequences that no font supports side by side, see
+/// [`crate::TextRenderer::set_decompose_missing_emoji`].
+#[derive(Default)]
+pub(crate) struct EmojiDecomposer {
+    /// The glyph found for each emoji of a missing sequence and the font preferred for it, or
+    /// `None` if no font supports it
+    glyphs: HashMap<(char, Option<fontdb::ID>), Option<EmojiGlyph>, BuildHasherDefault<FxHasher>>,
+    /// The hash of the IDs of the faces of the font database the glyphs were found in
+    faces: u64,
+}
+
+#[derive(Clone, Copy)]
+struct EmojiGlyph {
+    font_id: fontdb::ID,
+    glyph_id: u16,
+    /// The advance of the glyph in ems
+    advance: f32,
+}
+
+impl EmojiDecomposer {
+    /// Returns the glyphs of the emoji of `glyphs[index]` if it is the first missing glyph of a
+    /// ZWJ sequence that was entirely drawn as missing glyphs, an empty list for the other
+    /// missing glyphs of the sequence, or `None` if the glyph isn't part of such a sequence or if
+    /// none of its emoji are supported by a font.
+    ///
+    /// The line was laid out with the advance of the missing glyphs, so the emoji are centered in
+    /// that advance and shrunk if they don't fit in it. Modifiers and variation selectors of the
+    /// emoji are dropped.
+    pub(crate) fn decompose(
+        &mut self,
+        glyphs: &[LayoutGlyph],
+        index: usize,
+        text: &str,
+        font_system: &FontSystem,
+    ) -> Option<Vec<LayoutGlyph>> {
+        let glyph = &glyphs[index];
+        let cluster = &text[glyph.start..glyph.end];
+        if glyph.glyph_id != 0 || !cluster.contains(ZERO_WIDTH_JOINER) {
+            return None;
+        }
+
+        // The glyphs of a cluster are adjacent
+        let in_cluster =
+            |other: &&LayoutGlyph| other.start == glyph.start && other.end == glyph.end;
+        let before = glyphs[..index].iter().rev().take_while(in_cluster);
+        let after = glyphs[index..].iter().take_while(in_cluster);
+        let (mut left, mut right) = (glyph.x, glyph.x + glyph.w);
+        for other in before.clone().chain(after) {
+            // Zero width joiners can have glyphs of their own
+            if other.glyph_id != 0 && other.w > 0.0 {
+                return None;
+            }
+            left = left.min(other.x);
+            right = right.max(other.x + other.w);
+        }
+
+        self.check_faces(font_system);
+        let emoji_glyphs: Vec<EmojiGlyph> = cluster
+            .split(ZERO_WIDTH_JOINER)
+            .filter_map(|emoji| {
+                let base = emoji.chars().next()?;
+                self.emoji_glyph(base, Some(glyph.font_id), font_system)
+            })
+            .collect();
+        if emoji_glyphs.is_empty() {
+            return None;
+        }
+
+        // The emoji are drawn in place of the first missing glyph of the sequence
+        if before.clone().any(|other| other.glyph_id == 0) {
+            return Some(Vec::new());
+        }
+
+        let advance = emoji_glyphs.iter().map(|emoji| emoji.advance).sum::<f32>();
+        let width = right - left;
+        let font_size = if advance * glyph.font_size > width {
+            width / advance
+        } else {
+            glyph.font_size
+        };
+
+        let mut x = left + (width - advance * font_size) / 2.0;
+        let glyphs = emoji_glyphs
+            .into_iter()
+            .map(|emoji| {
+                let w = emoji.advance * font_size;
+                let decomposed = LayoutGlyph {
+                    font_size,
+                    font_id: emoji.font_id,
+                    glyph_id: emoji.glyph_id,
+                    x,
+                    w,
+                    ..glyph.clone()
+                };
+                x += w;
+                decomposed
+            })
+            .collect();
+
+        Some(glyphs)
+    }
+
+    /// Forgets the glyphs found so far if faces were added to or removed from the font database
+    /// since they were found.
+    ///
+    /// IDs of faces aren't reused, so hashing them tells apart any two sets of faces.
+    fn check_faces(&mut self, font_system: &FontSystem) {
+        let mut hasher = FxHasher::default();
+        for face in font_system.db().faces() {
+            face.id.hash(&mut hasher);
+        }
+
+        let faces = hasher.finish();
+        if faces != self.faces {
+            self.glyphs.clear();
+            self.faces = faces;
+        }
+    }
+
+    /// Finds the glyph of `c`, preferring `preferred_font` (the font of the missing sequence)
+    /// over the other fonts of the font system.
+    fn emoji_glyph(
+        &mut self,
+        c: char,
+        preferred_font: Option<fontdb::ID>,
+        font_system: &FontSystem,
+    ) -> Option<EmojiGlyph> {
+        *self.glyphs.entry((c, preferred_font)).or_insert_with(|| {
+            let db = font_system.db();
+            preferred_font
+                .into_iter()
+                .chain(db.faces().map(|face| face.id))
+                .find_map(|font_id| {
+                    db.with_face_data(font_id, |data, index| {
+                        let face = ttf_parser::Face::parse(data, index).ok()?;
+                        let glyph_id = face.glyph_index(c)?;
+                        let advance = face.glyph_hor_advance(glyph_id)?;
+                        Some(EmojiGlyph {
+                            font_id,
+                            glyph_id: glyph_id.0,
+                            advance: advance as f32 / face.units_per_em() as f32,
+                        })
+                    })
+                    .flatten()
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_emoji_edge_cases() {
+        // Pictographs
+        assert!(is_emoji("\u{1F600}"));
+        assert!(is_emoji("\u{1F44D}\u{1F3FD}"));
+        assert!(is_emoji("\u{1F1FA}\u{1F1F8}"));
+        // Requested with the emoji variation selector
+        assert!(is_emoji("\u{2764}\u{FE0F}"));
+        assert!(is_emoji("#\u{FE0F}\u{20E3}"));
+        // Symbols with a text presentation by default are only emoji on their own
+        assert!(is_emoji("\u{2600}"));
+        assert!(!is_emoji("\u{2600}\u{FE0E}"));
+        // Text
+        assert!(!is_emoji(""));
+        assert!(!is_emoji("a"));
+        assert!(!is_emoji("1\u{20E3}"));
+        assert!(!is_emoji("\u{00A9}"));
+        assert!(!is_emoji("\u{200D}"));
+    }
+}
@@ -1,27 +1,111 @@
+use crate::ColorMode;
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
 };
+use wgpu::TextureFormat;
 
 /// An error that occurred while preparing text for rendering.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PrepareError {
     AtlasFull,
+    MemoryBudgetExceeded,
+    /// More glyphs would be prepared than allowed by
+    /// [`crate::TextRenderer::set_max_glyphs_per_prepare`]
+    GlyphLimitExceeded {
+        /// The maximum number of glyphs
+        limit: usize,
+        /// The number of text areas that were prepared completely before the limit was reached,
+        /// or the number of runs for [`crate::TextRenderer::prepare_runs`]
+        text_areas: usize,
+    },
+    /// Positioned glyphs and runs were prepared with a renderer that uses the fallback, see
+    /// [`crate::TextRenderer::uses_fallback`]
+    FallbackUnsupported,
 }
 
 impl Display for PrepareError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "Prepare error: glyph texture atlas is full")
+        match self {
+            PrepareError::AtlasFull => write!(f, "Prepare error: glyph texture atlas is full"),
+            PrepareError::MemoryBudgetExceeded => write!(
+                f,
+                "Prepare error: growing GPU resources would exceed the memory budget"
+            ),
+            PrepareError::GlyphLimitExceeded { limit, text_areas } => write!(
+                f,
+                "Prepare error: more than {} glyphs would be prepared, after {} complete text areas",
+                limit, text_areas
+            ),
+            PrepareError::FallbackUnsupported => write!(
+                f,
+                "Prepare error: positioned glyphs and runs aren't supported by the fallback renderer"
+            ),
+        }
     }
 }
 
 impl Error for PrepareError {}
 
+/// An error that occurred while restoring a [`crate::PreparedSnapshot`] or revalidating prepared
+/// state with [`crate::TextRenderer::revalidate`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RestoreError {
+    GlyphEvicted,
+    MemoryBudgetExceeded,
+    AtlasFull,
+}
+
+impl Display for RestoreError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RestoreError::GlyphEvicted => write!(
+                f,
+                "Restore error: glyph no longer exists within the texture atlas"
+            ),
+            RestoreError::MemoryBudgetExceeded => write!(
+                f,
+                "Restore error: growing GPU resources would exceed the memory budget"
+            ),
+            RestoreError::AtlasFull => write!(f, "Restore error: glyph texture atlas is full"),
+        }
+    }
+}
+
+impl Error for RestoreError {}
+
+/// An error that occurred while exporting prepared text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportError {
+    RemovedFromAtlas,
+    ReadbackFailed,
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ExportError::RemovedFromAtlas => write!(
+                f,
+                "Export error: glyph no longer exists within the texture atlas"
+            ),
+            ExportError::ReadbackFailed => {
+                write!(f, "Export error: failed to read back the texture atlas")
+            }
+        }
+    }
+}
+
+impl Error for ExportError {}
+
 /// An error that occurred while rendering text.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum RenderError {
     RemovedFromAtlas,
     ScreenResolutionChanged,
+    DepthStencilMismatch {
+        renderer: Option<TextureFormat>,
+        pass: Option<TextureFormat>,
+    },
 }
 
 impl Display for RenderError {
@@ -37,8 +121,124 @@ impl Display for RenderError {
                 f,
                 "Render error: screen resolution changed since last `prepare` call"
             ),
+            RenderError::DepthStencilMismatch { renderer, pass } => match (renderer, pass) {
+                (Some(renderer), None) => write!(
+                    f,
+                    "Render error: the renderer was created with a {:?} depth-stencil state, but the render pass has no depth-stencil attachment",
+                    renderer
+                ),
+                (None, Some(pass)) => write!(
+                    f,
+                    "Render error: the render pass has a {:?} depth-stencil attachment, but the renderer was created without a depth-stencil state",
+                    pass
+                ),
+                _ => write!(
+                    f,
+                    "Render error: the renderer was created with a {:?} depth-stencil state, but the depth-stencil attachment of the render pass is {:?}",
+                    renderer, pass
+                ),
+            },
         }
     }
 }
 
 impl Error for RenderError {}
+
+/// An error that occurred while compressing the color atlas of a [`crate::TextAtlas`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompressError {
+    Unsupported,
+    AlreadyCompressed,
+    ReadbackFailed,
+    AtlasChanged,
+    MemoryBudgetExceeded,
+}
+
+impl Display for CompressError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CompressError::Unsupported => write!(
+                f,
+                "Compress error: the device doesn't support BC texture compression"
+            ),
+            CompressError::AlreadyCompressed => {
+                write!(f, "Compress error: the color atlas is already compressed")
+            }
+            CompressError::ReadbackFailed => {
+                write!(f, "Compress error: failed to read back the color atlas")
+            }
+            CompressError::AtlasChanged => write!(
+                f,
+                "Compress error: the color atlas changed since the snapshot was taken"
+            ),
+            CompressError::MemoryBudgetExceeded => write!(
+                f,
+                "Compress error: the compressed color atlas would exceed the memory budget"
+            ),
+        }
+    }
+}
+
+impl Error for CompressError {}
+
+/// An error that occurred while creating a [`crate::TextAtlas`] with
+/// [`crate::TextAtlas::try_with_color_mode`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorModeError {
+    MismatchedFormat {
+        color_mode: ColorMode,
+        format: TextureFormat,
+    },
+}
+
+impl Display for ColorModeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ColorModeError::MismatchedFormat { color_mode, format } => write!(
+                f,
+                "Color mode error: {:?} doesn't match the render target format {:?}, use {:?}",
+                color_mode,
+                format,
+                ColorMode::for_surface_format(*format),
+            ),
+        }
+    }
+}
+
+impl Error for ColorModeError {}
+
+/// An error that occurred while enabling or disabling a feature of the pipeline of a
+/// [`crate::TextRenderer`], e.g. with [`crate::TextRenderer::set_vertex_pulling`].
+///
+/// The renderer keeps its previous pipeline when this is returned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PipelineError {
+    /// The renderer [uses the fallback](crate::TextRenderer::uses_fallback), which has no
+    /// pipeline features.
+    UsesFallback,
+    /// The device doesn't support the feature, or the combination of it with the features that
+    /// are already enabled.
+    Unsupported,
+    /// The device failed to create the pipeline.
+    CreationFailed,
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PipelineError::UsesFallback => write!(
+                f,
+                "Pipeline error: the renderer rasterizes text on the CPU and has no pipeline features"
+            ),
+            PipelineError::Unsupported => write!(
+                f,
+                "Pipeline error: the device doesn't support this combination of pipeline features"
+            ),
+            PipelineError::CreationFailed => {
+                write!(f, "Pipeline error: the device failed to create the pipeline")
+            }
+        }
+    }
+}
+
+impl Error for PipelineError {}
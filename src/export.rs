@@ -0,0 +1,97 @@
+use crate::{fontdb, Color, ContentType, GlyphSourceInfo, TextBounds};
+
+/// A prepared glyph with its image, returned by [`crate::TextRenderer::export`]
+///
+/// This contains everything needed to reproduce the rendered text outside of the GPU, e.g. to
+/// generate a PDF for printing that matches the on-screen rendering.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedGlyph {
+    /// Where the glyph came from, if glyph sources are recorded (see
+    /// [`crate::TextRenderer::set_record_glyph_sources`])
+    pub source: Option<GlyphSourceInfo>,
+    /// The screen area covered by the glyph in physical pixels, after clipping
+    pub rect: TextBounds,
+    /// The color of the glyph (only relevant if the glyph is rendered with the type
+    /// [`ContentType::Mask`] or [`ContentType::TintedColor`])
+    ///
+    /// Only the alpha is relevant for glyphs with a `palette_index`.
+    pub color: Color,
+    /// The index of the color of the glyph in the palette of the viewport, see
+    /// [`crate::TextRenderer::set_metadata_to_palette_index`]
+    pub palette_index: Option<usize>,
+    /// The type of image data contained in `data`
+    pub content_type: ContentType,
+    /// The width of the image in the atlas
+    ///
+    /// This is the visible part of the image, which is stretched over `rect` for scaled glyphs.
+    pub width: u16,
+    /// The height of the image in the atlas
+    pub height: u16,
+    /// The image data of the glyph as cached in the atlas, with one byte per pixel for
+    /// [`ContentType::Mask`] and four bytes per pixel otherwise
+    ///
+    /// The image of rotated and flipped custom glyphs is rotated and flipped the same way, with
+    /// `width` and `height` swapped for odd quarter turns.
+    pub data: Vec<u8>,
+    /// The width of each copy of the image and the distance between the left edges of consecutive
+    /// copies, for images that are repeated along `rect` (e.g. dashed decorations)
+    pub repeat: Option<[u16; 2]>,
+    /// The font outline of the glyph, for glyphs of text that aren't rasterized with
+    /// [`crate::GlyphRasterOptions`]
+    pub outline: Option<GlyphOutline>,
+}
+
+/// Refers to the outline of a glyph in a font, so that it can be exported as vectors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphOutline {
+    /// The font of the glyph
+    pub font_id: fontdb::ID,
+    /// The index of the glyph in the font
+    pub glyph_id: u16,
+    /// The font size in physical pixels
+    pub font_size: f32,
+    /// The horizontal position of the origin of the glyph in physical pixels
+    pub x: f32,
+    /// The vertical position of the origin of the glyph (on the baseline) in physical pixels
+    pub y: f32,
+}
+
+/// The vector outline of a prepared glyph, returned by [`crate::TextRenderer::export_outlines`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedOutline {
+    /// Where the glyph came from, if glyph sources are recorded (see
+    /// [`crate::TextRenderer::set_record_glyph_sources`])
+    pub source: Option<GlyphSourceInfo>,
+    /// The glyph in its font and its position
+    pub outline: GlyphOutline,
+    /// The screen area the glyph is drawn in, in physical pixels, which parts of the path outside
+    /// of it should be clipped to
+    pub clip: TextBounds,
+    /// The color of the glyph
+    ///
+    /// Only the alpha is relevant for glyphs with a `palette_index`.
+    pub color: Color,
+    /// The index of the color of the glyph in the palette of the viewport, see
+    /// [`crate::TextRenderer::set_metadata_to_palette_index`]
+    pub palette_index: Option<usize>,
+    /// The path of the outline in physical pixels, with y pointing down, filled with the non-zero
+    /// winding rule
+    pub path: Vec<PathCommand>,
+}
+
+/// A command of the path of an [`ExportedOutline`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCommand {
+    /// Begins a new subpath at the point
+    MoveTo([f32; 2]),
+    /// A straight line from the previous point to the point
+    LineTo([f32; 2]),
+    /// A quadratic curve from the previous point to the second point with the first point as
+    /// control point
+    QuadTo([f32; 2], [f32; 2]),
+    /// A cubic curve from the previous point to the third point with the first two points as
+    /// control points
+    CurveTo([f32; 2], [f32; 2], [f32; 2]),
+    /// Closes the subpath, connecting its last point to its first point
+    Close,
+}
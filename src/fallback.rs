@@ -0,0 +1,847 @@
+use crate::{
+    decoration::DecorationPattern,
+    minimap::minimap_blocks,
+    text_atlas::MemoryTracker,
+    text_render::{glyph_quad, visible_runs, GlyphEmission},
+    BlendMode, Color, ColorGlyphStyle, ContentType, CustomGlyphPlaceholder, CustomGlyphZOrder,
+    FontSystem, MinimapStyle, PrepareError, PrepareOutput, RasterizeCustomGlyphRequest,
+    RasterizedCustomGlyph, SwashCache, SwashContent, TextArea, TextAtlas, TextBounds, Viewport,
+};
+use cosmic_text::SubpixelBin;
+use std::{borrow::Cow, mem, slice, sync::Arc};
+use wgpu::{
+    BindGroup, Buffer, BufferDescriptor, BufferUsages, DepthStencilState, Device, Extent3d,
+    MultisampleState, Origin3d, Queue, RenderPass, RenderPipeline, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureViewDescriptor,
+};
+
+/// Renders text by rasterizing it into a single texture on the CPU, for devices that fail to
+/// create the regular glyph pipeline.
+pub(crate) struct FallbackRenderer {
+    pipeline: RenderPipeline,
+    params_buffer: Buffer,
+    canvas: Vec<u8>,
+    /// The texture the canvas is uploaded to, along with its size and bind group
+    texture: Option<(wgpu::Texture, (u32, u32), BindGroup)>,
+    /// Whether anything was drawn by the last prepare
+    drawn: bool,
+    memory: Arc<MemoryTracker>,
+    /// The label of the renderer, see [`crate::TextRenderer::set_label`]
+    pub(crate) label: Option<String>,
+}
+
+impl FallbackRenderer {
+    pub(crate) fn new(
+        atlas: &TextAtlas,
+        device: &Device,
+        multisample: MultisampleState,
+        depth_stencil: Option<DepthStencilState>,
+        blend_mode: BlendMode,
+    ) -> Self {
+        let pipeline = atlas.cache.get_or_create_fallback_pipeline(
+            device,
+            atlas.format,
+            multisample,
+            depth_stencil,
+            blend_mode,
+        );
+
+        let params_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some(&atlas.cache.label("fallback params")),
+            size: mem::size_of::<[f32; 4]>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            params_buffer,
+            canvas: Vec::new(),
+            texture: None,
+            drawn: false,
+            memory: atlas.memory.clone(),
+            label: None,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare<'a>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+        placeholder: Option<CustomGlyphPlaceholder>,
+        minimap: Option<MinimapStyle>,
+        mut emission: GlyphEmission,
+        mut rasterize_custom_glyph: impl FnMut(
+            RasterizeCustomGlyphRequest,
+        ) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<PrepareOutput, PrepareError> {
+        trace_span!("prepare_fallback");
+
+        self.drawn = false;
+
+        let resolution = viewport.resolution();
+        let screen = TextBounds {
+            left: 0,
+            top: 0,
+            right: resolution.width as i32,
+            bottom: resolution.height as i32,
+        };
+
+        let mut culled_text_areas = Vec::new();
+
+        // The canvas only covers the visible parts of all text areas
+        let text_areas: Vec<(usize, TextArea, TextBounds, Cow<[TextBounds]>)> = text_areas
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, text_area)| {
+                let exclusions = text_area.physical_exclusions();
+                let text_area = text_area.physical();
+                let clip = intersect(text_area.bounds, screen);
+                if clip.left < clip.right && clip.top < clip.bottom {
+                    Some((index, text_area, clip, exclusions))
+                } else {
+                    culled_text_areas.push(index);
+                    None
+                }
+            })
+            .collect();
+        let Some(bounds) = text_areas
+            .iter()
+            .map(|(_, _, clip, _)| *clip)
+            .reduce(|a, b| TextBounds {
+                left: a.left.min(b.left),
+                top: a.top.min(b.top),
+                right: a.right.max(b.right),
+                bottom: a.bottom.max(b.bottom),
+            })
+        else {
+            return Ok(PrepareOutput { culled_text_areas });
+        };
+
+        let mut canvas = Canvas {
+            data: mem::take(&mut self.canvas),
+            bounds,
+            exclusions: Vec::new(),
+        };
+        canvas.data.clear();
+        canvas
+            .data
+            .resize(canvas.width() as usize * canvas.height() as usize * 4, 0);
+
+        let draw_custom_glyphs = |canvas: &mut Canvas,
+                                  font_system: &mut FontSystem,
+                                  cache: &mut SwashCache,
+                                  text_area: &TextArea,
+                                  clip: TextBounds,
+                                  z_order: CustomGlyphZOrder,
+                                  rasterize_custom_glyph: &mut dyn FnMut(
+            RasterizeCustomGlyphRequest,
+        ) -> Option<
+            RasterizedCustomGlyph,
+        >| {
+            let mut visible = false;
+            for glyph in text_area
+                .custom_glyphs
+                .iter()
+                .filter(|glyph| glyph.z_order == z_order)
+            {
+                let width = (glyph.width * text_area.scale).round() as u16;
+                let height = (glyph.height * text_area.scale).round() as u16;
+                if width == 0 || height == 0 {
+                    continue;
+                }
+
+                // Rotated glyphs are rasterized upright and rotated once rasterized
+                let orientation = glyph.orientation();
+                let upright_size = match orientation.transposes() {
+                    true => (height, width),
+                    false => (width, height),
+                };
+
+                // Scalable glyphs can only be rasterized at their registered size
+                let (source_width, source_height) = atlas
+                    .scalable_custom_glyphs
+                    .get(&glyph.id)
+                    .copied()
+                    .unwrap_or(upright_size);
+
+                let input = RasterizeCustomGlyphRequest {
+                    id: glyph.id,
+                    width: source_width,
+                    height: source_height,
+                    x_bin: SubpixelBin::Zero,
+                    y_bin: SubpixelBin::Zero,
+                    scale: text_area.scale,
+                };
+                let (content_type, data, source_width, source_height) =
+                    match ((rasterize_custom_glyph)(input), placeholder) {
+                        (Some(output), _) => {
+                            output.validate(&input, None);
+                            (
+                                output.content_type,
+                                output.data,
+                                source_width,
+                                source_height,
+                            )
+                        }
+                        (None, Some(placeholder)) => {
+                            let (data, width, height) = placeholder.rasterize();
+                            (ContentType::Mask, data, width, height)
+                        }
+                        (None, None) => continue,
+                    };
+                let (data, source_width, source_height) = match orientation.is_upright() {
+                    true => (data, source_width, source_height),
+                    false => orientation.apply(
+                        &data,
+                        source_width,
+                        source_height,
+                        content_type.bytes_per_pixel(),
+                    ),
+                };
+
+                visible |= canvas.draw(
+                    &Image {
+                        content_type,
+                        width: source_width as u32,
+                        height: source_height as u32,
+                        data: &data,
+                    },
+                    (text_area.left + glyph.left * text_area.scale).round() as i32,
+                    (text_area.top
+                        + glyph.aligned_top(text_area, font_system, cache) * text_area.scale)
+                        .round() as i32,
+                    width as u32,
+                    height as u32,
+                    glyph.color.unwrap_or(text_area.default_color),
+                    text_area.color_glyph_style,
+                    clip,
+                );
+            }
+            visible
+        };
+
+        for (index, text_area, clip, exclusions) in &text_areas {
+            canvas.exclusions.clear();
+            canvas.exclusions.extend_from_slice(exclusions);
+
+            // Integer scaling keeps the layout, but custom glyphs and decorations are rasterized
+            // at the scaled size, while the pixels of text glyphs are repeated
+            let mut text_area = text_area.clone();
+            let integer_scale = text_area.integer_scale();
+            if let Some(integer_scale) = integer_scale {
+                text_area.left = text_area.left.round();
+                text_area.top = text_area.top.round();
+                text_area.scale = integer_scale as f32;
+            }
+
+            let mut visible = draw_custom_glyphs(
+                &mut canvas,
+                font_system,
+                cache,
+                &text_area,
+                *clip,
+                CustomGlyphZOrder::BelowText,
+                &mut rasterize_custom_glyph,
+            );
+
+            let minimap_blocks = match minimap {
+                Some(style) => minimap_blocks(visible_runs(&text_area), style),
+                None => Vec::new(),
+            };
+            let decorations = text_area
+                .decorations
+                .iter()
+                .chain(minimap_blocks.iter().map(|(decoration, _)| decoration));
+
+            for decoration in decorations {
+                let Some(quad) = decoration.quad(text_area.left, text_area.top, text_area.scale)
+                else {
+                    continue;
+                };
+
+                let image = match quad.pattern {
+                    DecorationPattern::Custom(id) => {
+                        let input = RasterizeCustomGlyphRequest {
+                            id,
+                            width: quad.tile,
+                            height: quad.height,
+                            x_bin: SubpixelBin::Zero,
+                            y_bin: SubpixelBin::Zero,
+                            scale: text_area.scale,
+                        };
+                        (rasterize_custom_glyph)(input).map(|output| {
+                            output.validate(&input, None);
+                            (output.content_type, output.data, quad.tile, quad.height)
+                        })
+                    }
+                    pattern => pattern
+                        .rasterize()
+                        .map(|(data, width, height)| (ContentType::Mask, data, width, height)),
+                };
+                let Some((content_type, data, width, height)) = image else {
+                    continue;
+                };
+
+                let line_clip = intersect(
+                    *clip,
+                    TextBounds {
+                        left: quad.x,
+                        top: quad.y,
+                        right: quad.x.saturating_add(quad.width),
+                        bottom: quad.y.saturating_add(quad.height as i32),
+                    },
+                );
+                for x in quad.tiles(line_clip.left, line_clip.right) {
+                    visible |= canvas.draw(
+                        &Image {
+                            content_type,
+                            width: width as u32,
+                            height: height as u32,
+                            data: &data,
+                        },
+                        x,
+                        quad.y,
+                        quad.tile as u32,
+                        quad.height as u32,
+                        decoration.color.unwrap_or(text_area.default_color),
+                        text_area.color_glyph_style,
+                        line_clip,
+                    );
+                }
+            }
+
+            if minimap.is_none() {
+                visible |= canvas.draw_text(
+                    &text_area,
+                    integer_scale,
+                    *clip,
+                    &mut emission,
+                    font_system,
+                    cache,
+                );
+            }
+
+            visible |= draw_custom_glyphs(
+                &mut canvas,
+                font_system,
+                cache,
+                &text_area,
+                *clip,
+                CustomGlyphZOrder::AboveText,
+                &mut rasterize_custom_glyph,
+            );
+
+            if !visible {
+                culled_text_areas.push(*index);
+            }
+        }
+        culled_text_areas.sort_unstable();
+
+        self.upload(device, queue, atlas, &canvas, viewport)?;
+        self.canvas = canvas.data;
+        self.drawn = true;
+
+        Ok(PrepareOutput { culled_text_areas })
+    }
+
+    fn upload(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        atlas: &TextAtlas,
+        canvas: &Canvas,
+        viewport: &Viewport,
+    ) -> Result<(), PrepareError> {
+        trace_span!("upload_canvas");
+
+        let size = (canvas.width(), canvas.height());
+
+        if self.texture.as_ref().map(|(_, s, _)| *s) != Some(size) {
+            let old_bytes = self
+                .texture
+                .take()
+                .map_or(0, |(_, (width, height), _)| texture_bytes(width, height));
+            self.memory.release(old_bytes);
+
+            if !self.memory.try_reserve(texture_bytes(size.0, size.1)) {
+                return Err(PrepareError::MemoryBudgetExceeded);
+            }
+
+            let mut label = atlas
+                .cache
+                .generation_label("fallback canvas", format_args!("{}x{}", size.0, size.1));
+            if let Some(name) = &self.label {
+                label = format!("{label} [{name}]");
+            }
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some(&label),
+                size: Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: if atlas.format.is_srgb() {
+                    TextureFormat::Rgba8UnormSrgb
+                } else {
+                    TextureFormat::Rgba8Unorm
+                },
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            let bind_group =
+                atlas
+                    .cache
+                    .create_fallback_bind_group(device, &view, &self.params_buffer);
+
+            self.texture = Some((texture, size, bind_group));
+        }
+
+        let (texture, _, _) = self.texture.as_ref().unwrap();
+
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &canvas.data,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size.0 * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        // Position the canvas in clip space
+        let [left, top] = viewport.to_clip(canvas.bounds.left, canvas.bounds.top);
+        let [right, bottom] = viewport.to_clip(canvas.bounds.right, canvas.bounds.bottom);
+        let rect = [left, top, right, bottom];
+        queue.write_buffer(&self.params_buffer, 0, unsafe {
+            slice::from_raw_parts(rect.as_ptr() as *const u8, mem::size_of_val(&rect))
+        });
+
+        Ok(())
+    }
+
+    /// Clears the prepared canvas, so that nothing is rendered.
+    pub(crate) fn clear(&mut self) {
+        self.drawn = false;
+    }
+
+    pub(crate) fn render(&self, pass: &mut RenderPass<'_>) {
+        self.render_with(&self.pipeline, pass);
+    }
+
+    /// Renders the canvas with `pipeline`, e.g. one for another render target, returning `false`
+    /// if nothing was drawn by the last prepare.
+    pub(crate) fn render_with(&self, pipeline: &RenderPipeline, pass: &mut RenderPass<'_>) -> bool {
+        let Some((_, _, bind_group)) = &self.texture else {
+            return false;
+        };
+        if !self.drawn {
+            return false;
+        }
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..4, 0..1);
+        true
+    }
+
+    pub(crate) fn gpu_memory_usage(&self) -> u64 {
+        self.texture
+            .as_ref()
+            .map_or(0, |(_, (width, height), _)| texture_bytes(*width, *height))
+    }
+}
+
+impl Drop for FallbackRenderer {
+    fn drop(&mut self) {
+        if let Some((_, (width, height), _)) = &self.texture {
+            self.memory.release(texture_bytes(*width, *height));
+        }
+    }
+}
+
+fn texture_bytes(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64 * 4
+}
+
+fn intersect(a: TextBounds, b: TextBounds) -> TextBounds {
+    TextBounds {
+        left: a.left.max(b.left),
+        top: a.top.max(b.top),
+        right: a.right.min(b.right),
+        bottom: a.bottom.min(b.bottom),
+    }
+}
+
+struct Image<'a> {
+    content_type: ContentType,
+    width: u32,
+    height: u32,
+    data: &'a [u8],
+}
+
+/// An RGBA image covering `bounds` on the screen, with colors that aren't premultiplied.
+struct Canvas {
+    data: Vec<u8>,
+    bounds: TextBounds,
+    /// Areas of the screen that aren't drawn to
+    exclusions: Vec<TextBounds>,
+}
+
+impl Canvas {
+    fn width(&self) -> u32 {
+        (self.bounds.right - self.bounds.left) as u32
+    }
+
+    fn height(&self) -> u32 {
+        (self.bounds.bottom - self.bounds.top) as u32
+    }
+
+    /// Draws the text of `text_area`, which was already adjusted for `integer_scale`, clipped to
+    /// `clip` and the exclusions.
+    ///
+    /// Returns `false` if no glyph is visible.
+    fn draw_text(
+        &mut self,
+        text_area: &TextArea,
+        integer_scale: Option<u16>,
+        clip: TextBounds,
+        emission: &mut GlyphEmission,
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+    ) -> bool {
+        let mut visible = false;
+        let mut glyphs = Vec::new();
+
+        for run in visible_runs(text_area) {
+            emission.text_glyphs(&run, text_area, integer_scale, font_system, &mut glyphs);
+
+            for glyph in &glyphs {
+                let Some(image) = cache.get_image(font_system, glyph.physical.cache_key) else {
+                    continue;
+                };
+
+                let content_type = match image.content {
+                    SwashContent::Color => ContentType::Color,
+                    SwashContent::Mask | SwashContent::SubpixelMask => ContentType::Mask,
+                };
+                let (width, height) = (image.placement.width, image.placement.height);
+                let ([x, y], [quad_width, quad_height]) = glyph_quad(
+                    glyph.physical.x,
+                    glyph.physical.y,
+                    glyph.glyph_scale,
+                    [image.placement.left as i16, image.placement.top as i16],
+                    [width as u16, height as u16],
+                );
+
+                visible |= self.draw(
+                    &Image {
+                        content_type,
+                        width,
+                        height,
+                        data: &image.data,
+                    },
+                    x,
+                    y,
+                    quad_width as u32,
+                    quad_height as u32,
+                    glyph.color,
+                    text_area.color_glyph_style,
+                    clip,
+                );
+            }
+        }
+
+        visible
+    }
+
+    /// Draws `image` scaled to `width` by `height` pixels at `x`, `y` on the screen, clipped to
+    /// `clip` and the exclusions. Color images are adjusted by `style`.
+    ///
+    /// Returns `false` if the image was clipped entirely.
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &mut self,
+        image: &Image,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+        style: ColorGlyphStyle,
+        clip: TextBounds,
+    ) -> bool {
+        let clip = intersect(
+            clip,
+            TextBounds {
+                left: x,
+                top: y,
+                right: x.saturating_add(width as i32),
+                bottom: y.saturating_add(height as i32),
+            },
+        );
+        if clip.left >= clip.right || clip.top >= clip.bottom {
+            return false;
+        }
+        let parts = clip.subtract(&self.exclusions);
+
+        let bytes_per_pixel = image.content_type.bytes_per_pixel();
+        let canvas_width = self.width() as usize;
+
+        for clip in &parts {
+            for screen_y in clip.top..clip.bottom {
+                let image_y =
+                    ((screen_y - y) as u64 * image.height as u64 / height as u64) as usize;
+
+                for screen_x in clip.left..clip.right {
+                    let image_x =
+                        ((screen_x - x) as u64 * image.width as u64 / width as u64) as usize;
+                    let offset = (image_y * image.width as usize + image_x) * bytes_per_pixel;
+                    let Some(texel) = image.data.get(offset..offset + bytes_per_pixel) else {
+                        continue;
+                    };
+
+                    let source = match image.content_type {
+                        ContentType::Color => {
+                            let luminance = 0.2126 * texel[0] as f32
+                                + 0.7152 * texel[1] as f32
+                                + 0.0722 * texel[2] as f32;
+                            let saturation = style.saturation.clamp(0.0, 1.0);
+                            let tint = [
+                                style.tint.r(),
+                                style.tint.g(),
+                                style.tint.b(),
+                                style.tint.a(),
+                            ];
+                            [0, 1, 2, 3].map(|channel| {
+                                let value = if channel < 3 {
+                                    luminance + (texel[channel] as f32 - luminance) * saturation
+                                } else {
+                                    texel[channel] as f32
+                                };
+                                (value * tint[channel] as f32 / 255.0).round() as u8
+                            })
+                        }
+                        ContentType::Mask => [
+                            color.r(),
+                            color.g(),
+                            color.b(),
+                            (color.a() as u32 * texel[0] as u32 / 255) as u8,
+                        ],
+                        ContentType::TintedColor => {
+                            let luminance = (0.2126 * texel[0] as f32
+                                + 0.7152 * texel[1] as f32
+                                + 0.0722 * texel[2] as f32)
+                                / 255.0;
+                            [
+                                (color.r() as f32 * luminance).round() as u8,
+                                (color.g() as f32 * luminance).round() as u8,
+                                (color.b() as f32 * luminance).round() as u8,
+                                (color.a() as u32 * texel[3] as u32 / 255) as u8,
+                            ]
+                        }
+                    };
+
+                    let canvas_x = (screen_x - self.bounds.left) as usize;
+                    let canvas_y = (screen_y - self.bounds.top) as usize;
+                    let offset = (canvas_y * canvas_width + canvas_x) * 4;
+                    blend(&mut self.data[offset..offset + 4], source);
+                }
+            }
+        }
+
+        !parts.is_empty()
+    }
+}
+
+/// Blends `source` over `destination`, with colors that aren't premultiplied.
+fn blend(destination: &mut [u8], source: [u8; 4]) {
+    let source_alpha = source[3] as f32 / 255.0;
+    if source_alpha == 0.0 {
+        return;
+    }
+
+    let destination_alpha = destination[3] as f32 / 255.0 * (1.0 - source_alpha);
+    let alpha = source_alpha + destination_alpha;
+
+    for channel in 0..3 {
+        destination[channel] = ((source[channel] as f32 * source_alpha
+            + destination[channel] as f32 * destination_alpha)
+            / alpha)
+            .round() as u8;
+    }
+    destination[3] = (alpha * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attrs, Metrics, PixelSnapping, Resolution, Shaping, TextRenderer};
+    use cosmic_text::fontdb;
+
+    const SCREEN: TextBounds = TextBounds {
+        left: 0,
+        top: 0,
+        right: 400,
+        bottom: 200,
+    };
+
+    fn font_system() -> FontSystem {
+        let mut db = fontdb::Database::new();
+        db.load_font_data(include_bytes!("../examples/Inter-Bold.ttf").to_vec());
+        FontSystem::new_with_locale_and_db("en-US".into(), db)
+    }
+
+    /// Draws the text of `text_area` like the fallback renderer and returns the pixels it covered.
+    fn fallback_pixels(
+        text_area: &TextArea,
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+    ) -> Vec<(i32, i32)> {
+        let mut text_area = text_area.clone();
+        let integer_scale = text_area.integer_scale();
+        if let Some(integer_scale) = integer_scale {
+            text_area.left = text_area.left.round();
+            text_area.top = text_area.top.round();
+            text_area.scale = integer_scale as f32;
+        }
+
+        let mut canvas = Canvas {
+            data: vec![0; (SCREEN.right * SCREEN.bottom * 4) as usize],
+            bounds: SCREEN,
+            exclusions: Vec::new(),
+        };
+        let mut emission = GlyphEmission {
+            snapping: PixelSnapping::default(),
+            char_filter: None,
+            emoji_decomposer: None,
+        };
+        canvas.draw_text(
+            &text_area,
+            integer_scale,
+            SCREEN,
+            &mut emission,
+            font_system,
+            cache,
+        );
+
+        let width = canvas.width() as i32;
+        (0..canvas.height() as i32)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.data[((y * width + x) * 4 + 3) as usize] != 0)
+            .collect()
+    }
+
+    #[test]
+    fn fallback_draws_text_where_prepare_places_it() {
+        let mut font_system = font_system();
+        let mut cache = SwashCache::new();
+        let mut buffer = cosmic_text::Buffer::new(&mut font_system, Metrics::new(18.0, 22.0));
+        buffer.set_size(&mut font_system, Some(120.0), None);
+        buffer.set_text(
+            &mut font_system,
+            "Glyphon renders text, twice!",
+            Attrs::new(),
+            Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut font_system, false);
+
+        for (scale, integer_scaling) in [(1.0, false), (1.5, false), (2.0, true), (2.6, true)] {
+            let text_area =
+                TextArea::new(&buffer, 10.3, 5.6, scale, SCREEN, Color::rgb(255, 255, 255))
+                    .with_integer_scaling(integer_scaling);
+
+            let placed = TextRenderer::prepare_dry_run(
+                &mut font_system,
+                &mut cache,
+                Resolution {
+                    width: SCREEN.right as u32,
+                    height: SCREEN.bottom as u32,
+                },
+                [text_area.clone()],
+                |_| None,
+            );
+            let drawn = fallback_pixels(&text_area, &mut font_system, &mut cache);
+            assert!(!placed.is_empty());
+
+            let contains = |rect: &TextBounds, &(x, y): &(i32, i32)| {
+                rect.left <= x && x < rect.right && rect.top <= y && y < rect.bottom
+            };
+            for pixel in &drawn {
+                assert!(
+                    placed.iter().any(|glyph| contains(&glyph.rect, pixel)),
+                    "pixel {pixel:?} drawn outside of the placed glyphs at scale {scale}"
+                );
+            }
+            for glyph in &placed {
+                assert!(
+                    drawn.iter().any(|pixel| contains(&glyph.rect, pixel)),
+                    "{glyph:?} not drawn at scale {scale}"
+                );
+            }
+
+            // Glyph images are cropped to their outlines with a transparent border of at most a
+            // pixel, so the drawn pixels span the placed glyphs up to that border
+            let placed_extent = placed
+                .iter()
+                .map(|glyph| glyph.rect)
+                .reduce(|a, b| TextBounds {
+                    left: a.left.min(b.left),
+                    top: a.top.min(b.top),
+                    right: a.right.max(b.right),
+                    bottom: a.bottom.max(b.bottom),
+                });
+            let drawn_extent = drawn
+                .iter()
+                .fold(None, |extent: Option<TextBounds>, &(x, y)| {
+                    let pixel = TextBounds {
+                        left: x,
+                        top: y,
+                        right: x + 1,
+                        bottom: y + 1,
+                    };
+                    Some(extent.map_or(pixel, |extent| TextBounds {
+                        left: extent.left.min(pixel.left),
+                        top: extent.top.min(pixel.top),
+                        right: extent.right.max(pixel.right),
+                        bottom: extent.bottom.max(pixel.bottom),
+                    }))
+                });
+            let (Some(drawn_extent), Some(placed_extent)) = (drawn_extent, placed_extent) else {
+                panic!("nothing drawn at scale {scale}");
+            };
+            let border = text_area.integer_scale().unwrap_or(1) as i32;
+            let insets = [
+                drawn_extent.left - placed_extent.left,
+                drawn_extent.top - placed_extent.top,
+                placed_extent.right - drawn_extent.right,
+                placed_extent.bottom - drawn_extent.bottom,
+            ];
+            assert!(
+                insets.iter().all(|inset| (0..=border).contains(inset)),
+                "{drawn_extent:?} doesn't span {placed_extent:?} at scale {scale}"
+            );
+        }
+    }
+}
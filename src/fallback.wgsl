@@ -0,0 +1,34 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct Params {
+    // The left, top, right and bottom edges of the canvas in clip space
+    rect: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var canvas_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var canvas_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> VertexOutput {
+    let corner = vec2<f32>(f32(vertex_idx & 1u), f32((vertex_idx >> 1u) & 1u));
+
+    var vert_output: VertexOutput;
+    vert_output.position = vec4<f32>(mix(params.rect.xy, params.rect.zw, corner), 0.0, 1.0);
+    vert_output.uv = corner;
+
+    return vert_output;
+}
+
+@fragment
+fn fs_main(in_frag: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(canvas_texture, canvas_sampler, in_frag.uv, 0.0);
+}
@@ -0,0 +1,119 @@
+use crate::{fontdb, FontSystem};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// A pool of [`FontSystem`]s sharing the same font database, to shape text on multiple threads.
+///
+/// A `FontSystem` can only be used by one thread at a time, and creating one loads every system
+/// font. The pool loads the fonts once and creates further font systems from a copy of the
+/// database, which only copies the list of faces and shares the data of loaded fonts. Font
+/// systems are returned to the pool when they are dropped, keeping the fonts they parsed cached
+/// for the next thread.
+///
+/// All font systems of a pool use the same font IDs, so buffers shaped with any of them can be
+/// prepared with another one, e.g. to shape text areas on worker threads and prepare them with
+/// [`crate::TextRenderer::prepare`] on the render thread.
+pub struct FontSystemPool {
+    locale: String,
+    db: fontdb::Database,
+    idle: Mutex<Vec<FontSystem>>,
+}
+
+impl FontSystemPool {
+    /// Creates a new pool of font systems with the system locale and system fonts, loading the
+    /// fonts once.
+    pub fn new() -> Self {
+        Self::from_font_system(FontSystem::new())
+    }
+
+    /// Creates a new pool of font systems that share the locale and the fonts of `font_system`,
+    /// which becomes the first font system of the pool.
+    ///
+    /// Fonts have to be loaded into `font_system` before creating the pool, since the font
+    /// systems of the pool don't share fonts that are loaded later.
+    pub fn from_font_system(font_system: FontSystem) -> Self {
+        let locale = font_system.locale().to_owned();
+        let db = font_system.db().clone();
+
+        Self {
+            locale,
+            db,
+            idle: Mutex::new(vec![font_system]),
+        }
+    }
+
+    /// Returns a font system that isn't in use by another thread, creating one if all of them
+    /// are in use. The font system is returned to the pool when it is dropped.
+    pub fn get(&self) -> PooledFontSystem<'_> {
+        let font_system = self.idle().pop().unwrap_or_else(|| {
+            FontSystem::new_with_locale_and_db(self.locale.clone(), self.db.clone())
+        });
+
+        PooledFontSystem {
+            pool: self,
+            font_system: Some(font_system),
+        }
+    }
+
+    /// Returns the font database shared by the font systems of the pool.
+    pub fn db(&self) -> &fontdb::Database {
+        &self.db
+    }
+
+    /// Returns the locale of the font systems of the pool.
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Returns the number of font systems that aren't in use.
+    pub fn idle_count(&self) -> usize {
+        self.idle().len()
+    }
+
+    /// Drops the font systems that aren't in use, releasing the fonts they cached.
+    pub fn shrink(&self) {
+        self.idle().clear();
+    }
+
+    fn idle(&self) -> MutexGuard<'_, Vec<FontSystem>> {
+        // The list is only pushed to and popped from, so it stays valid if a thread panicked
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for FontSystemPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`FontSystem`] borrowed from a [`FontSystemPool`] with [`FontSystemPool::get`], which is
+/// returned to the pool when dropped.
+pub struct PooledFontSystem<'a> {
+    pool: &'a FontSystemPool,
+    font_system: Option<FontSystem>,
+}
+
+impl Deref for PooledFontSystem<'_> {
+    type Target = FontSystem;
+
+    fn deref(&self) -> &FontSystem {
+        self.font_system.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledFontSystem<'_> {
+    fn deref_mut(&mut self) -> &mut FontSystem {
+        self.font_system.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledFontSystem<'_> {
+    fn drop(&mut self) {
+        if let Some(font_system) = self.font_system.take() {
+            self.pool.idle().push(font_system);
+        }
+    }
+}
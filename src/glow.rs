@@ -0,0 +1,441 @@
+use crate::{
+    text_atlas::MemoryTracker, Cache, Color, ColorMode, PrepareError, TextAtlas, TextRenderer,
+    Viewport,
+};
+use std::{borrow::Cow, mem, num::NonZeroU64, slice, sync::Arc};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, CompareFunction,
+    DepthStencilState, Device, Extent3d, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPass,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+/// The largest number of taps of the blur on each side of the center.
+const MAX_TAPS: usize = 15;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Params {
+    tap_step: [i32; 2],
+    taps: u32,
+    tinted: u32,
+    color: [f32; 4],
+    weights: [f32; MAX_TAPS + 1],
+}
+
+/// Draws a soft glow behind the text of a [`TextRenderer`], e.g. to keep HUD text readable on
+/// busy backgrounds.
+///
+/// [`GlowRenderer::prepare`] renders the prepared glyphs of a renderer into an offscreen texture
+/// and blurs it with a separable Gaussian blur. [`GlowRenderer::render`] then composites the glow
+/// into a render pass, before the text itself is rendered into the same pass with
+/// [`TextRenderer::render`]. The offscreen textures have the format of the atlas and the
+/// resolution of the viewport, and count towards the memory budget of the atlas.
+pub struct GlowRenderer {
+    layout: BindGroupLayout,
+    blur_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+    /// The parameters of the horizontal blur, the vertical blur and the composite pass
+    params_buffers: [Buffer; 3],
+    targets: Option<Targets>,
+    format: TextureFormat,
+    color_mode: ColorMode,
+    radius: f32,
+    color: Option<Color>,
+    intensity: f32,
+    /// Whether any text was drawn by the last prepare
+    drawn: bool,
+    memory: Arc<MemoryTracker>,
+}
+
+/// The offscreen textures of a [`GlowRenderer`]. The text is rendered into `text`, blurred
+/// horizontally into `blurred` and vertically back into `text`.
+struct Targets {
+    size: (u32, u32),
+    text: TextureView,
+    blurred: TextureView,
+    /// The bind groups of the horizontal blur, the vertical blur and the composite pass
+    bind_groups: [BindGroup; 3],
+}
+
+impl GlowRenderer {
+    /// Creates a new `GlowRenderer` for text rendered with `atlas`.
+    ///
+    /// `multisample` and `depth_stencil` have to match the render pass the glow is composited
+    /// into. The glow doesn't write or test depth.
+    pub fn new(
+        atlas: &TextAtlas,
+        device: &Device,
+        multisample: MultisampleState,
+        depth_stencil: Option<DepthStencilState>,
+    ) -> Self {
+        let cache = &atlas.cache;
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&cache.label("glow shader")),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("glow.wgsl"))),
+        });
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(mem::size_of::<Params>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+            label: Some(&cache.label("glow bind group layout")),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline =
+            |label: &str,
+             entry_point: &str,
+             blend: Option<BlendState>,
+             multisample: MultisampleState,
+             depth_stencil: Option<DepthStencilState>| {
+                device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some(&cache.label(label)),
+                    layout: Some(&pipeline_layout),
+                    vertex: VertexState {
+                        module: &shader,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(FragmentState {
+                        module: &shader,
+                        entry_point: Some(entry_point),
+                        targets: &[Some(ColorTargetState {
+                            format: atlas.format,
+                            blend,
+                            write_mask: ColorWrites::default(),
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil,
+                    multisample,
+                    multiview: None,
+                    cache: None,
+                })
+            };
+        let blur_pipeline = create_pipeline(
+            "glow blur pipeline",
+            "fs_blur",
+            None,
+            MultisampleState::default(),
+            None,
+        );
+        let composite_pipeline = create_pipeline(
+            "glow composite pipeline",
+            "fs_composite",
+            Some(BlendState::ALPHA_BLENDING),
+            multisample,
+            depth_stencil.map(|depth_stencil| DepthStencilState {
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Always,
+                ..depth_stencil
+            }),
+        );
+
+        let params_buffers = [
+            "glow horizontal blur",
+            "glow vertical blur",
+            "glow composite",
+        ]
+        .map(|label| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(&cache.label(label)),
+                size: mem::size_of::<Params>() as u64,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        });
+
+        Self {
+            layout,
+            blur_pipeline,
+            composite_pipeline,
+            params_buffers,
+            targets: None,
+            format: atlas.format,
+            color_mode: atlas.color_mode,
+            radius: 4.0,
+            color: None,
+            intensity: 1.0,
+            drawn: false,
+            memory: atlas.memory.clone(),
+        }
+    }
+
+    /// Sets the standard deviation of the blur in physical pixels. Defaults to `4.0`.
+    ///
+    /// The blur covers up to 15 pixels on each side of the text, so larger radii are cut off.
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.max(0.0);
+    }
+
+    /// Returns the standard deviation of the blur in physical pixels.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Sets the color of the glow, or `None` to use the color of the text. Defaults to `None`.
+    pub fn set_color(&mut self, color: Option<Color>) {
+        self.color = color;
+    }
+
+    /// Returns the color of the glow, or `None` if it uses the color of the text.
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    /// Sets the factor the opacity of the glow is multiplied by. Values above `1.0` make the glow
+    /// stronger at the cost of a harder edge. Defaults to `1.0`.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    /// Returns the factor the opacity of the glow is multiplied by.
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Records the passes that render the glyphs prepared by `renderer` and blur them into
+    /// `encoder`.
+    ///
+    /// This has to be called after `renderer` was prepared, and before the glow is composited
+    /// with [`GlowRenderer::render`]. The offscreen textures are recreated whenever the
+    /// resolution of `viewport` changes.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        renderer: &TextRenderer,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+    ) -> Result<(), PrepareError> {
+        trace_span!("prepare_glow");
+
+        let resolution = viewport.resolution();
+        let size = (resolution.width.max(1), resolution.height.max(1));
+        let targets = match self.targets.take() {
+            Some(targets) if targets.size == size => targets,
+            old => {
+                if let Some(old) = old {
+                    self.memory.release(self.targets_bytes(old.size));
+                }
+                if !self.memory.try_reserve(self.targets_bytes(size)) {
+                    self.drawn = false;
+                    return Err(PrepareError::MemoryBudgetExceeded);
+                }
+                self.create_targets(device, &atlas.cache, size)
+            }
+        };
+
+        self.write_params(queue);
+
+        let mut pass = begin_pass(encoder, "glow text pass", &targets.text);
+        self.drawn = renderer.draw_offscreen(device, atlas, viewport, &mut pass);
+        drop(pass);
+
+        if self.drawn {
+            for (index, target) in [&targets.blurred, &targets.text].into_iter().enumerate() {
+                let mut pass = begin_pass(encoder, "glow blur pass", target);
+                pass.set_pipeline(&self.blur_pipeline);
+                pass.set_bind_group(0, &targets.bind_groups[index], &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+
+        self.targets = Some(targets);
+
+        Ok(())
+    }
+
+    /// Composites the glow prepared by the last call to [`GlowRenderer::prepare`] into `pass`.
+    ///
+    /// Call this before rendering the text with [`TextRenderer::render`], so that the glow is
+    /// drawn behind it. The render target has to have the resolution of the viewport the glow
+    /// was prepared with.
+    pub fn render(&self, pass: &mut RenderPass<'_>) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        if !self.drawn {
+            return;
+        }
+
+        pass.set_pipeline(&self.composite_pipeline);
+        pass.set_bind_group(0, &targets.bind_groups[2], &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Returns the number of bytes of GPU memory used by the offscreen textures.
+    pub fn gpu_memory_usage(&self) -> u64 {
+        self.targets
+            .as_ref()
+            .map_or(0, |targets| self.targets_bytes(targets.size))
+    }
+
+    fn targets_bytes(&self, (width, height): (u32, u32)) -> u64 {
+        let bytes_per_pixel = self.format.block_copy_size(None).unwrap_or(4) as u64;
+        2 * width as u64 * height as u64 * bytes_per_pixel
+    }
+
+    fn create_targets(&self, device: &Device, cache: &Cache, size: (u32, u32)) -> Targets {
+        let create_texture = |label: &str| {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some(&cache.label(label)),
+                size: Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: self.format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            texture.create_view(&TextureViewDescriptor::default())
+        };
+        let text = create_texture("glow text texture");
+        let blurred = create_texture("glow blurred texture");
+
+        let sources = [&text, &blurred, &text];
+        let bind_groups = [0, 1, 2].map(|index| {
+            device.create_bind_group(&BindGroupDescriptor {
+                layout: &self.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(sources[index]),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: self.params_buffers[index].as_entire_binding(),
+                    },
+                ],
+                label: Some(&cache.label("glow bind group")),
+            })
+        });
+
+        Targets {
+            size,
+            text,
+            blurred,
+            bind_groups,
+        }
+    }
+
+    fn write_params(&self, queue: &Queue) {
+        // Sample up to three standard deviations on each side of the center
+        let taps = ((self.radius * 3.0).ceil() as usize).min(MAX_TAPS);
+        let mut weights = [0.0; MAX_TAPS + 1];
+        for (tap, weight) in weights.iter_mut().enumerate().take(taps + 1) {
+            *weight = if self.radius > 0.0 {
+                (-(tap as f32).powi(2) / (2.0 * self.radius.powi(2))).exp()
+            } else {
+                1.0
+            };
+        }
+        let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+        weights.iter_mut().for_each(|weight| *weight /= total);
+
+        let color = self.color.unwrap_or(Color::rgb(255, 255, 255));
+        let channel = |c: u8| {
+            let c = c as f32 / 255.0;
+            match self.color_mode {
+                ColorMode::Accurate => srgb_to_linear(c),
+                ColorMode::Web | ColorMode::Auto => c,
+            }
+        };
+        let composite_color = [
+            channel(color.r()),
+            channel(color.g()),
+            channel(color.b()),
+            color.a() as f32 / 255.0 * self.intensity,
+        ];
+
+        for (buffer, tap_step) in self.params_buffers.iter().zip([[1, 0], [0, 1], [0, 0]]) {
+            let params = Params {
+                tap_step,
+                taps: taps as u32,
+                tinted: self.color.is_some() as u32,
+                color: composite_color,
+                weights,
+            };
+            queue.write_buffer(buffer, 0, unsafe {
+                slice::from_raw_parts(
+                    &params as *const Params as *const u8,
+                    mem::size_of::<Params>(),
+                )
+            });
+        }
+    }
+}
+
+impl Drop for GlowRenderer {
+    fn drop(&mut self) {
+        if let Some(targets) = &self.targets {
+            self.memory.release(self.targets_bytes(targets.size));
+        }
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut CommandEncoder,
+    label: &str,
+    target: &TextureView,
+) -> RenderPass<'a> {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,63 @@
+struct Params {
+    // The distance between the taps of the blur in texels
+    tap_step: vec2<i32>,
+    // The number of taps of the blur on each side of the center
+    taps: u32,
+    // Whether the glow is drawn in `color` instead of the color of the text
+    tinted: u32,
+    // The color of the glow, with the alpha multiplied by its intensity
+    color: vec4<f32>,
+    // The weights of the taps of the blur, starting at the center
+    weights: array<vec4<f32>, 4>,
+};
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> params: Params;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole target
+    let corner = vec2<f32>(f32((vertex_idx << 1u) & 2u), f32(vertex_idx & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn load(coords: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source_texture));
+    if any(coords < vec2<i32>(0)) || any(coords >= size) {
+        return vec4<f32>(0.0);
+    }
+    return textureLoad(source_texture, coords, 0);
+}
+
+fn weight(tap: u32) -> f32 {
+    return params.weights[tap / 4u][tap % 4u];
+}
+
+@fragment
+fn fs_blur(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let center = vec2<i32>(position.xy);
+    var color = load(center) * weight(0u);
+    for (var tap = 1u; tap <= params.taps; tap++) {
+        let offset = params.tap_step * i32(tap);
+        color += (load(center - offset) + load(center + offset)) * weight(tap);
+    }
+    return color;
+}
+
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let glow = load(vec2<i32>(position.xy));
+    if glow.a <= 0.0 {
+        discard;
+    }
+
+    // The text was blended onto a transparent texture, so its color is premultiplied
+    var color = glow.rgb / glow.a;
+    if params.tinted != 0u {
+        color = params.color.rgb;
+    }
+    return vec4<f32>(color, min(glow.a * params.color.a, 1.0));
+}
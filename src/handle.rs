@@ -0,0 +1,109 @@
+use crate::{text_render::GlyphonCacheKey, ContentType, GpuCacheStatus, TextAtlas};
+use wgpu::TextureView;
+
+/// A weak reference to a glyph cached in a [`TextAtlas`], returned by
+/// [`crate::TextRenderer::glyph_handles`].
+///
+/// Engines that build their own draw lists can keep handles across frames and resolve them to the
+/// current location of their glyphs with [`AtlasGlyphHandle::resolve`]. A handle doesn't keep its
+/// glyph in the atlas: once the glyph is evicted, the handle resolves to `None`, even if the same
+/// glyph is cached again later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AtlasGlyphHandle {
+    pub(crate) key: GlyphonCacheKey,
+    /// The generation of the glyph when the handle was created, which changes when the glyph is
+    /// evicted and cached again
+    pub(crate) generation: u64,
+}
+
+/// A texture of a [`TextAtlas`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtlasTexture {
+    /// The texture of glyphs with [`ContentType::Mask`], with a single channel
+    Mask,
+    /// The texture of color glyphs, in RGBA
+    Color,
+    /// The BC7 compressed texture created by [`TextAtlas::compress_color_atlas`]
+    CompressedColor,
+}
+
+/// The location of the image of a glyph in a [`TextAtlas`], resolved from an
+/// [`AtlasGlyphHandle`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasGlyph {
+    /// The texture containing the image, see [`TextAtlas::texture_view`]
+    pub texture: AtlasTexture,
+    /// The width and height of the texture in texels, which changes when the atlas grows
+    pub texture_size: u32,
+    /// The position of the top left corner of the image in the texture, in texels
+    pub uv: [u16; 2],
+    /// The width and height of the image in texels
+    pub size: [u16; 2],
+    /// The distance from the position of the glyph to the left edge of the image
+    pub left: i16,
+    /// The distance from the position of the glyph up to the top edge of the image
+    pub top: i16,
+    /// The type of image data of the glyph
+    pub content_type: ContentType,
+}
+
+impl AtlasGlyphHandle {
+    /// Returns the current location of the glyph in `atlas`, or `None` if it was evicted.
+    ///
+    /// Resolving a handle doesn't mark its glyph as in use, so the glyph may be evicted by the
+    /// next call to `prepare` unless a renderer that prepared it is rendered in the same frame.
+    pub fn resolve(&self, atlas: &TextAtlas) -> Option<AtlasGlyph> {
+        let (texture, texture_size, details) = [
+            (AtlasTexture::Mask, &atlas.mask_atlas),
+            (AtlasTexture::Color, &atlas.color_atlas),
+        ]
+        .into_iter()
+        .find_map(|(texture, inner)| {
+            Some((texture, inner.size, inner.glyph_cache.peek(&self.key)?))
+        })
+        .or_else(|| {
+            let page = atlas.compressed_color.as_ref()?;
+            Some((
+                AtlasTexture::CompressedColor,
+                page.size,
+                page.glyphs.get(&self.key)?,
+            ))
+        })?;
+
+        if details.generation != self.generation {
+            return None;
+        }
+        let GpuCacheStatus::InAtlas { x, y, content_type } = details.gpu_cache else {
+            return None;
+        };
+
+        Some(AtlasGlyph {
+            texture,
+            texture_size,
+            uv: [x, y],
+            size: [details.width, details.height],
+            left: details.left,
+            top: details.top,
+            content_type,
+        })
+    }
+}
+
+impl TextAtlas {
+    /// Returns the view of a texture of the atlas, to sample the glyphs resolved from
+    /// [`AtlasGlyphHandle`]s, or `None` for [`AtlasTexture::CompressedColor`] if the color atlas
+    /// wasn't compressed.
+    ///
+    /// The views are replaced when the atlas grows or is compressed, so they have to be fetched
+    /// again after every call to `prepare`.
+    pub fn texture_view(&self, texture: AtlasTexture) -> Option<&TextureView> {
+        match texture {
+            AtlasTexture::Mask => Some(&self.mask_atlas.texture_view),
+            AtlasTexture::Color => Some(&self.color_atlas.texture_view),
+            AtlasTexture::CompressedColor => self
+                .compressed_color
+                .as_ref()
+                .map(|page| &page.texture_view),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{Attrs, AttrsList, AttrsOwned, Buffer, Color};
+use std::ops::Range;
+
+/// Applies per-span attributes (e.g. syntax highlighting colors) to a [`Buffer`].
+///
+/// Span ranges are byte offsets into the whole text of the buffer, with lines joined by their
+/// original line endings. The highlighter remembers the spans it applied to each line and only
+/// updates lines whose spans changed, so unchanged lines keep their shaping.
+#[derive(Debug, Default)]
+pub struct SpanHighlighter {
+    default_attrs: Option<AttrsOwned>,
+    lines: Vec<Vec<(Range<usize>, AttrsOwned)>>,
+}
+
+impl SpanHighlighter {
+    /// Creates a new `SpanHighlighter`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `spans` to `buffer`, using `default_attrs` for text outside of any span.
+    ///
+    /// Returns the number of lines that were updated. Updated lines need to be shaped again, e.g.
+    /// with [`Buffer::shape_until_scroll`].
+    pub fn apply<'a>(
+        &mut self,
+        buffer: &mut Buffer,
+        default_attrs: Attrs,
+        spans: impl IntoIterator<Item = (Range<usize>, Attrs<'a>)>,
+    ) -> usize {
+        let line_starts = line_starts(buffer);
+        let mut new_lines: Vec<Vec<(Range<usize>, AttrsOwned)>> =
+            vec![Vec::new(); buffer.lines.len()];
+
+        for (range, attrs) in spans {
+            let first_line = line_starts
+                .partition_point(|&start| start <= range.start)
+                .saturating_sub(1);
+
+            for (line_i, line) in buffer.lines.iter().enumerate().skip(first_line) {
+                let line_start = line_starts[line_i];
+                if line_start >= range.end {
+                    break;
+                }
+
+                let start = range.start.saturating_sub(line_start);
+                let end = (range.end - line_start).min(line.text().len());
+                if start < end {
+                    new_lines[line_i].push((start..end, AttrsOwned::new(attrs)));
+                }
+            }
+        }
+
+        let default_attrs_owned = AttrsOwned::new(default_attrs);
+        let defaults_changed = self.default_attrs.as_ref() != Some(&default_attrs_owned);
+        self.default_attrs = Some(default_attrs_owned);
+        self.lines.resize(buffer.lines.len(), Vec::new());
+
+        let mut updated = 0;
+        for ((line, old_spans), new_spans) in buffer
+            .lines
+            .iter_mut()
+            .zip(self.lines.iter_mut())
+            .zip(new_lines)
+        {
+            if !defaults_changed && *old_spans == new_spans {
+                continue;
+            }
+
+            let mut attrs_list = AttrsList::new(default_attrs);
+            for (range, attrs) in &new_spans {
+                attrs_list.add_span(range.clone(), attrs.as_attrs());
+            }
+
+            if line.set_attrs_list(attrs_list) {
+                updated += 1;
+            }
+            *old_spans = new_spans;
+        }
+
+        if updated > 0 {
+            buffer.set_redraw(true);
+        }
+
+        updated
+    }
+
+    /// Applies colored `spans` to `buffer`, using `default_attrs` for everything else.
+    ///
+    /// This is a convenience wrapper around [`SpanHighlighter::apply`].
+    pub fn apply_colors(
+        &mut self,
+        buffer: &mut Buffer,
+        default_attrs: Attrs,
+        spans: impl IntoIterator<Item = (Range<usize>, Color)>,
+    ) -> usize {
+        self.apply(
+            buffer,
+            default_attrs,
+            spans
+                .into_iter()
+                .map(|(range, color)| (range, default_attrs.color(color))),
+        )
+    }
+
+    /// Forgets all previously applied spans, forcing every line to be updated on the next call to
+    /// [`SpanHighlighter::apply`].
+    pub fn reset(&mut self) {
+        self.default_attrs = None;
+        self.lines.clear();
+    }
+}
+
+fn line_starts(buffer: &Buffer) -> Vec<usize> {
+    let mut offset = 0;
+    buffer
+        .lines
+        .iter()
+        .map(|line| {
+            let start = offset;
+            offset += line.text().len() + line.ending().as_str().len();
+            start
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FontSystem, Metrics, Shaping};
+    use cosmic_text::fontdb;
+
+    const RED: Color = Color::rgb(255, 0, 0);
+    const BLUE: Color = Color::rgb(0, 0, 255);
+
+    fn buffer(text: &str) -> Buffer {
+        let mut db = fontdb::Database::new();
+        db.load_font_data(include_bytes!("../examples/Inter-Bold.ttf").to_vec());
+        let mut font_system = FontSystem::new_with_locale_and_db("en-US".into(), db);
+        let mut buffer = Buffer::new_empty(Metrics::new(10.0, 20.0));
+        buffer.set_text(&mut font_system, text, Attrs::new(), Shaping::Basic);
+        buffer
+    }
+
+    /// Returns the color of every byte of every line, `None` for the default attributes.
+    fn colors(buffer: &Buffer) -> Vec<Vec<Option<Color>>> {
+        buffer
+            .lines
+            .iter()
+            .map(|line| {
+                (0..line.text().len())
+                    .map(|index| line.attrs_list().get_span(index).color_opt)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spans_across_lines() {
+        let mut buffer = buffer("abc\ndef\r\nghi\njkl");
+        let mut highlighter = SpanHighlighter::new();
+        let updated =
+            highlighter.apply_colors(&mut buffer, Attrs::new(), [(2..6, RED), (8..12, BLUE)]);
+        assert_eq!(updated, 3);
+
+        let (r, b) = (Some(RED), Some(BLUE));
+        assert_eq!(
+            colors(&buffer),
+            [
+                vec![None, None, r],
+                vec![r, r, None],
+                // The span starts in the `\r\n` ending of the line before
+                vec![b, b, b],
+                vec![None, None, None],
+            ]
+        );
+    }
+
+    #[test]
+    fn clamps_spans_to_the_text() {
+        let mut buffer = buffer("abc\ndef");
+        let mut highlighter = SpanHighlighter::new();
+        highlighter.apply_colors(&mut buffer, Attrs::new(), [(5..100, RED), (50..60, BLUE)]);
+        assert_eq!(
+            colors(&buffer),
+            [vec![None, None, None], vec![None, Some(RED), Some(RED)]]
+        );
+    }
+
+    #[test]
+    fn only_updates_changed_lines() {
+        let mut buffer = buffer("abc\ndef\nghi");
+        let mut highlighter = SpanHighlighter::new();
+        // Lines that keep their attributes aren't counted
+        assert_eq!(
+            highlighter.apply_colors(&mut buffer, Attrs::new(), [(0..1, RED)]),
+            1
+        );
+        assert_eq!(
+            highlighter.apply_colors(&mut buffer, Attrs::new(), [(0..1, RED)]),
+            0
+        );
+        assert_eq!(
+            highlighter.apply_colors(&mut buffer, Attrs::new(), [(0..1, RED), (5..6, BLUE)]),
+            1
+        );
+        assert_eq!(colors(&buffer)[1], [None, Some(BLUE), None]);
+
+        // Changing the default attributes updates every line
+        let defaults = Attrs::new().color(BLUE);
+        assert_eq!(
+            highlighter.apply_colors(&mut buffer, defaults, [(0..1, RED), (5..6, BLUE)]),
+            3
+        );
+
+        highlighter.reset();
+        assert_eq!(
+            highlighter.apply_colors(&mut buffer, defaults, [(0..1, RED)]),
+            1
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{
+    Attrs, Buffer, Color, FontSystem, Metrics, PrepareError, PreparedSnapshot, RenderError,
+    Resolution, RestoreError, Shaping, SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer,
+    Viewport,
+};
+use wgpu::{DepthStencilState, Device, MultisampleState, Queue, RenderPass, TextureFormat};
+
+/// A piece of text with a position and a color that keeps its prepared glyphs between frames,
+/// rendered with a [`LabelRenderer`].
+///
+/// Changing the text, position, bounds or color of a label marks it as dirty, and only dirty
+/// labels are laid out again by [`LabelRenderer::prepare`]. Hiding a label doesn't discard its
+/// prepared glyphs.
+pub struct Label {
+    buffer: Buffer,
+    left: f32,
+    top: f32,
+    bounds: TextBounds,
+    color: Color,
+    visible: bool,
+    /// The glyphs of the label as prepared by the last call to `LabelRenderer::prepare`, or
+    /// `None` if the label is dirty
+    prepared: Option<PreparedSnapshot>,
+}
+
+impl Label {
+    /// Creates an empty, visible label at the origin with the given metrics.
+    pub fn new(font_system: &mut FontSystem, metrics: Metrics) -> Self {
+        Self {
+            buffer: Buffer::new(font_system, metrics),
+            left: 0.0,
+            top: 0.0,
+            bounds: TextBounds::default(),
+            color: Color::rgb(255, 255, 255),
+            visible: true,
+            prepared: None,
+        }
+    }
+
+    /// Replaces the text of the label and shapes it.
+    pub fn set_text(
+        &mut self,
+        font_system: &mut FontSystem,
+        text: &str,
+        attrs: Attrs,
+        shaping: Shaping,
+    ) {
+        self.buffer.set_text(font_system, text, attrs, shaping);
+        self.buffer.shape_until_scroll(font_system, false);
+        self.prepared = None;
+    }
+
+    /// Sets the size the text of the label is wrapped and clipped to, and shapes it again.
+    pub fn set_size(
+        &mut self,
+        font_system: &mut FontSystem,
+        width: Option<f32>,
+        height: Option<f32>,
+    ) {
+        self.buffer.set_size(font_system, width, height);
+        self.buffer.shape_until_scroll(font_system, false);
+        self.prepared = None;
+    }
+
+    /// Sets the position of the top left corner of the label in physical pixels.
+    pub fn set_position(&mut self, left: f32, top: f32) {
+        if (self.left, self.top) != (left, top) {
+            self.left = left;
+            self.top = top;
+            self.prepared = None;
+        }
+    }
+
+    /// Returns the position of the top left corner of the label.
+    pub fn position(&self) -> (f32, f32) {
+        (self.left, self.top)
+    }
+
+    /// Sets the bounds the label is clipped to. Defaults to [`TextBounds::default`].
+    pub fn set_bounds(&mut self, bounds: TextBounds) {
+        if self.bounds != bounds {
+            self.bounds = bounds;
+            self.prepared = None;
+        }
+    }
+
+    /// Returns the bounds the label is clipped to.
+    pub fn bounds(&self) -> TextBounds {
+        self.bounds
+    }
+
+    /// Sets the default color of the text of the label. Defaults to white.
+    pub fn set_color(&mut self, color: Color) {
+        if self.color != color {
+            self.color = color;
+            self.prepared = None;
+        }
+    }
+
+    /// Returns the default color of the text of the label.
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Shows or hides the label, keeping its prepared glyphs.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Returns `true` if the label is rendered.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Returns the buffer containing the text of the label.
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Returns the buffer containing the text of the label to change it directly, marking the
+    /// label as dirty. The buffer has to be shaped again after it was changed.
+    pub fn buffer_mut(&mut self) -> &mut Buffer {
+        self.prepared = None;
+        &mut self.buffer
+    }
+
+    /// Returns `true` if the label has to be laid out again by the next call to
+    /// [`LabelRenderer::prepare`].
+    pub fn is_dirty(&self) -> bool {
+        self.prepared.is_none()
+    }
+
+    fn text_area(&self) -> TextArea<'_> {
+        TextArea::new(
+            &self.buffer,
+            self.left,
+            self.top,
+            1.0,
+            self.bounds,
+            self.color,
+        )
+    }
+}
+
+/// Renders [`Label`]s, laying out only the labels that changed and drawing all visible labels
+/// with a single call to [`LabelRenderer::render`].
+pub struct LabelRenderer {
+    renderer: TextRenderer,
+    /// The resolution the prepared glyphs of the labels were clipped to
+    resolution: Resolution,
+}
+
+impl LabelRenderer {
+    /// Creates a new `LabelRenderer`.
+    pub fn new(
+        atlas: &mut TextAtlas,
+        device: &Device,
+        multisample: MultisampleState,
+        depth_stencil: Option<DepthStencilState>,
+    ) -> Self {
+        Self {
+            renderer: TextRenderer::new(atlas, device, multisample, depth_stencil),
+            resolution: Resolution {
+                width: 0,
+                height: 0,
+            },
+        }
+    }
+
+    /// Prepares the visible labels for rendering.
+    ///
+    /// Dirty labels are laid out again, while the glyphs of other labels are reused and only
+    /// marked as in use in the atlas. All labels are laid out again when the viewport was
+    /// resized, or if glyphs of a label were evicted from the atlas.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare<'a>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        labels: impl IntoIterator<Item = &'a mut Label>,
+        cache: &mut SwashCache,
+    ) -> Result<(), PrepareError> {
+        trace_span!("prepare_labels");
+
+        let mut labels: Vec<&mut Label> =
+            labels.into_iter().filter(|label| label.visible).collect();
+
+        // Snapshots aren't supported by the fallback, which has to prepare everything again
+        if self.renderer.uses_fallback() {
+            return self
+                .renderer
+                .prepare(
+                    device,
+                    queue,
+                    font_system,
+                    atlas,
+                    viewport,
+                    labels.iter().map(|label| label.text_area()),
+                    cache,
+                )
+                .map(|_| ());
+        }
+
+        if self.resolution != viewport.resolution() {
+            self.resolution = viewport.resolution();
+            for label in &mut labels {
+                label.prepared = None;
+            }
+        }
+
+        let mut snapshot = PreparedSnapshot::empty();
+        for label in &mut labels {
+            if label.prepared.is_none() {
+                self.renderer.prepare(
+                    device,
+                    queue,
+                    font_system,
+                    atlas,
+                    viewport,
+                    [label.text_area()],
+                    cache,
+                )?;
+                label.prepared = Some(self.renderer.snapshot());
+            }
+            snapshot.extend(label.prepared.as_ref().unwrap());
+        }
+
+        match self.renderer.restore(device, queue, atlas, &snapshot) {
+            Ok(()) => Ok(()),
+            Err(RestoreError::GlyphEvicted) => {
+                // Preparing a label may have evicted the glyphs of another one, so prepare all
+                // labels at once to keep all of their glyphs in the atlas
+                for label in &mut labels {
+                    label.prepared = None;
+                }
+                self.renderer
+                    .prepare(
+                        device,
+                        queue,
+                        font_system,
+                        atlas,
+                        viewport,
+                        labels.iter().map(|label| label.text_area()),
+                        cache,
+                    )
+                    .map(|_| ())
+            }
+            Err(RestoreError::MemoryBudgetExceeded) => Err(PrepareError::MemoryBudgetExceeded),
+            Err(RestoreError::AtlasFull) => Err(PrepareError::AtlasFull),
+        }
+    }
+
+    /// Renders the labels prepared by the last call to [`LabelRenderer::prepare`].
+    pub fn render(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+    ) -> Result<(), RenderError> {
+        self.renderer.render(atlas, viewport, pass)
+    }
+
+    /// Renders the labels like [`LabelRenderer::render`], after checking that the depth-stencil
+    /// attachment of `pass` matches the renderer, see [`TextRenderer::render_checked`].
+    pub fn render_checked(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass_depth_stencil: Option<TextureFormat>,
+        pass: &mut RenderPass<'_>,
+    ) -> Result<(), RenderError> {
+        self.renderer
+            .render_checked(atlas, viewport, pass_depth_stencil, pass)
+    }
+
+    /// Returns the renderer used to draw the labels, e.g. to change its settings.
+    ///
+    /// Labels prepared before a setting changed are only laid out again once they are dirty.
+    pub fn renderer_mut(&mut self) -> &mut TextRenderer {
+        &mut self.renderer
+    }
+}
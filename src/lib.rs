@@ -4,33 +4,110 @@
 //! [cosmic-text]: https://github.com/pop-os/cosmic-text
 //! [etagere]: https://github.com/nical/etagere
 
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "bench-utils")]
+pub mod bench_utils;
 mod cache;
+mod compress;
+mod cursor;
 mod custom_glyph;
+mod decoration;
+mod dry_run;
+#[cfg(feature = "emoji")]
+pub mod emoji;
+mod emoji_fallback;
 mod error;
+mod export;
+mod fallback;
+mod font_pool;
+mod glow;
+mod handle;
+mod highlight;
+mod label;
+mod line_strip;
+mod minimap;
+mod positioned;
+mod preedit;
+mod raster_cache;
+mod raster_options;
+mod revision;
+mod rich_text;
+mod scroll;
+mod shape_cache;
+mod shelf;
+mod sprite_sheet;
+mod staging;
 mod text_atlas;
 mod text_render;
 mod viewport;
 
 pub use cache::Cache;
+pub use compress::{ColorAtlasSnapshot, CompressedColorAtlas};
 pub use custom_glyph::{
-    ContentType, CustomGlyph, CustomGlyphId, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+    ContentType, CustomGlyph, CustomGlyphAlign, CustomGlyphId, CustomGlyphPlaceholder,
+    CustomGlyphZOrder, QuarterTurns, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+};
+pub use decoration::{Decoration, DecorationStyle};
+pub use dry_run::DryRunGlyph;
+pub use error::{
+    ColorModeError, CompressError, ExportError, PipelineError, PrepareError, RenderError,
+    RestoreError,
+};
+pub use export::{ExportedGlyph, ExportedOutline, GlyphOutline, PathCommand};
+pub use font_pool::{FontSystemPool, PooledFontSystem};
+pub use glow::GlowRenderer;
+pub use handle::{AtlasGlyph, AtlasGlyphHandle, AtlasTexture};
+pub use highlight::SpanHighlighter;
+pub use label::{Label, LabelRenderer};
+pub use minimap::MinimapStyle;
+pub use positioned::{GlyphColor, GlyphImage, PositionedGlyph, PositionedRun, RunGlyph};
+pub use preedit::{Preedit, PreeditStyle};
+pub use raster_cache::CustomGlyphCache;
+pub use raster_options::GlyphRasterOptions;
+pub use revision::BufferRevision;
+pub use rich_text::RichText;
+pub use shape_cache::{ShapeCache, ShapedTextId};
+pub use sprite_sheet::{SpriteRect, SpriteSheetGlyphSystem};
+pub use text_atlas::{
+    AtlasPartition, AtlasPartitionStats, AtlasStats, AtlasTextureStats, ColorMode,
+    EvictionCandidate, EvictionPolicy, GrowthPolicy, TextAtlas, UploadStrategy,
 };
-pub use error::{PrepareError, RenderError};
-pub use text_atlas::{ColorMode, TextAtlas};
-pub use text_render::TextRenderer;
-pub use viewport::Viewport;
+pub use text_render::{
+    BlendMode, ColorGlyphPolicy, GlyphAnimation, PixelSnap, PixelSnapping, PrepareOutput,
+    PreparedSnapshot, TextRenderer,
+};
+pub use viewport::{ColorSpace, DepthRange, Viewport, ViewportPool};
 
 // Re-export all top-level types from `cosmic-text` for convenience.
 #[doc(no_inline)]
 pub use cosmic_text::{
-    self, fontdb, Action, Affinity, Attrs, AttrsList, AttrsOwned, Buffer, BufferLine, CacheKey,
-    Color, Command, Cursor, Edit, Editor, Family, FamilyOwned, Font, FontSystem, LayoutCursor,
-    LayoutGlyph, LayoutLine, LayoutRun, LayoutRunIter, Metrics, ShapeGlyph, ShapeLine, ShapeSpan,
-    ShapeWord, Shaping, Stretch, Style, SubpixelBin, SwashCache, SwashContent, SwashImage, Weight,
-    Wrap,
+    self, fontdb, Action, Affinity, Angle, Attrs, AttrsList, AttrsOwned, Buffer, BufferLine,
+    CacheKey, Color, Command, Cursor, Edit, Editor, Family, FamilyOwned, Font, FontSystem,
+    LayoutCursor, LayoutGlyph, LayoutLine, LayoutRun, LayoutRunIter, Metrics, ShapeGlyph,
+    ShapeLine, ShapeSpan, ShapeWord, Shaping, Stretch, Style, SubpixelBin, SwashCache,
+    SwashContent, SwashImage, Transform, Weight, Wrap,
 };
 
-use etagere::AllocId;
+use custom_glyph::GlyphOrientation;
+use std::{borrow::Cow, ops::Range};
+use text_atlas::AtlasSlot;
+use text_render::ORIENTATION_SHIFT;
+
+// Rendering only reads the atlas, the viewport and the renderers, so they can be shared between
+// threads recording render passes in parallel. wgpu resources aren't `Send` and `Sync` on the web.
+#[cfg(not(target_arch = "wasm32"))]
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Cache>();
+    assert_send_sync::<TextAtlas>();
+    assert_send_sync::<TextRenderer>();
+    assert_send_sync::<Viewport>();
+    assert_send_sync::<ViewportPool>();
+    assert_send_sync::<GlowRenderer>();
+    assert_send_sync::<LabelRenderer>();
+};
 
 pub(crate) enum GpuCacheStatus {
     InAtlas {
@@ -45,9 +122,17 @@ pub(crate) struct GlyphDetails {
     width: u16,
     height: u16,
     gpu_cache: GpuCacheStatus,
-    atlas_id: Option<AllocId>,
+    atlas_id: Option<AtlasSlot>,
+    /// The hash of the image for images shared between custom glyphs, see
+    /// [`TextAtlas::set_deduplicate_custom_glyphs`]
+    content_hash: Option<u64>,
     top: i16,
     left: i16,
+    /// Distinguishes the glyph from earlier glyphs cached with the same key, see
+    /// [`AtlasGlyphHandle`]
+    generation: u64,
+    /// The partition that cached the glyph
+    partition: AtlasPartition,
 }
 
 #[repr(C)]
@@ -56,9 +141,90 @@ pub(crate) struct GlyphToRender {
     pos: [i32; 2],
     dim: [u16; 2],
     uv: [u16; 2],
+    uv_dim: [u16; 2],
     color: u32,
     content_type_with_srgb: [u16; 2],
     depth: f32,
+    /// The width of each copy of the image and the distance between copies, for images that are
+    /// repeated along the quad, or zero
+    repeat: [u16; 2],
+}
+
+impl GlyphToRender {
+    /// Returns the part of the glyph covering `rect`, which has to be within the glyph.
+    fn part(&self, rect: TextBounds) -> Self {
+        let orientation = self.orientation();
+        let dim = [
+            (rect.right - rect.left) as u16,
+            (rect.bottom - rect.top) as u16,
+        ];
+        let (mut uv, mut uv_dim) = (self.uv, self.uv_dim);
+
+        for image_axis in 0..2 {
+            let screen_axis = orientation.screen_axis(image_axis);
+            let to_atlas = |v: i32| {
+                (v as i64 * self.uv_dim[image_axis] as i64 / self.dim[screen_axis] as i64) as u16
+            };
+            let (start, end) = match screen_axis {
+                0 => (rect.left - self.pos[0], rect.right - self.pos[0]),
+                _ => (rect.top - self.pos[1], rect.bottom - self.pos[1]),
+            };
+            // Mirrored images start at the opposite edge of the quad
+            let offset = match orientation.flips(image_axis) {
+                true => self.dim[screen_axis] as i32 - end,
+                false => start,
+            };
+
+            uv[image_axis] = self.uv[image_axis] + to_atlas(offset);
+            uv_dim[image_axis] = to_atlas(end - start).max(1);
+        }
+
+        Self {
+            pos: [rect.left, rect.top],
+            dim,
+            uv,
+            uv_dim,
+            ..*self
+        }
+    }
+
+    /// Rotates and mirrors the image of the glyph on screen and clips it to `bounds`, returning
+    /// `None` if it is outside of them.
+    ///
+    /// The glyph has to be upright and unclipped.
+    fn oriented(mut self, orientation: GlyphOrientation, bounds: TextBounds) -> Option<Self> {
+        if orientation.transposes() {
+            self.dim.swap(0, 1);
+        }
+        self.content_type_with_srgb[1] |= orientation.bits() << ORIENTATION_SHIFT;
+
+        let rect = self.rect();
+        let clipped = TextBounds {
+            left: rect.left.max(bounds.left),
+            top: rect.top.max(bounds.top),
+            right: rect.right.min(bounds.right),
+            bottom: rect.bottom.min(bounds.bottom),
+        };
+        if clipped.left >= clipped.right || clipped.top >= clipped.bottom {
+            return None;
+        }
+
+        Some(self.part(clipped))
+    }
+
+    /// Returns how the image of the glyph is mapped to its quad on screen.
+    fn orientation(&self) -> GlyphOrientation {
+        GlyphOrientation::from_bits(self.content_type_with_srgb[1] >> ORIENTATION_SHIFT)
+    }
+
+    fn rect(&self) -> TextBounds {
+        TextBounds {
+            left: self.pos[0],
+            top: self.pos[1],
+            right: self.pos[0] + self.dim[0] as i32,
+            bottom: self.pos[1] + self.dim[1] as i32,
+        }
+    }
 }
 
 /// The screen resolution to use when rendering text.
@@ -72,10 +238,18 @@ pub struct Resolution {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) struct Params {
     screen_resolution: Resolution,
+    depth_range: DepthRange,
+    color_matrix: [[f32; 4]; 3],
+    convert_color_space: u32,
+    time: f32,
+    dither: u32,
+    letterbox_scale: u32,
+    letterbox_offset: [i32; 2],
     _pad: [u32; 2],
+    palette: [[u32; 4]; 4],
 }
 
 /// Controls the visible area of the text. Any text outside of the visible area will be clipped.
@@ -91,6 +265,78 @@ pub struct TextBounds {
     pub bottom: i32,
 }
 
+impl TextBounds {
+    /// Returns the bounds with every edge multiplied by `factor` and rounded.
+    pub(crate) fn scaled(self, factor: f32) -> Self {
+        let scale = |v: i32| (v as f32 * factor).round() as i32;
+        Self {
+            left: scale(self.left),
+            top: scale(self.top),
+            right: scale(self.right),
+            bottom: scale(self.bottom),
+        }
+    }
+
+    /// Returns the disjoint parts of the bounds that aren't covered by any of `exclusions`.
+    pub(crate) fn subtract(self, exclusions: &[TextBounds]) -> Vec<TextBounds> {
+        let mut parts = vec![self];
+        for exclusion in exclusions {
+            if exclusion.left >= exclusion.right || exclusion.top >= exclusion.bottom {
+                continue;
+            }
+
+            let mut i = 0;
+            while i < parts.len() {
+                let part = parts[i];
+                if exclusion.left >= part.right
+                    || exclusion.right <= part.left
+                    || exclusion.top >= part.bottom
+                    || exclusion.bottom <= part.top
+                {
+                    i += 1;
+                    continue;
+                }
+
+                // Replace the part by the bands above and below the exclusion and the pieces to
+                // its left and right
+                parts.swap_remove(i);
+                let top = part.top.max(exclusion.top);
+                let bottom = part.bottom.min(exclusion.bottom);
+                let pieces = [
+                    TextBounds {
+                        bottom: top,
+                        ..part
+                    },
+                    TextBounds {
+                        top: bottom,
+                        ..part
+                    },
+                    TextBounds {
+                        top,
+                        right: exclusion.left,
+                        bottom,
+                        ..part
+                    },
+                    TextBounds {
+                        left: exclusion.right,
+                        top,
+                        bottom,
+                        ..part
+                    },
+                ];
+                parts.extend(
+                    pieces
+                        .into_iter()
+                        .filter(|piece| piece.left < piece.right && piece.top < piece.bottom),
+                );
+                // The new pieces don't intersect the exclusion, but may be followed by parts that
+                // do, so keep going from the same index.
+            }
+        }
+        parts
+    }
+}
+
 /// The default visible area doesn't clip any text.
 impl Default for TextBounds {
     fn default() -> Self {
@@ -103,7 +349,74 @@ impl Default for TextBounds {
     }
 }
 
+/// A visible area with fractional edges, see [`TextArea::fractional_bounds`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FractionalBounds {
+    /// The position of the left edge of the visible area.
+    pub left: f32,
+    /// The position of the top edge of the visible area.
+    pub top: f32,
+    /// The position of the right edge of the visible area.
+    pub right: f32,
+    /// The position of the bottom edge of the visible area.
+    pub bottom: f32,
+}
+
+impl FractionalBounds {
+    /// Returns the whole pixels whose centers are within the bounds after multiplying every edge
+    /// by `factor`.
+    pub(crate) fn scaled(self, factor: f32) -> TextBounds {
+        // Casts saturate, so infinite edges don't clip anything
+        let scale = |v: f32| (v * factor).round() as i32;
+        TextBounds {
+            left: scale(self.left),
+            top: scale(self.top),
+            right: scale(self.right),
+            bottom: scale(self.bottom),
+        }
+    }
+}
+
+/// The default visible area doesn't clip any text.
+impl Default for FractionalBounds {
+    fn default() -> Self {
+        Self {
+            left: f32::NEG_INFINITY,
+            top: f32::NEG_INFINITY,
+            right: f32::INFINITY,
+            bottom: f32::INFINITY,
+        }
+    }
+}
+
+/// Adjusts how color glyphs (e.g. color emoji and [`ContentType::Color`] custom glyphs) are
+/// rendered, such as to show them as disabled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorGlyphStyle {
+    /// The saturation of color glyphs, from `0.0` (grayscale) to `1.0` (unchanged).
+    pub saturation: f32,
+    /// A color that color glyphs are multiplied with.
+    pub tint: Color,
+}
+
+impl ColorGlyphStyle {
+    /// Renders color glyphs unchanged.
+    pub const NORMAL: Self = Self {
+        saturation: 1.0,
+        tint: Color::rgba(255, 255, 255, 255),
+    };
+}
+
+impl Default for ColorGlyphStyle {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
 /// A text area containing text to be rendered along with its overflow behavior.
+///
+/// Use [`TextArea::new`] and the `with_*` methods to create a text area without listing every
+/// field, so that fields added in the future don't break it.
 #[derive(Clone)]
 pub struct TextArea<'a> {
     /// The buffer containing the text to be rendered.
@@ -114,11 +427,429 @@ pub struct TextArea<'a> {
     pub top: f32,
     /// The scaling to apply to the buffer.
     pub scale: f32,
+    /// The number of physical pixels per logical pixel of the text area, e.g. the scale factor of
+    /// the monitor it is shown on.
+    ///
+    /// `left`, `top` and `bounds` are given in logical pixels and the buffer is scaled by
+    /// `scale * scale_factor`. This allows laying out a window that spans monitors with different
+    /// scale factors in logical pixels, with each text area rendered at the density of its
+    /// monitor. Set to `1.0` to give the position and bounds in physical pixels.
+    pub scale_factor: f32,
     /// The visible bounds of the text area. This is used to clip the text and doesn't have to
     /// match the `left` and `top` values.
     pub bounds: TextBounds,
+    /// Additional visible bounds with fractional edges, given in the same pixels as `bounds`.
+    ///
+    /// The text is clipped to both `bounds` and these bounds. Each edge is converted to physical
+    /// pixels with `scale_factor` before it is rounded, so that the clipped area matches layout
+    /// rectangles with fractional positions, e.g. of widgets at a scale factor of 1.25 or 1.5.
+    /// Pixels are visible if their center is within the bounds.
+    pub fractional_bounds: Option<FractionalBounds>,
     /// The default color of the text area.
     pub default_color: Color,
     /// Additional custom glyphs to render.
     pub custom_glyphs: &'a [CustomGlyph],
+    /// Lines to draw below the text, such as underlines or tab leaders.
+    pub decorations: &'a [Decoration],
+    /// How color glyphs in the text area are rendered.
+    pub color_glyph_style: ColorGlyphStyle,
+    /// Colors for emoji that are drawn as masks, e.g. because only a monochrome emoji font is
+    /// available or with [`ColorGlyphPolicy::Monochrome`], which otherwise take the color of
+    /// their text.
+    ///
+    /// Each emoji cluster is drawn in one of the colors, picked by the emoji so that the same
+    /// emoji always get the same color, with its alpha multiplied by the alpha of the text. Leave
+    /// empty to draw emoji in the color of their text.
+    pub monochrome_emoji_colors: &'a [Color],
+    /// Areas in which the text area isn't drawn, e.g. because they are covered by floating
+    /// panels. They are given in the same pixels as `bounds`.
+    ///
+    /// Glyphs that are partially covered are split into the visible parts, which avoids blending
+    /// pixels that won't be seen. Repeated decorations are only skipped if they are covered
+    /// entirely.
+    pub exclusions: &'a [TextBounds],
+    /// If `true`, `scale` is rounded to a whole factor (of at least 1) and text is rasterized at
+    /// its original size, with every pixel repeated by that factor. The position of the text
+    /// area is rounded to whole pixels.
+    ///
+    /// This keeps pixel fonts crisp, instead of doubling pixels unevenly with fractional scale
+    /// factors. Custom glyphs are still rasterized at the scaled size.
+    pub integer_scaling: bool,
+    /// Extra space after each paragraph of the buffer, i.e. after each hard line break, in the
+    /// units of the buffer.
+    ///
+    /// The layout lines are moved down when the glyphs are positioned, so changing the spacing
+    /// doesn't require inserting empty lines into the buffer and shaping it again. Margins
+    /// collapse across empty paragraphs, i.e. no space is added after an empty paragraph, so
+    /// blank lines between paragraphs don't double the spacing. The lines are moved from the
+    /// first line shown by the buffer. Decorations and custom glyphs aren't moved, although
+    /// custom glyphs are aligned to the moved lines.
+    pub paragraph_spacing: f32,
+}
+
+impl<'a> TextArea<'a> {
+    /// Creates a new [`TextArea`] drawing `buffer` at `left`, `top` with the given `scale`,
+    /// clipped to `bounds`.
+    ///
+    /// The other fields start with values that leave the text as it is: a `scale_factor` of
+    /// `1.0`, no fractional bounds, custom glyphs, decorations, emoji colors or exclusions, the
+    /// normal color glyph style, no integer scaling and no paragraph spacing. They can be set
+    /// with the `with_*` methods.
+    pub fn new(
+        buffer: &'a Buffer,
+        left: f32,
+        top: f32,
+        scale: f32,
+        bounds: TextBounds,
+        default_color: Color,
+    ) -> Self {
+        Self {
+            buffer,
+            left,
+            top,
+            scale,
+            scale_factor: 1.0,
+            bounds,
+            fractional_bounds: None,
+            default_color,
+            custom_glyphs: &[],
+            decorations: &[],
+            color_glyph_style: ColorGlyphStyle::NORMAL,
+            monochrome_emoji_colors: &[],
+            exclusions: &[],
+            integer_scaling: false,
+            paragraph_spacing: 0.0,
+        }
+    }
+
+    /// Sets [`TextArea::scale_factor`].
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    /// Sets [`TextArea::fractional_bounds`].
+    pub fn with_fractional_bounds(mut self, fractional_bounds: FractionalBounds) -> Self {
+        self.fractional_bounds = Some(fractional_bounds);
+        self
+    }
+
+    /// Sets [`TextArea::custom_glyphs`].
+    pub fn with_custom_glyphs(mut self, custom_glyphs: &'a [CustomGlyph]) -> Self {
+        self.custom_glyphs = custom_glyphs;
+        self
+    }
+
+    /// Sets [`TextArea::decorations`].
+    pub fn with_decorations(mut self, decorations: &'a [Decoration]) -> Self {
+        self.decorations = decorations;
+        self
+    }
+
+    /// Sets [`TextArea::color_glyph_style`].
+    pub fn with_color_glyph_style(mut self, color_glyph_style: ColorGlyphStyle) -> Self {
+        self.color_glyph_style = color_glyph_style;
+        self
+    }
+
+    /// Sets [`TextArea::monochrome_emoji_colors`].
+    pub fn with_monochrome_emoji_colors(mut self, colors: &'a [Color]) -> Self {
+        self.monochrome_emoji_colors = colors;
+        self
+    }
+
+    /// Sets [`TextArea::exclusions`].
+    pub fn with_exclusions(mut self, exclusions: &'a [TextBounds]) -> Self {
+        self.exclusions = exclusions;
+        self
+    }
+
+    /// Sets [`TextArea::integer_scaling`].
+    pub fn with_integer_scaling(mut self, integer_scaling: bool) -> Self {
+        self.integer_scaling = integer_scaling;
+        self
+    }
+
+    /// Sets [`TextArea::paragraph_spacing`].
+    pub fn with_paragraph_spacing(mut self, paragraph_spacing: f32) -> Self {
+        self.paragraph_spacing = paragraph_spacing;
+        self
+    }
+
+    /// Returns the text area in physical pixels, with its `scale_factor` applied to its position,
+    /// bounds and scale.
+    ///
+    /// The exclusions are left unchanged, see [`TextArea::physical_exclusions`].
+    pub(crate) fn physical(&self) -> Self {
+        let mut text_area = self.clone();
+        let scale_factor = self.scale_factor;
+        if scale_factor != 1.0 {
+            text_area.left *= scale_factor;
+            text_area.top *= scale_factor;
+            text_area.scale *= scale_factor;
+            text_area.bounds = self.bounds.scaled(scale_factor);
+            text_area.scale_factor = 1.0;
+        }
+
+        if let Some(fractional_bounds) = text_area.fractional_bounds.take() {
+            let bounds = fractional_bounds.scaled(scale_factor);
+            text_area.bounds = TextBounds {
+                left: text_area.bounds.left.max(bounds.left),
+                top: text_area.bounds.top.max(bounds.top),
+                right: text_area.bounds.right.min(bounds.right),
+                bottom: text_area.bounds.bottom.min(bounds.bottom),
+            };
+        }
+        text_area
+    }
+
+    /// Returns the exclusions of the text area in physical pixels.
+    pub(crate) fn physical_exclusions(&self) -> Cow<'a, [TextBounds]> {
+        if self.scale_factor == 1.0 {
+            Cow::Borrowed(self.exclusions)
+        } else {
+            self.exclusions
+                .iter()
+                .map(|exclusion| exclusion.scaled(self.scale_factor))
+                .collect()
+        }
+    }
+
+    /// Returns the layout runs of the buffer, moved down by the paragraph spacing of the text
+    /// area.
+    pub(crate) fn layout_runs(&self) -> impl Iterator<Item = LayoutRun<'a>> + 'a {
+        let spacing = self.paragraph_spacing;
+        let mut offset = 0.0;
+        // The line of the previous run and whether it was empty
+        let mut previous: Option<(usize, bool)> = None;
+
+        self.buffer.layout_runs().map(move |mut run| {
+            if let Some((line, empty)) = previous {
+                if line != run.line_i && !empty {
+                    offset += spacing;
+                }
+            }
+            previous = Some((run.line_i, run.glyphs.is_empty()));
+
+            run.line_y += offset;
+            run.line_top += offset;
+            run
+        })
+    }
+
+    /// Returns the whole scale factor of the text area if it uses integer scaling.
+    pub(crate) fn integer_scale(&self) -> Option<u16> {
+        self.integer_scaling
+            .then(|| self.scale.round().clamp(1.0, u16::MAX as f32) as u16)
+    }
+}
+
+/// The source of a glyph emitted by [`TextRenderer::prepare`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GlyphSource {
+    /// A glyph of the text in the buffer of the text area.
+    Text {
+        /// The index of the line in the buffer.
+        line: usize,
+        /// The byte range of the cluster containing the glyph, relative to the start of the line.
+        cluster: Range<usize>,
+    },
+    /// A custom glyph of the text area.
+    Custom {
+        /// The index of the glyph in [`TextArea::custom_glyphs`].
+        index: usize,
+    },
+    /// A decoration of the text area.
+    Decoration {
+        /// The index of the decoration in [`TextArea::decorations`].
+        index: usize,
+    },
+    /// A glyph given to [`TextRenderer::prepare_positioned`]. The text area index of such glyphs
+    /// is always 0.
+    Positioned {
+        /// The index of the glyph in the glyphs given to `prepare_positioned`.
+        index: usize,
+    },
+    /// A glyph of a run given to [`TextRenderer::prepare_runs`]. The text area index of such
+    /// glyphs is the index of the run.
+    Run {
+        /// The index of the run in the runs given to `prepare_runs`.
+        run: usize,
+        /// The index of the glyph in [`PositionedRun::glyphs`].
+        glyph: usize,
+    },
+}
+
+/// Maps a glyph emitted by [`TextRenderer::prepare`] back to its source.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GlyphSourceInfo {
+    /// The index of the text area the glyph belongs to, in the order the text areas were given to
+    /// `prepare`.
+    pub text_area: usize,
+    /// Where the glyph came from in the text area.
+    pub source: GlyphSource,
+    /// The screen area covered by the glyph, after clipping.
+    pub rect: TextBounds,
+}
+
+/// A range of glyphs prepared by [`TextRenderer::prepare`] that sample the same atlas texture,
+/// as reported by [`TextRenderer::draw_ranges`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DrawRange {
+    /// The index of the text area the glyphs belong to, in the order the text areas were given to
+    /// `prepare`.
+    pub text_area: usize,
+    /// The atlas texture sampled by the glyphs.
+    pub texture: AtlasTexture,
+    /// The range of the glyphs in the order they are drawn, see
+    /// [`TextRenderer::glyph_source_map`].
+    pub glyphs: Range<usize>,
+}
+
+/// The direction of a run of text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextDirection {
+    /// Left-to-right text.
+    LeftToRight,
+    /// Right-to-left text.
+    RightToLeft,
+}
+
+/// A run of glyphs with the same direction on one layout line, as rendered by
+/// [`TextRenderer::prepare`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunInfo {
+    /// The index of the text area the run belongs to, in the order the text areas were given to
+    /// `prepare`.
+    pub text_area: usize,
+    /// The index of the line in the buffer.
+    pub line: usize,
+    /// The direction of the run.
+    pub direction: TextDirection,
+    /// The byte range of the text covered by the run, relative to the start of the line.
+    pub byte_range: Range<usize>,
+    /// The screen area covered by the run, before clipping.
+    pub screen_rect: TextBounds,
+    /// The vertical position of the baseline of the run on the screen, which is shared by all runs
+    /// of the line, even if they use different font sizes.
+    pub baseline: i32,
+}
+
+/// Text that was rendered with a fallback font instead of the font family requested by its
+/// attributes, as reported by [`TextRenderer::font_fallbacks`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FontFallback {
+    /// The index of the text area the text was first found in, in the order the text areas were
+    /// given to `prepare`.
+    pub text_area: usize,
+    /// The index of the line in the buffer.
+    pub line: usize,
+    /// The text of the cluster that required the fallback.
+    pub text: String,
+    /// The name of the font family requested by the attributes of the text.
+    pub requested_family: String,
+    /// The font that was used instead.
+    pub font_id: fontdb::ID,
+    /// The name of the font family that was used instead, if the font is in the font database.
+    pub font_family: Option<String>,
+    /// If `true`, no font contained a glyph for the text, so it was rendered as a missing glyph.
+    pub missing: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(left: i32, top: i32, right: i32, bottom: i32) -> TextBounds {
+        TextBounds {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    fn contains(bounds: &TextBounds, x: i32, y: i32) -> bool {
+        bounds.left <= x && x < bounds.right && bounds.top <= y && y < bounds.bottom
+    }
+
+    /// Checks that the parts are disjoint and cover every pixel of `area` that no exclusion
+    /// covers.
+    fn assert_subtracted(area: TextBounds, exclusions: &[TextBounds]) -> Vec<TextBounds> {
+        let parts = area.subtract(exclusions);
+        for y in -5..25 {
+            for x in -5..25 {
+                let expected = contains(&area, x, y)
+                    && !exclusions.iter().any(|exclusion| contains(exclusion, x, y));
+                let covering = parts.iter().filter(|part| contains(part, x, y)).count();
+                assert_eq!(covering, expected as usize, "({x}, {y}) in {parts:?}");
+            }
+        }
+        assert!(parts
+            .iter()
+            .all(|part| part.left < part.right && part.top < part.bottom));
+        parts
+    }
+
+    #[test]
+    fn subtract_disjoint() {
+        let area = bounds(0, 0, 10, 10);
+        assert_eq!(assert_subtracted(area, &[bounds(12, 0, 20, 10)]), [area]);
+    }
+
+    #[test]
+    fn subtract_contained() {
+        let area = bounds(0, 0, 10, 10);
+        assert_eq!(assert_subtracted(area, &[bounds(2, 3, 5, 7)]).len(), 4);
+        assert!(assert_subtracted(area, &[bounds(-1, -1, 11, 11)]).is_empty());
+        assert!(assert_subtracted(area, &[area]).is_empty());
+    }
+
+    #[test]
+    fn subtract_touching_edges() {
+        let area = bounds(0, 0, 10, 10);
+        for exclusion in [
+            bounds(10, 0, 20, 10),
+            bounds(-10, 0, 0, 10),
+            bounds(0, 10, 10, 20),
+            bounds(0, -10, 10, 0),
+        ] {
+            assert_eq!(assert_subtracted(area, &[exclusion]), [area]);
+        }
+        assert_eq!(
+            assert_subtracted(area, &[bounds(0, 0, 4, 10)]),
+            [bounds(4, 0, 10, 10)]
+        );
+    }
+
+    #[test]
+    fn subtract_overlapping_exclusions() {
+        let area = bounds(0, 0, 20, 20);
+        assert_subtracted(
+            area,
+            &[
+                bounds(2, 2, 8, 8),
+                bounds(5, 5, 12, 15),
+                bounds(-3, 10, 4, 30),
+                bounds(6, 6, 7, 7),
+                // Empty and inverted exclusions are ignored
+                bounds(3, 3, 3, 10),
+                bounds(15, 15, 10, 10),
+            ],
+        );
+    }
+
+    #[test]
+    fn subtract_from_default_bounds() {
+        let parts = assert_subtracted(TextBounds::default(), &[bounds(0, 0, 10, 10)]);
+        assert_eq!(parts.len(), 4);
+        assert!(parts.contains(&bounds(i32::MIN, i32::MIN, i32::MAX, 0)));
+        assert!(parts.contains(&bounds(i32::MIN, 10, i32::MAX, i32::MAX)));
+        assert!(parts.contains(&bounds(i32::MIN, 0, 0, 10)));
+        assert!(parts.contains(&bounds(10, 0, i32::MAX, 10)));
+
+        assert!(TextBounds::default()
+            .subtract(&[TextBounds::default()])
+            .is_empty());
+    }
 }
@@ -0,0 +1,135 @@
+use crate::{CacheKey, ContentType, GlyphImage, SwashContent, SwashImage};
+use cosmic_text::PhysicalGlyph;
+use rustc_hash::FxHasher;
+use std::{collections::HashMap, hash::BuildHasherDefault};
+
+/// The widest strip a baked line is split into, in physical pixels
+pub(crate) const LINE_STRIP_WIDTH: u32 = 1024;
+/// Lines that need more strips than this are prepared glyph by glyph
+const MAX_LINE_STRIPS: u32 = 16;
+
+/// The size of the image of a baked line and its offset from the origin of the line on the
+/// baseline, see [`crate::TextRenderer::set_line_strips`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LineExtent {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl LineExtent {
+    /// Returns the number of strips of `strip_width` the line is split into.
+    pub(crate) fn strips(&self, strip_width: u32) -> u32 {
+        self.width.div_ceil(strip_width)
+    }
+}
+
+/// The glyphs of a line rasterized into a single mask.
+pub(crate) struct BakedLine {
+    pub extent: LineExtent,
+    data: Vec<u8>,
+}
+
+impl BakedLine {
+    /// Rasterizes `glyphs`, which are positioned relative to the origin of the line, into a single
+    /// mask.
+    ///
+    /// Returns `None` if one of the glyphs doesn't have a mask image or if the line doesn't fit into
+    /// [`MAX_LINE_STRIPS`] strips of `strip_width`.
+    pub(crate) fn new(
+        glyphs: &[PhysicalGlyph],
+        strip_width: u32,
+        mut rasterize: impl FnMut(CacheKey) -> Option<SwashImage>,
+    ) -> Option<Self> {
+        let mut images: HashMap<CacheKey, Option<SwashImage>, BuildHasherDefault<FxHasher>> =
+            HashMap::default();
+        for glyph in glyphs {
+            images
+                .entry(glyph.cache_key)
+                .or_insert_with(|| rasterize(glyph.cache_key));
+        }
+
+        let mut placed = Vec::with_capacity(glyphs.len());
+        let (mut left, mut top) = (i32::MAX, i32::MAX);
+        let (mut right, mut bottom) = (i32::MIN, i32::MIN);
+        for glyph in glyphs {
+            let Some(image) = &images[&glyph.cache_key] else {
+                continue;
+            };
+            if !matches!(image.content, SwashContent::Mask) {
+                return None;
+            }
+            if image.placement.width == 0 || image.placement.height == 0 {
+                continue;
+            }
+
+            let x = glyph.x.saturating_add(image.placement.left);
+            let y = glyph.y.saturating_sub(image.placement.top);
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x.saturating_add(image.placement.width as i32));
+            bottom = bottom.max(y.saturating_add(image.placement.height as i32));
+            placed.push((x, y, image));
+        }
+
+        if placed.is_empty() {
+            return Some(Self {
+                extent: LineExtent {
+                    left: 0,
+                    top: 0,
+                    width: 0,
+                    height: 0,
+                },
+                data: Vec::new(),
+            });
+        }
+
+        let extent = LineExtent {
+            left,
+            top,
+            width: right.abs_diff(left),
+            height: bottom.abs_diff(top),
+        };
+        if extent.height > strip_width || extent.strips(strip_width) > MAX_LINE_STRIPS {
+            return None;
+        }
+
+        // Overlapping glyphs are blended like their quads would be
+        let mut data = vec![0u8; extent.width as usize * extent.height as usize];
+        for (x, y, image) in placed {
+            let width = image.placement.width as usize;
+            let (x, y) = ((x - left) as usize, (y - top) as usize);
+            for (row, src) in image.data.chunks_exact(width).enumerate() {
+                let start = (y + row) * extent.width as usize + x;
+                for (dst, &src) in data[start..start + width].iter_mut().zip(src) {
+                    *dst = (*dst as u32 + src as u32 * (255 - *dst as u32) / 255) as u8;
+                }
+            }
+        }
+
+        Some(Self { extent, data })
+    }
+
+    /// Returns the image of the strip with the given index, with its left edge at the position of
+    /// the strip.
+    pub(crate) fn strip(&self, index: u32, strip_width: u32) -> GlyphImage {
+        let line_width = self.extent.width as usize;
+        let start = (index * strip_width) as usize;
+        let width = (line_width - start).min(strip_width as usize);
+
+        GlyphImage {
+            content_type: ContentType::Mask,
+            top: -self.extent.top as i16,
+            left: 0,
+            width: width as u16,
+            height: self.extent.height as u16,
+            data: self
+                .data
+                .chunks_exact(line_width)
+                .flat_map(|row| &row[start..start + width])
+                .copied()
+                .collect(),
+        }
+    }
+}
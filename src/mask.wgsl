@@ -0,0 +1,16 @@
+// Appended to `shader.wgsl` for renderers that draw text through a mask texture. The group of the
+// mask follows the groups of the other features of the pipeline.
+
+@group(MASK_GROUP) @binding(0)
+var mask_texture: texture_2d<f32>;
+
+@group(MASK_GROUP) @binding(1)
+var mask_sampler: sampler;
+
+fn mask_alpha(position: vec2<f32>) -> f32 {
+    // The mask is stretched over the viewport, which may be letterboxed in the render target
+    let viewport_pos = (position - vec2<f32>(params.letterbox_offset))
+        / f32(params.letterbox_scale);
+    let uv = viewport_pos / vec2<f32>(params.screen_resolution);
+    return textureSampleLevel(mask_texture, mask_sampler, uv, 0.0).a;
+}
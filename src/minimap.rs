@@ -0,0 +1,80 @@
+use crate::{Decoration, DecorationStyle, GlyphSource};
+use cosmic_text::LayoutRun;
+
+/// How a [`crate::TextRenderer`] draws text as a minimap, see
+/// [`crate::TextRenderer::set_minimap`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinimapStyle {
+    /// The part of the height of each line covered by its blocks, between `0.0` and `1.0`
+    ///
+    /// The blocks are centered vertically in the line, so the rest of the height is left as a
+    /// gap between lines.
+    pub line_fill: f32,
+}
+
+impl Default for MinimapStyle {
+    fn default() -> Self {
+        Self { line_fill: 0.6 }
+    }
+}
+
+/// Returns the blocks drawn for the words of `runs`, as solid decorations in the coordinates of
+/// the text area, with the source of each block.
+///
+/// Consecutive glyphs form a block until a whitespace glyph or a glyph with a different color or
+/// metadata, so that syntax highlighted tokens keep their colors.
+pub(crate) fn minimap_blocks<'b>(
+    runs: impl Iterator<Item = LayoutRun<'b>>,
+    style: MinimapStyle,
+) -> Vec<(Decoration, GlyphSource)> {
+    let line_fill = style.line_fill.clamp(0.0, 1.0);
+    let mut blocks = Vec::new();
+
+    for run in runs {
+        let thickness = run.line_height * line_fill;
+        let top = run.line_top + (run.line_height - thickness) / 2.0;
+        let mut block: Option<(Decoration, GlyphSource)> = None;
+
+        for glyph in run.glyphs.iter() {
+            if run.text[glyph.start..glyph.end]
+                .chars()
+                .all(char::is_whitespace)
+            {
+                blocks.extend(block.take());
+                continue;
+            }
+
+            let (left, right) = (glyph.x, glyph.x + glyph.w);
+            if let Some((decoration, GlyphSource::Text { cluster, .. })) = &mut block {
+                if decoration.color == glyph.color_opt && decoration.metadata == glyph.metadata {
+                    let block_right = decoration.left + decoration.width;
+                    decoration.left = decoration.left.min(left);
+                    decoration.width = block_right.max(right) - decoration.left;
+                    cluster.start = cluster.start.min(glyph.start);
+                    cluster.end = cluster.end.max(glyph.end);
+                    continue;
+                }
+            }
+
+            blocks.extend(block.replace((
+                Decoration {
+                    left,
+                    top,
+                    width: glyph.w,
+                    thickness,
+                    color: glyph.color_opt,
+                    style: DecorationStyle::Solid,
+                    metadata: glyph.metadata,
+                },
+                GlyphSource::Text {
+                    line: run.line_i,
+                    cluster: glyph.start..glyph.end,
+                },
+            )));
+        }
+
+        blocks.extend(block);
+    }
+
+    blocks
+}
@@ -0,0 +1,166 @@
+use crate::{fontdb, Color, ContentType, GlyphAnimation, TextBounds};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// A glyph that was shaped and positioned by the application, rendered with
+/// [`crate::TextRenderer::prepare_positioned`]
+///
+/// This allows using glyphon's atlas and renderer with text laid out by a different shaper than
+/// cosmic-text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    /// Identifies the image of the glyph
+    ///
+    /// Glyphs with the same key share the image cached in the atlas, so the key has to cover
+    /// everything that affects rasterization, e.g. the font, glyph index, size and subpixel
+    /// offset.
+    pub key: u64,
+    /// The horizontal position of the glyph in physical pixels
+    pub x: i32,
+    /// The vertical position of the glyph in physical pixels, usually on the baseline
+    pub y: i32,
+    /// The color of the glyph (only relevant if the glyph is rendered with the type
+    /// [`ContentType::Mask`] or [`ContentType::TintedColor`])
+    pub color: GlyphColor,
+    /// The visible bounds of the glyph, used to clip it
+    pub bounds: TextBounds,
+    /// The depth of the glyph, see [`crate::TextRenderer::prepare_with_depth`]
+    pub depth: f32,
+    /// The animation of the glyph, see [`crate::Viewport::set_time`]
+    pub animation: GlyphAnimation,
+}
+
+/// The color of a positioned glyph, in sRGB with straight alpha
+///
+/// Converts from and to cosmic-text's [`Color`], so positioned glyphs don't depend on it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct GlyphColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl GlyphColor {
+    /// Creates an opaque color.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 255)
+    }
+
+    /// Creates a color with alpha.
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Returns the color packed as `0xAARRGGBB`, like the colors of the vertex buffer.
+    pub(crate) const fn packed(self) -> u32 {
+        (self.a as u32) << 24 | (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+}
+
+impl From<Color> for GlyphColor {
+    fn from(color: Color) -> Self {
+        let [r, g, b, a] = color.as_rgba();
+        Self::rgba(r, g, b, a)
+    }
+}
+
+impl From<GlyphColor> for Color {
+    fn from(color: GlyphColor) -> Self {
+        Color(color.packed())
+    }
+}
+
+/// A run of glyphs of a single font that was shaped and positioned by the application, rendered
+/// with [`crate::TextRenderer::prepare_runs`]
+///
+/// Unlike [`PositionedGlyph`]s, the glyphs of runs are rasterized from fonts loaded in the
+/// [`crate::FontSystem`], so only the layout has to be done by the application.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedRun<'a> {
+    /// The font of the glyphs
+    pub font_id: fontdb::ID,
+    /// The font size in physical pixels
+    pub font_size: f32,
+    /// The horizontal position of the origin of the run in physical pixels
+    pub x: f32,
+    /// The vertical position of the baseline of the run in physical pixels
+    pub y: f32,
+    /// The glyphs of the run, positioned relative to its origin
+    pub glyphs: &'a [RunGlyph],
+    /// The color of the glyphs
+    pub color: GlyphColor,
+    /// The visible bounds of the run, used to clip its glyphs
+    pub bounds: TextBounds,
+    /// The depth of the glyphs, see [`crate::TextRenderer::prepare_with_depth`]
+    pub depth: f32,
+    /// The animation of the glyphs, see [`crate::Viewport::set_time`]
+    pub animation: GlyphAnimation,
+}
+
+/// A glyph of a [`PositionedRun`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunGlyph {
+    /// The index of the glyph in the font
+    pub id: u16,
+    /// The horizontal offset of the glyph from the origin of the run in physical pixels
+    pub x: f32,
+    /// The vertical offset of the glyph from the baseline of the run in physical pixels, with
+    /// positive values moving the glyph down
+    pub y: f32,
+}
+
+/// The rasterized image of a [`PositionedGlyph`]
+#[derive(Debug, Clone)]
+pub struct GlyphImage {
+    /// The type of image data contained in `data`
+    pub content_type: ContentType,
+    /// The distance from the position of the glyph up to the top edge of the image
+    pub top: i16,
+    /// The distance from the position of the glyph to the left edge of the image
+    pub left: i16,
+    /// The width of the image in physical pixels
+    pub width: u16,
+    /// The height of the image in physical pixels
+    pub height: u16,
+    /// The raw image data
+    pub data: Vec<u8>,
+}
+
+impl GlyphImage {
+    /// Returns a hash of the size, type and data of the image, used to share identical images.
+    pub(crate) fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.content_type, self.width, self.height).hash(&mut hasher);
+        self.data.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub(crate) fn validate(&self, key: u64) {
+        let expected_len =
+            self.width as usize * self.height as usize * self.content_type.bytes_per_pixel();
+        assert_eq!(
+            self.data.len(),
+            expected_len,
+            "Invalid glyph image. Expected data of length {}, got length {}. Key: {}",
+            expected_len,
+            self.data.len(),
+            key,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyph_color_matches_cosmic_text() {
+        let color = Color::rgba(0x12, 0x34, 0x56, 0x78);
+        assert_eq!(
+            GlyphColor::from(color),
+            GlyphColor::rgba(0x12, 0x34, 0x56, 0x78)
+        );
+        assert_eq!(Color::from(GlyphColor::from(color)), color);
+        assert_eq!(GlyphColor::rgb(1, 2, 3).packed(), Color::rgb(1, 2, 3).0);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{Buffer, Color, Cursor, Decoration, DecorationStyle};
+use std::ops::Range;
+
+/// The composition text of an input method (IME) that was inserted into a [`Buffer`], e.g. the
+/// text of winit's `Ime::Preedit` event.
+///
+/// [`Preedit::decorations`] returns the underline of the composition text and its caret as
+/// [`Decoration`]s, to be drawn with the text area showing the buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preedit {
+    /// The line of the buffer containing the composition text
+    pub line: usize,
+    /// The byte range of the composition text in the line
+    pub range: Range<usize>,
+    /// The byte range of the cursor within the composition text, as reported by the input method
+    ///
+    /// An empty range is drawn as a caret, while a non-empty range (e.g. the clause that is being
+    /// converted) is drawn with a thicker underline. `None` hides the cursor.
+    pub cursor: Option<Range<usize>>,
+}
+
+/// How a [`Preedit`] is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreeditStyle {
+    /// How the composition text is underlined
+    pub underline: DecorationStyle,
+    /// The thickness of the underline, which is doubled under a non-empty cursor range
+    pub thickness: f32,
+    /// The width of the caret
+    pub caret_width: f32,
+    /// The color of the underline and the caret
+    ///
+    /// Set to `None` to use [`crate::TextArea::default_color`].
+    pub color: Option<Color>,
+    /// The metadata of the decorations, passed to `metadata_to_depth`
+    pub metadata: usize,
+}
+
+impl Default for PreeditStyle {
+    fn default() -> Self {
+        Self {
+            underline: DecorationStyle::Solid,
+            thickness: 1.0,
+            caret_width: 1.0,
+            color: None,
+            metadata: 0,
+        }
+    }
+}
+
+impl Preedit {
+    /// Returns the underline of the composition text along the bottom of each layout line it
+    /// covers, and the caret or the underline of the cursor range, in the coordinates of a text
+    /// area showing `buffer`.
+    ///
+    /// `buffer` must have been shaped with the composition text inserted.
+    pub fn decorations(&self, buffer: &Buffer, style: PreeditStyle) -> Vec<Decoration> {
+        let mut decorations = Vec::new();
+        let cursor = |index: usize| Cursor::new(self.line, index);
+        let mut caret_drawn = false;
+
+        for run in buffer.layout_runs().filter(|run| run.line_i == self.line) {
+            let bottom = run.line_top + run.line_height;
+            let mut underline = |range: Range<usize>, thickness: f32, underline| {
+                let Some((left, width)) = run.highlight(cursor(range.start), cursor(range.end))
+                else {
+                    return;
+                };
+                if width > 0.0 {
+                    decorations.push(Decoration {
+                        left,
+                        top: bottom - thickness,
+                        width,
+                        thickness,
+                        color: style.color,
+                        style: underline,
+                        metadata: style.metadata,
+                    });
+                }
+            };
+
+            underline(self.range.clone(), style.thickness, style.underline);
+
+            let Some(preedit_cursor) = &self.cursor else {
+                continue;
+            };
+            let start = (self.range.start + preedit_cursor.start).min(self.range.end);
+            let end = (self.range.start + preedit_cursor.end).min(self.range.end);
+
+            if start < end {
+                underline(start..end, style.thickness * 2.0, DecorationStyle::Solid);
+            } else if !caret_drawn {
+                // A caret at the end of a wrapped layout line is only drawn once
+                if let Some((x, _)) = run.highlight(cursor(start), cursor(start)) {
+                    decorations.push(Decoration {
+                        left: x,
+                        top: run.line_top,
+                        width: style.caret_width,
+                        thickness: run.line_height,
+                        color: style.color,
+                        style: DecorationStyle::Solid,
+                        metadata: style.metadata,
+                    });
+                    caret_drawn = true;
+                }
+            }
+        }
+
+        decorations
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    custom_glyph::CustomGlyphCacheKey, CustomGlyphId, RasterizeCustomGlyphRequest,
+    RasterizedCustomGlyph,
+};
+use rustc_hash::FxHasher;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::BuildHasherDefault,
+};
+
+/// A CPU-side cache of rasterized custom glyphs, which can be filled ahead of time so that
+/// `prepare` doesn't stall rasterizing many custom glyphs (e.g. SVG icons) the first time they
+/// are shown.
+///
+/// Use [`CustomGlyphCache::rasterize`] as the `rasterize_custom_glyph` callback of
+/// [`crate::TextRenderer::prepare_with_custom`]. Images stay in the cache until they are removed,
+/// so glyphs that were evicted from the atlas can be uploaded again without rasterizing them.
+#[derive(Default)]
+pub struct CustomGlyphCache {
+    images: HashMap<CustomGlyphCacheKey, RasterizedCustomGlyph, BuildHasherDefault<FxHasher>>,
+}
+
+impl CustomGlyphCache {
+    /// Creates an empty `CustomGlyphCache`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rasterizes the given requests with `rasterize` and caches the images, skipping requests
+    /// that are already cached.
+    ///
+    /// With the `rayon` feature, the requests are rasterized in parallel on the rayon thread
+    /// pool. Requests are typically created for the ids and sizes of the custom glyphs of a
+    /// screen that is about to be shown, with [`cosmic_text::SubpixelBin::Zero`] offsets for
+    /// glyphs that are snapped to physical pixels.
+    pub fn rasterize_ahead(
+        &mut self,
+        requests: impl IntoIterator<Item = RasterizeCustomGlyphRequest>,
+        rasterize: impl Fn(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph> + Sync,
+    ) {
+        trace_span!("rasterize_ahead");
+
+        let mut keys = HashSet::<_, BuildHasherDefault<FxHasher>>::default();
+        let requests: Vec<RasterizeCustomGlyphRequest> = requests
+            .into_iter()
+            .filter(|request| {
+                let key = cache_key(request);
+                !self.images.contains_key(&key) && keys.insert(key)
+            })
+            .collect();
+
+        let rasterize = |request: RasterizeCustomGlyphRequest| {
+            let output = rasterize(request)?;
+            output.validate(&request, None);
+            Some((cache_key(&request), output))
+        };
+
+        #[cfg(feature = "rayon")]
+        let images: Vec<_> = {
+            use rayon::iter::{IntoParallelIterator, ParallelIterator};
+            requests.into_par_iter().filter_map(rasterize).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let images: Vec<_> = requests.into_iter().filter_map(rasterize).collect();
+
+        self.images.extend(images);
+    }
+
+    /// Returns the cached image for `request`, rasterizing it with `rasterize` and caching it if
+    /// it isn't cached yet.
+    pub fn rasterize(
+        &mut self,
+        request: RasterizeCustomGlyphRequest,
+        rasterize: impl FnOnce(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Option<RasterizedCustomGlyph> {
+        let key = cache_key(&request);
+        if let Some(image) = self.images.get(&key) {
+            return Some(image.clone());
+        }
+
+        let image = rasterize(request)?;
+        self.images.insert(key, image.clone());
+        Some(image)
+    }
+
+    /// Returns the cached image for `request`, if any.
+    pub fn get(&self, request: &RasterizeCustomGlyphRequest) -> Option<&RasterizedCustomGlyph> {
+        self.images.get(&cache_key(request))
+    }
+
+    /// Removes all cached images of the custom glyph `id`, e.g. after its source image changed.
+    pub fn remove(&mut self, id: CustomGlyphId) {
+        self.images.retain(|key, _| key.glyph_id != id);
+    }
+
+    /// Removes all cached images.
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+
+    /// Returns the number of cached images.
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Returns `true` if no images are cached.
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+}
+
+fn cache_key(request: &RasterizeCustomGlyphRequest) -> CustomGlyphCacheKey {
+    CustomGlyphCacheKey {
+        glyph_id: request.id,
+        width: request.width,
+        height: request.height,
+        x_bin: request.x_bin,
+        y_bin: request.y_bin,
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{Angle, CacheKey, FontSystem, SwashImage, Transform};
+use cosmic_text::CacheKeyFlags;
+use rustc_hash::FxHasher;
+use std::hash::Hasher;
+use swash::{
+    scale::{Render, ScaleContext, Source, StrikeWith},
+    zeno::{Format, Vector},
+};
+
+/// Options for rasterizing the glyphs of fonts, see
+/// [`crate::TextRenderer::set_metadata_to_raster_options`].
+#[derive(Debug, Clone, Copy)]
+pub struct GlyphRasterOptions {
+    /// The strength of synthetic emboldening applied to the outlines of glyphs in pixels, or
+    /// `0.0` to draw glyphs with the weight of their font
+    pub embolden: f32,
+    /// A transform applied to the outlines of glyphs around their origin on the baseline, e.g.
+    /// to slant text with [`Transform::skew`]
+    ///
+    /// The transform doesn't change the layout, so transforms that make glyphs wider can make
+    /// them overlap.
+    pub transform: Option<Transform>,
+    /// Whether outlines are hinted, which makes small text crisper but slightly distorts the
+    /// shapes of glyphs
+    pub hint: bool,
+}
+
+impl GlyphRasterOptions {
+    /// Returns `true` if glyphs are rasterized like cosmic-text's [`crate::SwashCache`] does.
+    pub(crate) fn is_default(&self) -> bool {
+        self.embolden == 0.0 && self.transform.is_none() && self.hint
+    }
+
+    /// Returns a hash that distinguishes glyphs rasterized with these options in the atlas.
+    pub(crate) fn hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        hasher.write_u32(self.embolden.to_bits());
+        if let Some(transform) = self.transform {
+            let Transform {
+                xx,
+                xy,
+                yx,
+                yy,
+                x,
+                y,
+            } = transform;
+            for value in [xx, xy, yx, yy, x, y] {
+                hasher.write_u32(value.to_bits());
+            }
+        }
+        hasher.write_u8(self.transform.is_some() as u8);
+        hasher.write_u8(self.hint as u8);
+        hasher.finish()
+    }
+}
+
+impl Default for GlyphRasterOptions {
+    /// The options cosmic-text's [`crate::SwashCache`] rasterizes glyphs with.
+    fn default() -> Self {
+        Self {
+            embolden: 0.0,
+            transform: None,
+            hint: true,
+        }
+    }
+}
+
+/// Rasterizes a glyph like cosmic-text's [`crate::SwashCache`] does, with `options`.
+pub(crate) fn rasterize_with_options(
+    font_system: &mut FontSystem,
+    context: &mut ScaleContext,
+    cache_key: CacheKey,
+    options: &GlyphRasterOptions,
+) -> Option<SwashImage> {
+    let font = font_system.get_font(cache_key.font_id)?;
+    let mut scaler = context
+        .builder(font.as_swash())
+        .size(f32::from_bits(cache_key.font_size_bits))
+        .hint(options.hint)
+        .build();
+
+    let offset = Vector::new(cache_key.x_bin.as_float(), cache_key.y_bin.as_float());
+
+    // Slant faux italic glyphs like cosmic-text before applying the transform
+    let fake_italic = cache_key
+        .flags
+        .contains(CacheKeyFlags::FAKE_ITALIC)
+        .then(|| Transform::skew(Angle::from_degrees(14.0), Angle::from_degrees(0.0)));
+    let transform = match (fake_italic, options.transform) {
+        (Some(fake_italic), Some(transform)) => Some(fake_italic.then(&transform)),
+        (fake_italic, transform) => fake_italic.or(transform),
+    };
+
+    Render::new(&[
+        Source::ColorOutline(0),
+        Source::ColorBitmap(StrikeWith::BestFit),
+        Source::Outline,
+    ])
+    .format(Format::Alpha)
+    .offset(offset)
+    .transform(transform)
+    .embolden(options.embolden)
+    .render(&mut scaler, cache_key.glyph_id)
+}
@@ -0,0 +1,215 @@
+use crate::{
+    Buffer, Color, ColorGlyphStyle, CustomGlyph, Decoration, DecorationStyle, FractionalBounds,
+    Resolution, TextArea, TextBounds,
+};
+use rustc_hash::FxHasher;
+use std::hash::{Hash, Hasher};
+
+/// A fingerprint of everything in a [`Buffer`] that affects how it is rendered.
+///
+/// Two revisions of the same buffer compare equal if the visible layout of the buffer (glyphs,
+/// positions, colors and metadata) didn't change in between. Computing a revision only walks the
+/// visible layout runs, which is much cheaper than preparing the buffer again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferRevision(u64);
+
+impl BufferRevision {
+    /// Computes the current revision of `buffer`.
+    pub fn new(buffer: &Buffer) -> Self {
+        let mut hasher = FxHasher::default();
+
+        for run in buffer.layout_runs() {
+            run.line_i.hash(&mut hasher);
+            run.line_y.to_bits().hash(&mut hasher);
+            run.line_top.to_bits().hash(&mut hasher);
+            run.line_height.to_bits().hash(&mut hasher);
+
+            for glyph in run.glyphs {
+                glyph.start.hash(&mut hasher);
+                glyph.end.hash(&mut hasher);
+                glyph.font_id.hash(&mut hasher);
+                glyph.glyph_id.hash(&mut hasher);
+                glyph.font_size.to_bits().hash(&mut hasher);
+                glyph.x.to_bits().hash(&mut hasher);
+                glyph.y.to_bits().hash(&mut hasher);
+                glyph.w.to_bits().hash(&mut hasher);
+                glyph.x_offset.to_bits().hash(&mut hasher);
+                glyph.y_offset.to_bits().hash(&mut hasher);
+                glyph.color_opt.hash(&mut hasher);
+                glyph.metadata.hash(&mut hasher);
+                glyph.cache_key_flags.hash(&mut hasher);
+            }
+        }
+
+        Self(hasher.finish())
+    }
+}
+
+/// Everything about a prepared [`TextArea`] that affects the prepared glyphs.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TextAreaSignature {
+    pub revision: BufferRevision,
+    left: f32,
+    top: f32,
+    scale: f32,
+    scale_factor: f32,
+    bounds: TextBounds,
+    fractional_bounds: Option<FractionalBounds>,
+    default_color: Color,
+    custom_glyphs: u64,
+    decorations: u64,
+    color_glyph_style: ColorGlyphStyle,
+    monochrome_emoji_colors: Vec<Color>,
+    exclusions: Vec<TextBounds>,
+    integer_scaling: bool,
+    paragraph_spacing: f32,
+}
+
+impl TextAreaSignature {
+    pub(crate) fn new(text_area: &TextArea) -> Self {
+        Self {
+            revision: BufferRevision::new(text_area.buffer),
+            left: text_area.left,
+            top: text_area.top,
+            scale: text_area.scale,
+            scale_factor: text_area.scale_factor,
+            bounds: text_area.bounds,
+            fractional_bounds: text_area.fractional_bounds,
+            default_color: text_area.default_color,
+            custom_glyphs: hash_custom_glyphs(text_area.custom_glyphs),
+            decorations: hash_decorations(text_area.decorations),
+            color_glyph_style: text_area.color_glyph_style,
+            monochrome_emoji_colors: text_area.monochrome_emoji_colors.to_vec(),
+            exclusions: text_area.exclusions.to_vec(),
+            integer_scaling: text_area.integer_scaling,
+            paragraph_spacing: text_area.paragraph_spacing,
+        }
+    }
+}
+
+/// The state a [`crate::TextRenderer`] was last prepared with.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PrepareSignature {
+    pub resolution: Resolution,
+    pub atlas_evictions: u64,
+    pub text_areas: Vec<TextAreaSignature>,
+}
+
+fn hash_custom_glyphs(custom_glyphs: &[CustomGlyph]) -> u64 {
+    let mut hasher = FxHasher::default();
+
+    for glyph in custom_glyphs {
+        glyph.id.hash(&mut hasher);
+        glyph.left.to_bits().hash(&mut hasher);
+        glyph.top.to_bits().hash(&mut hasher);
+        glyph.width.to_bits().hash(&mut hasher);
+        glyph.height.to_bits().hash(&mut hasher);
+        glyph.color.hash(&mut hasher);
+        glyph.snap_to_physical_pixel.hash(&mut hasher);
+        glyph.z_order.hash(&mut hasher);
+        glyph.align.hash(&mut hasher);
+        glyph.metadata.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+fn hash_decorations(decorations: &[Decoration]) -> u64 {
+    let mut hasher = FxHasher::default();
+
+    for decoration in decorations {
+        decoration.left.to_bits().hash(&mut hasher);
+        decoration.top.to_bits().hash(&mut hasher);
+        decoration.width.to_bits().hash(&mut hasher);
+        decoration.thickness.to_bits().hash(&mut hasher);
+        decoration.color.hash(&mut hasher);
+        match decoration.style {
+            DecorationStyle::Solid => 0u8.hash(&mut hasher),
+            DecorationStyle::Dashed { dash, gap } => {
+                1u8.hash(&mut hasher);
+                dash.to_bits().hash(&mut hasher);
+                gap.to_bits().hash(&mut hasher);
+            }
+            DecorationStyle::Dotted { gap } => {
+                2u8.hash(&mut hasher);
+                gap.to_bits().hash(&mut hasher);
+            }
+            DecorationStyle::Pattern { id, width, gap } => {
+                3u8.hash(&mut hasher);
+                id.hash(&mut hasher);
+                width.to_bits().hash(&mut hasher);
+                gap.to_bits().hash(&mut hasher);
+            }
+        }
+        decoration.metadata.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attrs, FontSystem, Metrics, Shaping};
+    use cosmic_text::fontdb;
+
+    fn font_system() -> FontSystem {
+        let mut db = fontdb::Database::new();
+        db.load_font_data(include_bytes!("../examples/Inter-Bold.ttf").to_vec());
+        FontSystem::new_with_locale_and_db("en-US".into(), db)
+    }
+
+    fn revision(font_system: &mut FontSystem, text: &str, attrs: Attrs) -> BufferRevision {
+        let mut buffer = Buffer::new(font_system, Metrics::new(10.0, 20.0));
+        buffer.set_size(font_system, Some(200.0), Some(40.0));
+        buffer.set_text(font_system, text, attrs, Shaping::Advanced);
+        buffer.shape_until_scroll(font_system, false);
+        BufferRevision::new(&buffer)
+    }
+
+    #[test]
+    fn revision_follows_visible_layout() {
+        let mut font_system = font_system();
+        let mut revision = |text: &str, attrs: Attrs| revision(&mut font_system, text, attrs);
+        let text = "first\nsecond\nthird";
+        let base = revision(text, Attrs::new());
+
+        assert_eq!(revision(text, Attrs::new()), base);
+        assert_ne!(revision("first\nsecont\nthird", Attrs::new()), base);
+        assert_ne!(
+            revision(text, Attrs::new().color(Color::rgb(255, 0, 0))),
+            base
+        );
+        assert_ne!(revision(text, Attrs::new().metadata(1)), base);
+        // Only two lines fit into the height of the buffer
+        assert_eq!(revision("first\nsecond\nchanged", Attrs::new()), base);
+    }
+
+    #[test]
+    fn revision_follows_scrolling_and_wrapping() {
+        let mut font_system = font_system();
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(10.0, 20.0));
+        buffer.set_text(
+            &mut font_system,
+            "a line that wraps\nsecond\nthird",
+            Attrs::new(),
+            Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut font_system, false);
+        let base = BufferRevision::new(&buffer);
+
+        buffer.set_size(&mut font_system, Some(40.0), None);
+        buffer.shape_until_scroll(&mut font_system, false);
+        let wrapped = BufferRevision::new(&buffer);
+        assert_ne!(wrapped, base);
+
+        buffer.set_size(&mut font_system, Some(40.0), Some(20.0));
+        buffer.shape_until_scroll(&mut font_system, false);
+        let clipped = BufferRevision::new(&buffer);
+        let mut scroll = buffer.scroll();
+        scroll.vertical = 20.0;
+        buffer.set_scroll(scroll);
+        buffer.shape_until_scroll(&mut font_system, false);
+        assert_ne!(BufferRevision::new(&buffer), clipped);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{Attrs, AttrsOwned, Buffer, Color, FontSystem, Shaping};
+use std::ops::Range;
+
+/// Builds text made of spans with different attributes, e.g. to mix colors and font weights in a
+/// single [`Buffer`].
+///
+/// This is a shorthand for the spans passed to [`Buffer::set_rich_text`], e.g.
+/// `RichText::new().push("bold", Attrs::new().weight(Weight::BOLD)).push_colored("red", red)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RichText {
+    default_attrs: AttrsOwned,
+    text: String,
+    spans: Vec<(Range<usize>, AttrsOwned)>,
+}
+
+impl RichText {
+    /// Creates an empty `RichText` with the default attributes.
+    pub fn new() -> Self {
+        Self::with_default_attrs(Attrs::new())
+    }
+
+    /// Creates an empty `RichText` with the given default attributes, used by
+    /// [`RichText::push_plain`] and [`RichText::push_colored`] and for text without spans.
+    pub fn with_default_attrs(default_attrs: Attrs) -> Self {
+        Self {
+            default_attrs: AttrsOwned::new(default_attrs),
+            text: String::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Appends `text` with the given attributes.
+    pub fn push(mut self, text: &str, attrs: Attrs) -> Self {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.spans
+            .push((start..self.text.len(), AttrsOwned::new(attrs)));
+        self
+    }
+
+    /// Appends `text` with the default attributes.
+    pub fn push_plain(self, text: &str) -> Self {
+        let attrs = self.default_attrs.clone();
+        self.push(text, attrs.as_attrs())
+    }
+
+    /// Appends `text` with the default attributes and the given color.
+    pub fn push_colored(self, text: &str, color: Color) -> Self {
+        let attrs = self.default_attrs.clone();
+        self.push(text, attrs.as_attrs().color(color))
+    }
+
+    /// Returns the text of all spans.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns `true` if no text was pushed.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Returns the spans of text with their attributes, in the form expected by
+    /// [`Buffer::set_rich_text`].
+    pub fn spans(&self) -> impl Iterator<Item = (&str, Attrs<'_>)> + '_ {
+        self.spans
+            .iter()
+            .map(|(range, attrs)| (&self.text[range.clone()], attrs.as_attrs()))
+    }
+
+    /// Replaces the text of `buffer` with the spans.
+    ///
+    /// The buffer needs to be shaped again afterwards, e.g. with [`Buffer::shape_until_scroll`].
+    pub fn apply(&self, buffer: &mut Buffer, font_system: &mut FontSystem, shaping: Shaping) {
+        buffer.set_rich_text(
+            font_system,
+            self.spans(),
+            self.default_attrs.as_attrs(),
+            shaping,
+        );
+    }
+}
+
+impl Default for RichText {
+    fn default() -> Self {
+        Self::new()
+    }
+}
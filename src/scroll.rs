@@ -0,0 +1,30 @@
+use crate::TextArea;
+
+impl TextArea<'_> {
+    /// Returns the text area moved left by the horizontal scroll of its buffer, e.g. to keep the
+    /// cursor of a single-line text input visible after
+    /// [`crate::Buffer::shape_until_cursor`]. Custom glyphs and decorations move with the text.
+    ///
+    /// The text is clipped to the width of the buffer at the original position of the text area,
+    /// in addition to [`TextArea::bounds`], so that text scrolled out of the input isn't drawn
+    /// next to it. The scroll is rounded to whole physical pixels, which keeps the subpixel
+    /// positions of the glyphs and the clipped edges in place while scrolling.
+    pub fn with_horizontal_scroll(mut self) -> Self {
+        if let Some(width) = self.buffer.size().0 {
+            self.bounds.left = self.bounds.left.max(self.left.floor() as i32);
+            self.bounds.right = self
+                .bounds
+                .right
+                .min((self.left + width * self.scale).ceil() as i32);
+        }
+
+        let physical_scale = self
+            .physical()
+            .integer_scale()
+            .map_or(self.scale * self.scale_factor, f32::from);
+        let scroll = (self.buffer.scroll().horizontal * physical_scale).round();
+        self.left -= scroll / self.scale_factor;
+
+        self
+    }
+}
@@ -6,6 +6,8 @@ struct VertexInput {
     @location(3) color: u32,
     @location(4) content_type_with_srgb: u32,
     @location(5) depth: f32,
+    @location(6) uv_dim: u32,
+    @location(7) repeat: u32,
 }
 
 struct VertexOutput {
@@ -13,11 +15,37 @@ struct VertexOutput {
     @location(0) color: vec4<f32>,
     @location(1) uv: vec2<f32>,
     @location(2) @interpolate(flat) content_type: u32,
+    @location(3) @interpolate(flat) linear_filter: u32,
+    // The texel coordinates that filtered samples are clamped to
+    @location(4) @interpolate(flat) uv_bounds: vec4<f32>,
+    @location(5) @interpolate(flat) srgb: u32,
+    @location(6) @interpolate(flat) saturation: f32,
+    // The width of each repeated tile and the distance between tiles in pixels, followed by the
+    // horizontal texture coordinate and size of the tile
+    @location(7) @interpolate(flat) repeat: vec4<f32>,
+    // The horizontal distance from the left edge of the quad in pixels
+    @location(8) quad_x: f32,
+    @location(9) @interpolate(flat) animation: u32,
+    // Color glyphs moved to the compressed color atlas
+    @location(10) @interpolate(flat) compressed: u32,
+    // The pick ID of the glyph, see `TextRenderer::set_pick_ids`
+    @location(11) @interpolate(flat) pick_id: u32,
 };
 
 struct Params {
     screen_resolution: vec2<u32>,
-    _pad: vec2<u32>,
+    depth_range: vec2<f32>,
+    // Converts linear sRGB colors to the primaries of the target
+    color_matrix: mat3x3<f32>,
+    convert_color_space: u32,
+    // Seconds, wrapped around every hour
+    time: f32,
+    dither: u32,
+    // Maps virtual pixels to pixels of the render target
+    letterbox_scale: u32,
+    letterbox_offset: vec2<i32>,
+    // Colors looked up by glyphs with a palette index, packed like the colors of glyphs
+    palette: array<vec4<u32>, 4>,
 };
 
 @group(0) @binding(0)
@@ -29,6 +57,9 @@ var mask_atlas_texture: texture_2d<f32>;
 @group(0) @binding(2)
 var atlas_sampler: sampler;
 
+@group(0) @binding(3)
+var compressed_color_atlas_texture: texture_2d<f32>;
+
 @group(1) @binding(0)
 var<uniform> params: Params;
 
@@ -40,13 +71,38 @@ fn srgb_to_linear(c: f32) -> f32 {
     }
 }
 
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        return c * 12.92;
+    } else {
+        return 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+    }
+}
+
 @vertex
 fn vs_main(in_vert: VertexInput) -> VertexOutput {
+    return glyph_vertex(in_vert);
+}
+
+// Replaces the color of glyphs that aren't color glyphs with their color override, if they have
+// one, see `TextRenderer::set_color_overrides`
+fn apply_color_override(in_vert: VertexInput, color_override: vec2<u32>) -> VertexInput {
+    var glyph = in_vert;
+    if color_override.y != 0u && (glyph.content_type_with_srgb & 0x7fu) != 0u {
+        glyph.color = color_override.x;
+        // The override replaces the palette color of the glyph
+        glyph.content_type_with_srgb &= ~0x00800000u;
+    }
+    return glyph;
+}
+
+fn glyph_vertex(in_vert: VertexInput) -> VertexOutput {
     var pos = in_vert.pos;
     let width = in_vert.dim & 0xffffu;
     let height = (in_vert.dim & 0xffff0000u) >> 16u;
-    let color = in_vert.color;
-    var uv = vec2<u32>(in_vert.uv & 0xffffu, (in_vert.uv & 0xffff0000u) >> 16u);
+    var color = in_vert.color;
+    let uv = vec2<u32>(in_vert.uv & 0xffffu, (in_vert.uv & 0xffff0000u) >> 16u);
+    let uv_dim = vec2<u32>(in_vert.uv_dim & 0xffffu, (in_vert.uv_dim & 0xffff0000u) >> 16u);
     let v = in_vert.vertex_idx;
 
     let corner_position = vec2<u32>(
@@ -56,21 +112,41 @@ fn vs_main(in_vert: VertexInput) -> VertexOutput {
 
     let corner_offset = vec2<u32>(width, height) * corner_position;
 
-    uv = uv + corner_offset;
     pos = pos + vec2<i32>(corner_offset);
 
     var vert_output: VertexOutput;
 
+    let target_pos = params.letterbox_offset + pos * i32(params.letterbox_scale);
+
     vert_output.position = vec4<f32>(
-        2.0 * vec2<f32>(pos) / vec2<f32>(params.screen_resolution) - 1.0,
-        in_vert.depth,
+        2.0 * vec2<f32>(target_pos) / vec2<f32>(params.screen_resolution) - 1.0,
+        mix(params.depth_range.x, params.depth_range.y, in_vert.depth),
         1.0,
     );
 
     vert_output.position.y *= -1.0;
 
-    let content_type = in_vert.content_type_with_srgb & 0xffffu;
-    let srgb = (in_vert.content_type_with_srgb & 0xffff0000u) >> 16u;
+    let content_type = in_vert.content_type_with_srgb & 0x7fu;
+    let compressed = (in_vert.content_type_with_srgb & 0x80u) >> 7u;
+    let saturation = f32((in_vert.content_type_with_srgb & 0x7f00u) >> 8u) / 127.0;
+    let linear_filter = (in_vert.content_type_with_srgb & 0x8000u) >> 15u;
+    let srgb = (in_vert.content_type_with_srgb & 0x00010000u) >> 16u;
+    let orientation = (in_vert.content_type_with_srgb & 0x000e0000u) >> 17u;
+    let palette = (in_vert.content_type_with_srgb & 0x00800000u) >> 23u;
+    let animation = (in_vert.content_type_with_srgb & 0xff000000u) >> 24u;
+
+    // Blinking glyphs are hidden during the second half of every second
+    if animation == 1u && fract(params.time) >= 0.5 {
+        vert_output.position = vec4<f32>(2.0, 2.0, 0.0, 1.0);
+    }
+
+    // Glyphs with a palette index store it in the low byte of their color, keeping their alpha
+    if palette == 1u {
+        let index = color & 0xfu;
+        let palette_color = params.palette[index / 4u][index % 4u];
+        let alpha = ((palette_color >> 24u) * (color >> 24u) + 127u) / 255u;
+        color = (palette_color & 0x00ffffffu) | (alpha << 24u);
+    }
 
     switch srgb {
         case 0u: {
@@ -94,8 +170,12 @@ fn vs_main(in_vert: VertexInput) -> VertexOutput {
 
     var dim: vec2<u32> = vec2(0u);
     switch content_type {
-        case 0u: {
-            dim = textureDimensions(color_atlas_texture);
+        case 0u, 2u: {
+            if compressed == 1u {
+                dim = textureDimensions(compressed_color_atlas_texture);
+            } else {
+                dim = textureDimensions(color_atlas_texture);
+            }
             break;
         }
         case 1u: {
@@ -106,20 +186,168 @@ fn vs_main(in_vert: VertexInput) -> VertexOutput {
     }
 
     vert_output.content_type = content_type;
+    vert_output.linear_filter = linear_filter;
+    vert_output.srgb = srgb;
+    vert_output.saturation = saturation;
+    vert_output.animation = animation;
+    vert_output.compressed = compressed;
 
-    vert_output.uv = vec2<f32>(uv) / vec2<f32>(dim);
+    let atlas_dim = vec2<f32>(dim);
+    // Rotated and mirrored glyphs swap and mirror the corners of their image
+    var image_corner = corner_position;
+    if (orientation & 1u) != 0u {
+        image_corner = image_corner.yx;
+    }
+    if (orientation & 2u) != 0u {
+        image_corner.x = 1u - image_corner.x;
+    }
+    if (orientation & 4u) != 0u {
+        image_corner.y = 1u - image_corner.y;
+    }
+    vert_output.uv = (vec2<f32>(uv) + vec2<f32>(uv_dim * image_corner)) / atlas_dim;
+
+    vert_output.repeat = vec4<f32>(
+        f32(in_vert.repeat & 0xffffu),
+        f32((in_vert.repeat & 0xffff0000u) >> 16u),
+        f32(uv.x) / atlas_dim.x,
+        f32(uv_dim.x) / atlas_dim.x,
+    );
+    vert_output.quad_x = f32(corner_offset.x);
+
+    // Keep filtered samples inside of the glyph so that neighboring glyphs don't bleed in.
+    vert_output.uv_bounds = vec4<f32>(vec2<f32>(uv) + 0.5, vec2<f32>(uv + uv_dim) - 0.5);
 
     return vert_output;
 }
 
+fn sample_atlas(atlas_texture: texture_2d<f32>, in_frag: VertexOutput) -> vec4<f32> {
+    if in_frag.linear_filter == 0u {
+        return textureSampleLevel(atlas_texture, atlas_sampler, in_frag.uv, 0.0);
+    }
+
+    // Filter manually instead of using a second sampler, since some backends don't allow a
+    // texture to be used with multiple samplers.
+    let dim = vec2<f32>(textureDimensions(atlas_texture));
+    let texel = clamp(in_frag.uv * dim, in_frag.uv_bounds.xy, in_frag.uv_bounds.zw) - 0.5;
+    let base = vec2<i32>(floor(texel));
+    let t = fract(texel);
+
+    let top = mix(
+        textureLoad(atlas_texture, base, 0),
+        textureLoad(atlas_texture, base + vec2<i32>(1, 0), 0),
+        t.x,
+    );
+    let bottom = mix(
+        textureLoad(atlas_texture, base + vec2<i32>(0, 1), 0),
+        textureLoad(atlas_texture, base + vec2<i32>(1, 1), 0),
+        t.x,
+    );
+
+    return mix(top, bottom, t.y);
+}
+
+fn sample_color_atlas(in_frag: VertexOutput) -> vec4<f32> {
+    if in_frag.compressed == 1u {
+        return sample_atlas(compressed_color_atlas_texture, in_frag);
+    }
+
+    return sample_atlas(color_atlas_texture, in_frag);
+}
+
+fn to_target_color_space(color: vec4<f32>, srgb: u32) -> vec4<f32> {
+    if params.convert_color_space == 0u {
+        return color;
+    }
+
+    // Without sRGB conversion, colors are still encoded with the sRGB transfer function
+    if srgb == 0u {
+        let linear = vec3<f32>(
+            srgb_to_linear(color.r),
+            srgb_to_linear(color.g),
+            srgb_to_linear(color.b),
+        );
+        let converted = clamp(params.color_matrix * linear, vec3<f32>(0.0), vec3<f32>(1.0));
+        return vec4<f32>(
+            linear_to_srgb(converted.r),
+            linear_to_srgb(converted.g),
+            linear_to_srgb(converted.b),
+            color.a,
+        );
+    }
+
+    return vec4<f32>(params.color_matrix * color.rgb, color.a);
+}
+
 @fragment
 fn fs_main(in_frag: VertexOutput) -> @location(0) vec4<f32> {
+    return fragment_color(in_frag);
+}
+
+fn fragment_color(in_frag: VertexOutput) -> vec4<f32> {
+    var frag = in_frag;
+
+    // Repeated quads show their tile at the start of every period, followed by a gap
+    if frag.repeat.y > 0.0 {
+        // Marching ants move by one period every second
+        var shift = 0.0;
+        if frag.animation == 2u {
+            shift = fract(params.time) * frag.repeat.y;
+        }
+        let offset = (frag.quad_x + frag.repeat.y - shift) % frag.repeat.y;
+        if offset >= frag.repeat.x {
+            discard;
+        }
+        frag.uv.x = frag.repeat.z + offset / frag.repeat.x * frag.repeat.w;
+    }
+
+    var color = glyph_color(frag);
+    if params.dither != 0u && frag.content_type == 1u {
+        color.a = dither_alpha(color.a, frag.position.xy, frag.srgb);
+    }
+
+    return to_target_color_space(color, frag.srgb);
+}
+
+// Returns a threshold in [0, 1) from a 4x4 Bayer matrix for the pixel at `position`
+fn bayer_threshold(position: vec2<f32>) -> f32 {
+    let p = vec2<u32>(position) & vec2<u32>(3u);
+    let x = p.x ^ p.y;
+    // Interleave the bits of `x ^ y` and `y`, in reverse order
+    let index = ((x & 1u) << 3u) | ((p.y & 1u) << 2u) | (x & 2u) | ((p.y & 2u) >> 1u);
+    return (f32(index) + 0.5) / 16.0;
+}
+
+fn dither_alpha(alpha: f32, position: vec2<f32>, srgb: u32) -> f32 {
+    if alpha <= 0.0 || alpha >= 1.0 {
+        return alpha;
+    }
+
+    let noise = (bayer_threshold(position) - 0.5) / 255.0;
+
+    // Blending in linear space is quantized in the sRGB encoding of the target, so dither in
+    // that encoding for steps of the same size across the whole edge
+    if srgb == 1u {
+        return srgb_to_linear(clamp(linear_to_srgb(alpha) + noise, 0.0, 1.0));
+    }
+
+    return clamp(alpha + noise, 0.0, 1.0);
+}
+
+fn glyph_color(in_frag: VertexOutput) -> vec4<f32> {
     switch in_frag.content_type {
         case 0u: {
-            return textureSampleLevel(color_atlas_texture, atlas_sampler, in_frag.uv, 0.0);
+            let sample = sample_color_atlas(in_frag);
+            let luminance = dot(sample.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+            let color = mix(vec3<f32>(luminance), sample.rgb, in_frag.saturation);
+            return vec4<f32>(color, sample.a) * in_frag.color;
         }
         case 1u: {
-            return vec4<f32>(in_frag.color.rgb, in_frag.color.a * textureSampleLevel(mask_atlas_texture, atlas_sampler, in_frag.uv, 0.0).x);
+            return vec4<f32>(in_frag.color.rgb, in_frag.color.a * sample_atlas(mask_atlas_texture, in_frag).x);
+        }
+        case 2u: {
+            let sample = sample_color_atlas(in_frag);
+            let luminance = dot(sample.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+            return vec4<f32>(in_frag.color.rgb * luminance, in_frag.color.a * sample.a);
         }
         default: {
             return vec4<f32>(0.0);
@@ -0,0 +1,152 @@
+use crate::{Attrs, AttrsOwned, Buffer, FontSystem, Metrics, Shaping};
+use lru::LruCache;
+use rustc_hash::FxHasher;
+use std::{
+    hash::{BuildHasherDefault, Hash, Hasher},
+    num::NonZeroUsize,
+};
+
+/// Identifies a text shaped by a [`ShapeCache`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShapedTextId(u64);
+
+struct Entry {
+    text: String,
+    attrs: AttrsOwned,
+    metrics: Metrics,
+    width: Option<f32>,
+    shaping: Shaping,
+    buffer: Buffer,
+}
+
+impl Entry {
+    fn matches(
+        &self,
+        text: &str,
+        attrs: Attrs,
+        metrics: Metrics,
+        width: Option<f32>,
+        shaping: Shaping,
+    ) -> bool {
+        self.text == text
+            && self.attrs.as_attrs() == attrs
+            && self.metrics == metrics
+            && self.width == width
+            && self.shaping == shaping
+    }
+}
+
+/// A cache of shaped [`Buffer`]s, keyed by their text, attributes, metrics and wrap width.
+///
+/// This is useful for immediate-mode GUIs that provide all of their text every frame: text that
+/// didn't change since it was last shaped reuses the same buffer instead of being shaped again.
+/// The least recently used buffers are evicted once the cache is full.
+///
+/// With the `shape-run-cache` feature, the shaped runs of lines are also cached in the
+/// [`FontSystem`], so that lines repeated across different texts (e.g. in many text areas of a
+/// long document) are only shaped once. This applies to all buffers shaped with the font system,
+/// not only to those of the cache. Old runs are removed by [`ShapeCache::trim`].
+pub struct ShapeCache {
+    entries: LruCache<ShapedTextId, Entry, BuildHasherDefault<FxHasher>>,
+    #[cfg(feature = "shape-run-cache")]
+    run_cache_frames: u64,
+}
+
+impl ShapeCache {
+    /// Creates a new `ShapeCache` holding at most `capacity` buffers.
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: LruCache::with_hasher(capacity, BuildHasherDefault::default()),
+            #[cfg(feature = "shape-run-cache")]
+            run_cache_frames: 1,
+        }
+    }
+
+    /// Returns a buffer containing `text` shaped with the given parameters, shaping it only if
+    /// it isn't cached yet.
+    ///
+    /// The buffer can be retrieved with [`ShapeCache::get`]. Shaping more distinct texts than the
+    /// capacity of the cache evicts the buffers that were used least recently, so the capacity
+    /// should be large enough for all text rendered in one frame.
+    pub fn shape(
+        &mut self,
+        font_system: &mut FontSystem,
+        text: &str,
+        attrs: Attrs,
+        metrics: Metrics,
+        width: Option<f32>,
+        shaping: Shaping,
+    ) -> ShapedTextId {
+        let mut hasher = FxHasher::default();
+        text.hash(&mut hasher);
+        attrs.hash(&mut hasher);
+        metrics.font_size.to_bits().hash(&mut hasher);
+        metrics.line_height.to_bits().hash(&mut hasher);
+        width.map(f32::to_bits).hash(&mut hasher);
+        shaping.hash(&mut hasher);
+        let id = ShapedTextId(hasher.finish());
+
+        if let Some(entry) = self.entries.get(&id) {
+            if entry.matches(text, attrs, metrics, width, shaping) {
+                return id;
+            }
+        }
+
+        let mut buffer = Buffer::new(font_system, metrics);
+        buffer.set_size(font_system, width, None);
+        buffer.set_text(font_system, text, attrs, shaping);
+        buffer.shape_until_scroll(font_system, false);
+
+        self.entries.put(
+            id,
+            Entry {
+                text: text.to_owned(),
+                attrs: AttrsOwned::new(attrs),
+                metrics,
+                width,
+                shaping,
+                buffer,
+            },
+        );
+
+        id
+    }
+
+    /// Returns the buffer shaped by [`ShapeCache::shape`] for `id`, if it wasn't evicted since.
+    pub fn get(&self, id: ShapedTextId) -> Option<&Buffer> {
+        self.entries.peek(&id).map(|entry| &entry.buffer)
+    }
+
+    /// Returns the number of cached buffers.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no buffers are cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes all cached buffers, e.g. after fonts were added to the [`FontSystem`].
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Sets the number of calls to [`ShapeCache::trim`] that runs are kept in the shape run cache
+    /// of the [`FontSystem`] without being shaped again. Defaults to 1, which keeps the runs
+    /// shaped during the current and the previous frame.
+    #[cfg(feature = "shape-run-cache")]
+    pub fn set_run_cache_frames(&mut self, frames: u64) {
+        self.run_cache_frames = frames;
+    }
+
+    /// Removes the runs from the shape run cache of `font_system` that weren't shaped during the
+    /// frames kept by [`ShapeCache::set_run_cache_frames`].
+    ///
+    /// This should be called once per frame, e.g. after [`crate::TextAtlas::trim`], since the
+    /// run cache grows without bounds otherwise.
+    #[cfg(feature = "shape-run-cache")]
+    pub fn trim(&mut self, font_system: &mut FontSystem) {
+        font_system.shape_run_cache.trim(self.run_cache_frames);
+    }
+}
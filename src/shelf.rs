@@ -0,0 +1,219 @@
+use etagere::AllocId;
+
+/// A region of an atlas texture reserved for small glyphs, see
+/// [`crate::TextAtlas::set_small_glyph_shelves`].
+///
+/// The region is split into shelves from the top down. Each shelf holds glyphs of a single height
+/// class, which are packed from left to right, so that small glyphs of similar heights waste
+/// little space above them.
+pub(crate) struct ShelfRegion {
+    /// The allocation of the region in the packer of the texture
+    pub id: AllocId,
+    pub x: u16,
+    pub y: u16,
+    size: u16,
+    shelves: Vec<Shelf>,
+    /// The top of the space below the shelves
+    shelves_bottom: u16,
+    /// The number of glyph images in the region
+    glyphs: usize,
+}
+
+struct Shelf {
+    y: u16,
+    height: u16,
+    /// The unused spans of the shelf as `(x, width)`, sorted by `x`
+    free: Vec<(u16, u16)>,
+    /// The number of glyph images in the shelf
+    glyphs: usize,
+}
+
+impl ShelfRegion {
+    /// Glyph heights are rounded up to a multiple of this to form height classes.
+    const HEIGHT_CLASS: u16 = 2;
+
+    pub(crate) fn new(id: AllocId, x: u16, y: u16, size: u16) -> Self {
+        Self {
+            id,
+            x,
+            y,
+            size,
+            shelves: Vec::new(),
+            shelves_bottom: 0,
+            glyphs: 0,
+        }
+    }
+
+    /// Allocates space for a glyph, returning the index of its shelf and its position in the
+    /// texture, or `None` if the region is full.
+    pub(crate) fn allocate(&mut self, width: u16, height: u16) -> Option<(u16, u16, u16)> {
+        let height = height.div_ceil(Self::HEIGHT_CLASS) * Self::HEIGHT_CLASS;
+
+        let shelf_index = self
+            .shelves
+            .iter()
+            .position(|shelf| shelf.height == height && shelf.fits(width))
+            .or_else(|| self.add_shelf(height))
+            .or_else(|| {
+                // Reuse the lowest empty shelf that is tall enough
+                self.shelves
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, shelf)| shelf.glyphs == 0 && shelf.height >= height)
+                    .min_by_key(|(_, shelf)| shelf.height)
+                    .map(|(index, _)| index)
+            })?;
+
+        let shelf = &mut self.shelves[shelf_index];
+        let x = shelf.allocate(width)?;
+        self.glyphs += 1;
+
+        Some((shelf_index as u16, self.x + x, self.y + shelf.y))
+    }
+
+    /// Frees the space of a glyph allocated at `x` in the texture, returning `true` if the region
+    /// is empty afterwards.
+    pub(crate) fn deallocate(&mut self, shelf: u16, x: u16, width: u16) -> bool {
+        self.shelves[shelf as usize].deallocate(x - self.x, width);
+        self.glyphs -= 1;
+
+        self.glyphs == 0
+    }
+
+    fn add_shelf(&mut self, height: u16) -> Option<usize> {
+        if self.size - self.shelves_bottom < height {
+            return None;
+        }
+
+        self.shelves.push(Shelf {
+            y: self.shelves_bottom,
+            height,
+            free: vec![(0, self.size)],
+            glyphs: 0,
+        });
+        self.shelves_bottom += height;
+
+        Some(self.shelves.len() - 1)
+    }
+}
+
+impl Shelf {
+    fn fits(&self, width: u16) -> bool {
+        self.free.iter().any(|&(_, free)| free >= width)
+    }
+
+    fn allocate(&mut self, width: u16) -> Option<u16> {
+        let index = self.free.iter().position(|&(_, free)| free >= width)?;
+        let (x, free) = &mut self.free[index];
+        let allocated = *x;
+        *x += width;
+        *free -= width;
+        if *free == 0 {
+            self.free.remove(index);
+        }
+        self.glyphs += 1;
+
+        Some(allocated)
+    }
+
+    fn deallocate(&mut self, x: u16, width: u16) {
+        self.glyphs -= 1;
+
+        // Merge the span with the unused spans next to it
+        let index = self.free.partition_point(|&(free_x, _)| free_x < x);
+        let merges_next = self
+            .free
+            .get(index)
+            .is_some_and(|&(next_x, _)| x + width == next_x);
+        let merges_prev = index > 0 && {
+            let (prev_x, prev_width) = self.free[index - 1];
+            prev_x + prev_width == x
+        };
+
+        match (merges_prev, merges_next) {
+            (true, true) => {
+                let (_, next_width) = self.free.remove(index);
+                self.free[index - 1].1 += width + next_width;
+            }
+            (true, false) => self.free[index - 1].1 += width,
+            (false, true) => {
+                let next = &mut self.free[index];
+                next.0 = x;
+                next.1 += width;
+            }
+            (false, false) => self.free.insert(index, (x, width)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etagere::{size2, BucketedAtlasAllocator};
+
+    fn region() -> ShelfRegion {
+        let mut packer = BucketedAtlasAllocator::new(size2(64, 64));
+        let id = packer.allocate(size2(32, 32)).unwrap().id;
+        ShelfRegion::new(id, 100, 200, 32)
+    }
+
+    #[test]
+    fn packs_glyphs_of_a_height_class_into_one_shelf() {
+        let mut region = region();
+        assert_eq!(region.allocate(5, 3), Some((0, 100, 200)));
+        assert_eq!(region.allocate(7, 4), Some((0, 105, 200)));
+        assert_eq!(region.allocate(5, 5), Some((1, 100, 204)));
+        // The rest of the first shelf is too narrow
+        assert_eq!(region.allocate(21, 4), Some((2, 100, 210)));
+        assert_eq!(region.allocate(20, 4), Some((0, 112, 200)));
+    }
+
+    #[test]
+    fn returns_none_when_full() {
+        let mut region = region();
+        for row in 0..2 {
+            for column in 0..2 {
+                assert_eq!(
+                    region.allocate(16, 16),
+                    Some((row, 100 + column * 16, 200 + row * 16))
+                );
+            }
+        }
+        assert_eq!(region.allocate(1, 1), None);
+    }
+
+    #[test]
+    fn reuses_freed_space() {
+        let mut region = region();
+        let (shelf, a, _) = region.allocate(8, 4).unwrap();
+        let (_, b, _) = region.allocate(8, 4).unwrap();
+        let (_, c, _) = region.allocate(8, 4).unwrap();
+        assert!(!region.deallocate(shelf, a, 8));
+        assert!(!region.deallocate(shelf, c, 8));
+        // The span of `c` is merged with the free space at the end of the shelf
+        assert_eq!(region.allocate(16, 4), Some((0, 116, 200)));
+        // The span of `b` is merged with the span of `a`
+        assert!(!region.deallocate(shelf, b, 8));
+        assert_eq!(region.allocate(16, 4), Some((0, 100, 200)));
+    }
+
+    #[test]
+    fn reuses_empty_shelves_for_other_heights() {
+        let mut region = region();
+        let (tall, x, _) = region.allocate(32, 16).unwrap();
+        assert_eq!(region.allocate(32, 16), Some((1, 100, 216)));
+        assert_eq!(region.allocate(4, 4), None);
+
+        assert!(!region.deallocate(tall, x, 32));
+        assert_eq!(region.allocate(4, 4), Some((0, 100, 200)));
+    }
+
+    #[test]
+    fn reports_empty_regions() {
+        let mut region = region();
+        let (first, a, _) = region.allocate(4, 4).unwrap();
+        let (second, b, _) = region.allocate(4, 8).unwrap();
+        assert!(!region.deallocate(first, a, 4));
+        assert!(region.deallocate(second, b, 4));
+    }
+}
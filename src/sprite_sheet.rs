@@ -0,0 +1,265 @@
+use crate::{
+    ContentType, CustomGlyphCache, CustomGlyphId, RasterizeCustomGlyphRequest,
+    RasterizedCustomGlyph,
+};
+use rustc_hash::FxHasher;
+use std::{collections::HashMap, hash::BuildHasherDefault};
+
+/// A rectangle of a sprite sheet in pixels, see [`SpriteSheetGlyphSystem::add_sprite`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpriteRect {
+    /// The left edge of the sprite
+    pub x: u32,
+    /// The top edge of the sprite
+    pub y: u32,
+    /// The width of the sprite
+    pub width: u32,
+    /// The height of the sprite
+    pub height: u32,
+}
+
+/// Rasterizes custom glyphs from the sprites of a single image, e.g. the icon sprite sheet of a
+/// game, so that existing sprite assets can be drawn inline with text.
+///
+/// Each sprite is a named rectangle of the sheet that is drawn as the custom glyph with the id it
+/// was added with. Use [`SpriteSheetGlyphSystem::rasterize`] as (part of) the
+/// `rasterize_custom_glyph` callback of [`crate::TextRenderer::prepare_with_custom`]. Sprites are
+/// cropped from the sheet and scaled to the size of the request by averaging the pixels each
+/// output pixel covers, and the results are cached, so glyphs that were evicted from the atlas
+/// can be uploaded again without scaling them again.
+pub struct SpriteSheetGlyphSystem {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    content_type: ContentType,
+    sprites: HashMap<CustomGlyphId, SpriteRect, BuildHasherDefault<FxHasher>>,
+    names: HashMap<String, CustomGlyphId>,
+    cache: CustomGlyphCache,
+}
+
+impl SpriteSheetGlyphSystem {
+    /// Creates a system for the sprite sheet `data` of `width` x `height` pixels, with rows of
+    /// pixels of `content_type` from top to bottom.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `data` doesn't match the size of the sheet.
+    pub fn new(data: Vec<u8>, width: u32, height: u32, content_type: ContentType) -> Self {
+        assert_eq!(
+            data.len(),
+            width as usize * height as usize * content_type.bytes_per_pixel(),
+            "Invalid sprite sheet data for a sheet of {width}x{height} pixels of {content_type:?}",
+        );
+
+        Self {
+            data,
+            width,
+            height,
+            content_type,
+            sprites: HashMap::default(),
+            names: HashMap::new(),
+            cache: CustomGlyphCache::new(),
+        }
+    }
+
+    /// Adds the sprite `name` in `rect` of the sheet, drawn as the custom glyph `id`.
+    ///
+    /// A sprite that was added with the same id or name before is replaced, along with its cached
+    /// images. Its images cached in atlases have to be removed with
+    /// [`crate::TextAtlas::evict_custom_glyph`] for the new sprite to be drawn.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rect` is empty or extends past the sheet.
+    pub fn add_sprite(&mut self, id: CustomGlyphId, name: impl Into<String>, rect: SpriteRect) {
+        assert!(
+            rect.width > 0
+                && rect.height > 0
+                && rect
+                    .x
+                    .checked_add(rect.width)
+                    .is_some_and(|right| right <= self.width)
+                && rect
+                    .y
+                    .checked_add(rect.height)
+                    .is_some_and(|bottom| bottom <= self.height),
+            "Sprite {rect:?} is outside of the sprite sheet of {}x{} pixels",
+            self.width,
+            self.height,
+        );
+
+        self.remove_sprite(id);
+        if let Some(previous) = self.names.insert(name.into(), id) {
+            self.sprites.remove(&previous);
+            self.cache.remove(previous);
+        }
+        self.sprites.insert(id, rect);
+    }
+
+    /// Removes the sprite drawn as the custom glyph `id` and its cached images, returning `true`
+    /// if there was one.
+    pub fn remove_sprite(&mut self, id: CustomGlyphId) -> bool {
+        if self.sprites.remove(&id).is_none() {
+            return false;
+        }
+
+        self.names.retain(|_, sprite| *sprite != id);
+        self.cache.remove(id);
+        true
+    }
+
+    /// Returns the id of the custom glyph the sprite `name` is drawn as, if it was added.
+    pub fn id(&self, name: &str) -> Option<CustomGlyphId> {
+        self.names.get(name).copied()
+    }
+
+    /// Returns the rectangle of the sheet the custom glyph `id` is drawn from, if it is a sprite.
+    pub fn sprite(&self, id: CustomGlyphId) -> Option<SpriteRect> {
+        self.sprites.get(&id).copied()
+    }
+
+    /// Returns the image of the sprite requested by `request`, or `None` if its id isn't a
+    /// sprite of this sheet.
+    ///
+    /// Subpixel offsets are ignored, so sprites are best drawn as custom glyphs that are snapped
+    /// to physical pixels.
+    pub fn rasterize(
+        &mut self,
+        request: RasterizeCustomGlyphRequest,
+    ) -> Option<RasterizedCustomGlyph> {
+        let rect = *self.sprites.get(&request.id)?;
+        if request.width == 0 || request.height == 0 {
+            return None;
+        }
+
+        self.cache.rasterize(request, |request| {
+            Some(RasterizedCustomGlyph {
+                data: scale_sprite(
+                    &self.data,
+                    self.width,
+                    self.content_type,
+                    rect,
+                    request.width,
+                    request.height,
+                ),
+                content_type: self.content_type,
+            })
+        })
+    }
+
+    /// Removes all cached images of sprites, e.g. to free memory once the sprites were uploaded
+    /// to the atlas.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+}
+
+/// Crops `rect` from the sheet and scales it to `width` x `height` pixels.
+///
+/// Each output pixel is the average of the sheet pixels it covers, weighted by how much of them
+/// it covers. Colors are averaged with premultiplied alpha, so that transparent pixels don't
+/// darken the edges of sprites.
+fn scale_sprite(
+    data: &[u8],
+    sheet_width: u32,
+    content_type: ContentType,
+    rect: SpriteRect,
+    width: u16,
+    height: u16,
+) -> Vec<u8> {
+    let channels = content_type.bytes_per_pixel();
+    let premultiply = channels == 4;
+    let (src_width, src_height) = (rect.width as usize, rect.height as usize);
+
+    let mut pixels = Vec::with_capacity(src_width * src_height * channels);
+    for row in 0..src_height {
+        let start = ((rect.y as usize + row) * sheet_width as usize + rect.x as usize) * channels;
+        for pixel in data[start..start + src_width * channels].chunks_exact(channels) {
+            let alpha = if premultiply {
+                pixel[3] as f32 / 255.0
+            } else {
+                1.0
+            };
+            pixels.extend(pixel.iter().enumerate().map(|(channel, &value)| {
+                if premultiply && channel < 3 {
+                    value as f32 * alpha
+                } else {
+                    value as f32
+                }
+            }));
+        }
+    }
+
+    // Scale the rows, then the columns
+    let (width, height) = (width as usize, height as usize);
+    let column_weights = area_weights(src_width, width);
+    let mut scaled_rows = vec![0.0; width * src_height * channels];
+    for row in 0..src_height {
+        for (x, weights) in column_weights.iter().enumerate() {
+            let out = (row * width + x) * channels;
+            for &(src_x, weight) in weights {
+                let src = (row * src_width + src_x) * channels;
+                for channel in 0..channels {
+                    scaled_rows[out + channel] += pixels[src + channel] * weight;
+                }
+            }
+        }
+    }
+
+    let row_weights = area_weights(src_height, height);
+    let mut scaled = vec![0.0; width * height * channels];
+    for (y, weights) in row_weights.iter().enumerate() {
+        for &(src_y, weight) in weights {
+            let src = src_y * width * channels;
+            let out = y * width * channels;
+            for (out, src) in scaled[out..out + width * channels]
+                .iter_mut()
+                .zip(&scaled_rows[src..src + width * channels])
+            {
+                *out += src * weight;
+            }
+        }
+    }
+
+    scaled
+        .chunks_exact(channels)
+        .flat_map(|pixel| {
+            let alpha = if premultiply { pixel[3] / 255.0 } else { 1.0 };
+            pixel.iter().enumerate().map(move |(channel, &value)| {
+                let value = if premultiply && channel < 3 {
+                    if alpha > 0.0 {
+                        value / alpha
+                    } else {
+                        0.0
+                    }
+                } else {
+                    value
+                };
+                value.round().clamp(0.0, 255.0) as u8
+            })
+        })
+        .collect()
+}
+
+/// Returns the source pixels each of `dst` output pixels covers when `src` pixels are stretched
+/// over them, with the part of the output pixel each of them covers.
+fn area_weights(src: usize, dst: usize) -> Vec<Vec<(usize, f32)>> {
+    let scale = src as f32 / dst as f32;
+
+    (0..dst)
+        .map(|index| {
+            let start = index as f32 * scale;
+            let end = (start + scale).min(src as f32);
+            let first = start.floor() as usize;
+            let last = (end.ceil() as usize).min(src);
+
+            (first..last)
+                .map(|src_index| {
+                    let covered = end.min(src_index as f32 + 1.0) - start.max(src_index as f32);
+                    (src_index, covered / scale)
+                })
+                .filter(|&(_, weight)| weight > 0.0)
+                .collect()
+        })
+        .collect()
+}
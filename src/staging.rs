@@ -0,0 +1,125 @@
+use crate::Cache;
+use std::sync::{Arc, Mutex, PoisonError};
+use wgpu::{util::align_to, Buffer, BufferDescriptor, BufferUsages, Device, MapMode};
+
+/// A staging buffer that is sub-allocated by writes, see [`StagingRing`].
+struct Chunk {
+    buffer: Buffer,
+    size: u64,
+    offset: u64,
+}
+
+/// A ring of staging buffers for copies recorded into command encoders, like
+/// [`wgpu::util::StagingBelt`] but also usable for copies to textures.
+///
+/// Chunks are filled by [`StagingRing::write`], unmapped by [`StagingRing::finish`] once the
+/// copies are recorded and mapped again by [`StagingRing::recall`] after they were submitted, so
+/// that they can be reused once the GPU is done copying from them.
+pub(crate) struct StagingRing {
+    chunk_size: u64,
+    /// Chunks that are mapped and can be written to
+    active_chunks: Vec<Chunk>,
+    /// Chunks that were unmapped for copies that may not have been submitted yet
+    closed_chunks: Vec<Chunk>,
+    /// Chunks that the GPU is done with and that are mapped again, filled by the callbacks of
+    /// `map_async`
+    free_chunks: Arc<Mutex<Vec<Chunk>>>,
+}
+
+impl StagingRing {
+    /// The alignment of writes, which is enough for copies to buffers and textures.
+    const ALIGNMENT: u64 = wgpu::COPY_BUFFER_ALIGNMENT * 2;
+
+    pub(crate) fn new(chunk_size: u64) -> Self {
+        Self {
+            chunk_size,
+            active_chunks: Vec::new(),
+            closed_chunks: Vec::new(),
+            free_chunks: Arc::default(),
+        }
+    }
+
+    /// Writes `size` bytes with `fill` and returns the staging buffer and the offset of the bytes
+    /// in it, to be copied by a command recorded before [`StagingRing::finish`] is called.
+    pub(crate) fn write(
+        &mut self,
+        device: &Device,
+        cache: &Cache,
+        size: u64,
+        fill: impl FnOnce(&mut [u8]),
+    ) -> (Buffer, u64) {
+        let index = match self
+            .active_chunks
+            .iter()
+            .position(|chunk| chunk.offset + size <= chunk.size)
+        {
+            Some(index) => index,
+            None => {
+                let mut free_chunks = self
+                    .free_chunks
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let chunk = match free_chunks.iter().position(|chunk| size <= chunk.size) {
+                    Some(index) => free_chunks.swap_remove(index),
+                    None => {
+                        let size = align_to(self.chunk_size.max(size), Self::ALIGNMENT);
+                        Chunk {
+                            buffer: device.create_buffer(&BufferDescriptor {
+                                label: Some(&cache.label("staging ring")),
+                                size,
+                                usage: BufferUsages::MAP_WRITE | BufferUsages::COPY_SRC,
+                                mapped_at_creation: true,
+                            }),
+                            size,
+                            offset: 0,
+                        }
+                    }
+                };
+                self.active_chunks.push(chunk);
+                self.active_chunks.len() - 1
+            }
+        };
+
+        let chunk = &mut self.active_chunks[index];
+        let offset = chunk.offset;
+        chunk.offset = align_to(offset + size, Self::ALIGNMENT);
+        if size > 0 {
+            fill(
+                &mut chunk
+                    .buffer
+                    .slice(offset..offset + size)
+                    .get_mapped_range_mut(),
+            );
+        }
+
+        (chunk.buffer.clone(), offset)
+    }
+
+    /// Unmaps the chunks written to since the last call, which has to happen before the commands
+    /// copying from them are submitted.
+    pub(crate) fn finish(&mut self) {
+        for chunk in self.active_chunks.drain(..) {
+            chunk.buffer.unmap();
+            self.closed_chunks.push(chunk);
+        }
+    }
+
+    /// Maps the chunks closed by [`StagingRing::finish`] again, so that they can be reused once the
+    /// GPU is done copying from them. The commands copying from them must have been submitted.
+    pub(crate) fn recall(&mut self) {
+        for mut chunk in self.closed_chunks.drain(..) {
+            let free_chunks = self.free_chunks.clone();
+            let buffer = chunk.buffer.clone();
+            buffer.slice(..).map_async(MapMode::Write, move |result| {
+                // Chunks that fail to map are dropped, and replaced by new chunks when needed
+                if result.is_ok() {
+                    chunk.offset = 0;
+                    free_chunks
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push(chunk);
+                }
+            });
+        }
+    }
+}
@@ -1,16 +1,29 @@
 use crate::{
-    text_render::GlyphonCacheKey, Cache, ContentType, FontSystem, GlyphDetails, GpuCacheStatus,
-    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, SwashCache,
+    cache::PipelineFeatures, compress::CompressedPage, shelf::ShelfRegion, staging::StagingRing,
+    text_render::GlyphonCacheKey, BlendMode, Cache, ColorModeError, ContentType, CustomGlyphId,
+    GlyphDetails, GpuCacheStatus, PrepareError,
 };
-use etagere::{size2, Allocation, BucketedAtlasAllocator};
+use etagere::{size2, AllocId, BucketedAtlasAllocator};
 use lru::LruCache;
 use rustc_hash::FxHasher;
-use std::{collections::HashSet, hash::BuildHasherDefault};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    hash::BuildHasherDefault,
+    mem,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
 use wgpu::{
-    BindGroup, DepthStencilState, Device, Extent3d, MultisampleState, Origin3d, Queue,
-    RenderPipeline, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
-    TextureViewDescriptor,
+    util::align_to, Backend, BindGroup, BufferAsyncError, BufferDescriptor, BufferUsages,
+    CommandEncoder, CommandEncoderDescriptor, DepthStencilState, Device, Extent3d, Maintain,
+    MapMode, MultisampleState, Origin3d, Queue, RenderPipeline, TexelCopyBufferInfo,
+    TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 
 type Hasher = BuildHasherDefault<FxHasher>;
@@ -23,39 +36,117 @@ pub(crate) struct InnerAtlas {
     pub packer: BucketedAtlasAllocator,
     pub size: u32,
     pub glyph_cache: LruCache<GlyphonCacheKey, GlyphDetails, Hasher>,
-    pub glyphs_in_use: HashSet<GlyphonCacheKey, Hasher>,
+    /// The glyphs in use, with a bit for every partition using the glyph, see
+    /// [`AtlasPartition`]
+    pub glyphs_in_use: HashMap<GlyphonCacheKey, u64, Hasher>,
+    /// Glyphs that were in use before an incremental trim started, and are still protected until
+    /// the trim removes them.
+    pub glyphs_being_trimmed: HashSet<GlyphonCacheKey, Hasher>,
     pub max_texture_dimension_2d: u32,
+    pub growth_count: u32,
+    pub eviction_count: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub pending_uploads: Vec<PendingUpload>,
+    pub eviction_policy: EvictionPolicy,
+    pub upload_strategy: UploadStrategy,
+    pub growth_policy: GrowthPolicy,
+    /// Copies to be recorded into a command encoder instead of being submitted, while preparing
+    /// with [`crate::TextRenderer::prepare_with_encoder`]
+    pub deferred_copies: Option<Vec<DeferredCopy>>,
+    /// Images shared by custom glyphs with identical images, by the hash of their content
+    pub shared_images: HashMap<u64, SharedImage, Hasher>,
+    /// Whether small glyphs are packed into shelf regions, see
+    /// [`TextAtlas::set_small_glyph_shelves`]
+    pub small_glyph_shelves: bool,
+    /// The regions of the texture reserved for small glyphs, in the order they were allocated
+    pub shelf_regions: Vec<ShelfRegion>,
+}
+
+/// The space of a cached glyph in an atlas texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AtlasSlot {
+    /// Space allocated from the packer of the texture
+    Packer(AllocId),
+    /// Space at `x` in a shelf of the shelf region that was allocated from the packer as
+    /// `region`
+    Shelf {
+        region: AllocId,
+        shelf: u16,
+        x: u16,
+        width: u16,
+    },
+}
+
+/// An image in the atlas shared by all custom glyphs with the same content, see
+/// [`TextAtlas::set_deduplicate_custom_glyphs`].
+pub(crate) struct SharedImage {
+    atlas_id: AtlasSlot,
+    x: u16,
+    y: u16,
+    /// The number of cached glyphs using the image
+    users: usize,
+}
+
+/// A newly cached glyph that still needs to be copied into the atlas texture.
+pub(crate) struct PendingUpload {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// A copy to an atlas texture that is recorded into a command encoder by
+/// [`TextAtlas::record_deferred_copies`].
+pub(crate) enum DeferredCopy {
+    /// The image of a glyph copied to `texture`
+    Upload {
+        texture: Texture,
+        upload: PendingUpload,
+    },
+    /// The cached glyphs copied to a larger texture when the atlas grows
+    Grow {
+        from: Texture,
+        to: Texture,
+        size: u32,
+    },
 }
 
 impl InnerAtlas {
     const INITIAL_SIZE: u32 = 256;
 
-    fn new(device: &Device, _queue: &Queue, kind: Kind) -> Self {
+    /// The largest width and height of glyphs packed into shelf regions.
+    const SMALL_GLYPH_SIZE: usize = 16;
+
+    /// The width and height of shelf regions.
+    const SHELF_REGION_SIZE: i32 = 64;
+
+    /// Atlas textures are copied to a larger texture when the atlas grows.
+    const TEXTURE_USAGES: TextureUsages = TextureUsages::TEXTURE_BINDING
+        .union(TextureUsages::COPY_DST)
+        .union(TextureUsages::COPY_SRC);
+
+    fn new(
+        device: &Device,
+        _queue: &Queue,
+        cache: &Cache,
+        kind: Kind,
+        memory: &MemoryTracker,
+    ) -> Self {
         let max_texture_dimension_2d = device.limits().max_texture_dimension_2d;
         let size = Self::INITIAL_SIZE.min(max_texture_dimension_2d);
+        memory.add(kind.texture_bytes(size));
 
         let packer = BucketedAtlasAllocator::new(size2(size as i32, size as i32));
 
         // Create a texture to use for our atlas
-        let texture = device.create_texture(&TextureDescriptor {
-            label: Some("glyphon atlas"),
-            size: Extent3d {
-                width: size,
-                height: size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: kind.texture_format(),
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
+        let texture = Self::create_texture(device, cache, kind, size);
 
         let texture_view = texture.create_view(&TextureViewDescriptor::default());
 
         let glyph_cache = LruCache::unbounded_with_hasher(Hasher::default());
-        let glyphs_in_use = HashSet::with_hasher(Hasher::default());
+        let glyphs_in_use = HashMap::with_hasher(Hasher::default());
 
         Self {
             kind,
@@ -65,163 +156,881 @@ impl InnerAtlas {
             size,
             glyph_cache,
             glyphs_in_use,
+            glyphs_being_trimmed: HashSet::with_hasher(Hasher::default()),
             max_texture_dimension_2d,
+            growth_count: 0,
+            eviction_count: 0,
+            cache_hits: 0,
+            cache_misses: 0,
+            pending_uploads: Vec::new(),
+            eviction_policy: EvictionPolicy::default(),
+            upload_strategy: UploadStrategy::default(),
+            growth_policy: GrowthPolicy::default(),
+            deferred_copies: None,
+            shared_images: HashMap::default(),
+            small_glyph_shelves: false,
+            shelf_regions: Vec::new(),
         }
     }
 
-    pub(crate) fn try_allocate(&mut self, width: usize, height: usize) -> Option<Allocation> {
-        let size = size2(width as i32, height as i32);
+    fn create_texture(device: &Device, cache: &Cache, kind: Kind, size: u32) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some(&cache.generation_label(kind.atlas_name(), size)),
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: kind.texture_format(),
+            usage: Self::TEXTURE_USAGES,
+            view_formats: &[],
+        })
+    }
+
+    /// The size of a newly created texture.
+    pub(crate) fn initial_size(&self) -> u32 {
+        Self::INITIAL_SIZE.min(self.max_texture_dimension_2d)
+    }
 
+    /// Allocates space for a glyph cached by `partition`, evicting glyphs until it fits.
+    pub(crate) fn try_allocate(
+        &mut self,
+        width: usize,
+        height: usize,
+        partition: AtlasPartition,
+    ) -> Option<(AtlasSlot, u16, u16)> {
+        let mut scan = EvictionScan::new(partition);
         loop {
-            let allocation = self.packer.allocate(size);
+            let allocation = self.allocate(width, height);
 
             if allocation.is_some() {
                 return allocation;
             }
 
-            // Try to free least recently used allocation
-            let (mut key, mut value) = self.glyph_cache.peek_lru()?;
+            // All sized glyphs are in use, cache is full
+            let key = self.next_eviction(&mut scan)?;
 
-            // Find a glyph with an actual size
-            while value.atlas_id.is_none() {
-                // All sized glyphs are in use, cache is full
-                if self.glyphs_in_use.contains(key) {
-                    return None;
-                }
+            let value = self.glyph_cache.pop(&key).unwrap();
+            self.release(&value);
+            self.eviction_count += 1;
+        }
+    }
+
+    /// Allocates space for a glyph without evicting glyphs, returning its slot and position.
+    fn allocate(&mut self, width: usize, height: usize) -> Option<(AtlasSlot, u16, u16)> {
+        let size = size2(width as i32, height as i32);
 
-                let _ = self.glyph_cache.pop_lru();
+        let small = width <= Self::SMALL_GLYPH_SIZE && height <= Self::SMALL_GLYPH_SIZE;
+        if !self.small_glyph_shelves || !small || self.size < Self::SHELF_REGION_SIZE as u32 {
+            let allocation = self.packer.allocate(size)?;
+            let min = allocation.rectangle.min;
+            return Some((AtlasSlot::Packer(allocation.id), min.x as u16, min.y as u16));
+        }
+
+        // Small glyphs only go into shelf regions, so that they don't fragment the space of
+        // larger glyphs
+        let (width, height) = (width as u16, height as u16);
+        let allocation = self.shelf_regions.iter_mut().find_map(|region| {
+            let (shelf, x, y) = region.allocate(width, height)?;
+            Some((region.id, shelf, x, y))
+        });
+
+        let (region, shelf, x, y) = match allocation {
+            Some(allocation) => allocation,
+            None => {
+                let region_size = size2(Self::SHELF_REGION_SIZE, Self::SHELF_REGION_SIZE);
+                let allocation = self.packer.allocate(region_size)?;
+                let min = allocation.rectangle.min;
+                let mut region = ShelfRegion::new(
+                    allocation.id,
+                    min.x as u16,
+                    min.y as u16,
+                    Self::SHELF_REGION_SIZE as u16,
+                );
+                let (shelf, x, y) = region.allocate(width, height).unwrap();
+                self.shelf_regions.push(region);
 
-                (key, value) = self.glyph_cache.peek_lru()?;
+                (allocation.id, shelf, x, y)
             }
+        };
 
-            // All sized glyphs are in use, cache is full
-            if self.glyphs_in_use.contains(key) {
-                return None;
+        let slot = AtlasSlot::Shelf {
+            region,
+            shelf,
+            x,
+            width,
+        };
+        Some((slot, x, y))
+    }
+
+    /// Frees the space of an evicted glyph, unless its image is still shared with other glyphs.
+    fn release(&mut self, details: &GlyphDetails) {
+        let Some(atlas_id) = details.atlas_id else {
+            return;
+        };
+
+        if let Some(hash) = details.content_hash {
+            let shared = self.shared_images.get_mut(&hash).unwrap();
+            shared.users -= 1;
+            if shared.users > 0 {
+                return;
+            }
+            self.shared_images.remove(&hash);
+        }
+
+        match atlas_id {
+            AtlasSlot::Packer(id) => self.packer.deallocate(id),
+            AtlasSlot::Shelf {
+                region,
+                shelf,
+                x,
+                width,
+            } => {
+                let index = self
+                    .shelf_regions
+                    .iter()
+                    .position(|shelf_region| shelf_region.id == region)
+                    .unwrap();
+
+                // Empty regions are returned to the packer, e.g. for larger glyphs
+                if self.shelf_regions[index].deallocate(shelf, x, width) {
+                    self.shelf_regions.remove(index);
+                    self.packer.deallocate(region);
+                }
             }
+        }
+    }
+
+    /// Returns the position of the shared image with the content `hash` and adds a user to it, or
+    /// `None` if there is no such image.
+    pub(crate) fn share_image(&mut self, hash: u64) -> Option<(AtlasSlot, u16, u16)> {
+        let shared = self.shared_images.get_mut(&hash)?;
+        shared.users += 1;
+        Some((shared.atlas_id, shared.x, shared.y))
+    }
+
+    /// Makes a newly allocated image with the content `hash` available to other glyphs.
+    pub(crate) fn insert_shared_image(&mut self, hash: u64, atlas_id: AtlasSlot, x: u16, y: u16) {
+        self.shared_images.insert(
+            hash,
+            SharedImage {
+                atlas_id,
+                x,
+                y,
+                users: 1,
+            },
+        );
+    }
+
+    /// Returns the cached glyph to evict according to the eviction policy, or `None` if all
+    /// glyphs with an actual size are in use, and removes it from `scan`.
+    ///
+    /// Glyphs cached by the partition of `scan` are evicted before the glyphs of other partitions,
+    /// so that a partition with a lot of churn doesn't evict the glyphs of the others.
+    fn next_eviction(&self, scan: &mut EvictionScan) -> Option<GlyphonCacheKey> {
+        let count = self.eviction_policy.candidates();
+        if scan.own < count && !scan.complete {
+            // Gather twice as many candidates as needed, so that the cache is only skipped up to
+            // the end of the scan after every `count` evictions
+            self.continue_scan(scan, count * 2);
+        }
+
+        let partition = scan.partition;
+        let index = if scan.own > 0 {
+            self.pick_eviction(
+                scan.candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, _, candidate_partition))| *candidate_partition == partition),
+            )
+        } else {
+            self.pick_eviction(scan.candidates.iter().enumerate())
+        }?;
+
+        let (key, _, candidate_partition) = scan.candidates.remove(index)?;
+        if candidate_partition == partition {
+            scan.own -= 1;
+        }
+        // Evicting the glyph removes it from the scanned part of the cache
+        scan.scanned -= 1;
+
+        Some(key)
+    }
 
-            let (_, value) = self.glyph_cache.pop_lru().unwrap();
-            self.packer.deallocate(value.atlas_id.unwrap());
+    /// Gathers the glyphs that may be evicted from the least recently used end of the glyph
+    /// cache, until `scan` holds `target` glyphs of its partition or the whole cache was scanned.
+    ///
+    /// Partitions are trimmed separately, so glyphs used less recently than a protected glyph may
+    /// still be evicted.
+    fn continue_scan(&self, scan: &mut EvictionScan, target: usize) {
+        for (key, details) in self.glyph_cache.iter().rev().skip(scan.scanned) {
+            scan.scanned += 1;
+            if details.atlas_id.is_none() || self.is_protected(key) {
+                continue;
+            }
+
+            let candidate = EvictionCandidate {
+                width: details.width,
+                height: details.height,
+                age: 0,
+            };
+            scan.candidates
+                .push_back((*key, candidate, details.partition));
+            if details.partition == scan.partition {
+                scan.own += 1;
+                if scan.own >= target {
+                    return;
+                }
+            }
         }
+        scan.complete = true;
+    }
+
+    /// Picks the glyph to evict among `candidates` according to the eviction policy, from the
+    /// least recently used one, returning its index in the scan.
+    fn pick_eviction<'a>(
+        &self,
+        candidates: impl Iterator<
+            Item = (
+                usize,
+                &'a (GlyphonCacheKey, EvictionCandidate, AtlasPartition),
+            ),
+        >,
+    ) -> Option<usize> {
+        let candidates = candidates
+            .enumerate()
+            .map(|(age, (index, (_, candidate, _)))| {
+                (index, EvictionCandidate { age, ..*candidate })
+            });
+
+        let count = self.eviction_policy.candidates();
+        let mut candidates = candidates.take(count);
+        let (index, _) = match &self.eviction_policy {
+            EvictionPolicy::LeastRecentlyUsed => candidates.next(),
+            EvictionPolicy::SizeWeighted { .. } => candidates
+                .max_by_key(|(_, candidate)| candidate.area() * (count - candidate.age) as u64),
+            EvictionPolicy::Custom { score, .. } => {
+                candidates.max_by(|(_, a), (_, b)| score(a).total_cmp(&score(b)))
+            }
+        }?;
+
+        Some(index)
+    }
+
+    /// Returns `true` if the glyph may not be evicted because it is still needed for rendering.
+    fn is_protected(&self, key: &GlyphonCacheKey) -> bool {
+        self.glyphs_in_use.contains_key(key) || self.glyphs_being_trimmed.contains(key)
+    }
+
+    /// Marks a cached glyph as in use by `partition`.
+    pub(crate) fn mark_in_use(&mut self, key: GlyphonCacheKey, partition: AtlasPartition) {
+        *self.glyphs_in_use.entry(key).or_default() |= partition.bit();
     }
 
     pub fn num_channels(&self) -> usize {
         self.kind.num_channels()
     }
 
+    /// Queues the image of a newly cached glyph to be copied to the texture by
+    /// [`InnerAtlas::flush_uploads`].
+    pub(crate) fn queue_upload(&mut self, x: u32, y: u32, width: u32, height: u32, data: Vec<u8>) {
+        self.pending_uploads.push(PendingUpload {
+            x,
+            y,
+            width,
+            height,
+            data,
+        });
+    }
+
+    /// Copies all pending glyph images to the texture.
+    ///
+    /// With [`UploadStrategy::Batched`], when many glyphs were cached at once (e.g. when a large
+    /// document is first prepared), the images are gathered into one staging buffer and copied
+    /// with a single submission, instead of issuing a separate texture write for every glyph.
+    ///
+    /// While copies are deferred, the uploads are moved to the deferred copies instead.
+    fn flush_uploads(&mut self, device: &Device, queue: &Queue, cache: &Cache) {
+        if self.pending_uploads.is_empty() {
+            return;
+        }
+
+        if let Some(deferred_copies) = &mut self.deferred_copies {
+            deferred_copies.extend(self.pending_uploads.drain(..).map(|upload| {
+                DeferredCopy::Upload {
+                    texture: self.texture.clone(),
+                    upload,
+                }
+            }));
+            return;
+        }
+
+        trace_span!("upload_glyphs", count = self.pending_uploads.len());
+        let num_channels = self.num_channels() as u32;
+
+        let (batch_threshold, pad_rows) = match self.upload_strategy.resolve() {
+            UploadStrategy::Writes => (usize::MAX, false),
+            UploadStrategy::PaddedWrites => (usize::MAX, true),
+            UploadStrategy::Batched { threshold } => (threshold, false),
+            UploadStrategy::Auto => unreachable!(),
+        };
+
+        if self.pending_uploads.len() <= batch_threshold {
+            for upload in self.pending_uploads.drain(..) {
+                let row_size = upload.width * num_channels;
+                // `u32::is_multiple_of` needs Rust 1.87
+                #[allow(clippy::manual_is_multiple_of)]
+                let (data, bytes_per_row) =
+                    if pad_rows && row_size % COPY_BYTES_PER_ROW_ALIGNMENT != 0 {
+                        let padded_row = align_to(row_size, COPY_BYTES_PER_ROW_ALIGNMENT);
+                        let mut data = vec![0; (padded_row * upload.height) as usize];
+                        write_padded_rows(
+                            &mut data,
+                            &upload.data,
+                            row_size as usize,
+                            padded_row as usize,
+                        );
+                        (data, padded_row)
+                    } else {
+                        (upload.data, row_size)
+                    };
+
+                queue.write_texture(
+                    TexelCopyTextureInfo {
+                        texture: &self.texture,
+                        mip_level: 0,
+                        origin: Origin3d {
+                            x: upload.x,
+                            y: upload.y,
+                            z: 0,
+                        },
+                        aspect: TextureAspect::All,
+                    },
+                    &data,
+                    TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: None,
+                    },
+                    Extent3d {
+                        width: upload.width,
+                        height: upload.height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+
+            return;
+        }
+
+        // Buffer to texture copies require rows to be aligned
+        let padded_row = |upload: &PendingUpload| {
+            align_to(upload.width * num_channels, COPY_BYTES_PER_ROW_ALIGNMENT)
+        };
+        let staging_size = self
+            .pending_uploads
+            .iter()
+            .map(|upload| padded_row(upload) as u64 * upload.height as u64)
+            .sum();
+
+        let staging_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some(&cache.label("atlas staging")),
+            size: staging_size,
+            usage: BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some(&cache.label("atlas upload")),
+        });
+
+        {
+            let mut staging = staging_buffer.slice(..).get_mapped_range_mut();
+            let mut offset = 0;
+
+            for upload in &self.pending_uploads {
+                let row_size = (upload.width * num_channels) as usize;
+                let padded_row = padded_row(upload);
+
+                let end = offset as usize + padded_row as usize * upload.height as usize;
+                write_padded_rows(
+                    &mut staging[offset as usize..end],
+                    &upload.data,
+                    row_size,
+                    padded_row as usize,
+                );
+                copy_upload(
+                    &mut encoder,
+                    &staging_buffer,
+                    offset,
+                    padded_row,
+                    &self.texture,
+                    upload,
+                );
+
+                offset += padded_row as u64 * upload.height as u64;
+            }
+        }
+
+        staging_buffer.unmap();
+        queue.submit(Some(encoder.finish()));
+        self.pending_uploads.clear();
+    }
+
     pub(crate) fn grow(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        font_system: &mut FontSystem,
-        cache: &mut SwashCache,
-        scale_factor: f32,
-        mut rasterize_custom_glyph: impl FnMut(
-            RasterizeCustomGlyphRequest,
-        ) -> Option<RasterizedCustomGlyph>,
-    ) -> bool {
-        if self.size >= self.max_texture_dimension_2d {
-            return false;
-        }
-
-        // Grow each dimension by a factor of 2. The growth factor was chosen to match the growth
-        // factor of `Vec`.`
-        const GROWTH_FACTOR: u32 = 2;
-        let new_size = (self.size * GROWTH_FACTOR).min(self.max_texture_dimension_2d);
+        cache: &Cache,
+        memory: &MemoryTracker,
+    ) -> Result<(), PrepareError> {
+        trace_span!("grow_atlas", size = self.size);
+
+        let new_size = match self.growth_policy {
+            GrowthPolicy::Double => self.size.saturating_mul(2),
+            GrowthPolicy::Linear(step) => self.size.saturating_add(step),
+            GrowthPolicy::Never => self.size,
+        }
+        .min(self.max_texture_dimension_2d);
+
+        if new_size <= self.size {
+            return Err(PrepareError::AtlasFull);
+        }
+
+        if !memory.try_reserve(self.kind.texture_bytes(new_size) - self.gpu_memory_usage()) {
+            return Err(PrepareError::MemoryBudgetExceeded);
+        }
 
         self.packer.grow(size2(new_size as i32, new_size as i32));
 
+        // Pending uploads target the old texture, which is copied to the new one
+        self.flush_uploads(device, queue, cache);
+
         // Create a texture to use for our atlas
-        self.texture = device.create_texture(&TextureDescriptor {
-            label: Some("glyphon atlas"),
-            size: Extent3d {
-                width: new_size,
-                height: new_size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: self.kind.texture_format(),
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
+        let texture = Self::create_texture(device, cache, self.kind, new_size);
 
-        // Re-upload glyphs
-        for (&cache_key, glyph) in &self.glyph_cache {
-            let (x, y) = match glyph.gpu_cache {
-                GpuCacheStatus::InAtlas { x, y, .. } => (x, y),
-                GpuCacheStatus::SkipRasterization => continue,
-            };
+        // Copy the cached glyphs on the GPU instead of rasterizing them again, so that growing
+        // doesn't depend on how glyphs were rasterized
+        match &mut self.deferred_copies {
+            Some(deferred_copies) => deferred_copies.push(DeferredCopy::Grow {
+                from: self.texture.clone(),
+                to: texture.clone(),
+                size: self.size,
+            }),
+            None => {
+                let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some(&cache.label("atlas grow")),
+                });
+                copy_to_grown_texture(&mut encoder, &self.texture, &texture, self.size);
+                queue.submit(Some(encoder.finish()));
+            }
+        }
+
+        self.texture = texture;
+        self.texture_view = self.texture.create_view(&TextureViewDescriptor::default());
+        self.size = new_size;
+        self.growth_count += 1;
 
-            let (image_data, width, height) = match cache_key {
-                GlyphonCacheKey::Text(cache_key) => {
-                    let image = cache.get_image_uncached(font_system, cache_key).unwrap();
-                    let width = image.placement.width as usize;
-                    let height = image.placement.height as usize;
+        Ok(())
+    }
 
-                    (image.data, width, height)
-                }
-                GlyphonCacheKey::Custom(cache_key) => {
-                    let input = RasterizeCustomGlyphRequest {
-                        id: cache_key.glyph_id,
-                        width: cache_key.width,
-                        height: cache_key.height,
-                        x_bin: cache_key.x_bin,
-                        y_bin: cache_key.y_bin,
-                        scale: scale_factor,
-                    };
+    /// Removes all cached images of the custom glyphs for which `evict` returns `true`, returning
+    /// how many images were removed.
+    fn evict_custom_glyphs(&mut self, evict: impl Fn(CustomGlyphId) -> bool) -> usize {
+        let keys: Vec<GlyphonCacheKey> = self
+            .glyph_cache
+            .iter()
+            .map(|(key, _)| *key)
+            .filter(|key| matches!(key, GlyphonCacheKey::Custom(key) if evict(key.glyph_id)))
+            .collect();
 
-                    let Some(rasterized_glyph) = (rasterize_custom_glyph)(input) else {
-                        panic!("Custom glyph rasterizer returned `None` when it previously returned `Some` for the same input {:?}", &input);
-                    };
+        for key in &keys {
+            let details = self.glyph_cache.pop(key).unwrap();
+            self.release(&details);
+            self.glyphs_in_use.remove(key);
+            self.glyphs_being_trimmed.remove(key);
+        }
+        self.eviction_count += keys.len() as u64;
+
+        keys.len()
+    }
+
+    /// Removes all cached glyphs and resets the packer, keeping the texture and its size, and
+    /// returns how many glyphs were removed.
+    fn clear(&mut self) -> usize {
+        let count = self.glyph_cache.len();
+        self.glyph_cache.clear();
+        self.packer = BucketedAtlasAllocator::new(size2(self.size as i32, self.size as i32));
+        self.glyphs_in_use.clear();
+        self.glyphs_being_trimmed.clear();
+        self.shared_images.clear();
+        self.shelf_regions.clear();
+        self.pending_uploads.clear();
+        self.eviction_count += count as u64;
+
+        count
+    }
+
+    /// Replaces the texture with an empty texture of the initial size, returning all cached
+    /// glyphs. Pending uploads must have been flushed.
+    pub(crate) fn take_glyphs(
+        &mut self,
+        device: &Device,
+        cache: &Cache,
+    ) -> LruCache<GlyphonCacheKey, GlyphDetails, Hasher> {
+        let size = self.initial_size();
+        self.texture = Self::create_texture(device, cache, self.kind, size);
+        self.texture_view = self.texture.create_view(&TextureViewDescriptor::default());
+        self.packer = BucketedAtlasAllocator::new(size2(size as i32, size as i32));
+        self.size = size;
+        self.glyphs_in_use.clear();
+        self.glyphs_being_trimmed.clear();
+        self.shared_images.clear();
+        self.shelf_regions.clear();
+
+        let glyphs = mem::replace(
+            &mut self.glyph_cache,
+            LruCache::unbounded_with_hasher(Hasher::default()),
+        );
+        self.eviction_count += glyphs.len() as u64;
+
+        glyphs
+    }
+
+    /// Returns a value that changes whenever glyphs are cached in or evicted from the texture.
+    pub(crate) fn revision(&self) -> (u32, u64, u64) {
+        (self.growth_count, self.eviction_count, self.cache_misses)
+    }
 
-                    // Sanity checks on the rasterizer output
-                    rasterized_glyph.validate(&input, Some(self.kind.as_content_type()));
+    /// Records the deferred copies into `encoder`, staging glyph images in `staging`, and stops
+    /// deferring copies.
+    fn record_deferred_copies(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        staging: &mut StagingRing,
+        cache: &Cache,
+    ) {
+        self.flush_uploads(device, queue, cache);
+        let Some(deferred_copies) = self.deferred_copies.take() else {
+            return;
+        };
 
-                    (
-                        rasterized_glyph.data,
-                        cache_key.width as usize,
-                        cache_key.height as usize,
-                    )
+        let num_channels = self.num_channels() as u32;
+        for copy in deferred_copies {
+            match copy {
+                DeferredCopy::Upload { texture, upload } => {
+                    let row_size = upload.width * num_channels;
+                    let padded_row = align_to(row_size, COPY_BYTES_PER_ROW_ALIGNMENT);
+                    let (buffer, offset) = staging.write(
+                        device,
+                        cache,
+                        padded_row as u64 * upload.height as u64,
+                        |staging| {
+                            write_padded_rows(
+                                staging,
+                                &upload.data,
+                                row_size as usize,
+                                padded_row as usize,
+                            )
+                        },
+                    );
+                    copy_upload(encoder, &buffer, offset, padded_row, &texture, &upload);
                 }
-            };
+                DeferredCopy::Grow { from, to, size } => {
+                    copy_to_grown_texture(encoder, &from, &to, size);
+                }
+            }
+        }
+    }
 
-            queue.write_texture(
-                TexelCopyTextureInfo {
-                    texture: &self.texture,
-                    mip_level: 0,
-                    origin: Origin3d {
-                        x: x as u32,
-                        y: y as u32,
-                        z: 0,
-                    },
-                    aspect: TextureAspect::All,
-                },
-                &image_data,
-                TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(width as u32 * self.kind.num_channels() as u32),
-                    rows_per_image: None,
-                },
-                Extent3d {
-                    width: width as u32,
-                    height: height as u32,
-                    depth_or_array_layers: 1,
-                },
-            );
+    /// Marks the glyphs used by the partitions of the bits of `partitions` as unused by them.
+    fn trim(&mut self, partitions: u64) {
+        if partitions == AtlasPartition::ALL {
+            self.glyphs_in_use.clear();
+            self.glyphs_being_trimmed.clear();
+        } else {
+            self.glyphs_in_use.retain(|_, used_by| {
+                *used_by &= !partitions;
+                *used_by != 0
+            });
         }
+    }
 
-        self.texture_view = self.texture.create_view(&TextureViewDescriptor::default());
-        self.size = new_size;
+    /// Starts an incremental trim, moving all glyphs in use to the set of glyphs being trimmed.
+    fn start_trim(&mut self) {
+        let glyphs_in_use = self.glyphs_in_use.drain().map(|(key, _)| key);
+        self.glyphs_being_trimmed.extend(glyphs_in_use);
+    }
+
+    /// Unprotects up to `max_items` glyphs being trimmed, returning how many were unprotected.
+    fn continue_trim(&mut self, max_items: usize) -> usize {
+        let keys: Vec<GlyphonCacheKey> = self
+            .glyphs_being_trimmed
+            .iter()
+            .take(max_items)
+            .copied()
+            .collect();
+
+        for key in &keys {
+            self.glyphs_being_trimmed.remove(key);
+        }
 
-        true
+        keys.len()
     }
 
-    fn trim(&mut self) {
-        self.glyphs_in_use.clear();
+    pub(crate) fn gpu_memory_usage(&self) -> u64 {
+        self.kind.texture_bytes(self.size)
+    }
+
+    /// Copies the texture to the CPU, blocking until the copy is done. Returns the rows of the
+    /// texture, with `num_channels` bytes per texel.
+    pub(crate) fn read_texture(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        cache: &Cache,
+    ) -> Result<Vec<u8>, BufferAsyncError> {
+        read_texture_rows(
+            device,
+            queue,
+            cache,
+            &self.texture,
+            self.size,
+            self.size * self.num_channels() as u32,
+            self.size,
+        )
+    }
+
+    /// Returns the number of pixels covered by the images of cached glyphs, counting shared
+    /// images once.
+    // `Option::is_none_or` needs Rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    fn glyph_area(&self) -> u32 {
+        let mut shared = HashSet::<u64, Hasher>::default();
+        self.glyph_cache
+            .iter()
+            .filter(|(_, details)| details.atlas_id.is_some())
+            .filter(|(_, details)| {
+                details
+                    .content_hash
+                    .map_or(true, |hash| shared.insert(hash))
+            })
+            .map(|(_, details)| details.width as u32 * details.height as u32)
+            .sum()
+    }
+
+    fn stats(&self) -> AtlasTextureStats {
+        AtlasTextureStats {
+            size: self.size,
+            cached_glyphs: self.glyph_cache.len(),
+            glyphs_in_use: self.glyphs_in_use.len(),
+            allocated_area: self.packer.allocated_space().max(0) as u32,
+            glyph_area: self.glyph_area(),
+            shelf_regions: self.shelf_regions.len(),
+            growth_count: self.growth_count,
+            cache_hits: self.cache_hits,
+            cache_misses: self.cache_misses,
+            shared_glyphs: self
+                .shared_images
+                .values()
+                .map(|shared| shared.users - 1)
+                .sum(),
+        }
+    }
+}
+
+/// Copies a texture of `size` x `size` texels to the CPU, blocking until the copy is done. Returns
+/// the `rows` rows of `row_bytes` bytes of the texture (rows of blocks for compressed textures).
+pub(crate) fn read_texture_rows(
+    device: &Device,
+    queue: &Queue,
+    cache: &Cache,
+    texture: &Texture,
+    size: u32,
+    row_bytes: u32,
+    rows: u32,
+) -> Result<Vec<u8>, BufferAsyncError> {
+    trace_span!("read_atlas", size);
+
+    let bytes_per_row = align_to(row_bytes, COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some(&cache.label("atlas readback")),
+        size: bytes_per_row as u64 * rows as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some(&cache.label("atlas readback")),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = mpsc::channel();
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(Maintain::Wait);
+    receiver.recv().unwrap_or(Err(BufferAsyncError))?;
+
+    let data = slice
+        .get_mapped_range()
+        .chunks(bytes_per_row as usize)
+        .flat_map(|row| &row[..row_bytes as usize])
+        .copied()
+        .collect();
+    buffer.destroy();
+
+    Ok(data)
+}
+
+/// Copies the rows of a glyph image to `staging`, starting every row at a multiple of
+/// `padded_row` bytes as required by buffer to texture copies.
+fn write_padded_rows(staging: &mut [u8], data: &[u8], row_size: usize, padded_row: usize) {
+    for (padded, row) in staging
+        .chunks_mut(padded_row)
+        .zip(data.chunks_exact(row_size))
+    {
+        padded[..row_size].copy_from_slice(row);
+    }
+}
+
+/// Records the copy of a glyph image with padded rows from `buffer` to `texture`.
+fn copy_upload(
+    encoder: &mut CommandEncoder,
+    buffer: &wgpu::Buffer,
+    offset: u64,
+    padded_row: u32,
+    texture: &Texture,
+    upload: &PendingUpload,
+) {
+    encoder.copy_buffer_to_texture(
+        TexelCopyBufferInfo {
+            buffer,
+            layout: TexelCopyBufferLayout {
+                offset,
+                bytes_per_row: Some(padded_row),
+                rows_per_image: None,
+            },
+        },
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: upload.x,
+                y: upload.y,
+                z: 0,
+            },
+            aspect: TextureAspect::All,
+        },
+        Extent3d {
+            width: upload.width,
+            height: upload.height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Records the copy of the cached glyphs of an atlas texture of `size` to a grown texture.
+fn copy_to_grown_texture(encoder: &mut CommandEncoder, from: &Texture, to: &Texture, size: u32) {
+    encoder.copy_texture_to_texture(
+        from.as_image_copy(),
+        to.as_image_copy(),
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Usage statistics for one of the textures of a [`TextAtlas`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AtlasTextureStats {
+    /// The width and height of the texture in pixels.
+    pub size: u32,
+    /// The number of glyphs currently cached in the texture.
+    pub cached_glyphs: usize,
+    /// The number of cached glyphs used since the last call to [`TextAtlas::trim`].
+    pub glyphs_in_use: usize,
+    /// The number of pixels of the texture occupied by cached glyphs, including the whole area of
+    /// shelf regions.
+    pub allocated_area: u32,
+    /// The number of pixels of the texture covered by the images of cached glyphs.
+    ///
+    /// This is smaller than the allocated area because of the space the packer loses to
+    /// fragmentation and alignment, see [`AtlasTextureStats::packing_density`].
+    pub glyph_area: u32,
+    /// The number of regions of the texture reserved for small glyphs, see
+    /// [`TextAtlas::set_small_glyph_shelves`].
+    pub shelf_regions: usize,
+    /// The number of times the texture has grown since the atlas was created.
+    pub growth_count: u32,
+    /// The number of glyph lookups that were found in the texture since the atlas was created.
+    pub cache_hits: u64,
+    /// The number of glyph lookups that required rasterizing the glyph since the atlas was
+    /// created.
+    pub cache_misses: u64,
+    /// The number of cached custom glyphs that share the image of another custom glyph instead of
+    /// taking up space of their own, see [`TextAtlas::set_deduplicate_custom_glyphs`].
+    pub shared_glyphs: usize,
+}
+
+impl AtlasTextureStats {
+    /// Returns the part of the allocated area that is covered by the images of cached glyphs,
+    /// between `0.0` and `1.0`, or `1.0` if nothing is allocated.
+    pub fn packing_density(&self) -> f32 {
+        if self.allocated_area == 0 {
+            return 1.0;
+        }
+
+        self.glyph_area as f32 / self.allocated_area as f32
     }
 }
 
+/// Usage statistics for a partition of a [`TextAtlas`], see [`TextAtlas::partition_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AtlasPartitionStats {
+    /// The number of glyphs cached by renderers using the partition.
+    pub cached_glyphs: usize,
+    /// The number of cached glyphs used by renderers using the partition since the partition was
+    /// last trimmed.
+    pub glyphs_in_use: usize,
+}
+
+/// Usage statistics for a [`TextAtlas`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AtlasStats {
+    /// Statistics for the texture containing mask glyphs (most text).
+    pub mask: AtlasTextureStats,
+    /// Statistics for the texture containing color glyphs (emoji and colored custom glyphs).
+    pub color: AtlasTextureStats,
+    /// Statistics for the compressed texture that color glyphs were moved to by
+    /// [`TextAtlas::compress_color_atlas`], if any. Only the size, cached glyphs, allocated and
+    /// glyph area and cache hits are counted.
+    pub compressed_color: Option<AtlasTextureStats>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum Kind {
     Mask,
@@ -236,7 +1045,19 @@ impl Kind {
         }
     }
 
-    fn texture_format(self) -> wgpu::TextureFormat {
+    /// The name of the atlas texture in debug labels
+    fn atlas_name(self) -> &'static str {
+        match self {
+            Kind::Mask => "mask atlas",
+            Kind::Color { .. } => "color atlas",
+        }
+    }
+
+    pub(crate) fn texture_bytes(self, size: u32) -> u64 {
+        size as u64 * size as u64 * self.num_channels() as u64
+    }
+
+    pub(crate) fn texture_format(self) -> wgpu::TextureFormat {
         match self {
             Kind::Mask => TextureFormat::R8Unorm,
             Kind::Color { srgb } => {
@@ -248,13 +1069,6 @@ impl Kind {
             }
         }
     }
-
-    fn as_content_type(&self) -> ContentType {
-        match self {
-            Self::Mask => ContentType::Mask,
-            Self::Color { .. } => ContentType::Color,
-        }
-    }
 }
 
 /// The color mode of a [`TextAtlas`].
@@ -278,25 +1092,307 @@ pub enum ColorMode {
     /// This mode should be used to render to a linear RGB texture containing
     /// sRGB colors.
     Web,
+
+    /// Picks the color mode that matches the format of the render target.
+    ///
+    /// [`ColorMode::Accurate`] is used for sRGB formats (e.g. `Bgra8UnormSrgb`), and
+    /// [`ColorMode::Web`] for all other formats (e.g. an `Rgba8Unorm` offscreen target).
+    /// Rendering with [`ColorMode::Accurate`] to a non-sRGB target makes text look too dark, and
+    /// rendering with [`ColorMode::Web`] to an sRGB target makes it look washed out.
+    Auto,
 }
 
-/// An atlas containing a cache of rasterized glyphs that can be rendered.
-pub struct TextAtlas {
-    cache: Cache,
-    pub(crate) bind_group: BindGroup,
+impl ColorMode {
+    /// Returns the color mode that matches a render target with the given `format`.
+    ///
+    /// This is [`ColorMode::Accurate`] for sRGB formats and [`ColorMode::Web`] for all other
+    /// formats, which is also what [`ColorMode::Auto`] picks.
+    pub fn for_surface_format(format: TextureFormat) -> Self {
+        if format.is_srgb() {
+            ColorMode::Accurate
+        } else {
+            ColorMode::Web
+        }
+    }
+
+    /// Returns `true` if text rendered with this color mode to a render target with the given
+    /// `format` has the intended colors.
+    pub fn is_compatible_with(self, format: TextureFormat) -> bool {
+        self == ColorMode::Auto || self == Self::for_surface_format(format)
+    }
+
+    /// Resolves [`ColorMode::Auto`] for a render target with the given `format`.
+    fn resolve(self, format: TextureFormat) -> Self {
+        match self {
+            ColorMode::Auto => Self::for_surface_format(format),
+            color_mode => color_mode,
+        }
+    }
+}
+
+/// The glyphs that may be evicted to allocate a glyph, gathered lazily from the least recently
+/// used end of the glyph cache, so that evicting several glyphs scans the cache only once.
+struct EvictionScan {
+    /// The partition allocating the glyph
+    partition: AtlasPartition,
+    /// The unprotected glyphs that were scanned, from the least recently used one
+    candidates: VecDeque<(GlyphonCacheKey, EvictionCandidate, AtlasPartition)>,
+    /// The number of candidates cached by `partition`
+    own: usize,
+    /// The number of glyphs of the cache that were scanned
+    scanned: usize,
+    /// Whether the whole cache was scanned
+    complete: bool,
+}
+
+impl EvictionScan {
+    fn new(partition: AtlasPartition) -> Self {
+        Self {
+            partition,
+            candidates: VecDeque::new(),
+            own: 0,
+            scanned: 0,
+            complete: false,
+        }
+    }
+}
+
+/// Chooses which cached glyph a [`TextAtlas`] evicts when it is full.
+///
+/// Glyphs that are in use are never evicted.
+#[derive(Clone)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used glyph.
+    LeastRecentlyUsed,
+    /// Evict the glyph with the largest area multiplied by its staleness among the `candidates`
+    /// least recently used glyphs.
+    ///
+    /// Evicting a small glyph frees little space, so this evicts large, stale glyphs (e.g. color
+    /// emoji) before small ones, which reduces how often the atlas grows when mixing text and
+    /// large glyphs. This is the default, with 16 candidates.
+    SizeWeighted { candidates: usize },
+    /// Evict the glyph with the highest `score` among the `candidates` least recently used glyphs.
+    Custom {
+        candidates: usize,
+        score: Arc<dyn Fn(&EvictionCandidate) -> f32 + Send + Sync>,
+    },
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        Self::SizeWeighted { candidates: 16 }
+    }
+}
+
+impl EvictionPolicy {
+    /// The number of least recently used glyphs the glyph to evict is chosen from.
+    fn candidates(&self) -> usize {
+        match self {
+            Self::LeastRecentlyUsed => 1,
+            Self::SizeWeighted { candidates } | Self::Custom { candidates, .. } => {
+                (*candidates).max(1)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LeastRecentlyUsed => write!(f, "LeastRecentlyUsed"),
+            Self::SizeWeighted { candidates } => f
+                .debug_struct("SizeWeighted")
+                .field("candidates", candidates)
+                .finish(),
+            Self::Custom { candidates, .. } => f
+                .debug_struct("Custom")
+                .field("candidates", candidates)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// A cached glyph that may be evicted, passed to [`EvictionPolicy::Custom`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictionCandidate {
+    /// The width of the image of the glyph in the atlas.
+    pub width: u16,
+    /// The height of the image of the glyph in the atlas.
+    pub height: u16,
+    /// The number of candidates that were used less recently than this glyph, i.e. `0` for the
+    /// least recently used glyph.
+    pub age: usize,
+}
+
+impl EvictionCandidate {
+    /// Returns the area of the image of the glyph in the atlas.
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+/// A logical partition of the glyph cache of a [`TextAtlas`].
+///
+/// Each [`crate::TextRenderer`] caches its glyphs in a partition, set with
+/// [`crate::TextRenderer::set_atlas_partition`]. Glyphs are tracked as in use per partition and
+/// can be trimmed per partition with [`TextAtlas::trim_partition`], and a full atlas evicts the
+/// glyphs of the partition that needs space before the glyphs of other partitions. This isolates
+/// glyphs that are always needed (e.g. the text of the UI of an editor) from glyphs with a lot of
+/// churn (e.g. the text of a large document being scrolled).
+///
+/// Glyphs are shared between partitions: a glyph cached by one partition is used by the others
+/// instead of being cached again, and belongs to the partition that cached it for eviction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AtlasPartition(u8);
+
+impl AtlasPartition {
+    /// The number of partitions of an atlas.
+    pub const COUNT: u8 = 64;
+
+    /// The partition used by renderers unless another partition is set.
+    pub const DEFAULT: Self = Self(0);
+
+    /// The bits of all partitions
+    const ALL: u64 = u64::MAX;
+
+    /// Returns the partition with the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` isn't less than [`AtlasPartition::COUNT`].
+    pub const fn new(index: u8) -> Self {
+        assert!(index < Self::COUNT, "atlas partition index out of range");
+        Self(index)
+    }
+
+    /// Returns the index of the partition.
+    pub fn index(self) -> u8 {
+        self.0
+    }
+
+    /// The bit of the partition in the sets of partitions using a glyph
+    pub(crate) fn bit(self) -> u64 {
+        1 << self.0
+    }
+}
+
+/// Controls how the textures of a [`TextAtlas`] grow when they run out of space.
+///
+/// Textures never grow beyond the maximum texture dimension of the device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GrowthPolicy {
+    /// Double the width and height of the texture, matching the growth factor of `Vec`.
+    #[default]
+    Double,
+    /// Grow the width and height of the texture by the given number of pixels.
+    ///
+    /// This wastes less memory than [`GrowthPolicy::Double`] for large textures, at the cost of
+    /// growing (and copying the texture) more often.
+    Linear(u32),
+    /// Never grow the texture, so that preparing fails with [`PrepareError::AtlasFull`] once no
+    /// glyph can be evicted to make room.
+    Never,
+}
+
+/// Controls how a [`TextAtlas`] copies newly rasterized glyphs to its textures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UploadStrategy {
+    /// Use [`UploadStrategy::PaddedWrites`] on the web and [`UploadStrategy::Batched`] on other
+    /// platforms.
+    ///
+    /// Applications that know the backend of their adapter can pick a strategy with
+    /// [`UploadStrategy::for_backend`] instead.
+    #[default]
+    Auto,
+    /// Write every glyph with its own texture write, with tightly packed rows.
+    Writes,
+    /// Write every glyph with its own texture write, with rows padded to 256 bytes.
+    ///
+    /// Some backends (e.g. WebGL2) copy tightly packed rows one by one, so padding the rows on
+    /// the CPU first is faster.
+    PaddedWrites,
+    /// Write up to `threshold` glyphs one by one, and copy more glyphs from a single staging
+    /// buffer with padded rows, e.g. when a large document is first prepared.
+    Batched { threshold: usize },
+}
+
+impl UploadStrategy {
+    /// The number of glyphs above which [`UploadStrategy::Batched`] copies glyphs from a staging
+    /// buffer by default.
+    ///
+    /// Gathering glyphs into a staging buffer has a fixed cost (allocating or mapping the buffer
+    /// and an extra submission), which only pays off once it replaces enough texture writes.
+    /// Editing or scrolling text usually caches a handful of new glyphs per frame, which stay
+    /// below this and are written directly, while the first prepare of a document or a new font
+    /// size caches hundreds of glyphs at once, which are batched.
+    const DEFAULT_BATCH_THRESHOLD: usize = 32;
+
+    /// Returns the fastest strategy for the given backend.
+    pub fn for_backend(backend: Backend) -> Self {
+        match backend {
+            Backend::Gl => Self::PaddedWrites,
+            // Every write is a separate call into the browser, so batch all glyphs
+            Backend::BrowserWebGpu => Self::Batched { threshold: 0 },
+            Backend::Empty | Backend::Vulkan | Backend::Metal | Backend::Dx12 => Self::Batched {
+                threshold: Self::DEFAULT_BATCH_THRESHOLD,
+            },
+        }
+    }
+
+    /// Resolves [`UploadStrategy::Auto`] for the target platform.
+    fn resolve(self) -> Self {
+        match self {
+            Self::Auto if cfg!(target_arch = "wasm32") => Self::PaddedWrites,
+            Self::Auto => Self::Batched {
+                threshold: Self::DEFAULT_BATCH_THRESHOLD,
+            },
+            strategy => strategy,
+        }
+    }
+}
+
+/// An atlas containing a cache of rasterized glyphs that can be rendered.
+///
+/// Preparing text needs mutable access to the atlas, while rendering only reads it. Once all
+/// renderers using the atlas were prepared, it can be shared between threads (e.g. in an `Arc`)
+/// that record render passes in parallel, each with its own [`crate::TextRenderer`].
+pub struct TextAtlas {
+    pub(crate) cache: Cache,
+    pub(crate) bind_group: BindGroup,
     pub(crate) color_atlas: InnerAtlas,
     pub(crate) mask_atlas: InnerAtlas,
+    /// The color glyphs moved to a compressed texture by [`TextAtlas::compress_color_atlas`]
+    pub(crate) compressed_color: Option<CompressedPage>,
     pub(crate) format: TextureFormat,
     pub(crate) color_mode: ColorMode,
+    pub(crate) memory: Arc<MemoryTracker>,
+    pub(crate) prepared_glyphs: Arc<PreparedGlyphs>,
+    pub(crate) scalable_custom_glyphs: HashMap<CustomGlyphId, (u16, u16), Hasher>,
+    pub(crate) staging: StagingRing,
+    pub(crate) deduplicate_custom_glyphs: bool,
+    /// The generation of the glyph cached last
+    pub(crate) glyph_generation: u64,
+    /// The partition of the renderer being prepared, see [`AtlasPartition`]
+    pub(crate) partition: AtlasPartition,
+    frame_active: bool,
+    /// The bits of the partitions to trim at the end of the frame
+    trim_pending: u64,
 }
 
 impl TextAtlas {
+    /// The size of the staging buffers used by [`crate::TextRenderer::prepare_with_encoder`].
+    const STAGING_CHUNK_SIZE: u64 = 256 * 1024;
+
     /// Creates a new [`TextAtlas`].
     pub fn new(device: &Device, queue: &Queue, cache: &Cache, format: TextureFormat) -> Self {
         Self::with_color_mode(device, queue, cache, format, ColorMode::Accurate)
     }
 
     /// Creates a new [`TextAtlas`] with the given [`ColorMode`].
+    ///
+    /// Use [`ColorMode::Auto`] to pick the color mode that matches `format`. With the `tracing`
+    /// feature, a warning is emitted if the color mode doesn't match `format` (see
+    /// [`TextAtlas::try_with_color_mode`]).
     pub fn with_color_mode(
         device: &Device,
         queue: &Queue,
@@ -304,22 +1400,34 @@ impl TextAtlas {
         format: TextureFormat,
         color_mode: ColorMode,
     ) -> Self {
+        #[cfg(feature = "tracing")]
+        if !color_mode.is_compatible_with(format) {
+            tracing::warn!(
+                ?color_mode,
+                ?format,
+                suggested = ?ColorMode::for_surface_format(format),
+                "color mode of text atlas doesn't match the render target format"
+            );
+        }
+
+        let color_mode = color_mode.resolve(format);
+        let memory = Arc::new(MemoryTracker::default());
         let color_atlas = InnerAtlas::new(
             device,
             queue,
+            cache,
             Kind::Color {
-                srgb: match color_mode {
-                    ColorMode::Accurate => true,
-                    ColorMode::Web => false,
-                },
+                srgb: color_mode == ColorMode::Accurate,
             },
+            &memory,
         );
-        let mask_atlas = InnerAtlas::new(device, queue, Kind::Mask);
+        let mask_atlas = InnerAtlas::new(device, queue, cache, Kind::Mask, &memory);
 
         let bind_group = cache.create_atlas_bind_group(
             device,
             &color_atlas.texture_view,
             &mask_atlas.texture_view,
+            &color_atlas.texture_view,
         );
 
         Self {
@@ -327,55 +1435,485 @@ impl TextAtlas {
             bind_group,
             color_atlas,
             mask_atlas,
+            compressed_color: None,
             format,
             color_mode,
+            memory,
+            prepared_glyphs: Arc::default(),
+            scalable_custom_glyphs: HashMap::default(),
+            staging: StagingRing::new(Self::STAGING_CHUNK_SIZE),
+            deduplicate_custom_glyphs: false,
+            glyph_generation: 0,
+            partition: AtlasPartition::DEFAULT,
+            frame_active: false,
+            trim_pending: 0,
+        }
+    }
+
+    /// Creates a new [`TextAtlas`] with the given [`ColorMode`], or returns an error if the color
+    /// mode doesn't match `format`.
+    ///
+    /// Rendering with [`ColorMode::Web`] to an sRGB target (e.g. `Bgra8UnormSrgb`) makes text look
+    /// washed out, and rendering with [`ColorMode::Accurate`] to a non-sRGB target makes it look
+    /// too dark. Use [`ColorMode::for_surface_format`] to pick the matching color mode.
+    pub fn try_with_color_mode(
+        device: &Device,
+        queue: &Queue,
+        cache: &Cache,
+        format: TextureFormat,
+        color_mode: ColorMode,
+    ) -> Result<Self, ColorModeError> {
+        if !color_mode.is_compatible_with(format) {
+            return Err(ColorModeError::MismatchedFormat { color_mode, format });
         }
+
+        Ok(Self::with_color_mode(
+            device, queue, cache, format, color_mode,
+        ))
     }
 
+    /// Marks all glyphs as unused, allowing them to be evicted to make room for other glyphs.
+    ///
+    /// This should be called once all renderers using the atlas have rendered. If a frame was
+    /// started with [`TextAtlas::begin_frame`], trimming is deferred until
+    /// [`TextAtlas::end_frame`].
+    ///
+    /// Glyphs prepared by the last call to `prepare` of any renderer using the atlas stay in use,
+    /// so that renderers can still render what they prepared (e.g. when rendering was skipped
+    /// while a window was occluded). Renderers release their glyphs when they are dropped or
+    /// prepare other text, e.g. no text areas at all.
     pub fn trim(&mut self) {
-        self.mask_atlas.trim();
-        self.color_atlas.trim();
+        self.trim_partitions(AtlasPartition::ALL);
+    }
+
+    /// Marks the glyphs used by renderers of `partition` as unused by that partition, like
+    /// [`TextAtlas::trim`] does for all partitions.
+    ///
+    /// Glyphs that other partitions use stay protected until those partitions are trimmed, so
+    /// e.g. the partition of a document can be trimmed every frame while the glyphs of the UI
+    /// stay in the atlas.
+    pub fn trim_partition(&mut self, partition: AtlasPartition) {
+        self.trim_partitions(partition.bit());
+    }
+
+    fn trim_partitions(&mut self, partitions: u64) {
+        if self.frame_active {
+            self.trim_pending |= partitions;
+        } else {
+            self.mask_atlas.trim(partitions);
+            self.color_atlas.trim(partitions);
+            self.keep_prepared_glyphs();
+        }
+    }
+
+    /// Incrementally marks glyphs as unused, doing at most `max_items` units of work.
+    ///
+    /// This can be called once per frame instead of [`TextAtlas::trim`] to avoid spikes when
+    /// thousands of glyphs are in use. Calling it starts a new trim, which then continues over
+    /// the following calls until all glyphs that were in use when it started are marked as unused.
+    /// Until then, those glyphs are still protected from eviction. Returns `true` once the trim
+    /// is complete, after which the next call starts a new one.
+    ///
+    /// If a frame was started with [`TextAtlas::begin_frame`], a new trim isn't started until the
+    /// frame ends, although a trim that is already in progress continues.
+    pub fn trim_budgeted(&mut self, max_items: usize) -> bool {
+        let idle = self.mask_atlas.glyphs_being_trimmed.is_empty()
+            && self.color_atlas.glyphs_being_trimmed.is_empty();
+        if idle {
+            if self.frame_active {
+                return false;
+            }
+
+            self.mask_atlas.start_trim();
+            self.color_atlas.start_trim();
+            self.keep_prepared_glyphs();
+        }
+
+        let trimmed = self.mask_atlas.continue_trim(max_items);
+        self.color_atlas.continue_trim(max_items - trimmed);
+
+        self.mask_atlas.glyphs_being_trimmed.is_empty()
+            && self.color_atlas.glyphs_being_trimmed.is_empty()
+    }
+
+    /// Starts a frame, marking all glyphs as unused.
+    ///
+    /// Until [`TextAtlas::end_frame`] is called, glyphs prepared by any renderer using this atlas
+    /// are kept in the atlas, and calls to [`TextAtlas::trim`] are deferred. This makes it safe to
+    /// trim after some renderers have prepared while other renderers sharing the atlas still need
+    /// their glyphs for the same frame.
+    pub fn begin_frame(&mut self) {
+        self.frame_active = true;
+        self.trim_pending = 0;
+        self.mask_atlas.trim(AtlasPartition::ALL);
+        self.color_atlas.trim(AtlasPartition::ALL);
+        self.keep_prepared_glyphs();
+    }
+
+    /// Marks the glyphs prepared by the last call to `prepare` of every renderer as in use again
+    /// after trimming.
+    fn keep_prepared_glyphs(&mut self) {
+        let renderers = self.prepared_glyphs.lock();
+        for (partition, keys) in renderers.values() {
+            for key in keys {
+                for inner in [&mut self.mask_atlas, &mut self.color_atlas] {
+                    if inner.glyph_cache.contains(key) {
+                        inner.mark_in_use(*key, *partition);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends a frame started with [`TextAtlas::begin_frame`], applying any deferred trim.
+    pub fn end_frame(&mut self) {
+        self.frame_active = false;
+        let partitions = mem::take(&mut self.trim_pending);
+        if partitions != 0 {
+            self.trim_partitions(partitions);
+        }
+    }
+
+    /// Registers the custom glyph `id` as a scalable bitmap.
+    ///
+    /// Instead of rasterizing the glyph for every size it is drawn at, it is rasterized once at
+    /// `width` x `height` physical pixels and every instance is drawn by scaling that image with
+    /// linear filtering. This trades exactness for fewer atlas entries, which is useful for
+    /// images such as avatars that are drawn at many different sizes. `width` and `height`
+    /// should be the largest size the glyph is drawn at.
+    pub fn register_scalable_custom_glyph(&mut self, id: CustomGlyphId, width: u16, height: u16) {
+        self.scalable_custom_glyphs.insert(id, (width, height));
+    }
+
+    /// Registers several custom glyphs as scalable bitmaps, given as `(id, width, height)`.
+    ///
+    /// See [`TextAtlas::register_scalable_custom_glyph`].
+    pub fn register_scalable_custom_glyphs(
+        &mut self,
+        glyphs: impl IntoIterator<Item = (CustomGlyphId, u16, u16)>,
+    ) {
+        self.scalable_custom_glyphs.extend(
+            glyphs
+                .into_iter()
+                .map(|(id, width, height)| (id, (width, height))),
+        );
+    }
+
+    /// Removes a registration made with [`TextAtlas::register_scalable_custom_glyph`].
+    ///
+    /// Subsequent instances of the glyph are rasterized at the size they are drawn at again.
+    pub fn unregister_scalable_custom_glyph(&mut self, id: CustomGlyphId) {
+        self.scalable_custom_glyphs.remove(&id);
+    }
+
+    /// Removes the registrations of several custom glyphs made with
+    /// [`TextAtlas::register_scalable_custom_glyph`].
+    pub fn unregister_scalable_custom_glyphs(
+        &mut self,
+        ids: impl IntoIterator<Item = CustomGlyphId>,
+    ) {
+        for id in ids {
+            self.scalable_custom_glyphs.remove(&id);
+        }
+    }
+
+    /// Enables or disables sharing the space in the atlas between custom glyphs with identical
+    /// images.
+    ///
+    /// When enabled, the images of newly rasterized custom glyphs are hashed, and glyphs whose
+    /// image is identical to the image of a cached custom glyph (e.g. the same icon registered
+    /// under multiple ids) use the cached image instead of taking up space of their own. This
+    /// saves atlas space in UIs with many icons at the cost of hashing every rasterized custom
+    /// glyph. Disabled by default.
+    pub fn set_deduplicate_custom_glyphs(&mut self, deduplicate: bool) {
+        self.deduplicate_custom_glyphs = deduplicate;
+    }
+
+    /// Returns `true` if custom glyphs with identical images share space in the atlas.
+    pub fn deduplicates_custom_glyphs(&self) -> bool {
+        self.deduplicate_custom_glyphs
+    }
+
+    /// Enables or disables packing small glyphs into shelf regions.
+    ///
+    /// The atlas packs glyphs with a bucketed allocator, which rounds the height of its shelves
+    /// up to a multiple of 8 pixels and leaves gaps when small glyphs (e.g. Latin text at 6-16
+    /// pixels) are mixed with large ones (e.g. color emoji). When enabled, glyphs of up to 16 x 16
+    /// pixels are packed into regions of 64 x 64 pixels reserved for them, in shelves whose height
+    /// is rounded to 2 pixels, and larger glyphs are packed around those regions. Empty regions
+    /// are returned to the rest of the atlas, and glyphs that are already cached keep their space.
+    ///
+    /// This packs atlases with mostly small glyphs more densely. A region stays reserved while
+    /// any of its glyphs is cached though, so with many large glyphs, regions holding a few
+    /// glyphs in use can make the atlas grow sooner. Compare
+    /// [`AtlasTextureStats::packing_density`] and the size of the atlas with and without shelves
+    /// to pick the best option for an application. Disabled by default.
+    pub fn set_small_glyph_shelves(&mut self, enabled: bool) {
+        self.mask_atlas.small_glyph_shelves = enabled;
+        self.color_atlas.small_glyph_shelves = enabled;
+    }
+
+    /// Returns `true` if small glyphs are packed into shelf regions.
+    pub fn small_glyph_shelves(&self) -> bool {
+        self.mask_atlas.small_glyph_shelves
+    }
+
+    /// Removes all cached images of the custom glyph `id` from the atlas, at every size and
+    /// subpixel offset, and returns how many images were removed.
+    ///
+    /// This frees the atlas space of glyphs whose source was unloaded, instead of waiting for them
+    /// to be evicted to make room for other glyphs. If the glyph is drawn again, it is rasterized
+    /// again. Renderers that prepared the glyph have to be prepared again before rendering, since
+    /// its space may be reused by other glyphs.
+    ///
+    /// Registrations made with [`TextAtlas::register_scalable_custom_glyph`] are kept.
+    pub fn evict_custom_glyph(&mut self, id: CustomGlyphId) -> usize {
+        self.evict_custom_glyphs_where(|glyph_id| glyph_id == id)
+    }
+
+    /// Removes all cached images of several custom glyphs from the atlas, returning how many
+    /// images were removed.
+    ///
+    /// See [`TextAtlas::evict_custom_glyph`].
+    pub fn evict_custom_glyphs(&mut self, ids: impl IntoIterator<Item = CustomGlyphId>) -> usize {
+        let ids: HashSet<CustomGlyphId, Hasher> = ids.into_iter().collect();
+        if ids.is_empty() {
+            return 0;
+        }
+
+        self.evict_custom_glyphs_where(|glyph_id| ids.contains(&glyph_id))
+    }
+
+    /// Removes all cached glyphs from the atlas and returns how many were removed.
+    ///
+    /// The textures keep their size, but all of their space is freed at once instead of glyph by
+    /// glyph, so the atlas isn't left fragmented. This is useful when fonts are reloaded or
+    /// replaced, since glyphs of the previous fonts would otherwise stay cached until evicted.
+    /// Renderers using the atlas have to be prepared again before rendering.
+    ///
+    /// Registrations made with [`TextAtlas::register_scalable_custom_glyph`] are kept.
+    pub fn clear(&mut self) -> usize {
+        let mut count = self.mask_atlas.clear() + self.color_atlas.clear();
+        if let Some(page) = &mut self.compressed_color {
+            count += page.glyphs.len();
+            page.eviction_count += page.glyphs.len() as u64;
+            page.glyphs.clear();
+        }
+
+        count
+    }
+
+    fn evict_custom_glyphs_where(&mut self, evict: impl Fn(CustomGlyphId) -> bool) -> usize {
+        let mut count = self.mask_atlas.evict_custom_glyphs(&evict)
+            + self.color_atlas.evict_custom_glyphs(&evict);
+        if let Some(page) = &mut self.compressed_color {
+            count += page.evict_custom_glyphs(&evict);
+        }
+
+        count
+    }
+
+    /// Marks a cached glyph as in use, returning its position in the atlas and its generation if
+    /// it is still cached, and whether it is stored in the compressed color atlas.
+    pub(crate) fn mark_in_use(&mut self, key: &GlyphonCacheKey) -> Option<([u16; 2], u64, bool)> {
+        for inner in [&mut self.mask_atlas, &mut self.color_atlas] {
+            if let Some(details) = inner.glyph_cache.get(key) {
+                let GpuCacheStatus::InAtlas { x, y, .. } = details.gpu_cache else {
+                    return None;
+                };
+                let generation = details.generation;
+                inner.mark_in_use(*key, self.partition);
+                return Some(([x, y], generation, false));
+            }
+        }
+
+        let details = self.compressed_glyph(key)?;
+        match details.gpu_cache {
+            GpuCacheStatus::InAtlas { x, y, .. } => Some(([x, y], details.generation, true)),
+            GpuCacheStatus::SkipRasterization => None,
+        }
+    }
+
+    /// Copies the images of all glyphs cached since the last flush to the atlas textures.
+    pub(crate) fn flush_uploads(&mut self, device: &Device, queue: &Queue) {
+        self.mask_atlas.flush_uploads(device, queue, &self.cache);
+        self.color_atlas.flush_uploads(device, queue, &self.cache);
+    }
+
+    /// Defers all copies to the atlas textures until [`TextAtlas::record_deferred_copies`] is
+    /// called, instead of submitting them to the queue.
+    pub(crate) fn defer_copies(&mut self) {
+        self.mask_atlas.deferred_copies = Some(Vec::new());
+        self.color_atlas.deferred_copies = Some(Vec::new());
+    }
+
+    /// Records the copies deferred since [`TextAtlas::defer_copies`] into `encoder`.
+    pub(crate) fn record_deferred_copies(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+    ) {
+        for inner in [&mut self.mask_atlas, &mut self.color_atlas] {
+            inner.record_deferred_copies(device, queue, encoder, &mut self.staging, &self.cache);
+        }
+    }
+
+    /// Makes the staging buffers used by the commands recorded by
+    /// [`crate::TextRenderer::prepare_with_encoder`] available again.
+    ///
+    /// This must be called after the command encoder was submitted, and before preparing with an
+    /// encoder again. Otherwise, new staging buffers are created for every call.
+    pub fn recall_staging_buffers(&mut self) {
+        self.staging.recall();
+    }
+
+    /// Copies the atlas texture containing glyphs of `content_type` to the CPU, blocking until the
+    /// copy is done.
+    pub(crate) fn read_texture(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        content_type: ContentType,
+    ) -> Result<Vec<u8>, BufferAsyncError> {
+        match content_type {
+            ContentType::Mask => self.mask_atlas.read_texture(device, queue, &self.cache),
+            ContentType::Color | ContentType::TintedColor => {
+                self.color_atlas.read_texture(device, queue, &self.cache)
+            }
+        }
+    }
+
+    /// Returns the total number of glyphs evicted from the atlas to make room for other glyphs.
+    pub(crate) fn eviction_count(&self) -> u64 {
+        self.mask_atlas.eviction_count
+            + self.color_atlas.eviction_count
+            + self
+                .compressed_color
+                .as_ref()
+                .map_or(0, |page| page.eviction_count)
+    }
+
+    /// Sets how glyphs are chosen for eviction when the atlas is full. Defaults to
+    /// [`EvictionPolicy::SizeWeighted`].
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.mask_atlas.eviction_policy = policy.clone();
+        self.color_atlas.eviction_policy = policy;
+    }
+
+    /// Returns the eviction policy of the atlas.
+    pub fn eviction_policy(&self) -> &EvictionPolicy {
+        &self.mask_atlas.eviction_policy
+    }
+
+    /// Sets how newly rasterized glyphs are copied to the atlas textures. Defaults to
+    /// [`UploadStrategy::Auto`].
+    pub fn set_upload_strategy(&mut self, strategy: UploadStrategy) {
+        self.mask_atlas.upload_strategy = strategy;
+        self.color_atlas.upload_strategy = strategy;
+    }
+
+    /// Returns the upload strategy of the atlas.
+    pub fn upload_strategy(&self) -> UploadStrategy {
+        self.mask_atlas.upload_strategy
+    }
+
+    /// Sets how the atlas textures grow when they run out of space. Defaults to
+    /// [`GrowthPolicy::Double`].
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy) {
+        self.mask_atlas.growth_policy = policy;
+        self.color_atlas.growth_policy = policy;
+    }
+
+    /// Returns the growth policy of the atlas.
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.mask_atlas.growth_policy
+    }
+
+    /// Returns the color mode of the atlas, with [`ColorMode::Auto`] resolved for the format of
+    /// the render target.
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Returns the number of bytes of GPU memory used by the atlas textures.
+    pub fn gpu_memory_usage(&self) -> u64 {
+        self.mask_atlas.gpu_memory_usage()
+            + self.color_atlas.gpu_memory_usage()
+            + self
+                .compressed_color
+                .as_ref()
+                .map_or(0, CompressedPage::gpu_memory_usage)
+    }
+
+    /// Sets a limit on the total GPU memory used by the atlas textures and the vertex buffers of
+    /// all [`TextRenderer`](crate::TextRenderer)s created with this atlas.
+    ///
+    /// When preparing text would grow any of these resources beyond the limit, `prepare` fails
+    /// with [`PrepareError::MemoryBudgetExceeded`] instead. Memory that is already allocated is
+    /// never released by lowering the budget. Pass `None` to remove the limit.
+    pub fn set_memory_budget(&mut self, budget: Option<u64>) {
+        self.memory.set_limit(budget);
+    }
+
+    /// Returns the memory budget set with [`TextAtlas::set_memory_budget`], if any.
+    pub fn memory_budget(&self) -> Option<u64> {
+        self.memory.limit()
+    }
+
+    /// Returns usage statistics for the atlas textures.
+    pub fn stats(&self) -> AtlasStats {
+        AtlasStats {
+            mask: self.mask_atlas.stats(),
+            color: self.color_atlas.stats(),
+            compressed_color: self.compressed_color.as_ref().map(CompressedPage::stats),
+        }
+    }
+
+    /// Returns usage statistics for the glyphs of `partition` in the mask and color textures.
+    pub fn partition_stats(&self, partition: AtlasPartition) -> AtlasPartitionStats {
+        let mut stats = AtlasPartitionStats::default();
+        for inner in [&self.mask_atlas, &self.color_atlas] {
+            stats.cached_glyphs += inner
+                .glyph_cache
+                .iter()
+                .filter(|(_, details)| details.partition == partition)
+                .count();
+            stats.glyphs_in_use += inner
+                .glyphs_in_use
+                .values()
+                .filter(|used_by| **used_by & partition.bit() != 0)
+                .count();
+        }
+
+        stats
     }
 
     pub(crate) fn grow(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        font_system: &mut FontSystem,
-        cache: &mut SwashCache,
         content_type: ContentType,
-        scale_factor: f32,
-        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
-    ) -> bool {
-        let did_grow = match content_type {
-            ContentType::Mask => self.mask_atlas.grow(
-                device,
-                queue,
-                font_system,
-                cache,
-                scale_factor,
-                rasterize_custom_glyph,
-            ),
-            ContentType::Color => self.color_atlas.grow(
-                device,
-                queue,
-                font_system,
-                cache,
-                scale_factor,
-                rasterize_custom_glyph,
-            ),
-        };
+    ) -> Result<(), PrepareError> {
+        match content_type {
+            ContentType::Mask => self
+                .mask_atlas
+                .grow(device, queue, &self.cache, &self.memory),
+            ContentType::Color | ContentType::TintedColor => {
+                self.color_atlas
+                    .grow(device, queue, &self.cache, &self.memory)
+            }
+        }?;
 
-        if did_grow {
-            self.rebind(device);
-        }
+        self.rebind(device);
 
-        did_grow
+        Ok(())
     }
 
     pub(crate) fn inner_for_content_mut(&mut self, content_type: ContentType) -> &mut InnerAtlas {
         match content_type {
-            ContentType::Color => &mut self.color_atlas,
+            ContentType::Color | ContentType::TintedColor => &mut self.color_atlas,
             ContentType::Mask => &mut self.mask_atlas,
         }
     }
@@ -385,16 +1923,518 @@ impl TextAtlas {
         device: &Device,
         multisample: MultisampleState,
         depth_stencil: Option<DepthStencilState>,
-    ) -> RenderPipeline {
-        self.cache
-            .get_or_create_pipeline(device, self.format, multisample, depth_stencil)
+        blend_mode: BlendMode,
+        features: PipelineFeatures,
+    ) -> Option<RenderPipeline> {
+        self.cache.get_or_create_pipeline(
+            device,
+            self.format,
+            multisample,
+            depth_stencil,
+            blend_mode,
+            features,
+        )
     }
 
-    fn rebind(&mut self, device: &wgpu::Device) {
+    pub(crate) fn rebind(&mut self, device: &wgpu::Device) {
+        // Without a compressed color atlas, the color atlas is bound in its place
+        let compressed_color = self
+            .compressed_color
+            .as_ref()
+            .map_or(&self.color_atlas.texture_view, |page| &page.texture_view);
         self.bind_group = self.cache.create_atlas_bind_group(
             device,
             &self.color_atlas.texture_view,
             &self.mask_atlas.texture_view,
+            compressed_color,
+        );
+    }
+}
+
+/// Copies the images of the glyphs cached through it to the atlas textures when dropped.
+///
+/// Glyphs are cached in the atlas as soon as they are rasterized, so their images have to be
+/// uploaded even if preparing fails partway through (e.g. with [`PrepareError::AtlasFull`]).
+/// Otherwise, the atlas would refer to glyphs that were never written, and the queued images
+/// would be written by a later prepare, possibly after their space was reused.
+pub(crate) struct UploadGuard<'a> {
+    atlas: &'a mut TextAtlas,
+    device: &'a Device,
+    queue: &'a Queue,
+}
+
+impl<'a> UploadGuard<'a> {
+    pub(crate) fn new(atlas: &'a mut TextAtlas, device: &'a Device, queue: &'a Queue) -> Self {
+        Self {
+            atlas,
+            device,
+            queue,
+        }
+    }
+}
+
+impl Deref for UploadGuard<'_> {
+    type Target = TextAtlas;
+
+    fn deref(&self) -> &TextAtlas {
+        self.atlas
+    }
+}
+
+impl DerefMut for UploadGuard<'_> {
+    fn deref_mut(&mut self) -> &mut TextAtlas {
+        self.atlas
+    }
+}
+
+impl Drop for UploadGuard<'_> {
+    fn drop(&mut self) {
+        self.atlas.flush_uploads(self.device, self.queue);
+    }
+}
+
+/// The glyphs referenced by the prepared state of each renderer created with an atlas, by the id
+/// of the renderer.
+///
+/// Renderers keep these up to date as they prepare text, so that trimming the atlas doesn't evict
+/// glyphs that renderers may still render.
+#[derive(Debug, Default)]
+pub(crate) struct PreparedGlyphs {
+    renderers: Mutex<HashMap<u64, (AtlasPartition, Vec<GlyphonCacheKey>), Hasher>>,
+}
+
+impl PreparedGlyphs {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, (AtlasPartition, Vec<GlyphonCacheKey>), Hasher>> {
+        self.renderers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replaces the glyphs prepared by the renderer `renderer` in `partition`.
+    pub(crate) fn set(
+        &self,
+        renderer: u64,
+        partition: AtlasPartition,
+        keys: impl IntoIterator<Item = GlyphonCacheKey>,
+    ) {
+        let mut renderers = self.lock();
+        let prepared = renderers.entry(renderer).or_default();
+        prepared.0 = partition;
+        prepared.1.clear();
+        prepared.1.extend(keys);
+    }
+
+    /// Forgets the glyphs of the renderer `renderer`, e.g. once it is dropped.
+    pub(crate) fn remove(&self, renderer: u64) {
+        self.lock().remove(&renderer);
+    }
+}
+
+/// Tracks the GPU memory used by an atlas and the renderers created with it.
+#[derive(Debug)]
+pub(crate) struct MemoryTracker {
+    used: AtomicU64,
+    limit: AtomicU64,
+}
+
+impl Default for MemoryTracker {
+    fn default() -> Self {
+        Self {
+            used: AtomicU64::new(0),
+            limit: AtomicU64::new(u64::MAX),
+        }
+    }
+}
+
+impl MemoryTracker {
+    fn limit(&self) -> Option<u64> {
+        match self.limit.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            limit => Some(limit),
+        }
+    }
+
+    fn set_limit(&self, limit: Option<u64>) {
+        self.limit
+            .store(limit.unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    /// Records `bytes` as used, regardless of the limit.
+    pub(crate) fn add(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Records `bytes` as used if doing so stays within the limit.
+    pub(crate) fn try_reserve(&self, bytes: u64) -> bool {
+        let limit = self.limit.load(Ordering::Relaxed);
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|&total| total <= limit)
+            })
+            .is_ok()
+    }
+
+    pub(crate) fn release(&self, bytes: u64) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        GlyphAnimation, GlyphColor, GlyphImage, PositionedGlyph, Resolution, TextBounds,
+        TextRenderer, Viewport,
+    };
+
+    /// Returns a device of the default adapter, or `None` if there is none (e.g. on CI machines
+    /// without a software rasterizer).
+    pub(crate) fn device() -> Option<(Device, Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+    }
+
+    fn mask_atlas(device: &Device, queue: &Queue) -> InnerAtlas {
+        let cache = Cache::new(device);
+        InnerAtlas::new(device, queue, &cache, Kind::Mask, &MemoryTracker::default())
+    }
+
+    fn cache_glyph(atlas: &mut InnerAtlas, key: u64, size: u16, partition: AtlasPartition) {
+        let (atlas_id, x, y) = atlas.allocate(size as usize, size as usize).unwrap();
+        let details = GlyphDetails {
+            width: size,
+            height: size,
+            gpu_cache: GpuCacheStatus::InAtlas {
+                x,
+                y,
+                content_type: ContentType::Mask,
+            },
+            atlas_id: Some(atlas_id),
+            content_hash: None,
+            top: 0,
+            left: 0,
+            generation: 0,
+            partition,
+        };
+        atlas
+            .glyph_cache
+            .put(GlyphonCacheKey::Positioned(key), details);
+    }
+
+    /// Evicts glyphs until none is left, with a new scan for every eviction if `rescan` is set,
+    /// and returns their keys in the order they were evicted.
+    fn eviction_order(atlas: &mut InnerAtlas, partition: AtlasPartition, rescan: bool) -> Vec<u64> {
+        let mut scan = EvictionScan::new(partition);
+        let mut keys = Vec::new();
+        while let Some(key) = atlas.next_eviction(&mut scan) {
+            let details = atlas.glyph_cache.pop(&key).unwrap();
+            atlas.release(&details);
+            let GlyphonCacheKey::Positioned(key) = key else {
+                unreachable!();
+            };
+            keys.push(key);
+            if rescan {
+                scan = EvictionScan::new(partition);
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn packing_density() {
+        assert_eq!(AtlasTextureStats::default().packing_density(), 1.0);
+        let stats = AtlasTextureStats {
+            allocated_area: 400,
+            glyph_area: 100,
+            ..AtlasTextureStats::default()
+        };
+        assert_eq!(stats.packing_density(), 0.25);
+    }
+
+    #[test]
+    fn small_glyphs_share_shelf_regions() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut atlas = mask_atlas(&device, &queue);
+        atlas.small_glyph_shelves = true;
+        let partition = AtlasPartition::default();
+        for key in 0..4 {
+            cache_glyph(&mut atlas, key, 8, partition);
+        }
+        // Large glyphs are allocated from the packer
+        cache_glyph(&mut atlas, 4, 32, partition);
+
+        let stats = atlas.stats();
+        assert_eq!(stats.shelf_regions, 1);
+        let region_area = (InnerAtlas::SHELF_REGION_SIZE * InnerAtlas::SHELF_REGION_SIZE) as u32;
+        // The packer may round allocations up
+        assert!(stats.allocated_area >= region_area + 32 * 32);
+        assert_eq!(stats.glyph_area, 4 * 8 * 8 + 32 * 32);
+        assert_eq!(
+            stats.packing_density(),
+            stats.glyph_area as f32 / stats.allocated_area as f32
         );
+
+        // Regions are returned to the packer once their last glyph is evicted
+        assert_eq!(eviction_order(&mut atlas, partition, false).len(), 5);
+        let stats = atlas.stats();
+        assert_eq!((stats.shelf_regions, stats.allocated_area), (0, 0));
+    }
+
+    /// Returns the glyph `policy` evicts first from glyphs of the given sizes, cached from the
+    /// least to the most recently used one, after protecting the glyphs of `protected`.
+    fn first_eviction(policy: EvictionPolicy, sizes: &[u16], protected: &[u64]) -> Option<u64> {
+        let (device, queue) = device()?;
+        let mut atlas = mask_atlas(&device, &queue);
+        atlas.eviction_policy = policy;
+        let partition = AtlasPartition::default();
+        for (key, &size) in sizes.iter().enumerate() {
+            cache_glyph(&mut atlas, key as u64, size, partition);
+        }
+        for &key in protected {
+            atlas.mark_in_use(GlyphonCacheKey::Positioned(key), partition);
+        }
+        eviction_order(&mut atlas, partition, false)
+            .first()
+            .copied()
+    }
+
+    #[test]
+    fn least_recently_used_eviction() {
+        if device().is_none() {
+            return;
+        }
+        let policy = || EvictionPolicy::LeastRecentlyUsed;
+        assert_eq!(first_eviction(policy(), &[8, 32, 8], &[]), Some(0));
+        assert_eq!(first_eviction(policy(), &[8, 32, 8], &[0, 1]), Some(2));
+        assert_eq!(first_eviction(policy(), &[8, 32, 8], &[0, 1, 2]), None);
+    }
+
+    #[test]
+    fn size_weighted_eviction() {
+        if device().is_none() {
+            return;
+        }
+        let policy = |candidates| EvictionPolicy::SizeWeighted { candidates };
+        // Large, stale glyphs are evicted first
+        assert_eq!(first_eviction(policy(16), &[8, 32, 8], &[]), Some(1));
+        assert_eq!(first_eviction(policy(16), &[8, 32, 8], &[1]), Some(0));
+        // Staleness outweighs a slightly larger area
+        assert_eq!(first_eviction(policy(4), &[16, 17, 8], &[]), Some(0));
+        // Glyphs beyond the candidates aren't considered
+        assert_eq!(first_eviction(policy(2), &[8, 8, 32], &[]), Some(0));
+        assert_eq!(first_eviction(policy(0), &[8, 32], &[]), Some(0));
+    }
+
+    #[test]
+    fn custom_eviction() {
+        if device().is_none() {
+            return;
+        }
+        let newest = |candidates| EvictionPolicy::Custom {
+            candidates,
+            score: Arc::new(|candidate| candidate.age as f32),
+        };
+        assert_eq!(first_eviction(newest(16), &[8, 8, 8], &[]), Some(2));
+        assert_eq!(first_eviction(newest(2), &[8, 8, 8], &[]), Some(1));
+        // Ages only count glyphs that may be evicted
+        assert_eq!(first_eviction(newest(2), &[8, 8, 8], &[0]), Some(2));
+
+        let smallest = EvictionPolicy::Custom {
+            candidates: 16,
+            score: Arc::new(|candidate| -(candidate.area() as f32)),
+        };
+        assert_eq!(first_eviction(smallest, &[16, 4, 8], &[]), Some(1));
+    }
+
+    #[test]
+    fn partitions() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let cache = Cache::new(&device);
+        let mut atlas = TextAtlas::new(&device, &queue, &cache, TextureFormat::Rgba8UnormSrgb);
+        let (ui, document) = (AtlasPartition::new(0), AtlasPartition::new(1));
+        for key in 0..6 {
+            let partition = if key < 3 { ui } else { document };
+            cache_glyph(&mut atlas.mask_atlas, key, 8, partition);
+        }
+        // The UI also uses a glyph cached by the document
+        for (key, partition) in [(0, ui), (3, document), (3, ui), (4, document)] {
+            atlas
+                .mask_atlas
+                .mark_in_use(GlyphonCacheKey::Positioned(key), partition);
+        }
+        let stats = |atlas: &TextAtlas, partition| {
+            let stats = atlas.partition_stats(partition);
+            (stats.cached_glyphs, stats.glyphs_in_use)
+        };
+        assert_eq!(stats(&atlas, ui), (3, 2));
+        assert_eq!(stats(&atlas, document), (3, 2));
+
+        // Glyphs stay protected until every partition using them was trimmed
+        atlas.trim_partition(document);
+        assert_eq!(stats(&atlas, ui), (3, 2));
+        assert_eq!(stats(&atlas, document), (3, 0));
+
+        // Partitions evict their own glyphs before the glyphs of others
+        assert_eq!(
+            eviction_order(&mut atlas.mask_atlas, document, false),
+            [4, 5, 1, 2]
+        );
+    }
+
+    #[test]
+    fn trim_budgeted() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let cache = Cache::new(&device);
+        let mut atlas = TextAtlas::new(&device, &queue, &cache, TextureFormat::Rgba8UnormSrgb);
+        let partition = AtlasPartition::default();
+        for key in 0..5 {
+            cache_glyph(&mut atlas.mask_atlas, key, 8, partition);
+            atlas
+                .mask_atlas
+                .mark_in_use(GlyphonCacheKey::Positioned(key), partition);
+        }
+
+        // Glyphs being trimmed stay protected until the trim unprotects them
+        assert!(!atlas.trim_budgeted(2));
+        assert_eq!(atlas.partition_stats(partition).glyphs_in_use, 0);
+        assert_eq!(atlas.mask_atlas.glyphs_being_trimmed.len(), 3);
+        assert!(!atlas.trim_budgeted(2));
+        assert_eq!(atlas.mask_atlas.glyphs_being_trimmed.len(), 1);
+        assert!(atlas.trim_budgeted(2));
+        assert_eq!(
+            eviction_order(&mut atlas.mask_atlas, partition, false).len(),
+            5
+        );
+
+        // A trim doesn't start within a frame
+        atlas.begin_frame();
+        cache_glyph(&mut atlas.mask_atlas, 9, 8, partition);
+        atlas
+            .mask_atlas
+            .mark_in_use(GlyphonCacheKey::Positioned(9), partition);
+        assert!(!atlas.trim_budgeted(2));
+        assert_eq!(atlas.partition_stats(partition).glyphs_in_use, 1);
+        atlas.end_frame();
+        assert!(atlas.trim_budgeted(2));
+        assert_eq!(atlas.partition_stats(partition).glyphs_in_use, 0);
+    }
+
+    #[test]
+    fn single_scan_evicts_like_rescanning() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let policies = [
+            EvictionPolicy::LeastRecentlyUsed,
+            EvictionPolicy::SizeWeighted { candidates: 16 },
+            EvictionPolicy::Custom {
+                candidates: 5,
+                score: Arc::new(|candidate| candidate.age as f32),
+            },
+        ];
+        for policy in policies {
+            let [single, rescanned] = [false, true].map(|rescan| {
+                let mut atlas = mask_atlas(&device, &queue);
+                atlas.eviction_policy = policy.clone();
+                for key in 0..120 {
+                    let partition = AtlasPartition::new((key % 5 < 2) as u8);
+                    cache_glyph(&mut atlas, key, (key * 7 % 11) as u16 + 2, partition);
+                    if key % 3 == 0 {
+                        atlas.mark_in_use(GlyphonCacheKey::Positioned(key), partition);
+                    }
+                }
+                eviction_order(&mut atlas, AtlasPartition::new(1), rescan)
+            });
+            assert_eq!(single.len(), 80);
+            assert_eq!(single, rescanned, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn growing_keeps_cached_glyphs() {
+        // Needs an adapter, e.g. a software rasterizer
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let cache = Cache::new(&device);
+        let mut atlas = TextAtlas::new(&device, &queue, &cache, TextureFormat::Rgba8UnormSrgb);
+        let mut renderer =
+            TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
+        let mut viewport = Viewport::new(&device, &cache);
+        viewport.update(
+            &queue,
+            Resolution {
+                width: 1024,
+                height: 1024,
+            },
+        );
+
+        const SIZE: u16 = 64;
+        let glyph = |key: u64| PositionedGlyph {
+            key,
+            x: (key % 16) as i32 * SIZE as i32,
+            y: (key / 16 + 1) as i32 * SIZE as i32,
+            color: GlyphColor::rgb(255, 255, 255),
+            bounds: TextBounds::default(),
+            depth: 0.0,
+            animation: GlyphAnimation::default(),
+        };
+        let image = |key: u64| {
+            Some(GlyphImage {
+                content_type: ContentType::Mask,
+                top: SIZE as i16,
+                left: 0,
+                width: SIZE,
+                height: SIZE,
+                data: vec![key as u8 + 1; SIZE as usize * SIZE as usize],
+            })
+        };
+
+        // The first glyphs fill the initial texture and are still in use while the others grow it
+        for count in [16, 32] {
+            renderer
+                .prepare_positioned(
+                    &device,
+                    &queue,
+                    &mut atlas,
+                    &viewport,
+                    (0..count).map(glyph),
+                    image,
+                )
+                .unwrap();
+        }
+        assert!(atlas.mask_atlas.growth_count > 0);
+
+        let texels = atlas
+            .read_texture(&device, &queue, ContentType::Mask)
+            .unwrap();
+        let size = atlas.mask_atlas.size as usize;
+        for key in 0..32 {
+            let details = atlas
+                .mask_atlas
+                .glyph_cache
+                .peek(&GlyphonCacheKey::Positioned(key))
+                .unwrap();
+            let GpuCacheStatus::InAtlas { x, y, .. } = details.gpu_cache else {
+                panic!("glyph {key} isn't in the atlas");
+            };
+            for row in y as usize..(y + SIZE) as usize {
+                let start = row * size + x as usize;
+                assert!(
+                    texels[start..start + SIZE as usize]
+                        .iter()
+                        .all(|&texel| texel == key as u8 + 1),
+                    "glyph {key} changed"
+                );
+            }
+        }
     }
 }
@@ -1,51 +1,401 @@
 use crate::{
-    custom_glyph::CustomGlyphCacheKey, ColorMode, ContentType, FontSystem, GlyphDetails,
-    GlyphToRender, GpuCacheStatus, PrepareError, RasterizeCustomGlyphRequest,
-    RasterizedCustomGlyph, RenderError, SwashCache, SwashContent, TextArea, TextAtlas, Viewport,
+    cache::{PipelineFeatures, PUSH_CONSTANT_STAGES},
+    custom_glyph::CustomGlyphCacheKey,
+    decoration::{DecorationPattern, DecorationQuad},
+    emoji_fallback::{is_emoji, monochrome_emoji_color, EmojiDecomposer},
+    fallback::FallbackRenderer,
+    line_strip::{BakedLine, LineExtent, LINE_STRIP_WIDTH},
+    minimap::minimap_blocks,
+    raster_options::rasterize_with_options,
+    revision::{PrepareSignature, TextAreaSignature},
+    text_atlas::{MemoryTracker, PreparedGlyphs, UploadGuard},
+    Angle, AtlasGlyphHandle, AtlasPartition, AtlasTexture, BufferRevision, Cache, ColorGlyphStyle,
+    ColorMode, ContentType, CustomGlyphPlaceholder, CustomGlyphZOrder, DrawRange, ExportError,
+    ExportedGlyph, ExportedOutline, FontFallback, FontSystem, GlyphColor, GlyphDetails, GlyphImage,
+    GlyphOutline, GlyphRasterOptions, GlyphSource, GlyphSourceInfo, GlyphToRender, GpuCacheStatus,
+    MinimapStyle, PathCommand, PipelineError, PositionedGlyph, PositionedRun, PrepareError,
+    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, RenderError, Resolution, RestoreError,
+    RunInfo, SwashCache, SwashContent, SwashImage, TextArea, TextAtlas, TextBounds, TextDirection,
+    Transform, Viewport, ViewportPool,
+};
+use cosmic_text::{fontdb, CacheKey, CacheKeyFlags, Color, LayoutRun, SubpixelBin};
+use rustc_hash::FxHasher;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{BuildHasherDefault, Hash, Hasher},
+    mem,
+    ops::Range,
+    slice,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use swash::{
+    scale::ScaleContext,
+    zeno::{Command, PathData, Vector},
 };
-use cosmic_text::{Color, SubpixelBin};
-use std::slice;
 use wgpu::{
-    Buffer, BufferDescriptor, BufferUsages, DepthStencilState, Device, Extent3d, MultisampleState,
-    Origin3d, Queue, RenderPass, RenderPipeline, TexelCopyBufferLayout, TexelCopyTextureInfo,
-    TextureAspect, COPY_BUFFER_ALIGNMENT,
+    BindGroup, BlendState, Buffer, BufferDescriptor, BufferUsages, CommandEncoder,
+    DepthStencilState, Device, IndexFormat, MultisampleState, Queue, RenderPass, RenderPipeline,
+    TextureFormat, TextureView, COPY_BUFFER_ALIGNMENT,
 };
 
+/// The id of the next renderer that is created
+static NEXT_RENDERER_ID: AtomicU64 = AtomicU64::new(0);
+
 /// A text renderer that uses cached glyphs to render text into an existing render pass.
 pub struct TextRenderer {
+    cache: Cache,
     vertex_buffer: Buffer,
     vertex_buffer_size: u64,
-    pipeline: RenderPipeline,
+    pipeline: Pipeline,
+    /// The features of the pipeline, which stay disabled with the fallback
+    features: PipelineFeatures,
+    vertex_pulling: Option<VertexPulling>,
+    color_overrides: Option<GlyphBuffer>,
+    mask: Option<BindGroup>,
+    pick_id_buffer: Option<GlyphBuffer>,
     glyph_vertices: Vec<GlyphToRender>,
+    glyph_refs: Vec<GlyphRef>,
+    glyph_sources: Option<Vec<GlyphSourceInfo>>,
+    run_infos: Option<Vec<RunInfo>>,
+    font_fallbacks: Option<Vec<FontFallback>>,
+    /// The draw ranges of the glyphs if they are sorted by atlas texture
+    draw_ranges: Option<Vec<DrawRange>>,
+    /// The glyphs of each text area prepared by the last call to `prepare`
+    area_ranges: Vec<Range<usize>>,
+    emoji_decomposer: Option<EmojiDecomposer>,
+    char_filter: Option<Box<dyn Fn(char) -> bool + Send + Sync>>,
+    metadata_to_animation: Option<Box<dyn Fn(usize) -> GlyphAnimation + Send + Sync>>,
+    metadata_to_palette_index: Option<Box<dyn Fn(usize) -> Option<usize> + Send + Sync>>,
+    metadata_to_raster_options: Option<Box<dyn Fn(usize) -> GlyphRasterOptions + Send + Sync>>,
+    metadata_to_pick_id: Option<Box<dyn Fn(usize) -> u32 + Send + Sync>>,
+    /// The pick ID of each prepared glyph, recorded while pick IDs are enabled
+    pick_ids: Vec<u32>,
+    /// Rasterizes glyphs with options other than the default ones
+    scale_context: Option<ScaleContext>,
+    /// The options glyphs were rasterized with by their hash, to rasterize them again in
+    /// [`TextRenderer::revalidate`]
+    raster_options: HashMap<u64, GlyphRasterOptions, BuildHasherDefault<FxHasher>>,
+    line_strips: Option<usize>,
+    max_raster_font_size: Option<u16>,
+    /// The extents of the lines baked by the last call to `prepare` by their key, or `None` for
+    /// lines that can't be baked
+    baked_lines: HashMap<u64, Option<LineExtent>, BuildHasherDefault<FxHasher>>,
+    /// The extents of the lines baked by the current call to `prepare`
+    next_baked_lines: HashMap<u64, Option<LineExtent>, BuildHasherDefault<FxHasher>>,
+    max_glyphs: Option<usize>,
+    minimap: Option<MinimapStyle>,
+    label: Option<String>,
+    /// Whether a glyph was dropped since the last prepare because of `max_glyphs`
+    glyph_limit_exceeded: bool,
+    color_glyphs: ColorGlyphPolicy,
+    pixel_snapping: PixelSnapping,
+    custom_glyph_placeholder: Option<CustomGlyphPlaceholder>,
+    color_glyph_keys: HashSet<CacheKey, BuildHasherDefault<FxHasher>>,
+    last_prepare: Option<PrepareSignature>,
+    /// Whether `upload_vertices` leaves writing the vertex buffer to `prepare_with_encoder`
+    defer_vertex_writes: bool,
+    /// Whether the vertex buffer still needs to be written by `prepare_with_encoder`
+    pending_vertex_write: bool,
+    resolution_viewports: ViewportPool,
+    memory: Arc<MemoryTracker>,
+    /// Identifies the glyphs of this renderer in `prepared_glyphs`
+    id: u64,
+    atlas_partition: AtlasPartition,
+    prepared_glyphs: Arc<PreparedGlyphs>,
+    multisample: MultisampleState,
+    depth_stencil: Option<DepthStencilState>,
+    blend_mode: BlendMode,
+}
+
+enum Pipeline {
+    /// Renders glyphs from the atlas with the features of the renderer
+    Glyphs(RenderPipeline),
+    /// Used when the device failed to create the glyph pipeline
+    Fallback(Box<FallbackRenderer>),
+}
+
+/// The vertex buffer bound as a storage buffer, see [`TextRenderer::set_vertex_pulling`]
+struct VertexPulling {
+    bind_group: BindGroup,
+    /// The number of glyphs that fit in the bound range of the vertex buffer
+    bound_glyphs: u32,
+}
+
+/// A second vertex buffer with a value for each glyph, e.g. its color override
+struct GlyphBuffer {
+    buffer: Buffer,
+    size: u64,
+}
+
+/// Controls how a [`TextRenderer`] renders color glyphs, such as color emoji.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorGlyphPolicy {
+    /// Render color glyphs in color.
+    #[default]
+    Allow,
+    /// Skip color glyphs entirely, so they never take up space in the atlas.
+    Block,
+    /// Render the shape of color glyphs in the text color, like regular glyphs.
+    Monochrome,
+}
+
+/// How a coordinate of a glyph position is snapped to physical pixels, see [`PixelSnapping`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PixelSnap {
+    /// Keep the fractional position, rasterizing the glyph at one of four subpixel offsets.
+    #[default]
+    None,
+    /// Round to the nearest pixel.
+    Round,
+    /// Round down to the pixel containing the position.
+    Floor,
+}
+
+impl PixelSnap {
+    /// Returns the position at `offset` from `origin`, snapping both separately so that positions
+    /// with the same offset from any origin are the same distance apart.
+    fn apply(self, origin: f32, offset: f32) -> f32 {
+        match self {
+            Self::None => origin + offset,
+            Self::Round => origin.round() + offset.round(),
+            Self::Floor => origin.floor() + offset.floor(),
+        }
+    }
+}
+
+/// Controls how a [`TextRenderer`] snaps the positions of text glyphs to physical pixels, per
+/// axis.
+///
+/// The position of a text area and the offset of each glyph from it (including the baseline of
+/// its line) are snapped separately, so glyphs keep their distances to each other and move
+/// together when the text area is animated across fractional positions. With [`PixelSnap::None`],
+/// glyphs move smoothly at the cost of rasterizing more variants of each glyph, while
+/// [`PixelSnap::Round`] and [`PixelSnap::Floor`] keep text crisp at fractional scale factors.
+///
+/// Defaults to [`PixelSnap::None`] horizontally and [`PixelSnap::Round`] vertically, with
+/// subpixel positions at every font size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PixelSnapping {
+    /// How horizontal positions are snapped
+    pub x: PixelSnap,
+    /// How vertical positions are snapped
+    pub y: PixelSnap,
+    /// The largest font size in physical pixels at which glyphs keep subpixel positions, or
+    /// `None` for no limit
+    ///
+    /// Larger glyphs are rounded to the nearest pixel on axes with [`PixelSnap::None`], so that
+    /// a single variant of each of them is cached in the atlas instead of one per subpixel
+    /// offset. The offset is hardly visible at large sizes, e.g. in titles and presentations.
+    pub max_subpixel_size: Option<u16>,
+}
+
+impl PixelSnapping {
+    /// Snaps both axes the same way.
+    pub fn uniform(snap: PixelSnap) -> Self {
+        Self {
+            x: snap,
+            y: snap,
+            max_subpixel_size: None,
+        }
+    }
+
+    /// Returns how a glyph with the given font size in physical pixels is snapped.
+    fn for_size(self, font_size: f32) -> Self {
+        match self.max_subpixel_size {
+            Some(max_size) if font_size > max_size as f32 => {
+                let round = |snap| match snap {
+                    PixelSnap::None => PixelSnap::Round,
+                    snap => snap,
+                };
+                Self {
+                    x: round(self.x),
+                    y: round(self.y),
+                    max_subpixel_size: None,
+                }
+            }
+            _ => self,
+        }
+    }
+}
+
+impl Default for PixelSnapping {
+    fn default() -> Self {
+        Self {
+            x: PixelSnap::None,
+            y: PixelSnap::Round,
+            max_subpixel_size: None,
+        }
+    }
+}
+
+/// An effect animated by the shader, using the time set with [`crate::Viewport::set_time`].
+///
+/// Glyphs are animated according to their metadata, see
+/// [`TextRenderer::set_metadata_to_animation`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GlyphAnimation {
+    /// The glyph isn't animated.
+    #[default]
+    None,
+    /// The glyph is shown during the first half of every second and hidden during the second
+    /// half, e.g. for a blinking caret.
+    Blink,
+    /// The copies of the pattern of a dashed, dotted or patterned [`crate::Decoration`] move to
+    /// the right by one period every second, e.g. for a "marching ants" selection outline.
+    ///
+    /// Other glyphs aren't affected.
+    MarchingAnts,
+}
+
+/// Controls how a [`TextRenderer`] combines the edges of glyphs with the render target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Blend glyphs with the render target using their alpha.
+    #[default]
+    Alpha,
+    /// Use the alpha of glyphs as the multisample coverage instead of blending.
+    ///
+    /// This requires a multisampled render target. Since glyphs don't need to be drawn after all
+    /// other geometry, this gives better results for text that intersects 3D geometry with a
+    /// depth buffer, at the cost of coarser antialiasing.
+    AlphaToCoverage,
+}
+
+impl BlendMode {
+    pub(crate) fn blend_state(self) -> Option<BlendState> {
+        match self {
+            Self::Alpha => Some(BlendState::ALPHA_BLENDING),
+            Self::AlphaToCoverage => None,
+        }
+    }
 }
 
 impl TextRenderer {
     /// Creates a new `TextRenderer`.
+    ///
+    /// If the device fails to create the pipeline used to render glyphs (e.g. on old GL devices
+    /// without the required features), the renderer falls back to rasterizing whole text areas on
+    /// the CPU and rendering them as a single texture. See [`TextRenderer::uses_fallback`].
     pub fn new(
         atlas: &mut TextAtlas,
         device: &Device,
         multisample: MultisampleState,
         depth_stencil: Option<DepthStencilState>,
     ) -> Self {
-        let vertex_buffer_size = next_copy_buffer_size(4096);
+        Self::with_blend_mode(
+            atlas,
+            device,
+            multisample,
+            depth_stencil,
+            BlendMode::default(),
+        )
+    }
+
+    /// Creates a new `TextRenderer` with the given [`BlendMode`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `blend_mode` is [`BlendMode::AlphaToCoverage`] and `multisample` has a sample
+    /// count of 1.
+    pub fn with_blend_mode(
+        atlas: &mut TextAtlas,
+        device: &Device,
+        mut multisample: MultisampleState,
+        depth_stencil: Option<DepthStencilState>,
+        blend_mode: BlendMode,
+    ) -> Self {
+        if blend_mode == BlendMode::AlphaToCoverage {
+            assert!(
+                multisample.count > 1,
+                "Alpha to coverage requires a multisampled render target"
+            );
+            multisample.alpha_to_coverage_enabled = true;
+        }
+
+        let vertex_buffer_size = next_copy_buffer_size(MIN_VERTEX_BUFFER_SIZE);
         let vertex_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("glyphon vertices"),
+            label: Some(&atlas.cache.generation_label("vertices", vertex_buffer_size)),
             size: vertex_buffer_size,
             usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
 
-        let pipeline = atlas.get_or_create_pipeline(device, multisample, depth_stencil);
+        atlas.memory.add(vertex_buffer_size);
+
+        let pipeline = match atlas.get_or_create_pipeline(
+            device,
+            multisample,
+            depth_stencil.clone(),
+            blend_mode,
+            PipelineFeatures::default(),
+        ) {
+            Some(pipeline) => Pipeline::Glyphs(pipeline),
+            None => Pipeline::Fallback(Box::new(FallbackRenderer::new(
+                atlas,
+                device,
+                multisample,
+                depth_stencil.clone(),
+                blend_mode,
+            ))),
+        };
 
         Self {
+            cache: atlas.cache.clone(),
             vertex_buffer,
             vertex_buffer_size,
             pipeline,
+            features: PipelineFeatures::default(),
+            vertex_pulling: None,
+            color_overrides: None,
+            mask: None,
+            pick_id_buffer: None,
             glyph_vertices: Vec::new(),
+            glyph_refs: Vec::new(),
+            glyph_sources: None,
+            run_infos: None,
+            font_fallbacks: None,
+            draw_ranges: None,
+            area_ranges: Vec::new(),
+            emoji_decomposer: None,
+            char_filter: None,
+            metadata_to_animation: None,
+            metadata_to_palette_index: None,
+            metadata_to_raster_options: None,
+            metadata_to_pick_id: None,
+            pick_ids: Vec::new(),
+            scale_context: None,
+            raster_options: HashMap::default(),
+            line_strips: None,
+            max_raster_font_size: None,
+            baked_lines: HashMap::default(),
+            next_baked_lines: HashMap::default(),
+            max_glyphs: None,
+            minimap: None,
+            label: None,
+            glyph_limit_exceeded: false,
+            color_glyphs: ColorGlyphPolicy::Allow,
+            pixel_snapping: PixelSnapping::default(),
+            custom_glyph_placeholder: None,
+            color_glyph_keys: HashSet::default(),
+            last_prepare: None,
+            defer_vertex_writes: false,
+            pending_vertex_write: false,
+            resolution_viewports: ViewportPool::new(),
+            memory: atlas.memory.clone(),
+            id: NEXT_RENDERER_ID.fetch_add(1, Ordering::Relaxed),
+            atlas_partition: AtlasPartition::DEFAULT,
+            prepared_glyphs: atlas.prepared_glyphs.clone(),
+            multisample,
+            depth_stencil,
+            blend_mode,
         }
     }
 
     /// Prepares all of the provided text areas for rendering.
+    #[allow(clippy::too_many_arguments)]
     pub fn prepare<'a>(
         &mut self,
         device: &Device,
@@ -55,7 +405,7 @@ impl TextRenderer {
         viewport: &Viewport,
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
         cache: &mut SwashCache,
-    ) -> Result<(), PrepareError> {
+    ) -> Result<PrepareOutput, PrepareError> {
         self.prepare_with_depth_and_custom(
             device,
             queue,
@@ -70,6 +420,7 @@ impl TextRenderer {
     }
 
     /// Prepares all of the provided text areas for rendering.
+    #[allow(clippy::too_many_arguments)]
     pub fn prepare_with_depth<'a>(
         &mut self,
         device: &Device,
@@ -80,7 +431,7 @@ impl TextRenderer {
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
         cache: &mut SwashCache,
         metadata_to_depth: impl FnMut(usize) -> f32,
-    ) -> Result<(), PrepareError> {
+    ) -> Result<PrepareOutput, PrepareError> {
         self.prepare_with_depth_and_custom(
             device,
             queue,
@@ -95,6 +446,7 @@ impl TextRenderer {
     }
 
     /// Prepares all of the provided text areas for rendering.
+    #[allow(clippy::too_many_arguments)]
     pub fn prepare_with_custom<'a>(
         &mut self,
         device: &Device,
@@ -105,7 +457,7 @@ impl TextRenderer {
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
         cache: &mut SwashCache,
         rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
-    ) -> Result<(), PrepareError> {
+    ) -> Result<PrepareOutput, PrepareError> {
         self.prepare_with_depth_and_custom(
             device,
             queue,
@@ -120,6 +472,7 @@ impl TextRenderer {
     }
 
     /// Prepares all of the provided text areas for rendering.
+    #[allow(clippy::too_many_arguments)]
     pub fn prepare_with_depth_and_custom<'a>(
         &mut self,
         device: &Device,
@@ -133,36 +486,121 @@ impl TextRenderer {
         mut rasterize_custom_glyph: impl FnMut(
             RasterizeCustomGlyphRequest,
         ) -> Option<RasterizedCustomGlyph>,
-    ) -> Result<(), PrepareError> {
-        self.glyph_vertices.clear();
+    ) -> Result<PrepareOutput, PrepareError> {
+        trace_span!("prepare");
+        #[cfg(feature = "tracing")]
+        let lookups_before = atlas.stats();
+
+        let mut atlas = UploadGuard::new(atlas, device, queue);
+        let atlas = &mut *atlas;
+        self.clear_prepared();
+        atlas.partition = self.atlas_partition;
 
         let resolution = viewport.resolution();
 
-        for text_area in text_areas {
+        if let Pipeline::Fallback(fallback) = &mut self.pipeline {
+            return fallback.prepare(
+                device,
+                queue,
+                font_system,
+                atlas,
+                viewport,
+                text_areas,
+                cache,
+                self.custom_glyph_placeholder,
+                self.minimap,
+                GlyphEmission {
+                    snapping: self.pixel_snapping,
+                    char_filter: self.char_filter.as_deref(),
+                    emoji_decomposer: self.emoji_decomposer.as_mut(),
+                },
+                rasterize_custom_glyph,
+            );
+        }
+
+        let mut text_area_signatures = Vec::new();
+        let mut culled_text_areas = Vec::new();
+        let mut reported_fallbacks = HashSet::default();
+        let mut text_glyphs = Vec::new();
+
+        for (text_area_index, mut text_area) in text_areas.into_iter().enumerate() {
+            trace_span!("text_area", index = text_area_index);
+
+            text_area_signatures.push(TextAreaSignature::new(&text_area));
+            let glyphs_before = self.glyph_vertices.len();
+
+            let exclusions = text_area.physical_exclusions();
+            text_area = text_area.physical();
+
+            let integer_scale = text_area.integer_scale();
+            if let Some(integer_scale) = integer_scale {
+                text_area.left = text_area.left.round();
+                text_area.top = text_area.top.round();
+                text_area.scale = integer_scale as f32;
+            }
+
             let bounds_min_x = text_area.bounds.left.max(0);
             let bounds_min_y = text_area.bounds.top.max(0);
             let bounds_max_x = text_area.bounds.right.min(resolution.width as i32);
             let bounds_max_y = text_area.bounds.bottom.min(resolution.height as i32);
+            let visible_bounds = TextBounds {
+                left: bounds_min_x,
+                top: bounds_min_y,
+                right: bounds_max_x,
+                bottom: bounds_max_y,
+            };
 
-            for glyph in text_area.custom_glyphs.iter() {
+            // Custom glyphs above the text are pushed once the text of the area was prepared
+            let mut glyphs_above_text = Vec::new();
+
+            for (glyph_index, glyph) in text_area.custom_glyphs.iter().enumerate() {
                 let x = text_area.left + (glyph.left * text_area.scale);
-                let y = text_area.top + (glyph.top * text_area.scale);
-                let width = (glyph.width * text_area.scale).round() as u16;
-                let height = (glyph.height * text_area.scale).round() as u16;
+                let y = text_area.top
+                    + (glyph.aligned_top(&text_area, font_system, cache) * text_area.scale);
+                let mut width = (glyph.width * text_area.scale).round() as u16;
+                let mut height = (glyph.height * text_area.scale).round() as u16;
 
-                let (x, y, x_bin, y_bin) = if glyph.snap_to_physical_pixel {
-                    (
-                        x.round() as i32,
-                        y.round() as i32,
-                        SubpixelBin::Zero,
-                        SubpixelBin::Zero,
-                    )
+                // Rotated and flipped glyphs reuse the upright image and are oriented by their
+                // vertices, so they are clipped once they are oriented
+                let orientation = glyph.orientation();
+                let glyph_bounds = if orientation.is_upright() {
+                    visible_bounds
                 } else {
-                    let (x, x_bin) = SubpixelBin::new(x);
-                    let (y, y_bin) = SubpixelBin::new(y);
-                    (x, y, x_bin, y_bin)
+                    let (x, y, _, _) = custom_glyph_position(x, y, true);
+                    if x > bounds_max_x
+                        || x.saturating_add(width as i32) < bounds_min_x
+                        || y > bounds_max_y
+                        || y.saturating_add(height as i32) < bounds_min_y
+                    {
+                        continue;
+                    }
+                    if orientation.transposes() {
+                        (width, height) = (height, width);
+                    }
+                    TextBounds::default()
+                };
+
+                // Scalable glyphs are rasterized once at their registered size and drawn scaled.
+                let glyph_scale = match atlas.scalable_custom_glyphs.get(&glyph.id) {
+                    Some(&source_size) => {
+                        if width == 0 || height == 0 {
+                            continue;
+                        }
+                        let scaled_size = (width, height);
+                        (width, height) = source_size;
+                        GlyphScale::Size(scaled_size.0, scaled_size.1)
+                    }
+                    None => GlyphScale::None,
                 };
 
+                let (x, y, x_bin, y_bin) = custom_glyph_position(
+                    x,
+                    y,
+                    glyph.snap_to_physical_pixel
+                        || glyph_scale != GlyphScale::None
+                        || !orientation.is_upright(),
+                );
+
                 let cache_key = GlyphonCacheKey::Custom(CustomGlyphCacheKey {
                     glyph_id: glyph.id,
                     width,
@@ -172,25 +610,25 @@ impl TextRenderer {
                 });
 
                 let color = glyph.color.unwrap_or(text_area.default_color);
+                let mut rasterized = true;
 
-                if let Some(glyph_to_render) = prepare_glyph(
-                    x,
-                    y,
-                    0.0,
-                    color,
-                    glyph.metadata,
-                    cache_key,
+                self.check_glyph_limit(text_area_index)?;
+                let mut prepared = prepare_glyph(
+                    GlyphPlacement {
+                        x,
+                        y,
+                        line_y: 0.0,
+                        color: color.into(),
+                        metadata: glyph.metadata,
+                        cache_key,
+                        glyph_scale,
+                        scale_factor: text_area.scale,
+                        bounds: glyph_bounds,
+                    },
                     atlas,
                     device,
                     queue,
-                    cache,
-                    font_system,
-                    text_area.scale,
-                    bounds_min_x,
-                    bounds_min_y,
-                    bounds_max_x,
-                    bounds_max_y,
-                    |_cache, _font_system, rasterize_custom_glyph| -> Option<GetGlyphImageResult> {
+                    || -> Option<GlyphImage> {
                         if width == 0 || height == 0 {
                             return None;
                         }
@@ -204,11 +642,14 @@ impl TextRenderer {
                             scale: text_area.scale,
                         };
 
-                        let output = (rasterize_custom_glyph)(input)?;
+                        let Some(output) = (rasterize_custom_glyph)(input) else {
+                            rasterized = false;
+                            return None;
+                        };
 
                         output.validate(&input, None);
 
-                        Some(GetGlyphImageResult {
+                        Some(GlyphImage {
                             content_type: output.content_type,
                             top: 0,
                             left: 0,
@@ -218,370 +659,4279 @@ impl TextRenderer {
                         })
                     },
                     &mut metadata_to_depth,
-                    &mut rasterize_custom_glyph,
-                )? {
-                    self.glyph_vertices.push(glyph_to_render);
+                )?;
+
+                if let (false, Some(placeholder)) = (rasterized, self.custom_glyph_placeholder) {
+                    let (width, height) = match glyph_scale {
+                        GlyphScale::Size(width, height) => (width, height),
+                        GlyphScale::None | GlyphScale::Integer(_) | GlyphScale::Factor(_) => {
+                            (width, height)
+                        }
+                    };
+                    prepared = prepare_glyph(
+                        GlyphPlacement {
+                            x,
+                            y,
+                            line_y: 0.0,
+                            color: color.into(),
+                            metadata: glyph.metadata,
+                            cache_key: GlyphonCacheKey::Placeholder(placeholder),
+                            glyph_scale: GlyphScale::Size(width, height),
+                            scale_factor: text_area.scale,
+                            bounds: glyph_bounds,
+                        },
+                        atlas,
+                        device,
+                        queue,
+                        || {
+                            let (data, width, height) = placeholder.rasterize();
+                            Some(GlyphImage {
+                                content_type: ContentType::Mask,
+                                top: 0,
+                                left: 0,
+                                width,
+                                height,
+                                data,
+                            })
+                        },
+                        &mut metadata_to_depth,
+                    )?;
+                }
+
+                if !orientation.is_upright() {
+                    prepared = prepared.and_then(|(glyph_to_render, glyph_ref)| {
+                        Some((
+                            glyph_to_render.oriented(orientation, visible_bounds)?,
+                            glyph_ref,
+                        ))
+                    });
                 }
-            }
 
-            let is_run_visible = |run: &cosmic_text::LayoutRun| {
-                let start_y = (text_area.top + run.line_top) as i32;
-                let end_y = (text_area.top + run.line_top + run.line_height) as i32;
+                if let Some((glyph_to_render, glyph_ref)) = prepared {
+                    let mut glyph_to_render = glyph_to_render;
+                    apply_color_glyph_style(&mut glyph_to_render, text_area.color_glyph_style);
+                    apply_animation(&mut glyph_to_render, self.glyph_animation(glyph.metadata));
+                    apply_palette_index(&mut glyph_to_render, self.palette_index(glyph.metadata));
+                    let pick_id = self.pick_id(glyph.metadata);
+                    match glyph.z_order {
+                        CustomGlyphZOrder::BelowText => self.push_glyph_excluding(
+                            glyph_to_render,
+                            glyph_ref,
+                            text_area_index,
+                            GlyphSource::Custom { index: glyph_index },
+                            pick_id,
+                            &exclusions,
+                        ),
+                        CustomGlyphZOrder::AboveText => {
+                            glyphs_above_text.push((
+                                glyph_to_render,
+                                glyph_ref,
+                                glyph_index,
+                                pick_id,
+                            ));
+                        }
+                    }
+                }
+            }
 
-                start_y <= text_area.bounds.bottom && text_area.bounds.top <= end_y
+            // In minimap mode, the words of the text are drawn as blocks above the decorations
+            let minimap_blocks = match self.minimap {
+                Some(style) => minimap_blocks(visible_runs(&text_area), style),
+                None => Vec::new(),
             };
+            let decorations = text_area
+                .decorations
+                .iter()
+                .enumerate()
+                .map(|(index, decoration)| (decoration, GlyphSource::Decoration { index }))
+                .chain(
+                    minimap_blocks
+                        .iter()
+                        .map(|(decoration, source)| (decoration, source.clone())),
+                );
+
+            for (decoration, source) in decorations {
+                let Some(quad) = decoration.quad(text_area.left, text_area.top, text_area.scale)
+                else {
+                    continue;
+                };
+
+                let (cache_key, glyph_scale) = match quad.pattern {
+                    DecorationPattern::Solid => (
+                        GlyphonCacheKey::Decoration(quad.pattern),
+                        GlyphScale::Size(quad.tile, quad.height),
+                    ),
+                    DecorationPattern::Dot { .. } => {
+                        (GlyphonCacheKey::Decoration(quad.pattern), GlyphScale::None)
+                    }
+                    DecorationPattern::Custom(id) => {
+                        let (width, height, glyph_scale) =
+                            match atlas.scalable_custom_glyphs.get(&id) {
+                                Some(&(width, height)) => {
+                                    (width, height, GlyphScale::Size(quad.tile, quad.height))
+                                }
+                                None => (quad.tile, quad.height, GlyphScale::None),
+                            };
+                        let cache_key = GlyphonCacheKey::Custom(CustomGlyphCacheKey {
+                            glyph_id: id,
+                            width,
+                            height,
+                            x_bin: SubpixelBin::Zero,
+                            y_bin: SubpixelBin::Zero,
+                        });
+                        (cache_key, glyph_scale)
+                    }
+                };
+
+                let color = decoration.color.unwrap_or(text_area.default_color);
+                let animation = self.glyph_animation(decoration.metadata);
+                let palette_index = self.palette_index(decoration.metadata);
+                let pick_id = self.pick_id(decoration.metadata);
+                let line_max_x = bounds_max_x.min(quad.x.saturating_add(quad.width));
+                let period = quad.period.map_or(i32::MAX, i32::from);
 
-            let layout_runs = text_area
-                .buffer
-                .layout_runs()
-                .skip_while(|run| !is_run_visible(run))
-                .take_while(is_run_visible);
+                for (x, min_x, max_x) in
+                    decoration_quads(&quad, animation, bounds_min_x, bounds_max_x)
+                {
+                    self.check_glyph_limit(text_area_index)?;
+                    let Some((mut glyph_to_render, glyph_ref)) = prepare_glyph(
+                        GlyphPlacement {
+                            x,
+                            y: quad.y,
+                            line_y: 0.0,
+                            color: color.into(),
+                            metadata: decoration.metadata,
+                            cache_key,
+                            glyph_scale,
+                            scale_factor: text_area.scale,
+                            bounds: TextBounds {
+                                left: min_x,
+                                top: bounds_min_y,
+                                right: max_x,
+                                bottom: bounds_max_y,
+                            },
+                        },
+                        atlas,
+                        device,
+                        queue,
+                        || -> Option<GlyphImage> {
+                            let (content_type, data, width, height) = match cache_key {
+                                GlyphonCacheKey::Custom(key) => {
+                                    let input = RasterizeCustomGlyphRequest {
+                                        id: key.glyph_id,
+                                        width: key.width,
+                                        height: key.height,
+                                        x_bin: key.x_bin,
+                                        y_bin: key.y_bin,
+                                        scale: text_area.scale,
+                                    };
 
-            for run in layout_runs {
-                for glyph in run.glyphs.iter() {
-                    let physical_glyph =
-                        glyph.physical((text_area.left, text_area.top), text_area.scale);
+                                    let output = (rasterize_custom_glyph)(input)?;
+                                    output.validate(&input, None);
+
+                                    (output.content_type, output.data, key.width, key.height)
+                                }
+                                _ => {
+                                    let (data, width, height) = quad.pattern.rasterize()?;
+                                    (ContentType::Mask, data, width, height)
+                                }
+                            };
 
-                    let color = match glyph.color_opt {
-                        Some(some) => some,
-                        None => text_area.default_color,
+                            Some(GlyphImage {
+                                content_type,
+                                top: 0,
+                                left: 0,
+                                width,
+                                height,
+                                data,
+                            })
+                        },
+                        &mut metadata_to_depth,
+                    )?
+                    else {
+                        continue;
                     };
 
-                    if let Some(glyph_to_render) = prepare_glyph(
+                    if min_x == i32::MIN {
+                        // Extend the copy along the rest of the visible line, which is at most
+                        // `u16::MAX` wide
+                        let width = (line_max_x - x).min(u16::MAX as i32);
+                        glyph_to_render.pos[0] = x;
+                        glyph_to_render.dim[0] = width as u16;
+                        glyph_to_render.repeat = [quad.tile, period as u16];
+                    }
+
+                    apply_color_glyph_style(&mut glyph_to_render, text_area.color_glyph_style);
+                    apply_animation(&mut glyph_to_render, animation);
+                    apply_palette_index(&mut glyph_to_render, palette_index);
+                    self.push_glyph_excluding(
+                        glyph_to_render,
+                        glyph_ref,
+                        text_area_index,
+                        source.clone(),
+                        pick_id,
+                        &exclusions,
+                    );
+                }
+            }
+
+            for run in visible_runs(&text_area) {
+                trace_span!("layout_run", line = run.line_i);
+
+                if let Some(run_infos) = &mut self.run_infos {
+                    push_run_infos(run_infos, text_area_index, &text_area, &run);
+                }
+                if let Some(font_fallbacks) = &mut self.font_fallbacks {
+                    push_font_fallbacks(
+                        font_fallbacks,
+                        &mut reported_fallbacks,
+                        font_system,
+                        text_area_index,
+                        text_area.buffer,
+                        &run,
+                    );
+                }
+
+                if self.minimap.is_some() {
+                    continue;
+                }
+
+                let bake_line = integer_scale.is_none()
+                    && self
+                        .line_strips
+                        .is_some_and(|min_glyphs| run.glyphs.len() >= min_glyphs);
+                if bake_line
+                    && self.prepare_line_strips(
+                        &run,
+                        &text_area,
+                        text_area_index,
+                        visible_bounds,
+                        &exclusions,
+                        atlas,
+                        device,
+                        queue,
+                        font_system,
+                        cache,
+                        &mut metadata_to_depth,
+                    )?
+                {
+                    continue;
+                }
+
+                GlyphEmission {
+                    snapping: self.pixel_snapping,
+                    char_filter: self.char_filter.as_deref(),
+                    emoji_decomposer: self.emoji_decomposer.as_mut(),
+                }
+                .text_glyphs(
+                    &run,
+                    &text_area,
+                    integer_scale,
+                    font_system,
+                    &mut text_glyphs,
+                );
+
+                for glyph in text_glyphs.drain(..) {
+                    let (physical_glyph, glyph_scale) =
+                        self.limit_raster_font_size(glyph.physical, glyph.glyph_scale);
+
+                    self.check_glyph_limit(text_area_index)?;
+                    let glyph_to_render = self.prepare_text_glyph(
+                        physical_glyph.cache_key,
                         physical_glyph.x,
                         physical_glyph.y,
-                        run.line_y,
-                        color,
+                        0.0,
+                        glyph.color,
                         glyph.metadata,
-                        GlyphonCacheKey::Text(physical_glyph.cache_key),
+                        glyph_scale,
                         atlas,
                         device,
                         queue,
-                        cache,
-                        font_system,
                         text_area.scale,
                         bounds_min_x,
                         bounds_min_y,
                         bounds_max_x,
                         bounds_max_y,
-                        |cache,
-                         font_system,
-                         _rasterize_custom_glyph|
-                         -> Option<GetGlyphImageResult> {
-                            let image =
-                                cache.get_image_uncached(font_system, physical_glyph.cache_key)?;
-
-                            let content_type = match image.content {
-                                SwashContent::Color => ContentType::Color,
-                                SwashContent::Mask => ContentType::Mask,
-                                SwashContent::SubpixelMask => {
-                                    // Not implemented yet, but don't panic if this happens.
-                                    ContentType::Mask
-                                }
-                            };
-
-                            Some(GetGlyphImageResult {
-                                content_type,
-                                top: image.placement.top as i16,
-                                left: image.placement.left as i16,
-                                width: image.placement.width as u16,
-                                height: image.placement.height as u16,
-                                data: image.data,
-                            })
-                        },
+                        font_system,
+                        cache,
                         &mut metadata_to_depth,
-                        &mut rasterize_custom_glyph,
-                    )? {
-                        self.glyph_vertices.push(glyph_to_render);
+                    )?;
+                    if let Some((mut glyph_to_render, glyph_ref)) = glyph_to_render {
+                        apply_color_glyph_style(&mut glyph_to_render, text_area.color_glyph_style);
+                        apply_animation(&mut glyph_to_render, self.glyph_animation(glyph.metadata));
+                        apply_palette_index(
+                            &mut glyph_to_render,
+                            self.palette_index(glyph.metadata),
+                        );
+                        self.push_glyph_excluding(
+                            glyph_to_render,
+                            glyph_ref,
+                            text_area_index,
+                            GlyphSource::Text {
+                                line: run.line_i,
+                                cluster: glyph.cluster,
+                            },
+                            self.pick_id(glyph.metadata),
+                            &exclusions,
+                        );
                     }
                 }
             }
-        }
 
-        let will_render = !self.glyph_vertices.is_empty();
-        if !will_render {
-            return Ok(());
+            for (glyph_to_render, glyph_ref, glyph_index, pick_id) in glyphs_above_text {
+                self.push_glyph_excluding(
+                    glyph_to_render,
+                    glyph_ref,
+                    text_area_index,
+                    GlyphSource::Custom { index: glyph_index },
+                    pick_id,
+                    &exclusions,
+                );
+            }
+
+            // The parts of a glyph split by exclusions are pushed past the limit check
+            if self.glyph_limit_exceeded {
+                return Err(self.glyph_limit_error(text_area_index));
+            }
+            self.finish_text_area(text_area_index, glyphs_before, &mut culled_text_areas);
         }
 
-        let vertices = self.glyph_vertices.as_slice();
-        let vertices_raw = unsafe {
-            slice::from_raw_parts(
-                vertices as *const _ as *const u8,
-                std::mem::size_of_val(vertices),
-            )
-        };
+        self.last_prepare = Some(PrepareSignature {
+            resolution,
+            atlas_evictions: atlas.eviction_count(),
+            text_areas: text_area_signatures,
+        });
+        mem::swap(&mut self.baked_lines, &mut self.next_baked_lines);
+        self.next_baked_lines.clear();
 
-        if self.vertex_buffer_size >= vertices_raw.len() as u64 {
-            queue.write_buffer(&self.vertex_buffer, 0, vertices_raw);
-        } else {
-            self.vertex_buffer.destroy();
+        atlas.flush_uploads(device, queue);
 
-            let (buffer, buffer_size) = create_oversized_buffer(
-                device,
-                Some("glyphon vertices"),
-                vertices_raw,
-                BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        #[cfg(feature = "tracing")]
+        {
+            let lookups = atlas.stats();
+            tracing::debug!(
+                glyphs = self.glyph_vertices.len(),
+                cache_hits = lookups.mask.cache_hits + lookups.color.cache_hits
+                    - lookups_before.mask.cache_hits
+                    - lookups_before.color.cache_hits,
+                cache_misses = lookups.mask.cache_misses + lookups.color.cache_misses
+                    - lookups_before.mask.cache_misses
+                    - lookups_before.color.cache_misses,
+                "prepared text"
             );
-
-            self.vertex_buffer = buffer;
-            self.vertex_buffer_size = buffer_size;
         }
 
-        Ok(())
+        self.upload_vertices(device, queue)?;
+
+        Ok(PrepareOutput { culled_text_areas })
     }
 
-    /// Renders all layouts that were previously provided to `prepare`.
-    pub fn render(
-        &self,
-        atlas: &TextAtlas,
+    /// Prepares all of the provided text areas for rendering, recording all copies to the atlas
+    /// textures and the vertex buffer into `encoder` instead of submitting them to `queue`.
+    ///
+    /// This allows ordering the uploads of glyphon with other GPU work recorded into the same
+    /// encoder. The data is copied from staging buffers owned by the atlas, which have to be made
+    /// available again with [`TextAtlas::recall_staging_buffers`] after `encoder` was submitted.
+    /// The [`crate::UploadStrategy`] of the atlas isn't used.
+    ///
+    /// The encoder must be submitted before rendering, and before the atlas is prepared with
+    /// `queue` again, so that the copies are applied in order.
+    ///
+    /// Renderers that use the fallback (see [`TextRenderer::uses_fallback`]) still write to
+    /// `queue`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare_with_encoder<'a>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
         viewport: &Viewport,
-        pass: &mut RenderPass<'_>,
-    ) -> Result<(), RenderError> {
-        if self.glyph_vertices.is_empty() {
-            return Ok(());
-        }
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<PrepareOutput, PrepareError> {
+        atlas.defer_copies();
+        self.defer_vertex_writes = true;
 
-        pass.set_pipeline(&self.pipeline);
-        pass.set_bind_group(0, &atlas.bind_group, &[]);
-        pass.set_bind_group(1, &viewport.bind_group, &[]);
-        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        pass.draw(0..4, 0..self.glyph_vertices.len() as u32);
+        let result = self.prepare_with_depth_and_custom(
+            device,
+            queue,
+            font_system,
+            atlas,
+            viewport,
+            text_areas,
+            cache,
+            metadata_to_depth,
+            rasterize_custom_glyph,
+        );
 
-        Ok(())
+        self.defer_vertex_writes = false;
+        // Glyphs cached before an error are recorded as well, since the atlas already refers to
+        // them
+        atlas.record_deferred_copies(device, queue, encoder);
+        if mem::take(&mut self.pending_vertex_write) && result.is_ok() {
+            let vertices_raw = vertex_bytes(&self.glyph_vertices);
+            let size = vertices_raw.len() as u64;
+            let (staging_buffer, offset) =
+                atlas.staging.write(device, &self.cache, size, |staging| {
+                    staging.copy_from_slice(vertices_raw)
+                });
+            encoder.copy_buffer_to_buffer(&staging_buffer, offset, &self.vertex_buffer, 0, size);
+        }
+        atlas.staging.finish();
+
+        result
     }
-}
 
-#[repr(u16)]
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-enum TextColorConversion {
-    None = 0,
-    ConvertToLinear = 1,
-}
+    /// Prepares glyphs that were shaped and positioned by the application for rendering, instead
+    /// of the text areas of `prepare`.
+    ///
+    /// This makes it possible to use the atlas and renderer with a different shaper than
+    /// cosmic-text. `rasterize_glyph` is called with the key of every glyph whose image isn't
+    /// cached in the atlas yet, and glyphs it returns `None` for are skipped.
+    ///
+    /// All glyphs belong to a single text area with the index 0 in the [`PrepareOutput`] and
+    /// [`TextRenderer::area_ranges`].
+    ///
+    /// Renderers that use the fallback (see [`TextRenderer::uses_fallback`]) don't support
+    /// positioned glyphs and return [`PrepareError::FallbackUnsupported`].
+    pub fn prepare_positioned(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        glyphs: impl IntoIterator<Item = PositionedGlyph>,
+        mut rasterize_glyph: impl FnMut(u64) -> Option<GlyphImage>,
+    ) -> Result<PrepareOutput, PrepareError> {
+        trace_span!("prepare_positioned");
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(crate) enum GlyphonCacheKey {
-    Text(cosmic_text::CacheKey),
-    Custom(CustomGlyphCacheKey),
-}
+        let mut atlas = UploadGuard::new(atlas, device, queue);
+        let atlas = &mut *atlas;
+        self.clear_prepared();
+        atlas.partition = self.atlas_partition;
 
-fn next_copy_buffer_size(size: u64) -> u64 {
-    let align_mask = COPY_BUFFER_ALIGNMENT - 1;
-    ((size.next_power_of_two() + align_mask) & !align_mask).max(COPY_BUFFER_ALIGNMENT)
-}
+        if let Pipeline::Fallback(fallback) = &mut self.pipeline {
+            fallback.clear();
+            return Err(PrepareError::FallbackUnsupported);
+        }
 
-fn create_oversized_buffer(
-    device: &Device,
-    label: Option<&str>,
-    contents: &[u8],
-    usage: BufferUsages,
-) -> (Buffer, u64) {
-    let size = next_copy_buffer_size(contents.len() as u64);
-    let buffer = device.create_buffer(&BufferDescriptor {
-        label,
-        size,
-        usage,
-        mapped_at_creation: true,
+        let resolution = viewport.resolution();
+        let mut culled_text_areas = Vec::new();
+
+        for (glyph_index, glyph) in glyphs.into_iter().enumerate() {
+            self.check_glyph_limit(0)?;
+            let Some((mut glyph_to_render, glyph_ref)) = prepare_glyph(
+                GlyphPlacement {
+                    x: glyph.x,
+                    y: glyph.y,
+                    line_y: 0.0,
+                    color: glyph.color,
+                    metadata: 0,
+                    cache_key: GlyphonCacheKey::Positioned(glyph.key),
+                    glyph_scale: GlyphScale::None,
+                    scale_factor: 1.0,
+                    bounds: TextBounds {
+                        left: glyph.bounds.left.max(0),
+                        top: glyph.bounds.top.max(0),
+                        right: glyph.bounds.right.min(resolution.width as i32),
+                        bottom: glyph.bounds.bottom.min(resolution.height as i32),
+                    },
+                },
+                atlas,
+                device,
+                queue,
+                || -> Option<GlyphImage> {
+                    let image = rasterize_glyph(glyph.key)?;
+                    image.validate(glyph.key);
+                    Some(image)
+                },
+                |_| glyph.depth,
+            )?
+            else {
+                continue;
+            };
+
+            apply_animation(&mut glyph_to_render, glyph.animation);
+            self.push_glyph(
+                glyph_to_render,
+                glyph_ref,
+                0,
+                GlyphSource::Positioned { index: glyph_index },
+                0,
+            );
+        }
+        self.finish_text_area(0, 0, &mut culled_text_areas);
+
+        atlas.flush_uploads(device, queue);
+        self.upload_vertices(device, queue)?;
+
+        Ok(PrepareOutput { culled_text_areas })
+    }
+
+    /// Prepares runs of glyphs that were shaped and positioned by the application for rendering,
+    /// instead of the text areas of `prepare`.
+    ///
+    /// This makes it possible to use a custom layout engine without creating a [`Buffer`] for
+    /// its text. The glyphs are rasterized from the fonts in `font_system`, cached in the atlas
+    /// and follow the color glyph policy of the renderer, like the glyphs of text areas.
+    ///
+    /// [`Buffer`]: cosmic_text::Buffer
+    ///
+    /// Every run is a text area in the [`PrepareOutput`] and [`TextRenderer::area_ranges`], with
+    /// its index in `runs`.
+    ///
+    /// Renderers that use the fallback (see [`TextRenderer::uses_fallback`]) don't support
+    /// runs and return [`PrepareError::FallbackUnsupported`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare_runs(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        runs: &[PositionedRun],
+        cache: &mut SwashCache,
+    ) -> Result<PrepareOutput, PrepareError> {
+        trace_span!("prepare_runs");
+
+        let mut atlas = UploadGuard::new(atlas, device, queue);
+        let atlas = &mut *atlas;
+        self.clear_prepared();
+        atlas.partition = self.atlas_partition;
+
+        if let Pipeline::Fallback(fallback) = &mut self.pipeline {
+            fallback.clear();
+            return Err(PrepareError::FallbackUnsupported);
+        }
+
+        let resolution = viewport.resolution();
+        let mut culled_text_areas = Vec::new();
+
+        for (run_index, run) in runs.iter().enumerate() {
+            let glyphs_before = self.glyph_vertices.len();
+            for (glyph_index, glyph) in run.glyphs.iter().enumerate() {
+                let (physical_key, x, y) = CacheKey::new(
+                    run.font_id,
+                    glyph.id,
+                    run.font_size,
+                    (
+                        clamp_coordinate(run.x + glyph.x),
+                        clamp_coordinate(run.y + glyph.y),
+                    ),
+                    CacheKeyFlags::empty(),
+                );
+
+                self.check_glyph_limit(run_index)?;
+                let Some((mut glyph_to_render, glyph_ref)) = self.prepare_text_glyph(
+                    physical_key,
+                    x,
+                    y,
+                    0.0,
+                    run.color.into(),
+                    0,
+                    GlyphScale::None,
+                    atlas,
+                    device,
+                    queue,
+                    1.0,
+                    run.bounds.left.max(0),
+                    run.bounds.top.max(0),
+                    run.bounds.right.min(resolution.width as i32),
+                    run.bounds.bottom.min(resolution.height as i32),
+                    font_system,
+                    cache,
+                    |_| run.depth,
+                )?
+                else {
+                    continue;
+                };
+
+                apply_animation(&mut glyph_to_render, run.animation);
+                self.push_glyph(
+                    glyph_to_render,
+                    glyph_ref,
+                    run_index,
+                    GlyphSource::Run {
+                        run: run_index,
+                        glyph: glyph_index,
+                    },
+                    0,
+                );
+            }
+            self.finish_text_area(run_index, glyphs_before, &mut culled_text_areas);
+        }
+
+        atlas.flush_uploads(device, queue);
+        self.upload_vertices(device, queue)?;
+
+        Ok(PrepareOutput { culled_text_areas })
+    }
+
+    /// Clears everything prepared by the last call to `prepare`.
+    fn clear_prepared(&mut self) {
+        self.glyph_limit_exceeded = false;
+        self.glyph_vertices.clear();
+        self.glyph_refs.clear();
+        self.pick_ids.clear();
+        self.area_ranges.clear();
+        self.prepared_glyphs.set(self.id, self.atlas_partition, []);
+        self.last_prepare = None;
+        self.resolution_viewports.begin_frame();
+        if let Some(glyph_sources) = &mut self.glyph_sources {
+            glyph_sources.clear();
+        }
+        if let Some(run_infos) = &mut self.run_infos {
+            run_infos.clear();
+        }
+        if let Some(font_fallbacks) = &mut self.font_fallbacks {
+            font_fallbacks.clear();
+        }
+        if let Some(draw_ranges) = &mut self.draw_ranges {
+            draw_ranges.clear();
+        }
+    }
+
+    fn upload_vertices(&mut self, device: &Device, queue: &Queue) -> Result<(), PrepareError> {
+        trace_span!("upload_vertices", count = self.glyph_vertices.len());
+
+        self.prepared_glyphs.set(
+            self.id,
+            self.atlas_partition,
+            self.glyph_refs.iter().map(|glyph_ref| glyph_ref.key),
+        );
+
+        self.reset_color_overrides(device, queue)?;
+        self.write_pick_ids(device, queue)?;
+
+        let will_render = !self.glyph_vertices.is_empty();
+        if !will_render {
+            return Ok(());
+        }
+
+        let vertices_raw = vertex_bytes(&self.glyph_vertices);
+
+        if self.vertex_buffer_size >= vertices_raw.len() as u64 {
+            if self.defer_vertex_writes {
+                self.pending_vertex_write = true;
+            } else {
+                queue.write_buffer(&self.vertex_buffer, 0, vertices_raw);
+            }
+        } else {
+            let buffer_size = next_copy_buffer_size(vertices_raw.len() as u64);
+            if !self
+                .memory
+                .try_reserve(buffer_size - self.vertex_buffer_size)
+            {
+                return Err(PrepareError::MemoryBudgetExceeded);
+            }
+
+            self.vertex_buffer.destroy();
+
+            let buffer = create_vertex_buffer(
+                device,
+                &self.buffer_label("vertices", buffer_size),
+                buffer_size,
+                vertices_raw,
+                self.vertex_buffer_usage(),
+            );
+
+            self.vertex_buffer = buffer;
+            self.vertex_buffer_size = buffer_size;
+            self.rebind_vertex_pulling(device);
+        }
+
+        Ok(())
+    }
+
+    /// Shrinks the vertex buffer of this renderer to the size needed by the glyphs of the last
+    /// call to `prepare`, and releases unused CPU memory.
+    ///
+    /// The vertex buffer only grows while preparing, so a single frame with a lot of text keeps it
+    /// large. Long-running applications can call this occasionally, e.g. after such a frame, to
+    /// reclaim that memory. This must not be called while commands recorded by
+    /// [`TextRenderer::render`] are still waiting to be submitted.
+    pub fn shrink_to_fit(&mut self, device: &Device) {
+        let vertices_raw = vertex_bytes(&self.glyph_vertices);
+
+        let buffer_size =
+            next_copy_buffer_size((vertices_raw.len() as u64).max(MIN_VERTEX_BUFFER_SIZE));
+        if buffer_size < self.vertex_buffer_size {
+            let buffer = create_vertex_buffer(
+                device,
+                &self.buffer_label("vertices", buffer_size),
+                buffer_size,
+                vertices_raw,
+                self.vertex_buffer_usage(),
+            );
+
+            self.vertex_buffer.destroy();
+            self.memory.release(self.vertex_buffer_size - buffer_size);
+            self.vertex_buffer = buffer;
+            self.vertex_buffer_size = buffer_size;
+            self.rebind_vertex_pulling(device);
+        }
+
+        self.glyph_vertices.shrink_to_fit();
+        self.glyph_refs.shrink_to_fit();
+        if let Some(glyph_sources) = &mut self.glyph_sources {
+            glyph_sources.shrink_to_fit();
+        }
+        if let Some(run_infos) = &mut self.run_infos {
+            run_infos.shrink_to_fit();
+        }
+    }
+
+    /// Returns the number of bytes of GPU memory used by the vertex buffer of this renderer.
+    pub fn gpu_memory_usage(&self) -> u64 {
+        let glyph_buffers = [&self.color_overrides, &self.pick_id_buffer]
+            .into_iter()
+            .flatten()
+            .map(|buffer| buffer.size)
+            .sum::<u64>();
+
+        match &self.pipeline {
+            Pipeline::Glyphs(_) => self.vertex_buffer_size + glyph_buffers,
+            Pipeline::Fallback(fallback) => self.vertex_buffer_size + fallback.gpu_memory_usage(),
+        }
+    }
+
+    /// Switches to the pipeline with `features`, keeping the current pipeline if it can't be
+    /// created.
+    fn set_features(
+        &mut self,
+        atlas: &TextAtlas,
+        device: &Device,
+        features: PipelineFeatures,
+    ) -> Result<(), PipelineError> {
+        if features == self.features {
+            return Ok(());
+        }
+        let Pipeline::Glyphs(pipeline) = &mut self.pipeline else {
+            return Err(PipelineError::UsesFallback);
+        };
+        if !Cache::supports(device, features) {
+            return Err(PipelineError::Unsupported);
+        }
+
+        *pipeline = atlas
+            .get_or_create_pipeline(
+                device,
+                self.multisample,
+                self.depth_stencil.clone(),
+                self.blend_mode,
+                features,
+            )
+            .ok_or(PipelineError::CreationFailed)?;
+        self.features = features;
+
+        Ok(())
+    }
+
+    /// Enables or disables reading glyphs from a storage buffer in the vertex shader, instead of
+    /// from instance-stepped vertex attributes.
+    ///
+    /// This can be faster on drivers that handle large numbers of instances poorly, e.g. when
+    /// rendering hundreds of thousands of glyphs. Vertex pulling is disabled by default and can
+    /// be combined with the other features of the pipeline.
+    ///
+    /// Returns an error if the device doesn't support storage buffers in vertex shaders or if
+    /// the renderer [uses the fallback](TextRenderer::uses_fallback), in which case the renderer
+    /// keeps its current pipeline.
+    pub fn set_vertex_pulling(
+        &mut self,
+        atlas: &TextAtlas,
+        device: &Device,
+        enabled: bool,
+    ) -> Result<(), PipelineError> {
+        let features = PipelineFeatures {
+            vertex_pulling: enabled,
+            ..self.features
+        };
+        self.set_features(atlas, device, features)?;
+
+        if !enabled {
+            self.vertex_pulling = None;
+        } else if self.vertex_pulling.is_none() {
+            // The vertex buffer needs to be usable as a storage buffer
+            let vertex_buffer = create_vertex_buffer(
+                device,
+                &self.buffer_label("vertices", self.vertex_buffer_size),
+                self.vertex_buffer_size,
+                vertex_bytes(&self.glyph_vertices),
+                self.vertex_buffer_usage(),
+            );
+            self.vertex_buffer.destroy();
+            self.vertex_buffer = vertex_buffer;
+            self.rebind_vertex_pulling(device);
+        }
+
+        Ok(())
+    }
+
+    /// Enables or disables reading the params of the viewport (e.g. its resolution) from push
+    /// constants instead of binding its uniform buffer.
+    ///
+    /// Push constants are set with each draw, which makes rendering the same glyphs with many
+    /// viewports, e.g. in split views, cheaper on native backends. They are disabled by default
+    /// and can be combined with the other features of the pipeline.
+    ///
+    /// Returns an error if the device wasn't created with [`wgpu::Features::PUSH_CONSTANTS`] and
+    /// a [`max_push_constant_size`](wgpu::Limits::max_push_constant_size) of at least 160 bytes,
+    /// or if the renderer [uses the fallback](TextRenderer::uses_fallback), in which case the
+    /// renderer keeps its current pipeline.
+    pub fn set_push_constants(
+        &mut self,
+        atlas: &TextAtlas,
+        device: &Device,
+        enabled: bool,
+    ) -> Result<(), PipelineError> {
+        let features = PipelineFeatures {
+            push_constants: enabled,
+            ..self.features
+        };
+        self.set_features(atlas, device, features)
+    }
+
+    fn vertex_buffer_usage(&self) -> BufferUsages {
+        match self.features.vertex_pulling {
+            true => BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            false => BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        }
+    }
+
+    /// Binds the current vertex buffer as the storage buffer read when using vertex pulling.
+    fn rebind_vertex_pulling(&mut self, device: &Device) {
+        if self.features.vertex_pulling {
+            let (bind_group, bound_glyphs) = bind_vertex_pulling(
+                &self.cache,
+                device,
+                &self.vertex_buffer,
+                self.vertex_buffer_size,
+            );
+            self.vertex_pulling = Some(VertexPulling {
+                bind_group,
+                bound_glyphs,
+            });
+        }
+    }
+
+    /// Enables or disables overriding the colors of prepared glyphs without preparing them again,
+    /// see [`TextRenderer::write_color_overrides`]. Color overrides are disabled by default and
+    /// can be combined with the other features of the pipeline.
+    ///
+    /// This is useful to animate the colors of text every frame, e.g. to color the lyrics of
+    /// karaoke character by character. The overrides are stored in a second vertex buffer with 8
+    /// bytes per glyph, so changing them only writes the overrides that changed.
+    ///
+    /// Returns an error if the renderer [uses the fallback](TextRenderer::uses_fallback), in
+    /// which case the renderer keeps its current pipeline.
+    pub fn set_color_overrides(
+        &mut self,
+        atlas: &TextAtlas,
+        device: &Device,
+        enabled: bool,
+    ) -> Result<(), PipelineError> {
+        let features = PipelineFeatures {
+            color_overrides: enabled,
+            ..self.features
+        };
+        self.set_features(atlas, device, features)?;
+
+        if !enabled {
+            if let Some(GlyphBuffer { buffer, size }) = self.color_overrides.take() {
+                buffer.destroy();
+                self.memory.release(size);
+            }
+        } else if self.color_overrides.is_none() {
+            let size = color_override_buffer_size(self.glyph_vertices.len());
+            self.memory.add(size);
+            self.color_overrides = Some(GlyphBuffer {
+                buffer: self.create_color_override_buffer(device, size),
+                size,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Sets a texture through which text is drawn, or removes it given `None`.
+    ///
+    /// The alpha of the text is multiplied by the alpha of the mask, which is stretched over the
+    /// viewport. This reveals text only where the mask is opaque, e.g. for scratch cards or text
+    /// that fades in along a gradient. The mask is sampled with linear filtering, so it can be
+    /// smaller than the viewport. It must have a filterable float format, e.g.
+    /// `TextureFormat::Rgba8Unorm`. Changing the contents of the mask doesn't require setting it
+    /// again, but a new texture view does. The mask can be combined with the other features of
+    /// the pipeline.
+    ///
+    /// Returns an error if the device doesn't support enough bind groups for the mask together
+    /// with [vertex pulling](TextRenderer::set_vertex_pulling), or if the renderer [uses the
+    /// fallback](TextRenderer::uses_fallback), in which case the renderer keeps its current
+    /// pipeline and mask.
+    pub fn set_mask(
+        &mut self,
+        atlas: &TextAtlas,
+        device: &Device,
+        mask: Option<&TextureView>,
+    ) -> Result<(), PipelineError> {
+        let features = PipelineFeatures {
+            mask: mask.is_some(),
+            ..self.features
+        };
+        self.set_features(atlas, device, features)?;
+
+        self.mask = mask.map(|mask| self.cache.create_mask_bind_group(device, mask));
+        Ok(())
+    }
+
+    /// Enables or disables drawing each glyph as two indexed triangles instead of a triangle
+    /// strip of four vertices. Indexed quads are disabled by default and can be combined with the
+    /// other features of the pipeline.
+    ///
+    /// Some drivers for tile-based GPUs are reported to handle indexed triangle lists of many
+    /// small instances more efficiently, so this is worth measuring on such devices. Elsewhere
+    /// the strips are at least as fast.
+    ///
+    /// Returns an error if the renderer [uses the fallback](TextRenderer::uses_fallback), in
+    /// which case the renderer keeps its current pipeline.
+    pub fn set_indexed_quads(
+        &mut self,
+        atlas: &TextAtlas,
+        device: &Device,
+        enabled: bool,
+    ) -> Result<(), PipelineError> {
+        let features = PipelineFeatures {
+            indexed_quads: enabled,
+            ..self.features
+        };
+        self.set_features(atlas, device, features)
+    }
+
+    /// Overrides the colors of the prepared glyphs starting at `first_glyph`, or restores the
+    /// prepared colors of the glyphs given `None`.
+    ///
+    /// Glyphs are indexed in the order they were prepared, which is the order of
+    /// [`TextRenderer::glyph_source_map`] when glyph sources are recorded. Color glyphs keep
+    /// their colors. The overrides apply until they are written again or the renderer is
+    /// prepared again, which restores the prepared colors of all glyphs.
+    ///
+    /// Overrides past the prepared glyphs are ignored, as are all overrides if color overrides
+    /// [aren't enabled](TextRenderer::set_color_overrides).
+    pub fn write_color_overrides(
+        &self,
+        queue: &Queue,
+        first_glyph: usize,
+        colors: &[Option<Color>],
+    ) {
+        let Some(GlyphBuffer { buffer, .. }) = &self.color_overrides else {
+            return;
+        };
+
+        let count = colors
+            .len()
+            .min(self.glyph_vertices.len().saturating_sub(first_glyph));
+        if count == 0 {
+            return;
+        }
+
+        let data: Vec<u8> = colors[..count]
+            .iter()
+            .flat_map(|color| match color {
+                Some(color) => [color.0, 1],
+                None => [0, 0],
+            })
+            .flat_map(u32::to_ne_bytes)
+            .collect();
+        queue.write_buffer(buffer, (first_glyph * COLOR_OVERRIDE_SIZE) as u64, &data);
+    }
+
+    /// Clears the color overrides of the prepared glyphs, growing the buffer of overrides if
+    /// needed.
+    fn reset_color_overrides(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<(), PrepareError> {
+        let Some(GlyphBuffer {
+            buffer,
+            size: buffer_size,
+        }) = &self.color_overrides
+        else {
+            return Ok(());
+        };
+
+        let size = (self.glyph_vertices.len() * COLOR_OVERRIDE_SIZE) as u64;
+        if *buffer_size >= size {
+            queue.write_buffer(buffer, 0, &vec![0; size as usize]);
+            return Ok(());
+        }
+
+        // New buffers are zeroed, which clears all overrides
+        let new_size = color_override_buffer_size(self.glyph_vertices.len());
+        if !self.memory.try_reserve(new_size - buffer_size) {
+            return Err(PrepareError::MemoryBudgetExceeded);
+        }
+        let new_buffer = self.create_color_override_buffer(device, new_size);
+        if let Some(GlyphBuffer { buffer, size }) = &mut self.color_overrides {
+            mem::replace(buffer, new_buffer).destroy();
+            *size = new_size;
+        }
+
+        Ok(())
+    }
+
+    fn create_color_override_buffer(&self, device: &Device, size: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(&self.buffer_label("color overrides", size)),
+            size,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Enables or disables writing a pick ID for each glyph to a second color attachment, so
+    /// that the text under the cursor can be found by reading back a single pixel, e.g. to click
+    /// words in a node editor. Pick IDs are disabled by default.
+    ///
+    /// The pick ID of a glyph is given by the mapping set with
+    /// [`TextRenderer::set_metadata_to_pick_id`], or is its metadata truncated to `u32` without
+    /// a mapping. IDs are written over the whole quad of each glyph without blending, so the ID
+    /// of the glyph drawn last wins where glyphs overlap. Glyphs given to
+    /// [`TextRenderer::prepare_positioned`] or [`TextRenderer::prepare_runs`] have an ID of 0,
+    /// so an attachment cleared to 0 tells glyphs without an ID apart from the background only
+    /// if no other glyph is given an ID of 0. The IDs are stored in a second vertex buffer with 4
+    /// bytes per glyph and are recorded from the next call to `prepare`.
+    ///
+    /// While pick IDs are used, the renderer must render into passes with a second color
+    /// attachment of format `TextureFormat::R32Uint` with the sample count of the first one.
+    ///
+    /// Pick IDs can be combined with the other features of the pipeline. Returns an error if
+    /// the renderer [uses the fallback](TextRenderer::uses_fallback), in which case the renderer
+    /// keeps its current pipeline.
+    pub fn set_pick_ids(
+        &mut self,
+        atlas: &TextAtlas,
+        device: &Device,
+        enabled: bool,
+    ) -> Result<(), PipelineError> {
+        let features = PipelineFeatures {
+            pick_ids: enabled,
+            ..self.features
+        };
+        self.set_features(atlas, device, features)?;
+
+        if !enabled {
+            if let Some(GlyphBuffer { buffer, size }) = self.pick_id_buffer.take() {
+                buffer.destroy();
+                self.memory.release(size);
+            }
+            self.pick_ids = Vec::new();
+        } else if self.pick_id_buffer.is_none() {
+            let size = pick_id_buffer_size(self.glyph_vertices.len());
+            self.memory.add(size);
+            self.pick_id_buffer = Some(GlyphBuffer {
+                buffer: self.create_pick_id_buffer(device, size),
+                size,
+            });
+            self.last_prepare = None;
+        }
+
+        Ok(())
+    }
+
+    /// Gives glyphs pick IDs according to their metadata, see [`TextRenderer::set_pick_ids`].
+    ///
+    /// `metadata_to_pick_id` is called with the metadata of every glyph, custom glyph and
+    /// decoration while preparing, e.g. to map the metadata of a word to the ID of the node it
+    /// belongs to.
+    pub fn set_metadata_to_pick_id(
+        &mut self,
+        metadata_to_pick_id: impl Fn(usize) -> u32 + Send + Sync + 'static,
+    ) {
+        self.last_prepare = None;
+        self.metadata_to_pick_id = Some(Box::new(metadata_to_pick_id));
+    }
+
+    /// Removes the mapping set with [`TextRenderer::set_metadata_to_pick_id`].
+    pub fn clear_metadata_to_pick_id(&mut self) {
+        self.last_prepare = None;
+        self.metadata_to_pick_id = None;
+    }
+
+    fn pick_id(&self, metadata: usize) -> u32 {
+        match &self.metadata_to_pick_id {
+            Some(metadata_to_pick_id) => metadata_to_pick_id(metadata),
+            None => metadata as u32,
+        }
+    }
+
+    /// Writes the pick IDs of the prepared glyphs, growing the buffer of pick IDs if needed.
+    fn write_pick_ids(&mut self, device: &Device, queue: &Queue) -> Result<(), PrepareError> {
+        let Some(GlyphBuffer {
+            size: buffer_size, ..
+        }) = &self.pick_id_buffer
+        else {
+            return Ok(());
+        };
+
+        // Glyphs restored from snapshots taken without pick IDs have an ID of 0
+        self.pick_ids.resize(self.glyph_vertices.len(), 0);
+        if self.pick_ids.is_empty() {
+            return Ok(());
+        }
+
+        let size = mem::size_of_val(self.pick_ids.as_slice()) as u64;
+        if *buffer_size < size {
+            let new_size = pick_id_buffer_size(self.pick_ids.len());
+            if !self.memory.try_reserve(new_size - buffer_size) {
+                return Err(PrepareError::MemoryBudgetExceeded);
+            }
+            let new_buffer = self.create_pick_id_buffer(device, new_size);
+            if let Some(GlyphBuffer { buffer, size }) = &mut self.pick_id_buffer {
+                mem::replace(buffer, new_buffer).destroy();
+                *size = new_size;
+            }
+        }
+
+        if let Some(GlyphBuffer { buffer, .. }) = &self.pick_id_buffer {
+            let data: Vec<u8> = self
+                .pick_ids
+                .iter()
+                .copied()
+                .flat_map(u32::to_ne_bytes)
+                .collect();
+            queue.write_buffer(buffer, 0, &data);
+        }
+
+        Ok(())
+    }
+
+    fn create_pick_id_buffer(&self, device: &Device, size: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(&self.buffer_label("pick ids", size)),
+            size,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Sets the label of this renderer, or removes it if `None`. Renderers have no label by
+    /// default.
+    ///
+    /// The label is added to the names of the buffers and textures of the renderer, and rendering
+    /// is wrapped in a debug group named `glyphon: <label>`, so that the draws of each renderer
+    /// can be told apart in GPU captures of passes mixing many renderers. The vertex buffer is
+    /// recreated to rename it.
+    pub fn set_label(&mut self, device: &Device, label: Option<&str>) {
+        self.label = label.map(str::to_owned);
+        if let Pipeline::Fallback(fallback) = &mut self.pipeline {
+            fallback.label = self.label.clone();
+        }
+
+        let vertex_buffer = create_vertex_buffer(
+            device,
+            &self.buffer_label("vertices", self.vertex_buffer_size),
+            self.vertex_buffer_size,
+            vertex_bytes(&self.glyph_vertices),
+            self.vertex_buffer_usage(),
+        );
+        self.vertex_buffer.destroy();
+        self.vertex_buffer = vertex_buffer;
+        self.rebind_vertex_pulling(device);
+    }
+
+    /// Returns the label set with [`TextRenderer::set_label`].
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    fn buffer_label(&self, name: &'static str, size: u64) -> String {
+        let label = self.cache.generation_label(name, size);
+        match &self.label {
+            Some(name) => format!("{label} [{name}]"),
+            None => label,
+        }
+    }
+
+    fn push_debug_group(&self, pass: &mut RenderPass<'_>) {
+        if let Some(label) = &self.label {
+            pass.push_debug_group(&format!("glyphon: {label}"));
+        }
+    }
+
+    fn pop_debug_group(&self, pass: &mut RenderPass<'_>) {
+        if self.label.is_some() {
+            pass.pop_debug_group();
+        }
+    }
+
+    /// Returns `true` if the renderer rasterizes text on the CPU because the device failed to
+    /// create the glyph pipeline.
+    ///
+    /// The fallback renders each call to `prepare` as one texture, which is much slower than
+    /// rendering from the atlas. It ignores depth, color spaces, animations, character filters and
+    /// color glyph policies, and doesn't record glyph sources, runs, font fallbacks or snapshots.
+    pub fn uses_fallback(&self) -> bool {
+        matches!(self.pipeline, Pipeline::Fallback(_))
+    }
+
+    /// Returns `true` if preparing the given text areas could produce a different result than the
+    /// last call to `prepare`, i.e. if any buffer, position, scale, bounds, color or custom glyph
+    /// changed, if the viewport was resized, or if the atlas evicted glyphs.
+    ///
+    /// When this returns `false`, `prepare` can be skipped and the previously prepared text
+    /// rendered again. Note that the `metadata_to_depth` and `rasterize_custom_glyph` callbacks
+    /// are not tracked. The prepared glyphs stay in the atlas when it is trimmed, see
+    /// [`TextAtlas::trim`].
+    pub fn needs_prepare<'a>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+    ) -> bool {
+        let Some(last_prepare) = &self.last_prepare else {
+            return true;
+        };
+
+        if last_prepare.resolution != viewport.resolution()
+            || last_prepare.atlas_evictions != atlas.eviction_count()
+        {
+            return true;
+        }
+
+        let mut text_areas = text_areas.into_iter();
+        for signature in &last_prepare.text_areas {
+            match text_areas.next() {
+                Some(text_area) if TextAreaSignature::new(&text_area) == *signature => {}
+                _ => return true,
+            }
+        }
+
+        text_areas.next().is_some()
+    }
+
+    /// Checks that the buffers of the text areas given to the last call to `prepare` weren't
+    /// changed since, to catch buffers that are edited between `prepare` and `render`.
+    ///
+    /// The renderer draws the glyphs of buffers as they were prepared, so text that is edited
+    /// after `prepare` is drawn stale until the renderer is prepared again. `buffers` are the
+    /// buffers of the prepared text areas in the same order, which are compared with the
+    /// [`BufferRevision`]s captured by `prepare`. Computing a revision walks the visible layout
+    /// runs of a buffer.
+    ///
+    /// This only checks the buffers in debug builds, so it can be called before every call to
+    /// `render`. Nothing is checked after the settings of the renderer changed until it is
+    /// prepared again.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if a buffer changed since it was prepared.
+    pub fn debug_assert_buffers_unchanged<'b>(
+        &self,
+        buffers: impl IntoIterator<Item = &'b cosmic_text::Buffer>,
+    ) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let Some(last_prepare) = &self.last_prepare else {
+            return;
+        };
+
+        for (index, (signature, buffer)) in last_prepare.text_areas.iter().zip(buffers).enumerate()
+        {
+            assert!(
+                signature.revision == BufferRevision::new(buffer),
+                "The buffer of text area {index} changed after it was prepared, so its text is \
+                 rendered stale. Prepare the renderer again after editing buffers."
+            );
+        }
+    }
+
+    /// Restricts which characters are rendered.
+    ///
+    /// Glyphs of clusters containing a character for which `filter` returns `false` are skipped
+    /// and never rasterized. This can be used to forbid classes of glyphs (e.g. emoji) to keep
+    /// atlas usage predictable.
+    pub fn set_char_filter(&mut self, filter: impl Fn(char) -> bool + Send + Sync + 'static) {
+        self.last_prepare = None;
+        self.char_filter = Some(Box::new(filter));
+    }
+
+    /// Removes the filter set with [`TextRenderer::set_char_filter`].
+    pub fn clear_char_filter(&mut self) {
+        self.last_prepare = None;
+        self.char_filter = None;
+    }
+
+    /// Animates glyphs according to their metadata.
+    ///
+    /// `metadata_to_animation` is called with the metadata of every glyph, custom glyph and
+    /// decoration while preparing. The animation then runs in the shader, so that e.g. a blinking
+    /// caret or a marching selection outline only needs [`crate::Viewport::set_time`] every
+    /// frame instead of preparing the text again.
+    pub fn set_metadata_to_animation(
+        &mut self,
+        metadata_to_animation: impl Fn(usize) -> GlyphAnimation + Send + Sync + 'static,
+    ) {
+        self.last_prepare = None;
+        self.metadata_to_animation = Some(Box::new(metadata_to_animation));
+    }
+
+    /// Removes the mapping set with [`TextRenderer::set_metadata_to_animation`].
+    pub fn clear_metadata_to_animation(&mut self) {
+        self.last_prepare = None;
+        self.metadata_to_animation = None;
+    }
+
+    fn glyph_animation(&self, metadata: usize) -> GlyphAnimation {
+        self.metadata_to_animation
+            .as_ref()
+            .map_or(GlyphAnimation::None, |metadata_to_animation| {
+                metadata_to_animation(metadata)
+            })
+    }
+
+    /// Draws text as a minimap with the given style, or as glyphs if `None`, which is the
+    /// default.
+    ///
+    /// In minimap mode, the words of the text areas are drawn as solid blocks in the color of
+    /// their glyphs, like the minimaps of code editors. Tokens of different colors (e.g. from
+    /// [`crate::SpanHighlighter`]) are separate blocks. Text areas are typically prepared with a
+    /// small [`TextArea::scale`], e.g. so that lines are two or three pixels high. Since no
+    /// glyphs are rasterized, this is much cheaper than drawing scaled down text. Blocks are
+    /// drawn like [`crate::Decoration`]s, above the decorations of the text area, and their
+    /// glyph sources are [`GlyphSource::Text`] covering all clusters of the block.
+    pub fn set_minimap(&mut self, style: Option<MinimapStyle>) {
+        self.last_prepare = None;
+        self.minimap = style;
+    }
+
+    /// Returns the style set with [`TextRenderer::set_minimap`].
+    pub fn minimap(&self) -> Option<MinimapStyle> {
+        self.minimap
+    }
+
+    /// Limits the number of glyphs a single call to `prepare` may produce, or removes the limit
+    /// if `None`. There is no limit by default.
+    ///
+    /// Preparing fails with [`PrepareError::GlyphLimitExceeded`] and leaves the renderer empty
+    /// as soon as another glyph would be prepared once the limit is reached, instead of growing
+    /// the vertex buffer without bounds. This protects
+    /// applications rendering text from untrusted sources (e.g. plugins) from running out of
+    /// memory when they are passed a runaway number of text areas. Every part of a glyph split
+    /// by exclusions counts as a glyph. Renderers that [use the
+    /// fallback](TextRenderer::uses_fallback) draw into a texture covering at most the viewport
+    /// and aren't limited.
+    pub fn set_max_glyphs_per_prepare(&mut self, max_glyphs: Option<usize>) {
+        self.last_prepare = None;
+        self.max_glyphs = max_glyphs;
+    }
+
+    /// Returns the limit set with [`TextRenderer::set_max_glyphs_per_prepare`].
+    pub fn max_glyphs_per_prepare(&self) -> Option<usize> {
+        self.max_glyphs
+    }
+
+    /// Aborts preparing with [`PrepareError::GlyphLimitExceeded`] after `text_areas` complete
+    /// text areas if the glyph limit was reached.
+    ///
+    /// This is checked before preparing each glyph, so that glyphs past the limit are neither
+    /// rasterized nor uploaded to the atlas.
+    fn check_glyph_limit(&mut self, text_areas: usize) -> Result<(), PrepareError> {
+        let limit_reached = self
+            .max_glyphs
+            .is_some_and(|max_glyphs| self.glyph_vertices.len() >= max_glyphs);
+        if limit_reached || self.glyph_limit_exceeded {
+            return Err(self.glyph_limit_error(text_areas));
+        }
+
+        Ok(())
+    }
+
+    /// Clears the glyphs prepared so far and returns the error of exceeding the glyph limit after
+    /// `text_areas` complete text areas.
+    fn glyph_limit_error(&mut self, text_areas: usize) -> PrepareError {
+        self.clear_prepared();
+        PrepareError::GlyphLimitExceeded {
+            limit: self.max_glyphs.unwrap_or_default(),
+            text_areas,
+        }
+    }
+
+    /// Colors glyphs from the palette of the viewport according to their metadata.
+    ///
+    /// `metadata_to_palette_index` is called with the metadata of every glyph, custom glyph and
+    /// decoration while preparing. Glyphs with an index are drawn in the color at that index of
+    /// the palette set with [`crate::Viewport::set_palette`], with its alpha multiplied by the
+    /// alpha of their own color. Changing the palette then recolors text without preparing it
+    /// again, e.g. when switching between a light and a dark theme.
+    ///
+    /// Glyphs without an index or with an index of at least [`crate::Viewport::PALETTE_SIZE`]
+    /// keep their own color, and color glyphs (e.g. color emoji) are never recolored. Renderers
+    /// that [use the fallback](TextRenderer::uses_fallback) ignore the palette.
+    pub fn set_metadata_to_palette_index(
+        &mut self,
+        metadata_to_palette_index: impl Fn(usize) -> Option<usize> + Send + Sync + 'static,
+    ) {
+        self.last_prepare = None;
+        self.metadata_to_palette_index = Some(Box::new(metadata_to_palette_index));
+    }
+
+    /// Rasterizes glyphs with options chosen according to their metadata, e.g. to embolden or
+    /// slant text synthetically or to disable hinting.
+    ///
+    /// `metadata_to_raster_options` is called with the metadata of every glyph rasterized from a
+    /// font while preparing. Glyphs rasterized with different options are cached separately in
+    /// the atlas. Renderers that [use the fallback](TextRenderer::uses_fallback) and
+    /// [`TextRenderer::prepare_dry_run`] ignore the options.
+    pub fn set_metadata_to_raster_options(
+        &mut self,
+        metadata_to_raster_options: impl Fn(usize) -> GlyphRasterOptions + Send + Sync + 'static,
+    ) {
+        self.last_prepare = None;
+        self.metadata_to_raster_options = Some(Box::new(metadata_to_raster_options));
+    }
+
+    /// Removes the mapping set with [`TextRenderer::set_metadata_to_raster_options`].
+    pub fn clear_metadata_to_raster_options(&mut self) {
+        self.last_prepare = None;
+        self.metadata_to_raster_options = None;
+    }
+
+    /// Removes the mapping set with [`TextRenderer::set_metadata_to_palette_index`].
+    pub fn clear_metadata_to_palette_index(&mut self) {
+        self.last_prepare = None;
+        self.metadata_to_palette_index = None;
+    }
+
+    fn palette_index(&self, metadata: usize) -> Option<usize> {
+        self.metadata_to_palette_index
+            .as_ref()
+            .and_then(|metadata_to_palette_index| metadata_to_palette_index(metadata))
+            .filter(|&index| index < Viewport::PALETTE_SIZE)
+    }
+
+    /// Sets how color glyphs (e.g. color emoji) are rendered. Defaults to
+    /// [`ColorGlyphPolicy::Allow`].
+    pub fn set_color_glyph_policy(&mut self, policy: ColorGlyphPolicy) {
+        self.last_prepare = None;
+        self.color_glyphs = policy;
+    }
+
+    /// Sets the image drawn in place of custom glyphs for which `rasterize_custom_glyph` returns
+    /// `None`, or `None` to skip such glyphs. Defaults to `None`.
+    pub fn set_custom_glyph_placeholder(&mut self, placeholder: Option<CustomGlyphPlaceholder>) {
+        self.last_prepare = None;
+        self.custom_glyph_placeholder = placeholder;
+    }
+
+    /// Returns the image drawn in place of custom glyphs that couldn't be rasterized.
+    pub fn custom_glyph_placeholder(&self) -> Option<CustomGlyphPlaceholder> {
+        self.custom_glyph_placeholder
+    }
+
+    /// Sets how the positions of text glyphs are snapped to physical pixels. Defaults to
+    /// [`PixelSnapping::default`].
+    pub fn set_pixel_snapping(&mut self, snapping: PixelSnapping) {
+        self.last_prepare = None;
+        self.pixel_snapping = snapping;
+    }
+
+    /// Returns how the positions of text glyphs are snapped to physical pixels.
+    pub fn pixel_snapping(&self) -> PixelSnapping {
+        self.pixel_snapping
+    }
+
+    /// Enables or disables recording the source of every glyph emitted by `prepare`.
+    ///
+    /// Recording is disabled by default. See [`TextRenderer::glyph_source_map`].
+    pub fn set_record_glyph_sources(&mut self, record: bool) {
+        self.last_prepare = None;
+        self.glyph_sources = record.then(Vec::new);
+    }
+
+    /// Returns the source of every glyph emitted by the last call to `prepare`, in the order the
+    /// glyphs are drawn.
+    ///
+    /// This can be used to map rendered glyphs back to text offsets, e.g. to draw highlights for
+    /// accessibility overlays. Returns an empty slice unless recording was enabled with
+    /// [`TextRenderer::set_record_glyph_sources`].
+    pub fn glyph_source_map(&self) -> &[GlyphSourceInfo] {
+        self.glyph_sources.as_deref().unwrap_or_default()
+    }
+
+    /// Enables or disables recording the direction and visual order of the runs rendered by
+    /// `prepare`.
+    ///
+    /// Recording is disabled by default. See [`TextRenderer::run_info`].
+    pub fn set_record_runs(&mut self, record: bool) {
+        self.last_prepare = None;
+        self.run_infos = record.then(Vec::new);
+    }
+
+    /// Returns the runs rendered by the last call to `prepare`.
+    ///
+    /// Runs of each layout line are split wherever the direction changes and are returned in
+    /// visual order, from left to right. This can be used to implement selection and cursor
+    /// movement in bidirectional text that matches the rendered output. Returns an empty slice
+    /// unless recording was enabled with [`TextRenderer::set_record_runs`].
+    pub fn run_info(&self) -> &[RunInfo] {
+        self.run_infos.as_deref().unwrap_or_default()
+    }
+
+    /// Enables or disables recording text that is rendered with a fallback font by `prepare`.
+    ///
+    /// Recording is disabled by default. See [`TextRenderer::font_fallbacks`].
+    pub fn set_record_font_fallbacks(&mut self, record: bool) {
+        self.last_prepare = None;
+        self.font_fallbacks = record.then(Vec::new);
+    }
+
+    /// Returns the text rendered with a fallback font by the last call to `prepare`.
+    ///
+    /// Text is reported when the font used for it isn't part of the font family requested by its
+    /// attributes, e.g. because the requested fonts don't support its script. Each combination of
+    /// text and font is reported once, for the first visible line it was found in, and whitespace
+    /// is ignored. This can be used to find fonts that should be bundled with an application.
+    /// Returns an empty slice unless recording was enabled with
+    /// [`TextRenderer::set_record_font_fallbacks`].
+    pub fn font_fallbacks(&self) -> &[FontFallback] {
+        self.font_fallbacks.as_deref().unwrap_or_default()
+    }
+
+    /// Enables or disables sorting the glyphs of each text area by the atlas texture they sample.
+    ///
+    /// Glyphs are drawn in the order they are prepared, which interleaves glyphs sampling the
+    /// mask and color textures of the atlas in text with color emoji. Sorting groups the glyphs of
+    /// each text area by texture, keeping their order within each texture, which makes texture
+    /// sampling more coherent. Since glyphs of different textures are no longer drawn in order,
+    /// color glyphs may be drawn above decorations or custom glyphs that they overlap. Sorting is
+    /// disabled by default. See [`TextRenderer::draw_ranges`].
+    pub fn set_sort_by_texture(&mut self, sort: bool) {
+        self.last_prepare = None;
+        self.draw_ranges = sort.then(Vec::new);
+    }
+
+    /// Returns `true` if the glyphs of each text area are sorted by the atlas texture they sample.
+    pub fn sorts_by_texture(&self) -> bool {
+        self.draw_ranges.is_some()
+    }
+
+    /// Returns the ranges of glyphs prepared by the last call to `prepare` that sample the same
+    /// atlas texture, in the order they are drawn.
+    ///
+    /// Returns an empty slice unless sorting was enabled with
+    /// [`TextRenderer::set_sort_by_texture`].
+    pub fn draw_ranges(&self) -> &[DrawRange] {
+        self.draw_ranges.as_deref().unwrap_or_default()
+    }
+
+    /// Sets the partition of the atlas that the glyphs of this renderer are cached in and
+    /// marked as in use by, see [`AtlasPartition`]. Renderers use [`AtlasPartition::DEFAULT`]
+    /// unless another partition is set.
+    ///
+    /// Glyphs prepared before the partition changed stay in use by the previous partition until
+    /// the next call to `prepare`.
+    pub fn set_atlas_partition(&mut self, partition: AtlasPartition) {
+        self.last_prepare = None;
+        self.atlas_partition = partition;
+    }
+
+    /// Returns the partition set with [`TextRenderer::set_atlas_partition`].
+    pub fn atlas_partition(&self) -> AtlasPartition {
+        self.atlas_partition
+    }
+
+    /// Enables or disables drawing the emoji of ZWJ sequences that no font supports side by side.
+    ///
+    /// An emoji ZWJ sequence (e.g. a family made of several people joined with zero width
+    /// joiners) is drawn as a single glyph by fonts that support it, and as a missing glyph by
+    /// fonts that don't. When enabled, `prepare` draws the individual emoji of missing sequences
+    /// instead, using the fonts of the font system that support them. They are centered in the
+    /// space of the missing glyph and shrunk to fit in it, since the layout of the buffer doesn't
+    /// change. Disabled by default.
+    ///
+    /// [`TextRenderer::prepare_dry_run`] doesn't decompose sequences.
+    pub fn set_decompose_missing_emoji(&mut self, decompose: bool) {
+        self.last_prepare = None;
+        self.emoji_decomposer = decompose.then(EmojiDecomposer::default);
+    }
+
+    /// Returns `true` if the emoji of ZWJ sequences that no font supports are drawn side by side.
+    pub fn decomposes_missing_emoji(&self) -> bool {
+        self.emoji_decomposer.is_some()
+    }
+
+    /// Bakes lines with at least `min_glyphs` glyphs into a few wide strips in the atlas, or
+    /// prepares every glyph as a quad of its own if `None`, which is the default.
+    ///
+    /// This is meant for very long static lines, e.g. tickers scrolled horizontally, which are
+    /// then drawn with a handful of quads instead of one per glyph. The strips of a line are
+    /// cached in the atlas until its glyphs change, so scrolling a line doesn't bake it again, but
+    /// lines are positioned at whole pixels. Lines with glyphs of different colors or metadata,
+    /// lines with color glyphs and lines too large for the strips are prepared glyph by glyph, as
+    /// are the lines of text areas with [`TextArea::integer_scaling`] and of renderers with a
+    /// [char filter](TextRenderer::set_char_filter). Renderers that
+    /// [use the fallback](TextRenderer::uses_fallback) don't bake lines.
+    pub fn set_line_strips(&mut self, min_glyphs: Option<usize>) {
+        self.last_prepare = None;
+        self.line_strips = min_glyphs;
+    }
+
+    /// Returns the number of glyphs from which lines are baked into strips, see
+    /// [`TextRenderer::set_line_strips`].
+    pub fn line_strips(&self) -> Option<usize> {
+        self.line_strips
+    }
+
+    /// Limits the font size text glyphs are rasterized at to `size` physical pixels, or removes
+    /// the limit given `None`, which is the default.
+    ///
+    /// Glyphs with larger font sizes are rasterized at the limit and their quads are scaled up
+    /// with linear filtering, which blurs them. This keeps glyphs from filling the atlas at
+    /// extreme scales, e.g. while pinch zooming, since the image of a glyph is about as large as
+    /// its font size. The scaled glyphs lose their subpixel offsets. Glyphs of text areas with
+    /// [integer scaling](crate::TextArea::integer_scaling) and custom glyphs are drawn as usual,
+    /// and renderers that [use the fallback](TextRenderer::uses_fallback) ignore the limit.
+    pub fn set_max_raster_font_size(&mut self, size: Option<u16>) {
+        self.last_prepare = None;
+        self.max_raster_font_size = size.map(|size| size.max(1));
+    }
+
+    /// Returns the largest font size text glyphs are rasterized at, see
+    /// [`TextRenderer::set_max_raster_font_size`].
+    pub fn max_raster_font_size(&self) -> Option<u16> {
+        self.max_raster_font_size
+    }
+
+    /// Returns the glyph to rasterize in place of `glyph` and how its image is scaled, limiting
+    /// its font size to [`TextRenderer::max_raster_font_size`].
+    pub(crate) fn limit_raster_font_size(
+        &self,
+        glyph: cosmic_text::PhysicalGlyph,
+        glyph_scale: GlyphScale,
+    ) -> (cosmic_text::PhysicalGlyph, GlyphScale) {
+        let font_size = f32::from_bits(glyph.cache_key.font_size_bits);
+        let max_size = match (self.max_raster_font_size, glyph_scale) {
+            (Some(max_size), GlyphScale::None) if font_size > max_size as f32 => max_size as f32,
+            _ => return (glyph, glyph_scale),
+        };
+
+        let cache_key = CacheKey {
+            font_size_bits: max_size.to_bits(),
+            x_bin: SubpixelBin::Zero,
+            y_bin: SubpixelBin::Zero,
+            ..glyph.cache_key
+        };
+        (
+            cosmic_text::PhysicalGlyph { cache_key, ..glyph },
+            GlyphScale::Factor(font_size / max_size),
+        )
+    }
+
+    /// Prepares the strips of a baked line, see [`TextRenderer::set_line_strips`], returning
+    /// `false` if the line has to be prepared glyph by glyph instead.
+    #[allow(clippy::too_many_arguments)]
+    fn prepare_line_strips(
+        &mut self,
+        run: &LayoutRun,
+        text_area: &TextArea,
+        text_area_index: usize,
+        visible_bounds: TextBounds,
+        exclusions: &[TextBounds],
+        atlas: &mut TextAtlas,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+    ) -> Result<bool, PrepareError> {
+        let Some(first) = run.glyphs.first() else {
+            return Ok(false);
+        };
+        // Missing emoji may be decomposed, which changes the glyphs of the line, emoji may be
+        // colored differently from their text and large glyphs may be scaled up
+        // (`Option::is_none_or` needs Rust 1.82)
+        #[allow(clippy::unnecessary_map_or)]
+        let uniform = run.glyphs.iter().all(|glyph| {
+            glyph.color_opt == first.color_opt
+                && glyph.metadata == first.metadata
+                && (self.emoji_decomposer.is_none() || glyph.glyph_id != 0)
+                && (text_area.monochrome_emoji_colors.is_empty()
+                    || !is_emoji(&run.text[glyph.start..glyph.end]))
+                && self.max_raster_font_size.map_or(true, |max_size| {
+                    glyph.font_size * text_area.scale <= max_size as f32
+                })
+        });
+        if !uniform || self.char_filter.is_some() {
+            return Ok(false);
+        }
+
+        let raster_options = self
+            .metadata_to_raster_options
+            .as_ref()
+            .map(|metadata_to_raster_options| metadata_to_raster_options(first.metadata))
+            .filter(|raster_options| !raster_options.is_default());
+        let glyphs: Vec<cosmic_text::PhysicalGlyph> = run
+            .glyphs
+            .iter()
+            .map(|glyph| glyph.physical((0.0, 0.0), text_area.scale))
+            .collect();
+
+        let mut hasher = FxHasher::default();
+        for glyph in &glyphs {
+            (glyph.cache_key, glyph.x, glyph.y).hash(&mut hasher);
+        }
+        raster_options
+            .map(|raster_options| raster_options.hash())
+            .hash(&mut hasher);
+        let line = hasher.finish();
+
+        // Strips past the limit are dropped when they are pushed
+        self.check_glyph_limit(text_area_index)?;
+        let strip_width = LINE_STRIP_WIDTH.min(atlas.mask_atlas.max_texture_dimension_2d);
+        let mut bake = || {
+            BakedLine::new(&glyphs, strip_width, |cache_key| match &raster_options {
+                Some(raster_options) => rasterize_with_options(
+                    font_system,
+                    self.scale_context.get_or_insert_with(ScaleContext::new),
+                    cache_key,
+                    raster_options,
+                ),
+                None => cache.get_image_uncached(font_system, cache_key),
+            })
+        };
+
+        let mut baked = None;
+        let extent = match self.baked_lines.get(&line) {
+            Some(extent) => *extent,
+            None => {
+                baked = bake();
+                baked.as_ref().map(|baked| baked.extent)
+            }
+        };
+        self.next_baked_lines.insert(line, extent);
+        let Some(extent) = extent else {
+            return Ok(false);
+        };
+
+        let color = first.color_opt.unwrap_or(text_area.default_color);
+        let x = text_area.left.round() as i32;
+        let y = (text_area.top + run.line_y * text_area.scale).round() as i32;
+        let cluster = run
+            .glyphs
+            .iter()
+            .map(|glyph| glyph.start)
+            .min()
+            .unwrap_or(0)
+            ..run.glyphs.iter().map(|glyph| glyph.end).max().unwrap_or(0);
+        let mut metadata_to_depth = metadata_to_depth;
+
+        let mut strips = Vec::new();
+        for strip in 0..extent.strips(strip_width) {
+            let Some((mut glyph_to_render, glyph_ref)) = prepare_glyph(
+                GlyphPlacement {
+                    x: x.saturating_add(extent.left)
+                        .saturating_add((strip * strip_width) as i32),
+                    y,
+                    line_y: 0.0,
+                    color: color.into(),
+                    metadata: first.metadata,
+                    cache_key: GlyphonCacheKey::LineStrip {
+                        line,
+                        strip: strip as u16,
+                    },
+                    glyph_scale: GlyphScale::None,
+                    scale_factor: text_area.scale,
+                    bounds: visible_bounds,
+                },
+                atlas,
+                device,
+                queue,
+                || {
+                    if baked.is_none() {
+                        baked = bake();
+                    }
+                    Some(baked.as_ref()?.strip(strip, strip_width))
+                },
+                &mut metadata_to_depth,
+            )?
+            else {
+                continue;
+            };
+
+            apply_color_glyph_style(&mut glyph_to_render, text_area.color_glyph_style);
+            strips.push((glyph_to_render, glyph_ref));
+        }
+
+        for (mut glyph_to_render, glyph_ref) in strips {
+            apply_animation(&mut glyph_to_render, self.glyph_animation(first.metadata));
+            apply_palette_index(&mut glyph_to_render, self.palette_index(first.metadata));
+            self.push_glyph_excluding(
+                glyph_to_render,
+                glyph_ref,
+                text_area_index,
+                GlyphSource::Text {
+                    line: run.line_i,
+                    cluster: cluster.clone(),
+                },
+                self.pick_id(first.metadata),
+                exclusions,
+            );
+        }
+
+        Ok(true)
+    }
+
+    /// Prepares a glyph rasterized from a font, following the color glyph policy of the renderer.
+    #[allow(clippy::too_many_arguments)]
+    fn prepare_text_glyph(
+        &mut self,
+        physical_key: CacheKey,
+        x: i32,
+        y: i32,
+        line_y: f32,
+        color: Color,
+        metadata: usize,
+        glyph_scale: GlyphScale,
+        atlas: &mut TextAtlas,
+        device: &Device,
+        queue: &Queue,
+        scale_factor: f32,
+        bounds_min_x: i32,
+        bounds_min_y: i32,
+        bounds_max_x: i32,
+        bounds_max_y: i32,
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+        mut metadata_to_depth: impl FnMut(usize) -> f32,
+    ) -> Result<Option<(GlyphToRender, GlyphRef)>, PrepareError> {
+        let raster_options = self
+            .metadata_to_raster_options
+            .as_ref()
+            .map(|metadata_to_raster_options| metadata_to_raster_options(metadata))
+            .filter(|raster_options| !raster_options.is_default());
+        if let Some(raster_options) = raster_options {
+            self.raster_options
+                .entry(raster_options.hash())
+                .or_insert(raster_options);
+        }
+        let text_key = |monochrome: bool| match raster_options {
+            Some(raster_options) => GlyphonCacheKey::Rasterized {
+                key: physical_key,
+                options: raster_options.hash(),
+                monochrome,
+            },
+            None if monochrome => GlyphonCacheKey::Monochrome(physical_key),
+            None => GlyphonCacheKey::Text(physical_key),
+        };
+
+        let mut cache_key = text_key(false);
+        if self.color_glyphs != ColorGlyphPolicy::Allow {
+            let cached_as_color = matches!(
+                atlas
+                    .color_atlas
+                    .glyph_cache
+                    .peek(&cache_key)
+                    .or_else(|| atlas.compressed_glyph(&cache_key)),
+                Some(GlyphDetails {
+                    gpu_cache: GpuCacheStatus::InAtlas {
+                        content_type: ContentType::Color,
+                        ..
+                    },
+                    ..
+                })
+            );
+            if cached_as_color {
+                self.color_glyph_keys.insert(physical_key);
+            }
+            if self.color_glyph_keys.contains(&physical_key) {
+                match self.color_glyphs {
+                    ColorGlyphPolicy::Monochrome => {
+                        cache_key = text_key(true);
+                    }
+                    _ => return Ok(None),
+                }
+            }
+        }
+
+        let glyph_to_render = loop {
+            let mut is_color = false;
+
+            let glyph_to_render = prepare_glyph(
+                GlyphPlacement {
+                    x,
+                    y,
+                    line_y,
+                    color: color.into(),
+                    metadata,
+                    cache_key,
+                    glyph_scale,
+                    scale_factor,
+                    bounds: TextBounds {
+                        left: bounds_min_x,
+                        top: bounds_min_y,
+                        right: bounds_max_x,
+                        bottom: bounds_max_y,
+                    },
+                },
+                atlas,
+                device,
+                queue,
+                || -> Option<GlyphImage> {
+                    let image = match &raster_options {
+                        Some(raster_options) => rasterize_with_options(
+                            font_system,
+                            self.scale_context.get_or_insert_with(ScaleContext::new),
+                            physical_key,
+                            raster_options,
+                        )?,
+                        None => cache.get_image_uncached(font_system, physical_key)?,
+                    };
+
+                    let monochrome = matches!(
+                        cache_key,
+                        GlyphonCacheKey::Monochrome(_)
+                            | GlyphonCacheKey::Rasterized {
+                                monochrome: true,
+                                ..
+                            }
+                    );
+                    if matches!(image.content, SwashContent::Color)
+                        && !monochrome
+                        && self.color_glyphs != ColorGlyphPolicy::Allow
+                    {
+                        // Don't cache the color glyph in the atlas, since it won't be
+                        // rendered in color.
+                        is_color = true;
+                        return None;
+                    }
+
+                    Some(text_glyph_image(image, monochrome))
+                },
+                &mut metadata_to_depth,
+            )?;
+
+            if !is_color {
+                break glyph_to_render;
+            }
+
+            self.color_glyph_keys.insert(physical_key);
+            match self.color_glyphs {
+                ColorGlyphPolicy::Monochrome => {
+                    cache_key = text_key(true);
+                }
+                _ => break None,
+            }
+        };
+
+        Ok(glyph_to_render)
+    }
+
+    fn push_glyph(
+        &mut self,
+        glyph: GlyphToRender,
+        glyph_ref: GlyphRef,
+        text_area: usize,
+        source: GlyphSource,
+        pick_id: u32,
+    ) {
+        if self
+            .max_glyphs
+            .is_some_and(|max_glyphs| self.glyph_vertices.len() >= max_glyphs)
+        {
+            self.glyph_limit_exceeded = true;
+            return;
+        }
+
+        if let Some(glyph_sources) = &mut self.glyph_sources {
+            glyph_sources.push(GlyphSourceInfo {
+                text_area,
+                source,
+                rect: glyph.rect(),
+            });
+        }
+        if self.features.pick_ids {
+            self.pick_ids.push(pick_id);
+        }
+        self.glyph_vertices.push(glyph);
+        self.glyph_refs.push(glyph_ref);
+    }
+
+    /// Sorts the glyphs of a text area that starts at `first_glyph` and records its range, or
+    /// adds it to `culled_text_areas` if it has no glyphs.
+    fn finish_text_area(
+        &mut self,
+        text_area: usize,
+        first_glyph: usize,
+        culled_text_areas: &mut Vec<usize>,
+    ) {
+        self.sort_glyphs(text_area, first_glyph);
+        self.area_ranges
+            .push(first_glyph..self.glyph_vertices.len());
+
+        if self.glyph_vertices.len() == first_glyph {
+            culled_text_areas.push(text_area);
+        }
+    }
+
+    /// Sorts the glyphs of a text area, starting at `first_glyph`, by the atlas texture they
+    /// sample and records their draw ranges, if sorting is enabled.
+    fn sort_glyphs(&mut self, text_area: usize, first_glyph: usize) {
+        let Some(draw_ranges) = &mut self.draw_ranges else {
+            return;
+        };
+
+        let texture = |index: usize| atlas_texture(&self.glyph_vertices[index]);
+        let mut order: Vec<usize> = (first_glyph..self.glyph_vertices.len()).collect();
+        order.sort_by_key(|&index| texture(index) as u8);
+
+        if order
+            .iter()
+            .enumerate()
+            .any(|(i, &index)| first_glyph + i != index)
+        {
+            let vertices: Vec<GlyphToRender> = order
+                .iter()
+                .map(|&index| self.glyph_vertices[index])
+                .collect();
+            self.glyph_vertices[first_glyph..].copy_from_slice(&vertices);
+            let refs: Vec<GlyphRef> = order.iter().map(|&index| self.glyph_refs[index]).collect();
+            self.glyph_refs[first_glyph..].copy_from_slice(&refs);
+            if !self.pick_ids.is_empty() {
+                let pick_ids: Vec<u32> = order.iter().map(|&index| self.pick_ids[index]).collect();
+                self.pick_ids[first_glyph..].copy_from_slice(&pick_ids);
+            }
+            if let Some(glyph_sources) = &mut self.glyph_sources {
+                let sources: Vec<GlyphSourceInfo> = order
+                    .iter()
+                    .map(|&index| glyph_sources[index].clone())
+                    .collect();
+                glyph_sources.truncate(first_glyph);
+                glyph_sources.extend(sources);
+            }
+        }
+
+        for (index, vertex) in self.glyph_vertices.iter().enumerate().skip(first_glyph) {
+            let texture = atlas_texture(vertex);
+            match draw_ranges.last_mut() {
+                Some(range)
+                    if range.text_area == text_area
+                        && range.texture == texture
+                        && range.glyphs.end == index =>
+                {
+                    range.glyphs.end += 1;
+                }
+                _ => draw_ranges.push(DrawRange {
+                    text_area,
+                    texture,
+                    glyphs: index..index + 1,
+                }),
+            }
+        }
+    }
+
+    /// Pushes the parts of `glyph` that aren't covered by any of `exclusions`.
+    fn push_glyph_excluding(
+        &mut self,
+        glyph: GlyphToRender,
+        glyph_ref: GlyphRef,
+        text_area: usize,
+        source: GlyphSource,
+        pick_id: u32,
+        exclusions: &[TextBounds],
+    ) {
+        if exclusions.is_empty() {
+            self.push_glyph(glyph, glyph_ref, text_area, source, pick_id);
+            return;
+        }
+
+        let parts = glyph.rect().subtract(exclusions);
+        if glyph.repeat != [0, 0] {
+            // Splitting would shift the repeated copies, so only skip the glyph if it is covered
+            if !parts.is_empty() {
+                self.push_glyph(glyph, glyph_ref, text_area, source, pick_id);
+            }
+            return;
+        }
+
+        for part in parts {
+            self.push_glyph(
+                glyph.part(part),
+                glyph_ref,
+                text_area,
+                source.clone(),
+                pick_id,
+            );
+        }
+    }
+
+    /// Returns a handle to the atlas entry of every glyph prepared by the last call to `prepare`,
+    /// in the order the glyphs are drawn.
+    ///
+    /// Glyphs that are split into parts by [`TextArea::exclusions`] have a handle per part.
+    /// Renderers that use the fallback (see [`TextRenderer::uses_fallback`]) don't return any
+    /// handles.
+    pub fn glyph_handles(&self) -> impl Iterator<Item = AtlasGlyphHandle> + '_ {
+        self.glyph_refs.iter().map(|glyph_ref| AtlasGlyphHandle {
+            key: glyph_ref.key,
+            generation: glyph_ref.generation,
+        })
+    }
+
+    /// Captures the prepared state of the renderer, which can be restored later with
+    /// [`TextRenderer::restore`].
+    pub fn snapshot(&self) -> PreparedSnapshot {
+        PreparedSnapshot {
+            vertices: self.glyph_vertices.clone(),
+            glyphs: self.glyph_refs.clone(),
+            pick_ids: self.pick_ids.clone(),
+        }
+    }
+
+    /// Restores prepared state captured with [`TextRenderer::snapshot`], so that it is rendered
+    /// by the next call to `render`.
+    ///
+    /// The glyphs referenced by the snapshot must still be cached in `atlas`, although they may
+    /// have moved within it. Glyphs that were evicted since the snapshot was taken are not
+    /// rasterized again; instead [`RestoreError::GlyphEvicted`] is returned and the prepared
+    /// state of the renderer is left empty.
+    pub fn restore(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        atlas: &mut TextAtlas,
+        snapshot: &PreparedSnapshot,
+    ) -> Result<(), RestoreError> {
+        self.clear_prepared();
+        atlas.partition = self.atlas_partition;
+
+        for (vertex, glyph_ref) in snapshot.vertices.iter().zip(&snapshot.glyphs) {
+            let Some((origin, generation, compressed)) = atlas.mark_in_use(&glyph_ref.key) else {
+                self.glyph_vertices.clear();
+                self.glyph_refs.clear();
+                return Err(RestoreError::GlyphEvicted);
+            };
+
+            // Move the texture coordinates along with the glyph, keeping the offset of clipping
+            let mut vertex = *vertex;
+            vertex.uv = [
+                origin[0] + (vertex.uv[0] - glyph_ref.origin[0]),
+                origin[1] + (vertex.uv[1] - glyph_ref.origin[1]),
+            ];
+            vertex.content_type_with_srgb[0] &= !COMPRESSED_PAGE_FLAG;
+            if compressed {
+                vertex.content_type_with_srgb[0] |= COMPRESSED_PAGE_FLAG;
+            }
+
+            self.glyph_vertices.push(vertex);
+            self.glyph_refs.push(GlyphRef {
+                key: glyph_ref.key,
+                origin,
+                generation,
+            });
+        }
+        if self.features.pick_ids {
+            self.pick_ids.extend_from_slice(&snapshot.pick_ids);
+        }
+
+        self.upload_vertices(device, queue)
+            .map_err(|_| RestoreError::MemoryBudgetExceeded)
+    }
+
+    /// Checks that the glyphs prepared by the last call to `prepare` are still cached in `atlas`
+    /// and rasterizes the glyphs that were evicted since again, returning how many glyphs were
+    /// rasterized.
+    ///
+    /// This makes it safe to render prepared state over multiple frames without preparing it
+    /// again, even if other renderers sharing the atlas evicted some of its glyphs in the
+    /// meantime. Glyphs that moved within the atlas are drawn from their new position. Custom
+    /// glyphs, glyphs given to [`TextRenderer::prepare_positioned`] and the strips of
+    /// [baked lines](TextRenderer::set_line_strips) can't be rasterized without their layout or
+    /// images; if one of them was evicted, [`RestoreError::GlyphEvicted`] is returned and the
+    /// prepared state of the renderer is left empty.
+    pub fn revalidate(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        cache: &mut SwashCache,
+    ) -> Result<usize, RestoreError> {
+        let mut atlas = UploadGuard::new(atlas, device, queue);
+        let atlas = &mut *atlas;
+        atlas.partition = self.atlas_partition;
+
+        let mut rasterized = 0;
+        let mut moved = false;
+        for index in 0..self.glyph_refs.len() {
+            let glyph_ref = self.glyph_refs[index];
+            let cached = match atlas.mark_in_use(&glyph_ref.key) {
+                Some(cached) => Some(cached),
+                None => match self.rasterize_again(glyph_ref.key, font_system, cache) {
+                    Some(image) => {
+                        rasterized += 1;
+                        insert_glyph(glyph_ref.key, image, atlas, device, queue).map_err(
+                            |error| {
+                                self.clear_prepared();
+                                match error {
+                                    PrepareError::AtlasFull => RestoreError::AtlasFull,
+                                    _ => RestoreError::MemoryBudgetExceeded,
+                                }
+                            },
+                        )?;
+                        atlas.mark_in_use(&glyph_ref.key)
+                    }
+                    None => None,
+                },
+            };
+            let Some((origin, generation, compressed)) = cached else {
+                self.clear_prepared();
+                return Err(RestoreError::GlyphEvicted);
+            };
+            if origin == glyph_ref.origin && generation == glyph_ref.generation {
+                continue;
+            }
+
+            // Move the texture coordinates along with the glyph, keeping the offset of clipping
+            let vertex = &mut self.glyph_vertices[index];
+            vertex.uv = [
+                origin[0] + (vertex.uv[0] - glyph_ref.origin[0]),
+                origin[1] + (vertex.uv[1] - glyph_ref.origin[1]),
+            ];
+            vertex.content_type_with_srgb[0] &= !COMPRESSED_PAGE_FLAG;
+            if compressed {
+                vertex.content_type_with_srgb[0] |= COMPRESSED_PAGE_FLAG;
+            }
+            self.glyph_refs[index] = GlyphRef {
+                key: glyph_ref.key,
+                origin,
+                generation,
+            };
+            moved = true;
+        }
+
+        atlas.flush_uploads(device, queue);
+        if moved {
+            self.upload_vertices(device, queue)
+                .map_err(|_| RestoreError::MemoryBudgetExceeded)?;
+        }
+
+        Ok(rasterized)
+    }
+
+    /// Rasterizes the image of an evicted glyph again, or returns `None` if the renderer doesn't
+    /// know how to.
+    fn rasterize_again(
+        &mut self,
+        key: GlyphonCacheKey,
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+    ) -> Option<GlyphImage> {
+        let mask = |(data, width, height): (Vec<u8>, u16, u16)| GlyphImage {
+            content_type: ContentType::Mask,
+            top: 0,
+            left: 0,
+            width,
+            height,
+            data,
+        };
+
+        match key {
+            GlyphonCacheKey::Text(key) => Some(text_glyph_image(
+                cache.get_image_uncached(font_system, key)?,
+                false,
+            )),
+            GlyphonCacheKey::Monochrome(key) => Some(text_glyph_image(
+                cache.get_image_uncached(font_system, key)?,
+                true,
+            )),
+            GlyphonCacheKey::Rasterized {
+                key,
+                options,
+                monochrome,
+            } => {
+                let image = rasterize_with_options(
+                    font_system,
+                    self.scale_context.get_or_insert_with(ScaleContext::new),
+                    key,
+                    self.raster_options.get(&options)?,
+                )?;
+                Some(text_glyph_image(image, monochrome))
+            }
+            GlyphonCacheKey::Decoration(pattern) => pattern.rasterize().map(mask),
+            GlyphonCacheKey::Placeholder(placeholder) => Some(mask(placeholder.rasterize())),
+            GlyphonCacheKey::Custom(_)
+            | GlyphonCacheKey::Positioned(_)
+            | GlyphonCacheKey::LineStrip { .. } => None,
+        }
+    }
+
+    /// Returns every glyph prepared by the last call to `prepare`, with its placement and its image
+    /// read back from the atlas, in the order the glyphs are drawn.
+    ///
+    /// This can be used to export the text that is rendered on screen, e.g. to generate a PDF for
+    /// printing. Glyphs of text also refer to their outline in the font, so that exporters can
+    /// write vectors instead of images. Reading back the atlas blocks until the GPU is done with
+    /// it, so this shouldn't be called every frame. Renderers that use the fallback (see
+    /// [`TextRenderer::uses_fallback`]) don't export any glyphs.
+    pub fn export(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        atlas: &TextAtlas,
+    ) -> Result<Vec<ExportedGlyph>, ExportError> {
+        trace_span!("export", count = self.glyph_vertices.len());
+
+        let mut mask_texture = None;
+        let mut color_texture = None;
+        let mut compressed_texture = None;
+        let mut exported = Vec::with_capacity(self.glyph_vertices.len());
+
+        for (index, (vertex, glyph_ref)) in
+            self.glyph_vertices.iter().zip(&self.glyph_refs).enumerate()
+        {
+            let (size, details, content_type, compressed_page) =
+                exported_glyph_details(atlas, glyph_ref)?;
+
+            let texture = match (content_type, compressed_page) {
+                (_, Some(_)) => &mut compressed_texture,
+                (ContentType::Mask, None) => &mut mask_texture,
+                (ContentType::Color | ContentType::TintedColor, None) => &mut color_texture,
+            };
+            let texture = match texture {
+                Some(texture) => texture,
+                None => texture.insert(
+                    match compressed_page {
+                        Some(page) => page.read_texture(device, queue, &atlas.cache),
+                        None => atlas.read_texture(device, queue, content_type),
+                    }
+                    .map_err(|_| ExportError::ReadbackFailed)?,
+                ),
+            };
+
+            let bytes_per_pixel = content_type.bytes_per_pixel();
+            let [u, v] = vertex.uv.map(usize::from);
+            let [width, height] = vertex.uv_dim;
+            let data: Vec<u8> = (v..v + height as usize)
+                .flat_map(|row| {
+                    let start = (row * size as usize + u) * bytes_per_pixel;
+                    &texture[start..start + width as usize * bytes_per_pixel]
+                })
+                .copied()
+                .collect();
+            // Rotated and flipped custom glyphs are exported as they are shown on screen
+            let (data, width, height) = match vertex.orientation() {
+                orientation if orientation.is_upright() => (data, width, height),
+                orientation => orientation.apply(&data, width, height, bytes_per_pixel),
+            };
+
+            let outline = glyph_outline(vertex, glyph_ref, details).map(|(outline, ..)| outline);
+
+            exported.push(ExportedGlyph {
+                source: self
+                    .glyph_sources
+                    .as_ref()
+                    .and_then(|glyph_sources| glyph_sources.get(index))
+                    .cloned(),
+                rect: vertex.rect(),
+                color: Color(vertex.color),
+                palette_index: (vertex.content_type_with_srgb[1] & PALETTE_FLAG != 0)
+                    .then_some((vertex.color & PALETTE_INDEX_MASK) as usize),
+                content_type,
+                width,
+                height,
+                data,
+                repeat: (vertex.repeat[1] != 0).then_some(vertex.repeat),
+                outline,
+            });
+        }
+
+        Ok(exported)
+    }
+
+    /// Returns the vector outlines of the glyphs of text prepared by the last call to `prepare`,
+    /// positioned like they are drawn, in the order the glyphs are drawn.
+    ///
+    /// The outlines are scaled from the font size the glyphs are rasterized at, with the same
+    /// hinting and faux italic slant, so that vectors exported from them, e.g. to SVG or for
+    /// tessellation on the GPU, match the rendered text. Only glyphs drawn as masks have outlines,
+    /// so color glyphs, custom glyphs, decorations, glyphs rasterized with
+    /// [`GlyphRasterOptions`] and lines baked into strips (see
+    /// [`TextRenderer::set_line_strips`]) are skipped. Unlike [`TextRenderer::export`], this
+    /// doesn't read back the atlas. Renderers that use the fallback (see
+    /// [`TextRenderer::uses_fallback`]) don't export any outlines.
+    pub fn export_outlines(
+        &mut self,
+        font_system: &mut FontSystem,
+        atlas: &TextAtlas,
+    ) -> Result<Vec<ExportedOutline>, ExportError> {
+        trace_span!("export_outlines", count = self.glyph_vertices.len());
+
+        let context = self.scale_context.get_or_insert_with(ScaleContext::new);
+        let mut exported = Vec::new();
+
+        for (index, (vertex, glyph_ref)) in
+            self.glyph_vertices.iter().zip(&self.glyph_refs).enumerate()
+        {
+            if !matches!(
+                glyph_ref.key,
+                GlyphonCacheKey::Text(_) | GlyphonCacheKey::Monochrome(_)
+            ) {
+                continue;
+            }
+            let (_, details, content_type, _) = exported_glyph_details(atlas, glyph_ref)?;
+            if content_type != ContentType::Mask {
+                continue;
+            }
+            let Some((outline, key, scale)) = glyph_outline(vertex, glyph_ref, details) else {
+                continue;
+            };
+            let Some(path) = outline_path(font_system, context, key, outline.x, outline.y, scale)
+            else {
+                continue;
+            };
+
+            exported.push(ExportedOutline {
+                source: self
+                    .glyph_sources
+                    .as_ref()
+                    .and_then(|glyph_sources| glyph_sources.get(index))
+                    .cloned(),
+                outline,
+                clip: vertex.rect(),
+                color: Color(vertex.color),
+                palette_index: (vertex.content_type_with_srgb[1] & PALETTE_FLAG != 0)
+                    .then_some((vertex.color & PALETTE_INDEX_MASK) as usize),
+                path,
+            });
+        }
+
+        Ok(exported)
+    }
+
+    /// Returns the number of glyphs that were prepared by the last call to `prepare`.
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_vertices.len()
+    }
+
+    /// Renders all layouts that were previously provided to `prepare`.
+    ///
+    /// Returns [`RenderError::RemovedFromAtlas`] without drawing anything if `atlas` isn't the
+    /// atlas the renderer was created with.
+    pub fn render(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+    ) -> Result<(), RenderError> {
+        self.check_atlas(atlas)?;
+        self.push_debug_group(pass);
+        match &self.pipeline {
+            Pipeline::Fallback(fallback) => {
+                fallback.render(pass);
+            }
+            Pipeline::Glyphs(_) => {
+                self.draw_glyphs(atlas, viewport, pass);
+            }
+        }
+        self.pop_debug_group(pass);
+
+        Ok(())
+    }
+
+    /// Returns the range of glyphs each text area given to the last call to `prepare` produced,
+    /// in the order the glyphs are drawn, indexed like the text areas.
+    ///
+    /// The glyphs of a text area are always drawn consecutively, so these ranges can be drawn
+    /// separately with [`TextRenderer::render_areas`]. The ranges are empty for renderers that
+    /// [use the fallback](TextRenderer::uses_fallback) and after restoring glyphs with
+    /// [`TextRenderer::restore`].
+    pub fn area_ranges(&self) -> &[Range<usize>] {
+        &self.area_ranges
+    }
+
+    /// Renders the glyphs of some of the text areas given to the last call to `prepare`, in the
+    /// order of `text_areas`, which contains indices into [`TextRenderer::area_ranges`].
+    ///
+    /// This allows changing the state of the render pass between text areas, e.g. by calling
+    /// this for each text area with a different scissor rect or stencil reference. Indices of text
+    /// areas that weren't prepared are ignored. Renderers that [use the
+    /// fallback](TextRenderer::uses_fallback) draw all of their text areas at once instead.
+    pub fn render_areas(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+        text_areas: impl IntoIterator<Item = usize>,
+    ) -> Result<(), RenderError> {
+        self.check_atlas(atlas)?;
+        self.push_debug_group(pass);
+        if let Pipeline::Fallback(fallback) = &self.pipeline {
+            fallback.render(pass);
+        } else if let Some(glyphs) = self.bind_glyphs(pass, &mut None) {
+            bind_shared(atlas, viewport, pass);
+            self.set_push_constants_of(viewport, pass);
+            for index in text_areas {
+                let Some(range) = self.area_ranges.get(index) else {
+                    continue;
+                };
+                let range = range.start as u32..(range.end as u32).min(glyphs);
+                if !range.is_empty() {
+                    self.draw_quads(pass, range);
+                }
+            }
+        }
+        self.pop_debug_group(pass);
+
+        Ok(())
+    }
+
+    /// Renders all layouts that were previously provided to `prepare`, like
+    /// [`TextRenderer::render`], after checking that the renderer can draw into the pass.
+    ///
+    /// `pass_depth_stencil` is the format of the depth-stencil attachment of `pass`, or `None` if
+    /// it has none. Drawing with a renderer created with a different depth-stencil state is a
+    /// validation error in wgpu, so [`RenderError::DepthStencilMismatch`] is returned instead
+    /// without drawing anything.
+    pub fn render_checked(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass_depth_stencil: Option<TextureFormat>,
+        pass: &mut RenderPass<'_>,
+    ) -> Result<(), RenderError> {
+        let renderer_depth_stencil = self.depth_stencil.as_ref().map(|state| state.format);
+        if renderer_depth_stencil != pass_depth_stencil {
+            return Err(RenderError::DepthStencilMismatch {
+                renderer: renderer_depth_stencil,
+                pass: pass_depth_stencil,
+            });
+        }
+
+        self.render(atlas, viewport, pass)
+    }
+
+    /// Renders all layouts that were previously provided to `prepare` at the given `resolution`,
+    /// without requiring a [`Viewport`].
+    ///
+    /// The renderer keeps a [`ViewportPool`] for this, so it can be called multiple times with
+    /// different resolutions between calls to `prepare`. To share viewports between renderers or
+    /// change their settings, use a `ViewportPool` with [`TextRenderer::render`] instead.
+    pub fn render_with_resolution(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        atlas: &TextAtlas,
+        resolution: Resolution,
+        pass: &mut RenderPass<'_>,
+    ) -> Result<(), RenderError> {
+        self.check_atlas(atlas)?;
+        self.push_debug_group(pass);
+        if let Pipeline::Fallback(fallback) = &self.pipeline {
+            fallback.render(pass);
+        } else if !self.glyph_vertices.is_empty() {
+            // Viewports used since the last prepare aren't reused for other resolutions, so that
+            // their uniforms are not overwritten before the render pass is submitted.
+            let mut viewports = mem::take(&mut self.resolution_viewports);
+            let viewport = viewports.get(device, queue, &atlas.cache, resolution);
+            self.draw_glyphs(atlas, viewport, pass);
+            self.resolution_viewports = viewports;
+        }
+        self.pop_debug_group(pass);
+
+        Ok(())
+    }
+
+    /// Renders the layouts prepared by several renderers into the same pass, like calling
+    /// [`TextRenderer::render`] on each of them in order.
+    ///
+    /// The bind groups of `atlas` and `viewport` are only set once for the whole batch instead of
+    /// once per renderer, and pipelines only when they change between renderers. This reduces
+    /// the overhead of UIs drawing many small pieces of text with separate renderers. Renderers
+    /// that [use the fallback](TextRenderer::uses_fallback) bind their own resources, so the
+    /// shared bind groups are set again after them.
+    ///
+    /// Like [`TextRenderer::render`], this returns [`RenderError::RemovedFromAtlas`] for a
+    /// renderer that wasn't created with `atlas`, after drawing the renderers before it.
+    pub fn render_batch<'r>(
+        renderers: impl IntoIterator<Item = &'r TextRenderer>,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+    ) -> Result<(), RenderError> {
+        let mut bound_pipeline = None;
+        let mut shared_bound = false;
+
+        for renderer in renderers {
+            renderer.check_atlas(atlas)?;
+            renderer.push_debug_group(pass);
+            if let Pipeline::Fallback(fallback) = &renderer.pipeline {
+                fallback.render(pass);
+                bound_pipeline = None;
+                shared_bound = false;
+            } else if let Some(glyphs) = renderer.bind_glyphs(pass, &mut bound_pipeline) {
+                if !shared_bound {
+                    bind_shared(atlas, viewport, pass);
+                    shared_bound = true;
+                }
+                renderer.set_push_constants_of(viewport, pass);
+                renderer.draw_quads(pass, 0..glyphs);
+            }
+            renderer.pop_debug_group(pass);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the glyphs of the renderer were prepared in `atlas`.
+    fn check_atlas(&self, atlas: &TextAtlas) -> Result<(), RenderError> {
+        if Arc::ptr_eq(&self.prepared_glyphs, &atlas.prepared_glyphs) {
+            Ok(())
+        } else {
+            Err(RenderError::RemovedFromAtlas)
+        }
+    }
+
+    fn draw_glyphs(&self, atlas: &TextAtlas, viewport: &Viewport, pass: &mut RenderPass<'_>) {
+        let Some(glyphs) = self.bind_glyphs(pass, &mut None) else {
+            return;
+        };
+
+        bind_shared(atlas, viewport, pass);
+        self.set_push_constants_of(viewport, pass);
+        self.draw_quads(pass, 0..glyphs);
+    }
+
+    /// Sets the params of `viewport` as push constants if the renderer reads them from push
+    /// constants, after its pipeline was set.
+    fn set_push_constants_of(&self, viewport: &Viewport, pass: &mut RenderPass<'_>) {
+        if self.features.push_constants {
+            pass.set_push_constants(PUSH_CONSTANT_STAGES, 0, viewport.params_bytes());
+        }
+    }
+
+    /// Sets the pipeline of the renderer unless it is `bound_pipeline`, and binds its glyphs.
+    /// Returns the number of glyphs to draw, or `None` if there is nothing to draw.
+    fn bind_glyphs<'a>(
+        &'a self,
+        pass: &mut RenderPass<'_>,
+        bound_pipeline: &mut Option<&'a RenderPipeline>,
+    ) -> Option<u32> {
+        if self.glyph_vertices.is_empty() {
+            return None;
+        }
+
+        let Pipeline::Glyphs(pipeline) = &self.pipeline else {
+            return None;
+        };
+
+        let mut glyphs = self.glyph_vertices.len() as u32;
+        self.bind_features(self.features, pass);
+        if let Some(vertex_pulling) = &self.vertex_pulling {
+            glyphs = glyphs.min(vertex_pulling.bound_glyphs);
+        }
+
+        if *bound_pipeline != Some(pipeline) {
+            pass.set_pipeline(pipeline);
+            *bound_pipeline = Some(pipeline);
+        }
+
+        Some(glyphs)
+    }
+
+    /// Binds the vertex buffers and bind groups read by a pipeline with `features`, which are a
+    /// subset of the features of the renderer.
+    fn bind_features(&self, features: PipelineFeatures, pass: &mut RenderPass<'_>) {
+        let [glyph_slot, color_override_slot, pick_id_slot] = features.vertex_buffer_slots();
+        if let Some(slot) = glyph_slot {
+            pass.set_vertex_buffer(slot, self.vertex_buffer.slice(..));
+        }
+        if let (Some(slot), Some(color_overrides)) = (color_override_slot, &self.color_overrides) {
+            pass.set_vertex_buffer(slot, color_overrides.buffer.slice(..));
+        }
+        if let (Some(slot), Some(pick_ids)) = (pick_id_slot, &self.pick_id_buffer) {
+            pass.set_vertex_buffer(slot, pick_ids.buffer.slice(..));
+        }
+        if let (Some(group), Some(vertex_pulling)) =
+            (features.vertex_pulling_group(), &self.vertex_pulling)
+        {
+            pass.set_bind_group(group, &vertex_pulling.bind_group, &[]);
+        }
+        if let (Some(group), Some(mask)) = (features.mask_group(), &self.mask) {
+            pass.set_bind_group(group, mask, &[]);
+        }
+        if features.indexed_quads {
+            pass.set_index_buffer(self.cache.quad_indices().slice(..), IndexFormat::Uint16);
+        }
+    }
+
+    /// Draws the quads of the glyphs in `instances` with the bound pipeline.
+    fn draw_quads(&self, pass: &mut RenderPass<'_>, instances: Range<u32>) {
+        match self.features.indexed_quads {
+            true => pass.draw_indexed(0..6, 0, instances),
+            false => pass.draw(0..4, instances),
+        }
+    }
+
+    /// Draws the prepared glyphs into a pass without multisampling and depth, regardless of the
+    /// settings of the renderer, returning `false` if there was nothing to draw.
+    ///
+    /// Used by [`crate::GlowRenderer`] to render text into its offscreen texture.
+    pub(crate) fn draw_offscreen(
+        &self,
+        device: &Device,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+    ) -> bool {
+        if let Pipeline::Fallback(fallback) = &self.pipeline {
+            let pipeline = atlas.cache.get_or_create_fallback_pipeline(
+                device,
+                atlas.format,
+                MultisampleState::default(),
+                None,
+                BlendMode::Alpha,
+            );
+            return fallback.render_with(&pipeline, pass);
+        }
+
+        if self.glyph_vertices.is_empty() {
+            return false;
+        }
+        // The vertex buffer of renderers using vertex pulling can be read as a vertex buffer too,
+        // and the offscreen texture has neither push constants nor a second color attachment
+        let features = PipelineFeatures {
+            vertex_pulling: false,
+            push_constants: false,
+            pick_ids: false,
+            ..self.features
+        };
+        let Some(pipeline) = atlas.get_or_create_pipeline(
+            device,
+            MultisampleState::default(),
+            None,
+            BlendMode::Alpha,
+            features,
+        ) else {
+            return false;
+        };
+
+        pass.set_pipeline(&pipeline);
+        self.bind_features(features, pass);
+        pass.set_bind_group(0, &atlas.bind_group, &[]);
+        pass.set_bind_group(1, &viewport.bind_group, &[]);
+        self.draw_quads(pass, 0..self.glyph_vertices.len() as u32);
+        true
+    }
+}
+
+impl Drop for TextRenderer {
+    fn drop(&mut self) {
+        self.memory.release(self.vertex_buffer_size);
+        for GlyphBuffer { size, .. } in [&self.color_overrides, &self.pick_id_buffer]
+            .into_iter()
+            .flatten()
+        {
+            self.memory.release(*size);
+        }
+        self.prepared_glyphs.remove(self.id);
+    }
+}
+
+/// How the image of a glyph in the atlas is scaled when it is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum GlyphScale {
+    /// Drawn at its original size.
+    None,
+    /// Drawn with the given width and height, with linear filtering.
+    Size(u16, u16),
+    /// Drawn with each pixel repeated the given number of times, including its offset.
+    Integer(u16),
+    /// Drawn scaled up by the given factor, including its offset, with linear filtering.
+    Factor(f32),
+}
+
+/// Set in the content type of a glyph that is drawn scaled and should be filtered.
+const LINEAR_FILTER_FLAG: u16 = 0x8000;
+
+/// Set in the content type of a color glyph that is stored in the compressed color atlas.
+const COMPRESSED_PAGE_FLAG: u16 = 0x80;
+
+/// The bits of the content type that contain the [`ContentType`].
+const CONTENT_TYPE_MASK: u16 = 0x7f;
+
+/// The bits of the content type of a color glyph that contain its saturation.
+const SATURATION_SHIFT: u16 = 8;
+const SATURATION_MAX: u16 = 0x7f;
+
+/// The bits of the color conversion of a glyph that contain its animation.
+const ANIMATION_SHIFT: u16 = 8;
+
+/// The bits of the color conversion of a glyph that contain its orientation, see
+/// [`crate::custom_glyph::GlyphOrientation`].
+pub(crate) const ORIENTATION_SHIFT: u16 = 1;
+
+/// Returns the atlas texture sampled by a prepared glyph.
+fn atlas_texture(glyph: &GlyphToRender) -> AtlasTexture {
+    let content_type = glyph.content_type_with_srgb[0];
+    if content_type & CONTENT_TYPE_MASK == ContentType::Mask as u16 {
+        AtlasTexture::Mask
+    } else if content_type & COMPRESSED_PAGE_FLAG != 0 {
+        AtlasTexture::CompressedColor
+    } else {
+        AtlasTexture::Color
+    }
+}
+
+/// Binds the resources shared by all renderers drawing with `atlas` and `viewport`.
+fn bind_shared(atlas: &TextAtlas, viewport: &Viewport, pass: &mut RenderPass<'_>) {
+    pass.set_bind_group(0, &atlas.bind_group, &[]);
+    pass.set_bind_group(1, &viewport.bind_group, &[]);
+}
+
+/// Set in the color conversion of a glyph whose color is the index of a color in the palette.
+const PALETTE_FLAG: u16 = 0x80;
+
+/// The bits of the color of a glyph with [`PALETTE_FLAG`] that contain its palette index.
+const PALETTE_INDEX_MASK: u32 = 0xf;
+
+fn apply_animation(glyph: &mut GlyphToRender, animation: GlyphAnimation) {
+    glyph.content_type_with_srgb[1] |= (animation as u16) << ANIMATION_SHIFT;
+}
+
+/// Makes `glyph` look up its color in the palette at `index`, keeping the alpha of its color.
+///
+/// Color glyphs don't use the color of the glyph, so they keep their tint.
+fn apply_palette_index(glyph: &mut GlyphToRender, index: Option<usize>) {
+    let Some(index) = index else {
+        return;
+    };
+    if glyph.content_type_with_srgb[0] & CONTENT_TYPE_MASK == ContentType::Color as u16 {
+        return;
+    }
+
+    glyph.color = (glyph.color & 0xff00_0000) | index as u32;
+    glyph.content_type_with_srgb[1] |= PALETTE_FLAG;
+}
+
+/// Applies `style` to `glyph` if it is a color glyph.
+///
+/// Color glyphs don't use the color of the glyph, so it is replaced by the tint.
+fn apply_color_glyph_style(glyph: &mut GlyphToRender, style: ColorGlyphStyle) {
+    if glyph.content_type_with_srgb[0] & CONTENT_TYPE_MASK != ContentType::Color as u16 {
+        return;
+    }
+
+    let saturation = (style.saturation.clamp(0.0, 1.0) * SATURATION_MAX as f32).round() as u16;
+    glyph.color = style.tint.0;
+    glyph.content_type_with_srgb[0] |= saturation << SATURATION_SHIFT;
+}
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum TextColorConversion {
+    None = 0,
+    ConvertToLinear = 1,
+}
+
+/// Information about the text areas prepared by [`TextRenderer::prepare`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrepareOutput {
+    /// The indices of the text areas that produced no visible glyphs, e.g. because they were
+    /// clipped entirely, in the order the text areas were given to `prepare`.
+    ///
+    /// This can be used to skip other work related to these text areas, like drawing their
+    /// backgrounds.
+    pub culled_text_areas: Vec<usize>,
+}
+
+/// Returns the size of the atlas texture a prepared glyph is cached in, its details, its content
+/// type and the compressed page it was moved to, if any.
+fn exported_glyph_details<'a>(
+    atlas: &'a TextAtlas,
+    glyph_ref: &GlyphRef,
+) -> Result<
+    (
+        u32,
+        &'a GlyphDetails,
+        ContentType,
+        Option<&'a crate::compress::CompressedPage>,
+    ),
+    ExportError,
+> {
+    let found = [&atlas.mask_atlas, &atlas.color_atlas]
+        .into_iter()
+        .find_map(|inner| Some((inner.size, inner.glyph_cache.peek(&glyph_ref.key)?)));
+    let compressed_page = match found {
+        Some(_) => None,
+        None => atlas.compressed_color.as_ref(),
+    };
+    let Some((size, details)) = found.or_else(|| {
+        let page = compressed_page?;
+        Some((page.size, page.glyphs.get(&glyph_ref.key)?))
+    }) else {
+        return Err(ExportError::RemovedFromAtlas);
+    };
+    match details.gpu_cache {
+        GpuCacheStatus::InAtlas { x, y, content_type } if [x, y] == glyph_ref.origin => {
+            Ok((size, details, content_type, compressed_page))
+        }
+        _ => Err(ExportError::RemovedFromAtlas),
+    }
+}
+
+/// Returns the outline of a prepared glyph of text, with the cache key its image was rasterized
+/// with and the scale its image is drawn at.
+fn glyph_outline(
+    vertex: &GlyphToRender,
+    glyph_ref: &GlyphRef,
+    details: &GlyphDetails,
+) -> Option<(GlyphOutline, CacheKey, f32)> {
+    let (GlyphonCacheKey::Text(key) | GlyphonCacheKey::Monochrome(key)) = glyph_ref.key else {
+        return None;
+    };
+
+    // Integer scaled glyphs repeat every pixel of their image, while glyphs above the maximum
+    // raster font size are scaled by any factor
+    let axis = (vertex.uv_dim[1] > vertex.uv_dim[0]) as usize;
+    let scale = vertex.dim[axis] as f32 / vertex.uv_dim[axis] as f32;
+    let scale = match vertex.content_type_with_srgb[0] & LINEAR_FILTER_FLAG {
+        0 => scale.round().max(1.0),
+        _ => scale,
+    };
+
+    // Undo clipping to find the position of the whole image
+    let left = vertex.pos[0] as f32 - (vertex.uv[0] - glyph_ref.origin[0]) as f32 * scale;
+    let top = vertex.pos[1] as f32 - (vertex.uv[1] - glyph_ref.origin[1]) as f32 * scale;
+
+    let outline = GlyphOutline {
+        font_id: key.font_id,
+        glyph_id: key.glyph_id,
+        font_size: f32::from_bits(key.font_size_bits) * scale,
+        x: left - (details.left as f32 - key.x_bin.as_float()) * scale,
+        y: top + (details.top as f32 + key.y_bin.as_float()) * scale,
+    };
+    Some((outline, key, scale))
+}
+
+/// Returns the outline of the glyph of `key` like cosmic-text's [`SwashCache`] rasterizes it,
+/// scaled by `scale` around its origin at `x` and `y`.
+fn outline_path(
+    font_system: &mut FontSystem,
+    context: &mut ScaleContext,
+    key: CacheKey,
+    x: f32,
+    y: f32,
+    scale: f32,
+) -> Option<Vec<PathCommand>> {
+    let font = font_system.get_font(key.font_id)?;
+    let mut outline = context
+        .builder(font.as_swash())
+        .size(f32::from_bits(key.font_size_bits))
+        .hint(true)
+        .build()
+        .scale_outline(key.glyph_id)?;
+    if key.flags.contains(CacheKeyFlags::FAKE_ITALIC) {
+        outline.transform(&Transform::skew(
+            Angle::from_degrees(14.0),
+            Angle::from_degrees(0.0),
+        ));
+    }
+
+    let point = |point: Vector| [x + point.x * scale, y - point.y * scale];
+    let path = outline
+        .path()
+        .commands()
+        .map(|command| match command {
+            Command::MoveTo(to) => PathCommand::MoveTo(point(to)),
+            Command::LineTo(to) => PathCommand::LineTo(point(to)),
+            Command::QuadTo(control, to) => PathCommand::QuadTo(point(control), point(to)),
+            Command::CurveTo(first, second, to) => {
+                PathCommand::CurveTo(point(first), point(second), point(to))
+            }
+            Command::Close => PathCommand::Close,
+        })
+        .collect();
+    Some(path)
+}
+
+/// The atlas entry referenced by a prepared glyph.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GlyphRef {
+    key: GlyphonCacheKey,
+    /// The position of the glyph in the atlas when it was prepared
+    origin: [u16; 2],
+    generation: u64,
+}
+
+/// The prepared state of a [`TextRenderer`], captured with [`TextRenderer::snapshot`].
+#[derive(Debug, Clone)]
+pub struct PreparedSnapshot {
+    vertices: Vec<GlyphToRender>,
+    glyphs: Vec<GlyphRef>,
+    /// The pick IDs of the glyphs, which may be fewer than the glyphs if pick IDs weren't
+    /// enabled for all of them
+    pick_ids: Vec<u32>,
+}
+
+impl PreparedSnapshot {
+    /// Returns the number of glyphs in the snapshot.
+    pub fn glyph_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Returns a snapshot of a renderer without prepared glyphs.
+    pub(crate) fn empty() -> Self {
+        Self {
+            vertices: Vec::new(),
+            glyphs: Vec::new(),
+            pick_ids: Vec::new(),
+        }
+    }
+
+    /// Appends the glyphs of `other`, which are drawn after the glyphs of this snapshot.
+    pub(crate) fn extend(&mut self, other: &PreparedSnapshot) {
+        self.pick_ids.resize(self.vertices.len(), 0);
+        self.pick_ids.extend_from_slice(&other.pick_ids);
+        self.vertices.extend_from_slice(&other.vertices);
+        self.glyphs.extend_from_slice(&other.glyphs);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum GlyphonCacheKey {
+    Text(cosmic_text::CacheKey),
+    /// A color text glyph that is rasterized as a mask
+    Monochrome(cosmic_text::CacheKey),
+    /// A text glyph rasterized with the options with the hash `options`, see
+    /// [`TextRenderer::set_metadata_to_raster_options`]
+    Rasterized {
+        key: cosmic_text::CacheKey,
+        options: u64,
+        monochrome: bool,
+    },
+    Custom(CustomGlyphCacheKey),
+    Decoration(DecorationPattern),
+    /// Drawn in place of custom glyphs that couldn't be rasterized
+    Placeholder(CustomGlyphPlaceholder),
+    /// A glyph given to [`TextRenderer::prepare_positioned`]
+    Positioned(u64),
+    /// A strip of a line baked into a single image, see [`TextRenderer::set_line_strips`]
+    LineStrip {
+        line: u64,
+        strip: u16,
+    },
+}
+
+/// Converts an image rasterized from a font to the image cached in the atlas.
+fn text_glyph_image(image: SwashImage, monochrome: bool) -> GlyphImage {
+    let (content_type, data) = match image.content {
+        SwashContent::Color if monochrome => (ContentType::Mask, color_to_monochrome(&image.data)),
+        SwashContent::Color => (ContentType::Color, image.data),
+        SwashContent::Mask => (ContentType::Mask, image.data),
+        SwashContent::SubpixelMask => {
+            // Not implemented yet, but don't panic if this happens.
+            (ContentType::Mask, image.data)
+        }
+    };
+
+    GlyphImage {
+        content_type,
+        top: image.placement.top as i16,
+        left: image.placement.left as i16,
+        width: image.placement.width as u16,
+        height: image.placement.height as u16,
+        data,
+    }
+}
+
+/// Converts the rgba data of a color glyph to a mask of its shape.
+pub(crate) fn color_to_monochrome(data: &[u8]) -> Vec<u8> {
+    data.iter().skip(3).step_by(4).copied().collect()
+}
+
+/// The initial size of the vertex buffer, which it is never shrunk below
+const MIN_VERTEX_BUFFER_SIZE: u64 = 4096;
+
+fn next_copy_buffer_size(size: u64) -> u64 {
+    let align_mask = COPY_BUFFER_ALIGNMENT - 1;
+    ((size.next_power_of_two() + align_mask) & !align_mask).max(COPY_BUFFER_ALIGNMENT)
+}
+
+/// The size of the color override of a glyph, its color and whether it is overridden.
+const COLOR_OVERRIDE_SIZE: usize = mem::size_of::<[u32; 2]>();
+
+/// Returns the size of a buffer of color overrides for `glyphs` glyphs.
+fn color_override_buffer_size(glyphs: usize) -> u64 {
+    next_copy_buffer_size((glyphs.max(1) * COLOR_OVERRIDE_SIZE) as u64)
+}
+
+/// Returns the size of a buffer of pick IDs for `glyphs` glyphs.
+fn pick_id_buffer_size(glyphs: usize) -> u64 {
+    next_copy_buffer_size((glyphs.max(1) * mem::size_of::<u32>()) as u64)
+}
+
+fn vertex_bytes(vertices: &[GlyphToRender]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(
+            vertices as *const _ as *const u8,
+            std::mem::size_of_val(vertices),
+        )
+    }
+}
+
+fn create_vertex_buffer(
+    device: &Device,
+    label: &str,
+    size: u64,
+    contents: &[u8],
+    usage: BufferUsages,
+) -> Buffer {
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size,
+        usage,
+        mapped_at_creation: true,
     });
     buffer.slice(..).get_mapped_range_mut()[..contents.len()].copy_from_slice(contents);
     buffer.unmap();
-    (buffer, size)
+    buffer
 }
 
-fn zero_depth(_: usize) -> f32 {
-    0f32
+/// Binds as much of the vertex `buffer` as the device allows to be bound as a storage buffer,
+/// returning the bind group and the number of glyphs in the bound range.
+fn bind_vertex_pulling(
+    cache: &Cache,
+    device: &Device,
+    buffer: &Buffer,
+    buffer_size: u64,
+) -> (BindGroup, u32) {
+    let glyph_size = std::mem::size_of::<GlyphToRender>() as u64;
+    let max_size = device.limits().max_storage_buffer_binding_size as u64;
+    let size = buffer_size.min(max_size / glyph_size * glyph_size);
+
+    (
+        cache.create_vertex_pulling_bind_group(device, buffer, size),
+        (size / glyph_size) as u32,
+    )
+}
+
+/// Returns the layout runs of `text_area` that may be visible within its vertical bounds.
+pub(crate) fn visible_runs<'b>(
+    text_area: &TextArea<'b>,
+) -> impl Iterator<Item = cosmic_text::LayoutRun<'b>> + 'b {
+    let (top, scale, bounds) = (text_area.top, text_area.scale, text_area.bounds);
+    let is_run_visible = move |run: &cosmic_text::LayoutRun| {
+        let start_y = (top + run.line_top * scale) as i32;
+        let end_y = (top + (run.line_top + run.line_height) * scale) as i32;
+
+        start_y <= bounds.bottom && bounds.top <= end_y
+    };
+
+    text_area
+        .layout_runs()
+        .skip_while(move |run| !is_run_visible(run))
+        .take_while(move |run| is_run_visible(run))
+}
+
+/// The largest distance from the origin of physical positions, far beyond any render target.
+///
+/// Positions are clamped to it before they are split into pixels and subpixel offsets, which
+/// overflows for positions close to the range of `i32`. Undefined positions (e.g. from infinite
+/// scales) are moved to the origin.
+const MAX_COORDINATE: f32 = (1 << 30) as f32;
+
+fn clamp_coordinate(v: f32) -> f32 {
+    if v.is_nan() {
+        return 0.0;
+    }
+    v.clamp(-MAX_COORDINATE, MAX_COORDINATE)
+}
+
+/// Returns the physical position of a custom glyph at `x`, `y` and its subpixel offset.
+pub(crate) fn custom_glyph_position(
+    x: f32,
+    y: f32,
+    snap_to_physical_pixel: bool,
+) -> (i32, i32, SubpixelBin, SubpixelBin) {
+    let (x, y) = (clamp_coordinate(x), clamp_coordinate(y));
+    if snap_to_physical_pixel {
+        (
+            x.round() as i32,
+            y.round() as i32,
+            SubpixelBin::Zero,
+            SubpixelBin::Zero,
+        )
+    } else {
+        let (x, x_bin) = SubpixelBin::new(x);
+        let (y, y_bin) = SubpixelBin::new(y);
+        (x, y, x_bin, y_bin)
+    }
+}
+
+/// Returns the physical position of a glyph of `text_area` on the line with the baseline at
+/// `line_y` and how its image is scaled.
+pub(crate) fn physical_text_glyph(
+    glyph: &cosmic_text::LayoutGlyph,
+    text_area: &TextArea,
+    line_y: f32,
+    integer_scale: Option<u16>,
+    snapping: PixelSnapping,
+) -> (cosmic_text::PhysicalGlyph, GlyphScale) {
+    match integer_scale {
+        Some(integer_scale) => {
+            // Rasterize at the original size and repeat each pixel instead, so that pixel fonts
+            // stay crisp.
+            let mut physical_glyph = glyph.physical((0.0, 0.0), 1.0);
+            physical_glyph.x = (text_area.left as i32)
+                .saturating_add(physical_glyph.x.saturating_mul(integer_scale as i32));
+            physical_glyph.y = (text_area.top as i32)
+                .saturating_add((line_y * integer_scale as f32).round() as i32)
+                .saturating_add(physical_glyph.y.saturating_mul(integer_scale as i32));
+            (physical_glyph, GlyphScale::Integer(integer_scale))
+        }
+        None => (
+            snapped_physical_glyph(
+                glyph,
+                text_area.left,
+                text_area.top,
+                line_y,
+                text_area.scale,
+                snapping,
+            ),
+            GlyphScale::None,
+        ),
+    }
+}
+
+/// A glyph of the text of a text area to draw, positioned in physical pixels.
+pub(crate) struct TextGlyph {
+    pub(crate) physical: cosmic_text::PhysicalGlyph,
+    pub(crate) glyph_scale: GlyphScale,
+    pub(crate) color: Color,
+    pub(crate) metadata: usize,
+    /// The byte range of the cluster containing the glyph, relative to the start of the line
+    pub(crate) cluster: Range<usize>,
+}
+
+/// Decides which glyphs of the text are drawn and where, so that the regular renderer, the
+/// fallback renderer and dry runs draw the same glyphs.
+pub(crate) struct GlyphEmission<'a> {
+    pub(crate) snapping: PixelSnapping,
+    pub(crate) char_filter: Option<&'a (dyn Fn(char) -> bool + Send + Sync)>,
+    pub(crate) emoji_decomposer: Option<&'a mut EmojiDecomposer>,
+}
+
+impl GlyphEmission<'_> {
+    /// Replaces `glyphs` with the glyphs of `run` in `text_area`, which was already adjusted for
+    /// `integer_scale`.
+    pub(crate) fn text_glyphs(
+        &mut self,
+        run: &LayoutRun,
+        text_area: &TextArea,
+        integer_scale: Option<u16>,
+        font_system: &FontSystem,
+        glyphs: &mut Vec<TextGlyph>,
+    ) {
+        glyphs.clear();
+
+        for (glyph_index, glyph) in run.glyphs.iter().enumerate() {
+            if let Some(char_filter) = self.char_filter {
+                if !run.text[glyph.start..glyph.end].chars().all(char_filter) {
+                    continue;
+                }
+            }
+
+            let decomposed = self.emoji_decomposer.as_mut().and_then(|decomposer| {
+                decomposer.decompose(run.glyphs, glyph_index, run.text, font_system)
+            });
+            for glyph in decomposed.as_deref().unwrap_or(slice::from_ref(glyph)) {
+                let (physical, glyph_scale) =
+                    physical_text_glyph(glyph, text_area, run.line_y, integer_scale, self.snapping);
+
+                let color = glyph.color_opt.unwrap_or(text_area.default_color);
+                let color = monochrome_emoji_color(
+                    &run.text[glyph.start..glyph.end],
+                    text_area.monochrome_emoji_colors,
+                    color,
+                )
+                .unwrap_or(color);
+
+                glyphs.push(TextGlyph {
+                    physical,
+                    glyph_scale,
+                    color,
+                    metadata: glyph.metadata,
+                    cluster: glyph.start..glyph.end,
+                });
+            }
+        }
+    }
+}
+
+/// Returns the physical position of a glyph on the line with the baseline at `line_y`, relative
+/// to `left`, `top`.
+///
+/// Unlike [`cosmic_text::LayoutGlyph::physical`], the baseline is part of the offset of the glyph
+/// from the origin, so that it is snapped together with the glyph.
+pub(crate) fn snapped_physical_glyph(
+    glyph: &cosmic_text::LayoutGlyph,
+    left: f32,
+    top: f32,
+    line_y: f32,
+    scale: f32,
+    snapping: PixelSnapping,
+) -> cosmic_text::PhysicalGlyph {
+    let x_offset = glyph.font_size * glyph.x_offset;
+    let y_offset = glyph.font_size * glyph.y_offset;
+    let snapping = snapping.for_size(glyph.font_size * scale);
+
+    let (cache_key, x, y) = CacheKey::new(
+        glyph.font_id,
+        glyph.glyph_id,
+        glyph.font_size * scale,
+        (
+            clamp_coordinate(snapping.x.apply(left, (glyph.x + x_offset) * scale)),
+            clamp_coordinate(snapping.y.apply(top, (line_y + glyph.y - y_offset) * scale)),
+        ),
+        glyph.cache_key_flags,
+    );
+
+    cosmic_text::PhysicalGlyph { cache_key, x, y }
+}
+
+/// Returns the quads of a decoration as their left edge and the horizontal bounds they are
+/// clipped to.
+///
+/// The first visible copy of the pattern may be clipped on the left, so it gets a quad of its
+/// own. The remaining copies are repeated along a single quad, which is unbounded and has to be
+/// extended along the rest of the visible line.
+pub(crate) fn decoration_quads(
+    quad: &DecorationQuad,
+    animation: GlyphAnimation,
+    bounds_min_x: i32,
+    bounds_max_x: i32,
+) -> impl Iterator<Item = (i32, i32, i32)> {
+    let line_max_x = bounds_max_x.min(quad.x.saturating_add(quad.width));
+    // Lines ending left of the bounds are hidden, even if their copies of the pattern aren't
+    let visible = line_max_x > bounds_min_x;
+    let period = quad.period.map_or(i32::MAX, i32::from);
+
+    // The line may start far left of the bounds, beyond the range of `i32` from them
+    let skipped = (bounds_min_x as i64 - quad.x as i64).max(0) / period as i64;
+    let first_tile = (quad.x as i64 + skipped * period as i64) as i32;
+    let mut first = Some(first_tile);
+    let mut repeated = Some(first_tile.saturating_add(period));
+    if quad.period.is_none() {
+        repeated = None;
+    } else if animation == GlyphAnimation::MarchingAnts && first_tile >= bounds_min_x {
+        // Moving copies enter the line from the left, so all of them need to be part of the
+        // repeated quad
+        (first, repeated) = (None, first);
+    }
+
+    first
+        .map(|x| (x, bounds_min_x, line_max_x))
+        .into_iter()
+        .chain(repeated.map(|x| (x, i32::MIN, i32::MAX)))
+        .filter(move |&(x, ..)| visible && x < line_max_x)
+}
+
+fn push_run_infos(
+    run_infos: &mut Vec<RunInfo>,
+    text_area_index: usize,
+    text_area: &TextArea,
+    run: &cosmic_text::LayoutRun,
+) {
+    let direction_of = |rtl: bool| {
+        if rtl {
+            TextDirection::RightToLeft
+        } else {
+            TextDirection::LeftToRight
+        }
+    };
+    let top = (text_area.top + run.line_top * text_area.scale).floor() as i32;
+    let baseline = (text_area.top + run.line_y * text_area.scale).round() as i32;
+    let bottom = (text_area.top + (run.line_top + run.line_height) * text_area.scale).ceil() as i32;
+
+    if run.glyphs.is_empty() {
+        let x = (text_area.left + run.line_w * text_area.scale).round() as i32;
+        run_infos.push(RunInfo {
+            text_area: text_area_index,
+            line: run.line_i,
+            direction: direction_of(run.rtl),
+            byte_range: 0..0,
+            screen_rect: TextBounds {
+                left: x,
+                top,
+                right: x,
+                bottom,
+            },
+            baseline,
+        });
+        return;
+    }
+
+    for glyphs in run
+        .glyphs
+        .chunk_by(|a, b| a.level.is_rtl() == b.level.is_rtl())
+    {
+        let start = glyphs.iter().map(|glyph| glyph.start).min().unwrap_or(0);
+        let end = glyphs.iter().map(|glyph| glyph.end).max().unwrap_or(0);
+        let left = glyphs.iter().map(|glyph| glyph.x).fold(f32::MAX, f32::min);
+        let right = glyphs
+            .iter()
+            .map(|glyph| glyph.x + glyph.w)
+            .fold(f32::MIN, f32::max);
+
+        run_infos.push(RunInfo {
+            text_area: text_area_index,
+            line: run.line_i,
+            direction: direction_of(glyphs[0].level.is_rtl()),
+            byte_range: start..end,
+            screen_rect: TextBounds {
+                left: (text_area.left + left * text_area.scale).floor() as i32,
+                top,
+                right: (text_area.left + right * text_area.scale).ceil() as i32,
+                bottom,
+            },
+            baseline,
+        });
+    }
 }
 
-struct GetGlyphImageResult {
-    content_type: ContentType,
-    top: i16,
-    left: i16,
-    width: u16,
-    height: u16,
-    data: Vec<u8>,
+fn zero_depth(_: usize) -> f32 {
+    0f32
 }
 
-fn prepare_glyph<R>(
+/// A glyph to be prepared by [`prepare_glyph`] and where to place it.
+struct GlyphPlacement {
     x: i32,
     y: i32,
     line_y: f32,
-    color: Color,
+    color: GlyphColor,
     metadata: usize,
     cache_key: GlyphonCacheKey,
+    glyph_scale: GlyphScale,
+    scale_factor: f32,
+    /// The bounds the glyph is clipped to
+    bounds: TextBounds,
+}
+
+fn prepare_glyph(
+    placement: GlyphPlacement,
     atlas: &mut TextAtlas,
     device: &Device,
     queue: &Queue,
-    cache: &mut SwashCache,
-    font_system: &mut FontSystem,
-    scale_factor: f32,
-    bounds_min_x: i32,
-    bounds_min_y: i32,
-    bounds_max_x: i32,
-    bounds_max_y: i32,
-    get_glyph_image: impl FnOnce(
-        &mut SwashCache,
-        &mut FontSystem,
-        &mut R,
-    ) -> Option<GetGlyphImageResult>,
+    get_glyph_image: impl FnOnce() -> Option<GlyphImage>,
     mut metadata_to_depth: impl FnMut(usize) -> f32,
-    mut rasterize_custom_glyph: R,
-) -> Result<Option<GlyphToRender>, PrepareError>
-where
-    R: FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
-{
+) -> Result<Option<(GlyphToRender, GlyphRef)>, PrepareError> {
+    let GlyphPlacement {
+        x,
+        y,
+        line_y,
+        color,
+        metadata,
+        cache_key,
+        glyph_scale,
+        scale_factor,
+        bounds,
+    } = placement;
+
+    let mut page_flag = 0;
+    let partition = atlas.partition;
     let details = if let Some(details) = atlas.mask_atlas.glyph_cache.get(&cache_key) {
-        atlas.mask_atlas.glyphs_in_use.insert(cache_key);
+        *atlas.mask_atlas.glyphs_in_use.entry(cache_key).or_default() |= partition.bit();
+        atlas.mask_atlas.cache_hits += 1;
         details
     } else if let Some(details) = atlas.color_atlas.glyph_cache.get(&cache_key) {
-        atlas.color_atlas.glyphs_in_use.insert(cache_key);
+        *atlas
+            .color_atlas
+            .glyphs_in_use
+            .entry(cache_key)
+            .or_default() |= partition.bit();
+        atlas.color_atlas.cache_hits += 1;
         details
+    } else if let Some(page) = atlas
+        .compressed_color
+        .as_mut()
+        .filter(|page| page.glyphs.contains_key(&cache_key))
+    {
+        page.cache_hits += 1;
+        page_flag = COMPRESSED_PAGE_FLAG;
+        &page.glyphs[&cache_key]
     } else {
-        let Some(image) = (get_glyph_image)(cache, font_system, &mut rasterize_custom_glyph) else {
+        trace_span!("rasterize");
+
+        let Some(image) = (get_glyph_image)() else {
             return Ok(None);
         };
 
-        let should_rasterize = image.width > 0 && image.height > 0;
+        insert_glyph(cache_key, image, atlas, device, queue)?
+    };
 
-        let (gpu_cache, atlas_id, inner) = if should_rasterize {
-            let mut inner = atlas.inner_for_content_mut(image.content_type);
+    let (atlas_x, atlas_y, content_type) = match details.gpu_cache {
+        GpuCacheStatus::InAtlas { x, y, content_type } => (x, y, content_type),
+        GpuCacheStatus::SkipRasterization => return Ok(None),
+    };
+    let glyph_ref = GlyphRef {
+        key: cache_key,
+        origin: [atlas_x, atlas_y],
+        generation: details.generation,
+    };
 
-            // Find a position in the packer
-            let allocation = loop {
-                match inner.try_allocate(image.width as usize, image.height as usize) {
-                    Some(a) => break a,
-                    None => {
-                        if !atlas.grow(
-                            device,
-                            queue,
-                            font_system,
-                            cache,
-                            image.content_type,
-                            scale_factor,
-                            &mut rasterize_custom_glyph,
-                        ) {
-                            return Err(PrepareError::AtlasFull);
-                        }
+    let Some(placed) = place_glyph(
+        x,
+        y,
+        line_y,
+        scale_factor,
+        glyph_scale,
+        [details.left, details.top],
+        [details.width, details.height],
+        [atlas_x, atlas_y],
+        bounds,
+    ) else {
+        return Ok(None);
+    };
+
+    let depth = metadata_to_depth(metadata);
+
+    let filter_flags = match glyph_scale {
+        GlyphScale::Size(..) | GlyphScale::Factor(_) => LINEAR_FILTER_FLAG,
+        GlyphScale::None | GlyphScale::Integer(_) => 0,
+    };
+
+    let glyph_to_render = GlyphToRender {
+        pos: placed.pos,
+        dim: placed.dim,
+        uv: placed.uv,
+        uv_dim: placed.uv_dim,
+        color: color.packed(),
+        content_type_with_srgb: [
+            content_type as u16 | filter_flags | page_flag,
+            match atlas.color_mode {
+                ColorMode::Accurate => TextColorConversion::ConvertToLinear,
+                ColorMode::Web | ColorMode::Auto => TextColorConversion::None,
+            } as u16,
+        ],
+        depth,
+        repeat: [0, 0],
+    };
+
+    Ok(Some((glyph_to_render, glyph_ref)))
+}
 
-                        inner = atlas.inner_for_content_mut(image.content_type);
+/// Rasterizes `image` into the atlas as the glyph with `cache_key`, growing the atlas if needed.
+fn insert_glyph<'a>(
+    cache_key: GlyphonCacheKey,
+    image: GlyphImage,
+    atlas: &'a mut TextAtlas,
+    device: &Device,
+    queue: &Queue,
+) -> Result<&'a GlyphDetails, PrepareError> {
+    let partition = atlas.partition;
+    let should_rasterize = image.width > 0 && image.height > 0;
+    atlas.glyph_generation += 1;
+    let generation = atlas.glyph_generation;
+
+    let (gpu_cache, atlas_id, content_hash, inner) = if should_rasterize {
+        let content_hash = (atlas.deduplicate_custom_glyphs
+            && matches!(cache_key, GlyphonCacheKey::Custom(_)))
+        .then(|| image.content_hash());
+        let shared = content_hash.and_then(|hash| {
+            atlas
+                .inner_for_content_mut(image.content_type)
+                .share_image(hash)
+        });
+
+        let (atlas_id, x, y) = match shared {
+            Some(shared) => shared,
+            None => {
+                // Find a position in the packer
+                let (slot, x, y) = loop {
+                    match atlas
+                        .inner_for_content_mut(image.content_type)
+                        .try_allocate(image.width as usize, image.height as usize, partition)
+                    {
+                        Some(a) => break a,
+                        None => atlas.grow(device, queue, image.content_type)?,
                     }
+                };
+
+                let inner = atlas.inner_for_content_mut(image.content_type);
+                inner.queue_upload(
+                    x as u32,
+                    y as u32,
+                    image.width as u32,
+                    image.height as u32,
+                    image.data,
+                );
+
+                if let Some(hash) = content_hash {
+                    inner.insert_shared_image(hash, slot, x, y);
                 }
-            };
-            let atlas_min = allocation.rectangle.min;
-
-            queue.write_texture(
-                TexelCopyTextureInfo {
-                    texture: &inner.texture,
-                    mip_level: 0,
-                    origin: Origin3d {
-                        x: atlas_min.x as u32,
-                        y: atlas_min.y as u32,
-                        z: 0,
-                    },
-                    aspect: TextureAspect::All,
-                },
-                &image.data,
-                TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(image.width as u32 * inner.num_channels() as u32),
-                    rows_per_image: None,
-                },
-                Extent3d {
-                    width: image.width as u32,
-                    height: image.height as u32,
-                    depth_or_array_layers: 1,
-                },
-            );
 
-            (
-                GpuCacheStatus::InAtlas {
-                    x: atlas_min.x as u16,
-                    y: atlas_min.y as u16,
-                    content_type: image.content_type,
-                },
-                Some(allocation.id),
-                inner,
-            )
-        } else {
-            let inner = &mut atlas.color_atlas;
-            (GpuCacheStatus::SkipRasterization, None, inner)
-        };
-
-        inner.glyphs_in_use.insert(cache_key);
-        // Insert the glyph into the cache and return the details reference
-        inner.glyph_cache.get_or_insert(cache_key, || GlyphDetails {
-            width: image.width,
-            height: image.height,
-            gpu_cache,
-            atlas_id,
-            top: image.top,
-            left: image.left,
-        })
+                (slot, x, y)
+            }
+        };
+
+        (
+            GpuCacheStatus::InAtlas {
+                x,
+                y,
+                content_type: image.content_type,
+            },
+            Some(atlas_id),
+            content_hash,
+            atlas.inner_for_content_mut(image.content_type),
+        )
+    } else {
+        let inner = &mut atlas.color_atlas;
+        (GpuCacheStatus::SkipRasterization, None, None, inner)
     };
 
-    let mut x = x + details.left as i32;
-    let mut y = (line_y * scale_factor).round() as i32 + y - details.top as i32;
+    inner.mark_in_use(cache_key, partition);
+    inner.cache_misses += 1;
+    // Insert the glyph into the cache and return the details reference
+    let details = inner.glyph_cache.get_or_insert(cache_key, || GlyphDetails {
+        width: image.width,
+        height: image.height,
+        gpu_cache,
+        atlas_id,
+        content_hash,
+        top: image.top,
+        left: image.left,
+        generation,
+        partition,
+    });
 
-    let (mut atlas_x, mut atlas_y, content_type) = match details.gpu_cache {
-        GpuCacheStatus::InAtlas { x, y, content_type } => (x, y, content_type),
-        GpuCacheStatus::SkipRasterization => return Ok(None),
+    Ok(details)
+}
+
+/// The quad of a glyph on screen and the part of its image in the atlas it shows, after clipping.
+pub(crate) struct PlacedGlyph {
+    pub(crate) pos: [i32; 2],
+    pub(crate) dim: [u16; 2],
+    pub(crate) uv: [u16; 2],
+    pub(crate) uv_dim: [u16; 2],
+}
+
+/// Places the image of a glyph with the given `offset` (left, top) and `size` at `x`, `y` and
+/// clips it to the bounds, returning `None` if it is outside of them.
+///
+/// `uv` is the position of the image in the atlas.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn place_glyph(
+    x: i32,
+    y: i32,
+    line_y: f32,
+    scale_factor: f32,
+    glyph_scale: GlyphScale,
+    offset: [i16; 2],
+    size: [u16; 2],
+    uv: [u16; 2],
+    bounds: TextBounds,
+) -> Option<PlacedGlyph> {
+    let y = ((line_y * scale_factor).round() as i32).saturating_add(y);
+    let ([x, y], [quad_width, quad_height]) = glyph_quad(x, y, glyph_scale, offset, size);
+    let to_atlas_x = |v: i32| (v as i64 * size[0] as i64 / quad_width as i64) as u16;
+    let to_atlas_y = |v: i32| (v as i64 * size[1] as i64 / quad_height as i64) as u16;
+
+    let clipped = clip_quad([x, y], [quad_width as i32, quad_height as i32], bounds)?;
+    let [width, height] = clipped.size;
+    let [shift_x, shift_y] = clipped.shift;
+
+    let placed = PlacedGlyph {
+        pos: clipped.pos,
+        dim: [width as u16, height as u16],
+        uv: [uv[0] + to_atlas_x(shift_x), uv[1] + to_atlas_y(shift_y)],
+        uv_dim: [to_atlas_x(width).max(1), to_atlas_y(height).max(1)],
     };
 
-    let mut width = details.width as i32;
-    let mut height = details.height as i32;
+    // The texture coordinates move along with the clipped edges, so the visible part of the quad
+    // never samples outside of the image
+    debug_assert!(placed.uv[0] + placed.uv_dim[0] <= uv[0] + size[0].max(1));
+    debug_assert!(placed.uv[1] + placed.uv_dim[1] <= uv[1] + size[1].max(1));
 
-    // Starts beyond right edge or ends beyond left edge
-    let max_x = x + width;
-    if x > bounds_max_x || max_x < bounds_min_x {
-        return Ok(None);
+    Some(placed)
+}
+
+/// Returns the position and size on screen of the image of a glyph with the given `offset` (left,
+/// top) and `size` at `x`, `y`, before it is clipped.
+///
+/// The size differs from the size of the image for scaled glyphs.
+pub(crate) fn glyph_quad(
+    x: i32,
+    y: i32,
+    glyph_scale: GlyphScale,
+    offset: [i16; 2],
+    size: [u16; 2],
+) -> ([i32; 2], [u16; 2]) {
+    let offset = match glyph_scale {
+        GlyphScale::Integer(integer_scale) => offset.map(|v| v as i32 * integer_scale as i32),
+        GlyphScale::Factor(factor) => offset.map(|v| (v as f32 * factor).round() as i32),
+        GlyphScale::None | GlyphScale::Size(..) => offset.map(i32::from),
+    };
+
+    let size = match glyph_scale {
+        GlyphScale::None => size,
+        GlyphScale::Size(width, height) => [width, height],
+        GlyphScale::Integer(integer_scale) => size.map(|v| v.saturating_mul(integer_scale)),
+        GlyphScale::Factor(factor) => size.map(|v| ((v as f32 * factor).round() as u16).max(1)),
+    };
+
+    // Text areas far off screen may be close to the range of `i32`, so saturate instead of
+    // overflowing and wrapping around to the screen
+    (
+        [x.saturating_add(offset[0]), y.saturating_sub(offset[1])],
+        size,
+    )
+}
+
+/// A quad clipped by [`clip_quad`].
+pub(crate) struct ClippedQuad {
+    /// The position of the visible part of the quad
+    pub(crate) pos: [i32; 2],
+    /// The size of the visible part of the quad, which is never empty
+    pub(crate) size: [i32; 2],
+    /// How far the top left corner of the quad moved right and down, which texture coordinates
+    /// have to be shifted by
+    pub(crate) shift: [i32; 2],
+}
+
+/// Clips the quad at `pos` with `size` to `bounds`, returning `None` if no part of it is visible.
+///
+/// Bounds may be empty or inverted (e.g. bounds starting beyond the right edge of the screen
+/// clamped to the screen), in which case nothing is visible.
+pub(crate) fn clip_quad(pos: [i32; 2], size: [i32; 2], bounds: TextBounds) -> Option<ClippedQuad> {
+    let [x, y] = pos;
+    let left = x.max(bounds.left);
+    let top = y.max(bounds.top);
+    let right = x.saturating_add(size[0]).min(bounds.right);
+    let bottom = y.saturating_add(size[1]).min(bounds.bottom);
+    if left >= right || top >= bottom {
+        return None;
     }
 
-    // Starts beyond bottom edge or ends beyond top edge
-    let max_y = y + height;
-    if y > bounds_max_y || max_y < bounds_min_y {
-        return Ok(None);
+    let clipped = ClippedQuad {
+        pos: [left, top],
+        size: [right.saturating_sub(left), bottom.saturating_sub(top)],
+        shift: [left.saturating_sub(x), top.saturating_sub(y)],
+    };
+
+    debug_assert!(clipped.size[0] > 0 && clipped.size[0] <= size[0]);
+    debug_assert!(clipped.size[1] > 0 && clipped.size[1] <= size[1]);
+    debug_assert!(left >= bounds.left && top >= bounds.top);
+    debug_assert!(right <= bounds.right && bottom <= bounds.bottom);
+
+    Some(clipped)
+}
+
+fn push_font_fallbacks<'a>(
+    font_fallbacks: &mut Vec<FontFallback>,
+    reported: &mut HashSet<(&'a str, fontdb::ID), BuildHasherDefault<FxHasher>>,
+    font_system: &FontSystem,
+    text_area_index: usize,
+    buffer: &cosmic_text::Buffer,
+    run: &cosmic_text::LayoutRun<'a>,
+) {
+    let db = font_system.db();
+    let attrs_list = buffer.lines[run.line_i].attrs_list();
+
+    for glyph in run.glyphs.iter() {
+        let text = &run.text[glyph.start..glyph.end];
+        if text.chars().all(char::is_whitespace) {
+            continue;
+        }
+
+        let attrs = attrs_list.get_span(glyph.start);
+        let requested_family = db.family_name(&attrs.family);
+        let face = db.face(glyph.font_id);
+        let is_requested_family = face.is_some_and(|face| {
+            face.families
+                .iter()
+                .any(|(family, _)| family == requested_family)
+        });
+        // cosmic-text uses glyph 0 of the last font it tried for text that no font supports
+        let missing = glyph.glyph_id == 0;
+        if (is_requested_family && !missing) || !reported.insert((text, glyph.font_id)) {
+            continue;
+        }
+
+        font_fallbacks.push(FontFallback {
+            text_area: text_area_index,
+            line: run.line_i,
+            text: text.to_owned(),
+            requested_family: requested_family.to_owned(),
+            font_id: glyph.font_id,
+            font_family: face
+                .and_then(|face| face.families.first())
+                .map(|(family, _)| family.clone()),
+            missing,
+        });
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Decoration, DecorationStyle, PixelSnap};
+    use proptest::prelude::*;
+
+    const EXTREMES: [f32; 9] = [
+        0.0,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::NAN,
+        f32::MAX,
+        f32::MIN,
+        i32::MAX as f32,
+        i32::MIN as f32,
+        1e30,
+    ];
 
-    // Clip left ege
-    if x < bounds_min_x {
-        let right_shift = bounds_min_x - x;
+    const SCREEN: TextBounds = TextBounds {
+        left: 0,
+        top: 0,
+        right: 1000,
+        bottom: 1000,
+    };
 
-        x = bounds_min_x;
-        width = max_x - bounds_min_x;
-        atlas_x += right_shift as u16;
+    fn layout_glyph(x: f32, font_size: f32) -> cosmic_text::LayoutGlyph {
+        cosmic_text::LayoutGlyph {
+            start: 0,
+            end: 1,
+            font_size,
+            line_height_opt: None,
+            font_id: fontdb::ID::dummy(),
+            glyph_id: 1,
+            x,
+            y: 0.0,
+            w: font_size / 2.0,
+            level: 0.into(),
+            x_offset: 0.0,
+            y_offset: 0.0,
+            color_opt: None,
+            metadata: 0,
+            cache_key_flags: CacheKeyFlags::empty(),
+        }
     }
 
-    // Clip right edge
-    if x + width > bounds_max_x {
-        width = bounds_max_x - x;
+    fn assert_within_max_coordinate(v: i32) {
+        assert!(
+            v.unsigned_abs() <= MAX_COORDINATE as u32 + 1,
+            "{v} wasn't clamped"
+        );
     }
 
-    // Clip top edge
-    if y < bounds_min_y {
-        let bottom_shift = bounds_min_y - y;
+    #[test]
+    fn clamp_coordinate_extremes() {
+        for v in EXTREMES {
+            let clamped = clamp_coordinate(v);
+            assert!(
+                clamped.abs() <= MAX_COORDINATE,
+                "{v} was clamped to {clamped}"
+            );
+            // Splitting the position into pixels and subpixel offsets must not overflow
+            let (pixels, _) = SubpixelBin::new(clamped);
+            assert_within_max_coordinate(pixels);
+        }
+        assert_eq!(clamp_coordinate(f32::NAN), 0.0);
+        assert_eq!(clamp_coordinate(-12.25), -12.25);
+    }
 
-        y = bounds_min_y;
-        height = max_y - bounds_min_y;
-        atlas_y += bottom_shift as u16;
+    #[test]
+    fn snapped_physical_glyph_extremes() {
+        let snappings = [
+            PixelSnapping::default(),
+            PixelSnapping::uniform(PixelSnap::Round),
+            PixelSnapping::uniform(PixelSnap::Floor),
+        ];
+        for glyph_x in [0.0, 100.0, -100.0, 1e20] {
+            let glyph = layout_glyph(glyph_x, 16.0);
+            for (left, top, scale) in EXTREMES.iter().flat_map(|&left| {
+                EXTREMES.iter().flat_map(move |&top| {
+                    [1.0, 2.5]
+                        .into_iter()
+                        .chain(EXTREMES)
+                        .map(move |scale| (left, top, scale))
+                })
+            }) {
+                for snapping in snappings {
+                    let physical = snapped_physical_glyph(&glyph, left, top, 12.0, scale, snapping);
+                    assert_within_max_coordinate(physical.x);
+                    assert_within_max_coordinate(physical.y);
+                }
+            }
+        }
     }
 
-    // Clip bottom edge
-    if y + height > bounds_max_y {
-        height = bounds_max_y - y;
+    #[test]
+    fn custom_glyph_position_extremes() {
+        for (x, y) in EXTREMES.iter().flat_map(|&x| EXTREMES.map(|y| (x, y))) {
+            for snap in [false, true] {
+                let (x, y, ..) = custom_glyph_position(x, y, snap);
+                assert_within_max_coordinate(x);
+                assert_within_max_coordinate(y);
+            }
+        }
     }
 
-    let depth = metadata_to_depth(metadata);
+    #[test]
+    fn place_glyph_near_i32_limits() {
+        let place = |x: i32, y: i32, line_y: f32, glyph_scale: GlyphScale| {
+            place_glyph(
+                x,
+                y,
+                line_y,
+                1.0,
+                glyph_scale,
+                [-5, 10],
+                [20, 20],
+                [100, 100],
+                SCREEN,
+            )
+        };
+        let scales = [
+            GlyphScale::None,
+            GlyphScale::Size(u16::MAX, u16::MAX),
+            GlyphScale::Integer(u16::MAX),
+            GlyphScale::Factor(1000.0),
+        ];
+        // Scaled offsets of infinite factors saturate, which may move the glyph anywhere, but
+        // mustn't panic
+        let undefined_scales = [
+            GlyphScale::Factor(f32::MAX),
+            GlyphScale::Factor(f32::INFINITY),
+            GlyphScale::Factor(f32::NAN),
+        ];
 
-    Ok(Some(GlyphToRender {
-        pos: [x, y],
-        dim: [width as u16, height as u16],
-        uv: [atlas_x, atlas_y],
-        color: color.0,
-        content_type_with_srgb: [
-            content_type as u16,
-            match atlas.color_mode {
-                ColorMode::Accurate => TextColorConversion::ConvertToLinear,
-                ColorMode::Web => TextColorConversion::None,
-            } as u16,
-        ],
-        depth,
-    }))
+        // Glyphs far off screen stay off screen instead of wrapping around onto it
+        for glyph_scale in scales {
+            for (x, y) in [
+                (i32::MAX, 0),
+                (i32::MAX - 10, 0),
+                (0, i32::MAX),
+                (0, i32::MAX - 10),
+                (i32::MAX, i32::MAX),
+            ] {
+                assert!(
+                    place(x, y, 0.0, glyph_scale).is_none(),
+                    "{x} {y} {glyph_scale:?}"
+                );
+            }
+            for line_y in [f32::INFINITY, f32::MAX, 1e30] {
+                assert!(place(0, 0, line_y, glyph_scale).is_none());
+            }
+        }
+        for (x, y) in [(i32::MIN, 0), (0, i32::MIN), (i32::MIN, i32::MIN)] {
+            assert!(place(x, y, 0.0, GlyphScale::None).is_none());
+        }
+        for line_y in [f32::NEG_INFINITY, f32::MIN] {
+            assert!(place(0, 0, line_y, GlyphScale::None).is_none());
+        }
+
+        // Undefined line positions don't move the glyph
+        let placed = place(50, 50, f32::NAN, GlyphScale::None).unwrap();
+        assert_eq!(placed.pos, [45, 40]);
+        assert_eq!(placed.dim, [20, 20]);
+
+        // Huge quads starting off screen are clipped to it, with texture coordinates inside of
+        // the image
+        for glyph_scale in scales.into_iter().chain(undefined_scales) {
+            for (x, y) in [
+                (i32::MIN, i32::MIN),
+                (i32::MAX, i32::MAX),
+                (-70000, -70000),
+                (0, 0),
+            ] {
+                if let Some(placed) = place(x, y, 0.0, glyph_scale) {
+                    assert!(placed.pos[0] >= SCREEN.left && placed.pos[1] >= SCREEN.top);
+                    assert!(placed.pos[0] + placed.dim[0] as i32 <= SCREEN.right);
+                    assert!(placed.pos[1] + placed.dim[1] as i32 <= SCREEN.bottom);
+                    assert!(placed.uv[0] + placed.uv_dim[0] <= 120);
+                    assert!(placed.uv[1] + placed.uv_dim[1] <= 120);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decoration_quads_near_i32_limits() {
+        let quad = |x: i32, width: i32, period: Option<u16>| DecorationQuad {
+            x,
+            y: 0,
+            width,
+            height: 1,
+            pattern: DecorationPattern::Solid,
+            tile: 4,
+            period,
+        };
+
+        for animation in [GlyphAnimation::None, GlyphAnimation::MarchingAnts] {
+            // A repeated line starting at the far left is skipped to the first copy on screen
+            let quads: Vec<_> =
+                decoration_quads(&quad(i32::MIN + 1000, i32::MAX, Some(7)), animation, 0, 100)
+                    .collect();
+            assert!(!quads.is_empty());
+            for &(x, min_x, max_x) in &quads {
+                assert!(x > -7 && x < 100, "{x} is off screen");
+                assert!(min_x <= max_x);
+            }
+
+            // Lines starting at the far right are never visible
+            for period in [None, Some(7), Some(u16::MAX)] {
+                let quads = decoration_quads(&quad(i32::MAX, i32::MAX, period), animation, 0, 100);
+                assert_eq!(quads.count(), 0);
+                let quads =
+                    decoration_quads(&quad(i32::MAX - 3, i32::MAX, period), animation, 0, 100);
+                assert_eq!(quads.count(), 0);
+            }
+
+            // Bounds at the limits of `i32` don't overflow
+            let quads = decoration_quads(
+                &quad(i32::MIN, i32::MAX, Some(u16::MAX)),
+                animation,
+                i32::MIN,
+                i32::MAX,
+            );
+            assert!(quads.count() > 0);
+            let quads = decoration_quads(
+                &quad(0, i32::MAX, Some(3)),
+                animation,
+                i32::MAX - 1,
+                i32::MAX,
+            );
+            assert!(quads.into_iter().all(|(x, ..)| x < i32::MAX));
+        }
+
+        // Lines without a period are stretched along the visible part of the line
+        let quads: Vec<_> = decoration_quads(
+            &quad(i32::MIN + 1000, i32::MAX, None),
+            GlyphAnimation::None,
+            0,
+            100,
+        )
+        .collect();
+        assert_eq!(quads, [(i32::MIN + 1000, 0, 100)]);
+        // Lines ending left of the bounds are hidden
+        let quads = decoration_quads(
+            &quad(i32::MIN, i32::MAX, None),
+            GlyphAnimation::None,
+            0,
+            100,
+        );
+        assert_eq!(quads.count(), 0);
+    }
+
+    #[test]
+    fn decoration_quad_extremes() {
+        for style in [
+            DecorationStyle::Solid,
+            DecorationStyle::Dashed {
+                dash: 4.0,
+                gap: 2.0,
+            },
+            DecorationStyle::Dotted { gap: 1.0 },
+        ] {
+            let decoration = Decoration {
+                left: 10.0,
+                top: 10.0,
+                width: 100.0,
+                thickness: 1.0,
+                color: None,
+                style,
+                metadata: 0,
+            };
+            for (left, top, scale) in EXTREMES.iter().flat_map(|&left| {
+                EXTREMES
+                    .iter()
+                    .flat_map(move |&top| EXTREMES.map(move |scale| (left, top, scale)))
+            }) {
+                if let Some(quad) = decoration.quad(left, top, scale) {
+                    assert!(quad.width > 0 && quad.tile > 0);
+                    for animation in [GlyphAnimation::None, GlyphAnimation::MarchingAnts] {
+                        for (x, min_x, max_x) in decoration_quads(&quad, animation, 0, 1000) {
+                            assert!(x < 1000 && min_x <= max_x);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn visible_runs_apply_scale() {
+        let mut db = fontdb::Database::new();
+        db.load_font_data(include_bytes!("../examples/Inter-Bold.ttf").to_vec());
+        let mut font_system = FontSystem::new_with_locale_and_db("en-US".into(), db);
+        let mut buffer =
+            cosmic_text::Buffer::new(&mut font_system, cosmic_text::Metrics::new(10.0, 20.0));
+        buffer.set_text(
+            &mut font_system,
+            &"line\n".repeat(10),
+            cosmic_text::Attrs::new(),
+            cosmic_text::Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut font_system, false);
+
+        let visible_lines = |scale: f32, top: i32, bottom: i32| -> Vec<usize> {
+            let bounds = TextBounds {
+                bottom,
+                top,
+                ..SCREEN
+            };
+            let text_area = TextArea::new(&buffer, 0.0, 0.0, scale, bounds, Color::rgb(0, 0, 0));
+            visible_runs(&text_area).map(|run| run.line_i).collect()
+        };
+
+        // Lines are 20 pixels high at a scale of 1
+        assert_eq!(visible_lines(1.0, 0, 50), [0, 1, 2]);
+        assert_eq!(visible_lines(0.5, 0, 50), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(visible_lines(2.0, 0, 50), [0, 1]);
+        assert_eq!(visible_lines(2.0, 90, 130), [2, 3]);
+        assert_eq!(visible_lines(0.5, 90, 130), [8, 9]);
+    }
+
+    #[test]
+    fn render_rejects_other_atlases() {
+        let Some((device, queue)) = crate::text_atlas::tests::device() else {
+            return;
+        };
+        let cache = Cache::new(&device);
+        let format = TextureFormat::Rgba8UnormSrgb;
+        let mut atlas = TextAtlas::new(&device, &queue, &cache, format);
+        let mut other_atlas = TextAtlas::new(&device, &queue, &cache, format);
+        let renderer = TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
+        let other = TextRenderer::new(&mut other_atlas, &device, MultisampleState::default(), None);
+        let viewport = Viewport::new(&device, &cache);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations::default(),
+            })],
+            ..Default::default()
+        });
+
+        assert_eq!(renderer.render(&atlas, &viewport, &mut pass), Ok(()));
+        assert_eq!(
+            other.render(&atlas, &viewport, &mut pass),
+            Err(RenderError::RemovedFromAtlas)
+        );
+        assert_eq!(
+            TextRenderer::render_batch([&renderer, &other], &atlas, &viewport, &mut pass),
+            Err(RenderError::RemovedFromAtlas)
+        );
+        assert_eq!(
+            TextRenderer::render_batch([&other], &other_atlas, &viewport, &mut pass),
+            Ok(())
+        );
+    }
+
+    /// Any bounds, including empty and inverted bounds
+    fn bounds() -> impl Strategy<Value = TextBounds> {
+        let edge = prop_oneof![-2000..2000, Just(i32::MIN), Just(i32::MAX), any::<i32>(),];
+        (edge.clone(), edge.clone(), edge.clone(), edge).prop_map(|(left, top, right, bottom)| {
+            TextBounds {
+                left,
+                top,
+                right,
+                bottom,
+            }
+        })
+    }
+
+    /// Any position, mostly close to the bounds
+    fn position() -> impl Strategy<Value = i32> {
+        prop_oneof![-2000..2000, Just(i32::MIN), Just(i32::MAX), any::<i32>(),]
+    }
+
+    proptest! {
+        #[test]
+        fn clipped_quad_stays_inside_bounds_and_quad(
+            x in position(),
+            y in position(),
+            width in 0..=u16::MAX as i32,
+            height in 0..=u16::MAX as i32,
+            bounds in bounds(),
+        ) {
+            let quad_right = x as i64 + width as i64;
+            let quad_bottom = y as i64 + height as i64;
+            let visible = (x.max(bounds.left) as i64) < quad_right.min(bounds.right as i64)
+                && (y.max(bounds.top) as i64) < quad_bottom.min(bounds.bottom as i64);
+
+            match clip_quad([x, y], [width, height], bounds) {
+                None => prop_assert!(!visible),
+                Some(clipped) => {
+                    prop_assert!(visible);
+                    let [left, top] = clipped.pos;
+                    let [clipped_width, clipped_height] = clipped.size;
+
+                    // Width and height never underflow
+                    prop_assert!(clipped_width > 0 && clipped_width <= width);
+                    prop_assert!(clipped_height > 0 && clipped_height <= height);
+
+                    // The visible part is inside of the bounds and the quad
+                    let right = left as i64 + clipped_width as i64;
+                    let bottom = top as i64 + clipped_height as i64;
+                    prop_assert!(left >= bounds.left && right <= bounds.right as i64);
+                    prop_assert!(top >= bounds.top && bottom <= bounds.bottom as i64);
+                    prop_assert!(left >= x && right <= quad_right);
+                    prop_assert!(top >= y && bottom <= quad_bottom);
+
+                    // Texture coordinates shift by as much as the position moved
+                    prop_assert_eq!(clipped.shift, [left - x, top - y]);
+                    prop_assert!(clipped.shift[0] < width && clipped.shift[1] < height);
+                }
+            }
+        }
+
+        #[test]
+        fn placed_glyph_samples_inside_its_image(
+            x in position(),
+            y in position(),
+            offset in any::<[i16; 2]>(),
+            // Images are inside of the atlas, which is at most 16384 pixels large
+            size in [0..8192u16, 0..8192u16],
+            uv in [0..8192u16, 0..8192u16],
+            bounds in bounds(),
+        ) {
+            let Some(placed) = place_glyph(x, y, 0.0, 1.0, GlyphScale::None, offset, size, uv, bounds) else {
+                return Ok(());
+            };
+
+            // Unscaled glyphs sample exactly the pixels they cover
+            let left = x.saturating_add(offset[0] as i32);
+            let top = y.saturating_sub(offset[1] as i32);
+            prop_assert_eq!(placed.uv[0] - uv[0], (placed.pos[0] - left) as u16);
+            prop_assert_eq!(placed.uv[1] - uv[1], (placed.pos[1] - top) as u16);
+            prop_assert_eq!(placed.uv_dim, placed.dim);
+            prop_assert!(placed.uv[0] as u32 + placed.uv_dim[0] as u32 <= uv[0] as u32 + size[0] as u32);
+            prop_assert!(placed.uv[1] as u32 + placed.uv_dim[1] as u32 <= uv[1] as u32 + size[1] as u32);
+        }
+    }
 }
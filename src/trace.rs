@@ -0,0 +1,11 @@
+//! Optional instrumentation with [`tracing`](https://docs.rs/tracing), enabled with the `tracing`
+//! feature.
+
+/// Enters a trace-level span until the end of the enclosing block if the `tracing` feature is
+/// enabled.
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!($($args)*).entered();
+    };
+}
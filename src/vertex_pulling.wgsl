@@ -0,0 +1,34 @@
+// Appended to `shader.wgsl` for renderers that read glyphs from a storage buffer instead of
+// instance-stepped vertex attributes.
+
+// Matches the layout of `GlyphToRender`, which isn't aligned to 8 bytes
+struct Glyph {
+    x: i32,
+    y: i32,
+    dim: u32,
+    uv: u32,
+    uv_dim: u32,
+    color: u32,
+    content_type_with_srgb: u32,
+    depth: f32,
+    repeat: u32,
+}
+
+@group(2) @binding(0)
+var<storage, read> glyphs: array<Glyph>;
+
+fn pulled_glyph(vertex_idx: u32, instance_idx: u32) -> VertexInput {
+    let glyph = glyphs[instance_idx];
+
+    return VertexInput(
+        vertex_idx,
+        vec2<i32>(glyph.x, glyph.y),
+        glyph.dim,
+        glyph.uv,
+        glyph.color,
+        glyph.content_type_with_srgb,
+        glyph.depth,
+        glyph.uv_dim,
+        glyph.repeat,
+    );
+}
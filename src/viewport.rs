@@ -1,5 +1,5 @@
-use crate::{Cache, Params, Resolution};
-use std::{mem, slice};
+use crate::{Cache, Color, Params, Resolution};
+use std::{mem, slice, time::Duration};
 use wgpu::{BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue};
 
 /// Controls the visible area of all text for a given renderer. Any text outside of the visible
@@ -11,11 +11,19 @@ use wgpu::{BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue};
 #[derive(Debug)]
 pub struct Viewport {
     params: Params,
+    color_space: ColorSpace,
+    virtual_resolution: Option<Resolution>,
     params_buffer: Buffer,
     pub(crate) bind_group: BindGroup,
 }
 
 impl Viewport {
+    /// The time passed to the shader wraps around after this many seconds.
+    const TIME_WRAP_SECONDS: u64 = 3600;
+
+    /// The number of colors in the palette of a viewport, see [`Viewport::set_palette`].
+    pub const PALETTE_SIZE: usize = 16;
+
     /// Creates a new `Viewport` with the given `device` and `cache`.
     pub fn new(device: &Device, cache: &Cache) -> Self {
         let params = Params {
@@ -23,11 +31,19 @@ impl Viewport {
                 width: 0,
                 height: 0,
             },
-            _pad: [0, 0],
+            depth_range: DepthRange::default(),
+            color_matrix: ColorSpace::Srgb.color_matrix(),
+            convert_color_space: 0,
+            time: 0.0,
+            dither: 0,
+            letterbox_scale: 1,
+            letterbox_offset: [0; 2],
+            _pad: [0; 2],
+            palette: [[0; 4]; 4],
         };
 
         let params_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("glyphon params"),
+            label: Some(&cache.label("params")),
             size: mem::size_of::<Params>() as u64,
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
             mapped_at_creation: false,
@@ -37,6 +53,8 @@ impl Viewport {
 
         Self {
             params,
+            color_space: ColorSpace::Srgb,
+            virtual_resolution: None,
             params_buffer,
             bind_group,
         }
@@ -46,18 +64,340 @@ impl Viewport {
     pub fn update(&mut self, queue: &Queue, resolution: Resolution) {
         if self.params.screen_resolution != resolution {
             self.params.screen_resolution = resolution;
+            self.update_letterbox();
+            self.write_params(queue);
+        }
+    }
+
+    /// Lays out text at a fixed virtual resolution (e.g. 640x360) that is scaled up to the
+    /// resolution of the render target, or at the resolution of the render target if `None`.
+    ///
+    /// Text is positioned and clipped in virtual pixels and scaled up by the largest integer
+    /// factor that fits the render target, so that text stays crisp. The scaled area is centered,
+    /// leaving empty bars around it (letterboxing). This is meant for applications such as
+    /// emulators that render everything at a fixed virtual resolution. While a virtual resolution
+    /// is set, [`Viewport::resolution`] returns it instead of the resolution of the render target.
+    pub fn set_virtual_resolution(&mut self, queue: &Queue, resolution: Option<Resolution>) {
+        if self.virtual_resolution != resolution {
+            self.virtual_resolution = resolution;
+            self.update_letterbox();
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the virtual resolution set with [`Viewport::set_virtual_resolution`].
+    pub fn virtual_resolution(&self) -> Option<Resolution> {
+        self.virtual_resolution
+    }
+
+    /// Returns the factor virtual pixels are scaled up by, or 1 without a virtual resolution.
+    pub fn virtual_scale(&self) -> u32 {
+        self.params.letterbox_scale
+    }
 
-            queue.write_buffer(&self.params_buffer, 0, unsafe {
-                slice::from_raw_parts(
-                    &self.params as *const Params as *const u8,
-                    mem::size_of::<Params>(),
+    /// Converts a position on the render target in physical pixels (e.g. of the mouse) to
+    /// virtual pixels. Without a virtual resolution, the position is returned unchanged.
+    pub fn physical_to_virtual(&self, x: f32, y: f32) -> (f32, f32) {
+        let [offset_x, offset_y] = self.params.letterbox_offset;
+        let scale = self.params.letterbox_scale as f32;
+        ((x - offset_x as f32) / scale, (y - offset_y as f32) / scale)
+    }
+
+    /// Converts a position in the pixels text is laid out in to clip space.
+    pub(crate) fn to_clip(&self, x: i32, y: i32) -> [f32; 2] {
+        let [offset_x, offset_y] = self.params.letterbox_offset;
+        let scale = self.params.letterbox_scale as i32;
+        let screen = self.params.screen_resolution;
+        [
+            2.0 * (offset_x + x * scale) as f32 / screen.width as f32 - 1.0,
+            1.0 - 2.0 * (offset_y + y * scale) as f32 / screen.height as f32,
+        ]
+    }
+
+    /// Fits the virtual resolution into the resolution of the render target.
+    fn update_letterbox(&mut self) {
+        let screen = self.params.screen_resolution;
+        let (scale, offset) = match self.virtual_resolution {
+            Some(resolution) if resolution.width > 0 && resolution.height > 0 => {
+                let scale = (screen.width / resolution.width)
+                    .min(screen.height / resolution.height)
+                    .max(1);
+                let offset = |screen: u32, size: u32| (screen as i32 - (size * scale) as i32) / 2;
+                (
+                    scale,
+                    [
+                        offset(screen.width, resolution.width),
+                        offset(screen.height, resolution.height),
+                    ],
                 )
-            });
+            }
+            _ => (1, [0, 0]),
+        };
+
+        self.params.letterbox_scale = scale;
+        self.params.letterbox_offset = offset;
+    }
+
+    /// Sets the range that the depth values of glyphs are mapped to.
+    ///
+    /// The depth returned by `metadata_to_depth` in [`crate::TextRenderer::prepare_with_depth`]
+    /// is remapped in the shader, so it can always be produced in the `[0, 1]` range regardless
+    /// of the depth convention of the render pass.
+    pub fn set_depth_range(&mut self, queue: &Queue, depth_range: DepthRange) {
+        if self.params.depth_range != depth_range {
+            self.params.depth_range = depth_range;
+            self.write_params(queue);
         }
     }
 
-    /// Returns the current resolution of the `Viewport`.
+    /// Returns the current depth range of the `Viewport`.
+    pub fn depth_range(&self) -> DepthRange {
+        self.params.depth_range
+    }
+
+    /// Sets the color space of the render target.
+    ///
+    /// Text colors are always given in sRGB. When rendering to a surface with different primaries
+    /// (e.g. a Display P3 swapchain), set its color space here so that colors are converted to
+    /// the primaries of the surface instead of looking oversaturated. Renderers drawing to
+    /// surfaces with different color spaces should use separate viewports.
+    pub fn set_color_space(&mut self, queue: &Queue, color_space: ColorSpace) {
+        if self.color_space != color_space {
+            self.color_space = color_space;
+            self.params.color_matrix = color_space.color_matrix();
+            self.params.convert_color_space = (color_space != ColorSpace::Srgb) as u32;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the current color space of the `Viewport`.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Sets the time used to animate glyphs, see [`crate::GlyphAnimation`].
+    ///
+    /// Animations are driven by the shader, so text doesn't need to be prepared again when only
+    /// the time changes. The time is typically the time elapsed since the application started.
+    /// It wraps around every hour to keep its precision in the shader.
+    pub fn set_time(&mut self, queue: &Queue, time: Duration) {
+        let time =
+            (time.as_secs() % Self::TIME_WRAP_SECONDS) as f32 + time.subsec_nanos() as f32 / 1e9;
+        if self.params.time != time {
+            self.params.time = time;
+            self.write_params(queue);
+        }
+    }
+
+    /// Enables ordered dithering of the antialiased edges of glyphs. Disabled by default.
+    ///
+    /// Rendering text to 8-bit targets that are heavily post-processed afterwards (e.g. with
+    /// tone mapping or color grading) shows banding in the edges of glyphs. Dithering adds a
+    /// fixed pattern of noise of a single 8-bit step to the coverage of mask glyphs, scaled to
+    /// the sRGB encoding of the target when blending happens in linear space. Fully transparent
+    /// and fully covered pixels are left unchanged.
+    pub fn set_dithering(&mut self, queue: &Queue, dithering: bool) {
+        if self.dithering() != dithering {
+            self.params.dither = dithering as u32;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns `true` if the edges of glyphs are dithered.
+    pub fn dithering(&self) -> bool {
+        self.params.dither != 0
+    }
+
+    /// Sets the colors of glyphs with a palette index, see
+    /// [`crate::TextRenderer::set_metadata_to_palette_index`].
+    ///
+    /// The colors are looked up in the shader, so switching between themes (e.g. a light and a
+    /// dark mode) only writes the palette instead of preparing all text again. Indices without a
+    /// color in `palette` are transparent, and colors after the first
+    /// [`Viewport::PALETTE_SIZE`] are ignored.
+    pub fn set_palette(&mut self, queue: &Queue, palette: &[Color]) {
+        let mut colors = [[0; 4]; 4];
+        for (index, color) in palette.iter().take(Self::PALETTE_SIZE).enumerate() {
+            colors[index / 4][index % 4] = color.0;
+        }
+
+        if self.params.palette != colors {
+            self.params.palette = colors;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the palette set with [`Viewport::set_palette`].
+    pub fn palette(&self) -> [Color; Self::PALETTE_SIZE] {
+        std::array::from_fn(|index| Color(self.params.palette[index / 4][index % 4]))
+    }
+
+    fn write_params(&self, queue: &Queue) {
+        queue.write_buffer(&self.params_buffer, 0, self.params_bytes());
+    }
+
+    /// Returns the params of the viewport as they are laid out in the shader.
+    pub(crate) fn params_bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                &self.params as *const Params as *const u8,
+                mem::size_of::<Params>(),
+            )
+        }
+    }
+
+    /// Returns the resolution text is laid out at, which is the virtual resolution if one is set
+    /// (see [`Viewport::set_virtual_resolution`]) and the resolution of the render target
+    /// otherwise.
     pub fn resolution(&self) -> Resolution {
+        self.virtual_resolution
+            .unwrap_or(self.params.screen_resolution)
+    }
+
+    /// Returns the resolution of the render target given to [`Viewport::update`].
+    pub fn target_resolution(&self) -> Resolution {
         self.params.screen_resolution
     }
 }
+
+/// A pool of [`Viewport`]s for rendering the same text to targets of different resolutions in
+/// every frame, e.g. to a main view and to thumbnails.
+///
+/// A viewport can only hold one resolution per submission, since its uniforms are written when
+/// the queue is submitted. The pool hands out one viewport per distinct resolution used in a frame
+/// and keeps them between frames, so that rendering to the same set of resolutions every frame
+/// doesn't allocate or write any uniforms.
+#[derive(Debug, Default)]
+pub struct ViewportPool {
+    viewports: Vec<Viewport>,
+    /// The number of viewports at the start of `viewports` used since the last call to
+    /// `begin_frame`
+    used: usize,
+}
+
+impl ViewportPool {
+    /// Creates an empty `ViewportPool`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes all viewports available for other resolutions again.
+    ///
+    /// This should be called once per frame, after the commands rendering with the viewports of
+    /// the previous frame were submitted.
+    pub fn begin_frame(&mut self) {
+        self.used = 0;
+    }
+
+    /// Returns a viewport for rendering at `resolution`.
+    ///
+    /// Within a frame, the same viewport is returned for every request with the same resolution.
+    /// Viewports are reused across frames, preferring a viewport that had the same resolution in
+    /// the last frame, and only created when a frame uses more distinct resolutions than any
+    /// frame before. Other settings of the returned viewport (e.g. the time) can be changed, but
+    /// are kept when the viewport is reused for another resolution.
+    pub fn get(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        cache: &Cache,
+        resolution: Resolution,
+    ) -> &mut Viewport {
+        let matches = |viewport: &Viewport| viewport.target_resolution() == resolution;
+
+        let index = match self.viewports[..self.used].iter().position(matches) {
+            Some(index) => index,
+            None => {
+                // Take a free viewport with the same resolution, or any free viewport
+                let free = &self.viewports[self.used..];
+                match free
+                    .iter()
+                    .position(matches)
+                    .or((!free.is_empty()).then_some(0))
+                {
+                    Some(offset) => self.viewports.swap(self.used, self.used + offset),
+                    None => self.viewports.push(Viewport::new(device, cache)),
+                }
+                self.used += 1;
+                self.used - 1
+            }
+        };
+
+        let viewport = &mut self.viewports[index];
+        viewport.update(queue, resolution);
+        viewport
+    }
+
+    /// Returns the number of viewports in the pool.
+    pub fn len(&self) -> usize {
+        self.viewports.len()
+    }
+
+    /// Returns `true` if the pool has no viewports.
+    pub fn is_empty(&self) -> bool {
+        self.viewports.is_empty()
+    }
+}
+
+/// Maps glyph depth values to the depth range of a render pass.
+///
+/// A depth value `d` in `[0, 1]` is written as `near + d * (far - near)`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthRange {
+    /// The depth that a glyph depth of `0.0` is mapped to.
+    pub near: f32,
+    /// The depth that a glyph depth of `1.0` is mapped to.
+    pub far: f32,
+}
+
+impl DepthRange {
+    /// The default range, which leaves depth values unchanged.
+    pub const STANDARD: Self = Self {
+        near: 0.0,
+        far: 1.0,
+    };
+
+    /// A reversed range for reversed-Z depth buffers, where near values are larger.
+    pub const REVERSED: Self = Self {
+        near: 1.0,
+        far: 0.0,
+    };
+}
+
+impl Default for DepthRange {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// The color space of a render target.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorSpace {
+    /// sRGB primaries. Colors are rendered unchanged.
+    #[default]
+    Srgb,
+    /// Display P3 primaries, as used by wide-gamut displays on Apple devices.
+    DisplayP3,
+    /// Custom primaries, given as a row-major matrix that converts linear sRGB to the primaries
+    /// of the target.
+    Custom([[f32; 3]; 3]),
+}
+
+impl ColorSpace {
+    /// Returns the matrix that converts linear sRGB to this color space, laid out as the columns
+    /// of a WGSL `mat3x3<f32>`.
+    fn color_matrix(self) -> [[f32; 4]; 3] {
+        let rows = match self {
+            Self::Srgb => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Self::DisplayP3 => [
+                [0.822_462_1, 0.177_538, 0.0],
+                [0.033_194_1, 0.966_805_8, 0.0],
+                [0.017_082_7, 0.072_397_4, 0.910_519_9],
+            ],
+            Self::Custom(rows) => rows,
+        };
+
+        [0, 1, 2].map(|column| [rows[0][column], rows[1][column], rows[2][column], 0.0])
+    }
+}